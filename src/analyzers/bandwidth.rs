@@ -0,0 +1,296 @@
+/// 带宽与总线负载统计：按服务、方法、发送方ECU与角色对（矩阵中的roles）、传输层协议、VLAN分别统计字节数与报文数
+/// 按固定时间窗口统计每秒的字节数，给出平均值、峰值以及峰值所在的窗口，每个维度只输出字节数最多的前N项
+/// 字节数为SomeIP报文头与载荷的长度，不包括以太网、IP与传输层的报文头
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::errors::MyError;
+use crate::matrix::types::Matrix;
+use crate::types::{
+    SomeipMessage, SomeipMethodId, SomeipServiceId, SomeipTransportPortocol, VlanId,
+};
+
+use super::Analyzer;
+
+/// Length字段之前的报文头长度
+const HEADER_BEFORE_LENGTH: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BandwidthGroup {
+    Service(SomeipServiceId),
+    Method(SomeipServiceId, SomeipMethodId),
+    /// 发送方的角色名，矩阵中没有的使用IP地址，用于核对每个ECU的带宽预算
+    Sender(String),
+    /// 发送方与接收方的角色名，矩阵中没有的使用IP地址
+    RolePair(String, String),
+    Transport(SomeipTransportPortocol),
+    Vlan(Option<VlanId>),
+}
+
+impl BandwidthGroup {
+    fn dimension(&self) -> &'static str {
+        match self {
+            BandwidthGroup::Service(..) => "service",
+            BandwidthGroup::Method(..) => "method",
+            BandwidthGroup::Sender(..) => "sender",
+            BandwidthGroup::RolePair(..) => "role pair",
+            BandwidthGroup::Transport(..) => "transport",
+            BandwidthGroup::Vlan(..) => "vlan",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct BandwidthStats {
+    pub bytes: u64,
+    pub messages: u64,
+    /// 窗口序号 -> (字节数, 报文数)
+    pub windows: BTreeMap<u64, (u64, u64)>,
+}
+
+impl BandwidthStats {
+    /// 字节数最多的窗口
+    pub fn peak(&self) -> Option<(u64, u64)> {
+        self.windows
+            .iter()
+            .max_by(|a, b| a.1 .0.cmp(&b.1 .0).then(b.0.cmp(a.0)))
+            .map(|(window, (bytes, _))| (*window, *bytes))
+    }
+}
+
+pub struct BandwidthAnalyzer<'a> {
+    matrix: &'a Matrix,
+    window: Duration,
+    top: usize,
+    first_timestamp: Option<Duration>,
+    last_timestamp: Duration,
+    groups: BTreeMap<BandwidthGroup, BandwidthStats>,
+}
+
+impl<'a> BandwidthAnalyzer<'a> {
+    pub fn new(matrix: &'a Matrix, window: Duration, top: usize) -> Self {
+        BandwidthAnalyzer {
+            matrix,
+            window: window.max(Duration::from_millis(1)),
+            top,
+            first_timestamp: None,
+            last_timestamp: Duration::ZERO,
+            groups: BTreeMap::new(),
+        }
+    }
+
+    fn group_name(&self, group: &BandwidthGroup) -> String {
+        match group {
+            BandwidthGroup::Service(service_id) => match self.matrix.service_name(*service_id) {
+                Some(name) => format!("0x{:04x} {}", service_id, name),
+                None => format!("0x{:04x}", service_id),
+            },
+            BandwidthGroup::Method(service_id, method_id) => {
+                let name = self
+                    .matrix
+                    .services
+                    .get(service_id)
+                    .and_then(|service| service.methods.get(method_id))
+                    .map(|method| format!(" {}", method.method_name))
+                    .unwrap_or_default();
+                format!("0x{:04x}.0x{:04x}{}", service_id, method_id, name)
+            }
+            BandwidthGroup::Sender(sender) => sender.clone(),
+            BandwidthGroup::RolePair(sender, receiver) => format!("{} -> {}", sender, receiver),
+            BandwidthGroup::Transport(transport) => format!("{:?}", transport),
+            BandwidthGroup::Vlan(Some(vlan_id)) => format!("{}", vlan_id),
+            BandwidthGroup::Vlan(None) => "untagged".to_owned(),
+        }
+    }
+
+    /// 抓包时长，不足一个窗口的按一个窗口计算
+    fn duration(&self) -> Duration {
+        self.last_timestamp
+            .saturating_sub(self.first_timestamp.unwrap_or_default())
+            .max(self.window)
+    }
+
+    /// 按维度分组，每个维度按字节数从大到小取前N项
+    fn top_groups(&self) -> BTreeMap<&'static str, Vec<(&BandwidthGroup, &BandwidthStats)>> {
+        let mut dimensions: BTreeMap<&'static str, Vec<(&BandwidthGroup, &BandwidthStats)>> =
+            BTreeMap::new();
+        for (group, stats) in &self.groups {
+            dimensions
+                .entry(group.dimension())
+                .or_default()
+                .push((group, stats));
+        }
+        for groups in dimensions.values_mut() {
+            groups.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.bytes));
+            groups.truncate(self.top);
+        }
+        dimensions
+    }
+
+    /// (平均每秒字节数, 峰值每秒字节数, 峰值窗口开始的相对时间)
+    fn rates(&self, stats: &BandwidthStats) -> (f64, f64, f64) {
+        let average = stats.bytes as f64 / self.duration().as_secs_f64();
+        let (window, bytes) = stats.peak().unwrap_or_default();
+        let peak = bytes as f64 / self.window.as_secs_f64();
+        (average, peak, self.window.as_secs_f64() * window as f64)
+    }
+}
+
+impl<'a> Analyzer for BandwidthAnalyzer<'a> {
+    fn name(&self) -> &'static str {
+        "bandwidth"
+    }
+
+    fn handle_message(&mut self, msg: &SomeipMessage) {
+        let first_timestamp = *self.first_timestamp.get_or_insert(msg.timestamp);
+        self.last_timestamp = self.last_timestamp.max(msg.timestamp);
+        let window = (msg.timestamp.saturating_sub(first_timestamp).as_nanos()
+            / self.window.as_nanos()) as u64;
+        let bytes = (HEADER_BEFORE_LENGTH + msg.available_length) as u64;
+        let sender = self.matrix.role_name_or_ip(&msg.source.ip_addr);
+
+        let groups = [
+            BandwidthGroup::Service(msg.service_id),
+            BandwidthGroup::Method(msg.service_id, msg.method_id),
+            BandwidthGroup::Sender(sender.clone()),
+            BandwidthGroup::RolePair(
+                sender,
+                self.matrix.role_name_or_ip(&msg.destination.ip_addr),
+            ),
+            BandwidthGroup::Transport(msg.transport_protocol),
+            BandwidthGroup::Vlan(msg.vlan_id),
+        ];
+        for group in groups {
+            let stats = self.groups.entry(group).or_default();
+            stats.bytes += bytes;
+            stats.messages += 1;
+            let window = stats.windows.entry(window).or_default();
+            window.0 += bytes;
+            window.1 += 1;
+        }
+    }
+
+    fn write_report(&self, w: &mut dyn Write) -> Result<(), MyError> {
+        let (bytes, messages) = self
+            .groups
+            .iter()
+            .filter(|(group, _)| matches!(group, BandwidthGroup::Transport(..)))
+            .fold((0, 0), |acc, (_, stats)| {
+                (acc.0 + stats.bytes, acc.1 + stats.messages)
+            });
+        writeln!(
+            w,
+            "Bandwidth: {} messages, {} bytes in {:.6}s, window {} ms",
+            messages,
+            bytes,
+            self.duration().as_secs_f64(),
+            self.window.as_millis()
+        )?;
+        for (dimension, groups) in self.top_groups() {
+            writeln!(w, "by {}:", dimension)?;
+            for (group, stats) in groups {
+                let (average, peak, peak_time) = self.rates(stats);
+                writeln!(
+                    w,
+                    "  {:>12} B  {:>8} msgs  {:>12.1} B/s avg  {:>12.1} B/s peak at {:>12.6}s  {}",
+                    stats.bytes,
+                    stats.messages,
+                    average,
+                    peak,
+                    peak_time,
+                    self.group_name(group)
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    fn json_report(&self) -> Option<Value> {
+        let dimensions: serde_json::Map<String, Value> = self
+            .top_groups()
+            .into_iter()
+            .map(|(dimension, groups)| {
+                let groups: Vec<Value> = groups
+                    .into_iter()
+                    .map(|(group, stats)| {
+                        let (average, peak, peak_time) = self.rates(stats);
+                        json!({
+                            "name": self.group_name(group),
+                            "bytes": stats.bytes,
+                            "messages": stats.messages,
+                            "average_bytes_per_second": average,
+                            "peak_bytes_per_second": peak,
+                            "peak_time": peak_time,
+                        })
+                    })
+                    .collect();
+                (dimension.to_owned(), Value::Array(groups))
+            })
+            .collect();
+        Some(json!({
+            "window_ms": self.window.as_millis() as u64,
+            "duration": self.duration().as_secs_f64(),
+            "groups": dimensions,
+        }))
+    }
+}
+
+#[test]
+fn bandwidth_peak_and_top() {
+    use crate::analyzers::test_message;
+    use crate::types::SomeipMessageType;
+
+    let matrix = Matrix::default();
+    let mut analyzer = BandwidthAnalyzer::new(&matrix, Duration::from_millis(100), 1);
+    for (ts_ms, service_id) in [(0, 0x1000), (10, 0x1000), (20, 0x2000), (150, 0x1000)] {
+        let mut msg = test_message(
+            ts_ms,
+            "10.0.0.1:30501",
+            "10.0.0.2:30501",
+            SomeipMessageType::Notification,
+            service_id,
+            0x8001,
+        );
+        msg.available_length = 92;
+        analyzer.handle_message(&msg);
+    }
+
+    let stats = &analyzer.groups[&BandwidthGroup::Service(0x1000)];
+    assert_eq!((stats.bytes, stats.messages), (300, 3));
+    assert_eq!(stats.peak(), Some((0, 200)));
+    assert_eq!(analyzer.rates(stats), (2000.0, 2000.0, 0.0));
+
+    let top = analyzer.top_groups();
+    assert_eq!(top["service"].len(), 1);
+    assert_eq!(top["service"][0].0, &BandwidthGroup::Service(0x1000));
+    let sender = &analyzer.groups[&BandwidthGroup::Sender("10.0.0.1".to_owned())];
+    assert_eq!((sender.bytes, sender.messages), (400, 4));
+}
+
+#[test]
+fn bandwidth_long_capture() {
+    use crate::analyzers::test_message;
+    use crate::types::SomeipMessageType;
+
+    // 1ms的窗口，50天之后的窗口序号超过u32
+    let matrix = Matrix::default();
+    let mut analyzer = BandwidthAnalyzer::new(&matrix, Duration::from_millis(1), 1);
+    for (ts_ms, available_length) in [(0, 8), (5_000_000_000, 92)] {
+        let mut msg = test_message(
+            ts_ms,
+            "10.0.0.1:30501",
+            "10.0.0.2:30501",
+            SomeipMessageType::Notification,
+            0x1000,
+            0x8001,
+        );
+        msg.available_length = available_length;
+        analyzer.handle_message(&msg);
+    }
+    let stats = &analyzer.groups[&BandwidthGroup::Service(0x1000)];
+    assert_eq!(stats.peak(), Some((5_000_000_000, 100)));
+    assert_eq!(analyzer.rates(stats).2, 5_000_000.0);
+}
@@ -0,0 +1,722 @@
+/// 两次抓包的行为回归对比：分别统计基准抓包（--file）与待测抓包（--compare）的行为特征，输出两者的差异
+/// 对比的内容：出现的服务、Notification与FF方法的发送周期、Request到Response的时延分布、
+/// 新出现的返回码、没有订阅就收到的事件、按矩阵解析出的每个信号的取值范围
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::Write;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::errors::MyError;
+use crate::matrix::types::Matrix;
+use crate::types::{
+    Port, SomeipClientId, SomeipEventgroupId, SomeipMessage, SomeipMessageType, SomeipMethodId,
+    SomeipReturnCode, SomeipSdEntryType, SomeipServiceId, SomeipSessionId,
+};
+
+use super::return_code::return_code_name;
+use super::Analyzer;
+
+/// 周期的平均值变化超过该比例认为发生了变化
+const CYCLE_TOLERANCE: f64 = 0.1;
+/// 时延的中位数或95分位变化超过该比例且超过LATENCY_MIN_CHANGE_MS认为发生了变化
+const LATENCY_TOLERANCE: f64 = 0.2;
+const LATENCY_MIN_CHANGE_MS: f64 = 1.0;
+/// 超过该时间仍未收到应答的Request不计入时延
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+type MethodKey = (SomeipServiceId, SomeipMethodId);
+type RequestKey = (
+    SomeipServiceId,
+    SomeipMethodId,
+    SomeipClientId,
+    SomeipSessionId,
+);
+
+/// 采样值，单位毫秒
+#[derive(Debug, Default)]
+pub struct Distribution {
+    samples: Vec<f64>,
+}
+
+impl Distribution {
+    fn push(&mut self, sample: f64) {
+        self.samples.push(sample);
+    }
+
+    pub fn count(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.samples.iter().sum::<f64>() / self.samples.len().max(1) as f64
+    }
+
+    /// 最近秩法求分位数，p取0~1
+    pub fn percentile(&self, p: f64) -> f64 {
+        let mut samples = self.samples.clone();
+        samples.sort_by(f64::total_cmp);
+        let rank = ((p * samples.len() as f64).ceil() as usize).max(1);
+        samples.get(rank - 1).copied().unwrap_or_default()
+    }
+}
+
+/// 一次抓包的行为特征
+#[derive(Debug, Default)]
+pub struct CaptureProfile {
+    pub services: BTreeSet<SomeipServiceId>,
+    pub cycles: BTreeMap<MethodKey, Distribution>,
+    pub latencies: BTreeMap<MethodKey, Distribution>,
+    pub errors: BTreeSet<(SomeipServiceId, SomeipMethodId, SomeipReturnCode)>,
+    /// 单播发送给没有订阅过该服务的客户端的Notification
+    pub unsubscribed: BTreeSet<(SomeipServiceId, SomeipMethodId, IpAddr)>,
+    /// 信号名 -> (最小值, 最大值)
+    pub ranges: BTreeMap<String, (f64, f64)>,
+    last_sent: HashMap<(IpAddr, Port, SomeipServiceId, SomeipMethodId), Duration>,
+    pending: HashMap<RequestKey, Duration>,
+    /// 上一次丢弃超时Request的时间
+    expired_at: Duration,
+    subscribed: HashSet<(IpAddr, SomeipServiceId, SomeipEventgroupId)>,
+}
+
+impl CaptureProfile {
+    pub(crate) fn record(&mut self, matrix: &Matrix, msg: &SomeipMessage) {
+        if let Some(sd) = &msg.sd {
+            for entry in &sd.entries {
+                match entry.entry_type {
+                    SomeipSdEntryType::OfferService => {
+                        self.services.insert(entry.service_id);
+                    }
+                    SomeipSdEntryType::Subscribe if entry.ttl > 0 => {
+                        self.subscribed.insert((
+                            msg.source.ip_addr,
+                            entry.service_id,
+                            entry.eventgroup_id,
+                        ));
+                    }
+                    // TTL为0是StopSubscribe
+                    SomeipSdEntryType::Subscribe => {
+                        self.subscribed.remove(&(
+                            msg.source.ip_addr,
+                            entry.service_id,
+                            entry.eventgroup_id,
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+            return;
+        }
+
+        self.services.insert(msg.service_id);
+        let method_key = (msg.service_id, msg.method_id);
+        match msg.message_type {
+            SomeipMessageType::Notification | SomeipMessageType::RequestWithoutResponse => {
+                let sender = (
+                    msg.source.ip_addr,
+                    msg.source.port,
+                    msg.service_id,
+                    msg.method_id,
+                );
+                if let Some(last) = self.last_sent.insert(sender, msg.timestamp) {
+                    let interval = msg.timestamp.saturating_sub(last).as_secs_f64() * 1000.0;
+                    self.cycles.entry(method_key).or_default().push(interval);
+                }
+                if msg.message_type == SomeipMessageType::Notification
+                    && !msg.destination.ip_addr.is_multicast()
+                    && !self.is_subscribed(matrix, msg)
+                {
+                    self.unsubscribed.insert((
+                        msg.service_id,
+                        msg.method_id,
+                        msg.destination.ip_addr,
+                    ));
+                }
+            }
+            SomeipMessageType::Request => {
+                // 超时未应答的Request不会再计入时延，定期丢弃
+                if msg.timestamp.saturating_sub(self.expired_at) >= RESPONSE_TIMEOUT {
+                    self.pending
+                        .retain(|_, sent| msg.timestamp.saturating_sub(*sent) < RESPONSE_TIMEOUT);
+                    self.expired_at = msg.timestamp;
+                }
+                self.pending.insert(
+                    (msg.service_id, msg.method_id, msg.client_id, msg.session_id),
+                    msg.timestamp,
+                );
+            }
+            SomeipMessageType::Response | SomeipMessageType::ResponseWithError => {
+                let request = (msg.service_id, msg.method_id, msg.client_id, msg.session_id);
+                if let Some(sent) = self.pending.remove(&request) {
+                    let latency = msg.timestamp.saturating_sub(sent);
+                    if latency < RESPONSE_TIMEOUT {
+                        self.latencies
+                            .entry(method_key)
+                            .or_default()
+                            .push(latency.as_secs_f64() * 1000.0);
+                    }
+                }
+                if msg.return_code != 0 {
+                    self.errors
+                        .insert((msg.service_id, msg.method_id, msg.return_code));
+                }
+            }
+            _ => {}
+        }
+
+        if msg.return_code == 0 {
+            self.record_ranges(matrix, msg);
+        }
+    }
+
+    /// 矩阵中定义了事件所属的Eventgroup时按Eventgroup判断，否则订阅了服务的任意Eventgroup即可
+    fn is_subscribed(&self, matrix: &Matrix, msg: &SomeipMessage) -> bool {
+        let client = msg.destination.ip_addr;
+        let eventgroups: Vec<SomeipEventgroupId> = matrix
+            .services
+            .get(&msg.service_id)
+            .map(|service| {
+                service
+                    .eventgroups
+                    .values()
+                    .filter(|eventgroup| eventgroup.methods.contains(&msg.method_id))
+                    .map(|eventgroup| eventgroup.eventgroup_id)
+                    .collect()
+            })
+            .unwrap_or_default();
+        self.subscribed
+            .iter()
+            .any(|(ip_addr, service_id, eventgroup_id)| {
+                *ip_addr == client
+                    && *service_id == msg.service_id
+                    && (eventgroups.is_empty() || eventgroups.contains(eventgroup_id))
+            })
+    }
+
+    fn record_ranges(&mut self, matrix: &Matrix, msg: &SomeipMessage) {
+        let method = match matrix
+            .services
+            .get(&msg.service_id)
+            .and_then(|service| service.methods.get(&msg.method_id))
+        {
+            Some(method) => method,
+            None => return,
+        };
+        let types = match method.method_type.parameter_types(msg.message_type) {
+            Some(types) if !types.is_empty() => types,
+            _ => return,
+        };
+        if let Ok(values) = matrix.decode_parameters(&types, &msg.payload) {
+            let prefix = format!(
+                "{}.{}",
+                matrix.service_name(msg.service_id).unwrap_or_default(),
+                method.method_name
+            );
+            for (index, (_, value)) in values.iter().enumerate() {
+                let name = match values.len() {
+                    1 => prefix.clone(),
+                    _ => format!("{}.{}", prefix, index),
+                };
+                flatten(&name, value, &mut self.ranges);
+            }
+        }
+    }
+
+    fn finish(&mut self) {
+        self.last_sent.clear();
+        self.pending.clear();
+        self.expired_at = Duration::ZERO;
+        self.subscribed.clear();
+    }
+}
+
+/// 把数值型的叶子节点记入取值范围，数组的所有元素合并为一个信号
+fn flatten(name: &str, value: &Value, ranges: &mut BTreeMap<String, (f64, f64)>) {
+    let number = match value {
+        Value::Number(number) => number.as_f64(),
+        Value::Bool(b) => Some(*b as u8 as f64),
+        Value::Object(members) => {
+            for (member, value) in members {
+                flatten(&format!("{}.{}", name, member), value, ranges);
+            }
+            None
+        }
+        Value::Array(values) => {
+            for value in values {
+                flatten(&format!("{}[]", name), value, ranges);
+            }
+            None
+        }
+        _ => None,
+    };
+    if let Some(number) = number {
+        let range = ranges.entry(name.to_owned()).or_insert((number, number));
+        range.0 = range.0.min(number);
+        range.1 = range.1.max(number);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Regression {
+    ServiceMissing(SomeipServiceId),
+    ServiceNew(SomeipServiceId),
+    /// 平均周期，单位毫秒
+    CycleTime {
+        method: MethodKey,
+        baseline: f64,
+        candidate: f64,
+    },
+    /// (中位数, 95分位)，单位毫秒
+    Latency {
+        method: MethodKey,
+        baseline: (f64, f64),
+        candidate: (f64, f64),
+    },
+    NewErrorCode {
+        method: MethodKey,
+        return_code: SomeipReturnCode,
+    },
+    NewUnsubscribedEvent {
+        method: MethodKey,
+        client: IpAddr,
+    },
+    ValueRange {
+        signal: String,
+        baseline: (f64, f64),
+        candidate: (f64, f64),
+    },
+}
+
+impl Regression {
+    fn kind(&self) -> &'static str {
+        match self {
+            Regression::ServiceMissing(..) => "service missing",
+            Regression::ServiceNew(..) => "service new",
+            Regression::CycleTime { .. } => "cycle time",
+            Regression::Latency { .. } => "latency",
+            Regression::NewErrorCode { .. } => "new error code",
+            Regression::NewUnsubscribedEvent { .. } => "new unsubscribed event",
+            Regression::ValueRange { .. } => "value range",
+        }
+    }
+}
+
+fn changed(baseline: f64, candidate: f64, tolerance: f64) -> bool {
+    (candidate - baseline).abs() > baseline.abs() * tolerance
+}
+
+/// 对比两次抓包的行为特征，只对比两次都出现的方法与信号
+pub fn compare(baseline: &CaptureProfile, candidate: &CaptureProfile) -> Vec<Regression> {
+    let mut regressions = vec![];
+    for service_id in baseline.services.difference(&candidate.services) {
+        regressions.push(Regression::ServiceMissing(*service_id));
+    }
+    for service_id in candidate.services.difference(&baseline.services) {
+        regressions.push(Regression::ServiceNew(*service_id));
+    }
+    for (method, cycles) in &baseline.cycles {
+        if let Some(candidate) = candidate.cycles.get(method) {
+            if changed(cycles.mean(), candidate.mean(), CYCLE_TOLERANCE) {
+                regressions.push(Regression::CycleTime {
+                    method: *method,
+                    baseline: cycles.mean(),
+                    candidate: candidate.mean(),
+                });
+            }
+        }
+    }
+    for (method, latencies) in &baseline.latencies {
+        if let Some(candidate) = candidate.latencies.get(method) {
+            let baseline = (latencies.percentile(0.5), latencies.percentile(0.95));
+            let candidate = (candidate.percentile(0.5), candidate.percentile(0.95));
+            let latency_changed = |baseline: f64, candidate: f64| {
+                changed(baseline, candidate, LATENCY_TOLERANCE)
+                    && (candidate - baseline).abs() > LATENCY_MIN_CHANGE_MS
+            };
+            if latency_changed(baseline.0, candidate.0) || latency_changed(baseline.1, candidate.1)
+            {
+                regressions.push(Regression::Latency {
+                    method: *method,
+                    baseline,
+                    candidate,
+                });
+            }
+        }
+    }
+    for (service_id, method_id, return_code) in candidate.errors.difference(&baseline.errors) {
+        regressions.push(Regression::NewErrorCode {
+            method: (*service_id, *method_id),
+            return_code: *return_code,
+        });
+    }
+    for (service_id, method_id, client) in candidate.unsubscribed.difference(&baseline.unsubscribed)
+    {
+        regressions.push(Regression::NewUnsubscribedEvent {
+            method: (*service_id, *method_id),
+            client: *client,
+        });
+    }
+    for (signal, range) in &baseline.ranges {
+        if let Some(candidate) = candidate.ranges.get(signal) {
+            if range != candidate {
+                regressions.push(Regression::ValueRange {
+                    signal: signal.clone(),
+                    baseline: *range,
+                    candidate: *candidate,
+                });
+            }
+        }
+    }
+    regressions
+}
+
+/// 先接收基准抓包的报文，调用start_candidate后接收待测抓包的报文
+pub struct CompareAnalyzer<'a> {
+    matrix: &'a Matrix,
+    baseline: CaptureProfile,
+    candidate: CaptureProfile,
+    in_candidate: bool,
+}
+
+impl<'a> CompareAnalyzer<'a> {
+    pub fn new(matrix: &'a Matrix) -> Self {
+        CompareAnalyzer {
+            matrix,
+            baseline: CaptureProfile::default(),
+            candidate: CaptureProfile::default(),
+            in_candidate: false,
+        }
+    }
+
+    pub fn start_candidate(&mut self) {
+        self.baseline.finish();
+        self.in_candidate = true;
+    }
+
+    fn method_name(&self, (service_id, method_id): MethodKey) -> String {
+        let name = self
+            .matrix
+            .services
+            .get(&service_id)
+            .and_then(|service| service.methods.get(&method_id))
+            .map(|method| format!(" {}", method.method_name))
+            .unwrap_or_default();
+        format!("0x{:04x}.0x{:04x}{}", service_id, method_id, name)
+    }
+
+    fn service_name(&self, service_id: SomeipServiceId) -> String {
+        match self.matrix.service_name(service_id) {
+            Some(name) => format!("0x{:04x} {}", service_id, name),
+            None => format!("0x{:04x}", service_id),
+        }
+    }
+
+    fn describe(&self, regression: &Regression) -> String {
+        match regression {
+            Regression::ServiceMissing(service_id) | Regression::ServiceNew(service_id) => {
+                self.service_name(*service_id)
+            }
+            Regression::CycleTime {
+                method,
+                baseline,
+                candidate,
+            } => format!(
+                "{}: {:.1}ms -> {:.1}ms",
+                self.method_name(*method),
+                baseline,
+                candidate
+            ),
+            Regression::Latency {
+                method,
+                baseline,
+                candidate,
+            } => format!(
+                "{}: p50 {:.1}ms p95 {:.1}ms -> p50 {:.1}ms p95 {:.1}ms",
+                self.method_name(*method),
+                baseline.0,
+                baseline.1,
+                candidate.0,
+                candidate.1
+            ),
+            Regression::NewErrorCode {
+                method,
+                return_code,
+            } => {
+                let matrix_method = self
+                    .matrix
+                    .services
+                    .get(&method.0)
+                    .and_then(|service| service.methods.get(&method.1));
+                format!(
+                    "{}: {}",
+                    self.method_name(*method),
+                    return_code_name(matrix_method, *return_code)
+                )
+            }
+            Regression::NewUnsubscribedEvent { method, client } => format!(
+                "{} to {}",
+                self.method_name(*method),
+                self.matrix.role_name_or_ip(client)
+            ),
+            Regression::ValueRange {
+                signal,
+                baseline,
+                candidate,
+            } => format!(
+                "{}: {}..{} -> {}..{}",
+                signal, baseline.0, baseline.1, candidate.0, candidate.1
+            ),
+        }
+    }
+}
+
+impl<'a> Analyzer for CompareAnalyzer<'a> {
+    fn name(&self) -> &'static str {
+        "compare"
+    }
+
+    fn handle_message(&mut self, msg: &SomeipMessage) {
+        match self.in_candidate {
+            true => self.candidate.record(self.matrix, msg),
+            false => self.baseline.record(self.matrix, msg),
+        }
+    }
+
+    fn write_report(&self, w: &mut dyn Write) -> Result<(), MyError> {
+        let regressions = compare(&self.baseline, &self.candidate);
+        writeln!(
+            w,
+            "Regression: {} differences between baseline and candidate",
+            regressions.len()
+        )?;
+        for regression in &regressions {
+            writeln!(
+                w,
+                "  {:<24}  {}",
+                regression.kind(),
+                self.describe(regression)
+            )?;
+        }
+        Ok(())
+    }
+
+    fn json_report(&self) -> Option<Value> {
+        let regressions: Vec<Value> = compare(&self.baseline, &self.candidate)
+            .iter()
+            .map(|regression| {
+                json!({
+                    "kind": regression.kind(),
+                    "description": self.describe(regression),
+                })
+            })
+            .collect();
+        Some(Value::Array(regressions))
+    }
+}
+
+#[test]
+fn compare_captures() {
+    use crate::analyzers::test_message;
+
+    let matrix = Matrix::default();
+    let mut analyzer = CompareAnalyzer::new(&matrix);
+    let notification = |ts_ms: u64, service_id: SomeipServiceId| {
+        test_message(
+            ts_ms,
+            "10.0.0.1:30501",
+            "239.0.0.1:30501",
+            SomeipMessageType::Notification,
+            service_id,
+            0x8001,
+        )
+    };
+    for ts_ms in [0, 100, 200, 300] {
+        analyzer.handle_message(&notification(ts_ms, 0x1000));
+        analyzer.handle_message(&notification(ts_ms, 0x2000));
+    }
+    analyzer.start_candidate();
+    for ts_ms in [0, 200, 400, 600] {
+        analyzer.handle_message(&notification(ts_ms, 0x1000));
+        analyzer.handle_message(&notification(ts_ms, 0x3000));
+    }
+    let mut error = test_message(
+        700,
+        "10.0.0.1:30501",
+        "10.0.0.2:40000",
+        SomeipMessageType::ResponseWithError,
+        0x1000,
+        1,
+    );
+    error.return_code = 0x01;
+    analyzer.handle_message(&error);
+
+    assert_eq!(
+        compare(&analyzer.baseline, &analyzer.candidate),
+        vec![
+            Regression::ServiceMissing(0x2000),
+            Regression::ServiceNew(0x3000),
+            Regression::CycleTime {
+                method: (0x1000, 0x8001),
+                baseline: 100.0,
+                candidate: 200.0
+            },
+            Regression::NewErrorCode {
+                method: (0x1000, 1),
+                return_code: 0x01
+            },
+        ]
+    );
+}
+
+#[test]
+fn compare_latency() {
+    use crate::analyzers::test_message;
+
+    let mut distribution = Distribution::default();
+    assert_eq!(distribution.percentile(0.5), 0.0);
+    for sample in [10.0, 1.0, 9.0, 2.0, 8.0, 3.0, 7.0, 4.0, 6.0, 5.0] {
+        distribution.push(sample);
+    }
+    assert_eq!(distribution.count(), 10);
+    assert_eq!(distribution.mean(), 5.5);
+    assert_eq!(distribution.percentile(0.0), 1.0);
+    assert_eq!(distribution.percentile(0.5), 5.0);
+    assert_eq!(distribution.percentile(0.95), 10.0);
+
+    let matrix = Matrix::default();
+    let mut analyzer = CompareAnalyzer::new(&matrix);
+    let exchange = |analyzer: &mut CompareAnalyzer, ts_ms, latency_ms, session_id| {
+        let mut request = test_message(
+            ts_ms,
+            "10.0.0.1:40000",
+            "10.0.0.2:30501",
+            SomeipMessageType::Request,
+            0x1234,
+            1,
+        );
+        request.session_id = session_id;
+        let mut response = test_message(
+            ts_ms + latency_ms,
+            "10.0.0.2:30501",
+            "10.0.0.1:40000",
+            SomeipMessageType::Response,
+            0x1234,
+            1,
+        );
+        response.session_id = session_id;
+        analyzer.handle_message(&request);
+        analyzer.handle_message(&response);
+    };
+    for session_id in 1..=10 {
+        exchange(&mut analyzer, session_id as u64 * 100, 2, session_id);
+    }
+    analyzer.start_candidate();
+    for session_id in 1..=10 {
+        exchange(&mut analyzer, session_id as u64 * 100, 10, session_id);
+    }
+    assert_eq!(
+        compare(&analyzer.baseline, &analyzer.candidate),
+        vec![Regression::Latency {
+            method: (0x1234, 1),
+            baseline: (2.0, 2.0),
+            candidate: (10.0, 10.0)
+        }]
+    );
+
+    // 超时未应答的Request被丢弃，之后到达的应答不计入时延
+    let mut profile = CaptureProfile::default();
+    let request = |ts_ms, session_id| {
+        let mut request = test_message(
+            ts_ms,
+            "10.0.0.1:40000",
+            "10.0.0.2:30501",
+            SomeipMessageType::Request,
+            0x1234,
+            1,
+        );
+        request.session_id = session_id;
+        request
+    };
+    profile.record(&matrix, &request(0, 1));
+    profile.record(&matrix, &request(500, 2));
+    assert_eq!(profile.pending.len(), 2);
+    profile.record(&matrix, &request(1200, 3));
+    assert_eq!(profile.pending.len(), 2);
+    let mut response = request(1600, 1);
+    response.message_type = SomeipMessageType::Response;
+    profile.record(&matrix, &response);
+    assert!(profile.latencies.is_empty());
+}
+
+#[test]
+fn compare_subscriptions_and_ranges() {
+    use crate::analyzers::{
+        test_data_type, test_event, test_eventgroup, test_matrix, test_message, test_payload,
+        test_sd_entry, test_sd_message,
+    };
+    use crate::matrix::types::{MatrixType, NumberType};
+    use crate::types::SomeipSdEntry;
+
+    let mut matrix = test_matrix(vec![test_event(0x8001, "Temperature", "uint8")]);
+    test_eventgroup(&mut matrix, 1, "Values", vec![0x8001]);
+    test_data_type(
+        &mut matrix,
+        "uint8",
+        MatrixType::Number {
+            size: NumberType::Uint8,
+        },
+    );
+    let mut analyzer = CompareAnalyzer::new(&matrix);
+    let subscribe = |ts_ms, ttl| {
+        test_sd_message(
+            ts_ms,
+            "10.0.0.1:30490",
+            "10.0.0.2:30490",
+            1,
+            false,
+            SomeipSdEntry {
+                ttl,
+                ..test_sd_entry(SomeipSdEntryType::Subscribe)
+            },
+        )
+    };
+    let notification = |ts_ms, value| {
+        let msg = test_message(
+            ts_ms,
+            "10.0.0.2:30501",
+            "10.0.0.1:30501",
+            SomeipMessageType::Notification,
+            0x1234,
+            0x8001,
+        );
+        test_payload(msg, &[value])
+    };
+
+    // 基准抓包中订阅之后才收到事件
+    analyzer.handle_message(&subscribe(0, 3));
+    analyzer.handle_message(&notification(10, 20));
+    analyzer.handle_message(&notification(20, 25));
+    assert!(analyzer.baseline.unsubscribed.is_empty());
+    // 待测抓包中StopSubscribe之后仍然收到事件，取值也超出了基准的范围
+    analyzer.start_candidate();
+    analyzer.handle_message(&subscribe(0, 3));
+    analyzer.handle_message(&notification(10, 20));
+    analyzer.handle_message(&subscribe(15, 0));
+    analyzer.handle_message(&notification(20, 40));
+
+    assert_eq!(
+        compare(&analyzer.baseline, &analyzer.candidate),
+        vec![
+            Regression::NewUnsubscribedEvent {
+                method: (0x1234, 0x8001),
+                client: "10.0.0.1".parse().unwrap()
+            },
+            Regression::ValueRange {
+                signal: "Climate.Temperature".to_owned(),
+                baseline: (20.0, 25.0),
+                candidate: (20.0, 40.0)
+            },
+        ]
+    );
+}
@@ -0,0 +1,616 @@
+/// 矩阵覆盖率统计：集成测试时确认矩阵中的服务、方法、事件、字段与Eventgroup是否在抓包中出现过
+/// 对每个元素记录出现次数、报文类型、通信方向与客户端，同时列出矩阵中找不到的流量
+/// 普通报文：Request/RequestNoReturn的发送方是客户端，Response/Error/Notification的接收方是客户端
+/// SD报文：Offer与Find计入服务，Subscribe/SubscribeAck等计入Eventgroup
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::Write;
+
+use serde::Serialize;
+
+use crate::errors::MyError;
+use crate::matrix::types::Matrix;
+use crate::types::{
+    SomeipEndpoint, SomeipEventgroupId, SomeipMessage, SomeipMessageType, SomeipMethodId,
+    SomeipSdEntryType, SomeipServiceId,
+};
+
+use super::Analyzer;
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct Usage {
+    pub count: usize,
+    /// 报文类型或SD Entry类型 -> 次数
+    pub message_types: BTreeMap<String, usize>,
+    /// "发送方 -> 接收方"
+    pub directions: BTreeSet<String>,
+    pub clients: BTreeSet<String>,
+}
+
+impl Usage {
+    fn record(&mut self, message_type: String, direction: String, client: Option<String>) {
+        self.count += 1;
+        *self.message_types.entry(message_type).or_default() += 1;
+        self.directions.insert(direction);
+        if let Some(client) = client {
+            self.clients.insert(client);
+        }
+    }
+}
+
+/// 在矩阵中找不到的流量
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum UnmatchedKey {
+    Service {
+        service_id: SomeipServiceId,
+    },
+    Method {
+        service_id: SomeipServiceId,
+        method_id: SomeipMethodId,
+    },
+    Eventgroup {
+        service_id: SomeipServiceId,
+        eventgroup_id: SomeipEventgroupId,
+    },
+}
+
+#[derive(Debug, Serialize)]
+pub struct ElementCoverage {
+    pub id: u16,
+    pub name: String,
+    pub kind: &'static str,
+    pub seen: bool,
+    #[serde(flatten)]
+    pub usage: Usage,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ServiceCoverage {
+    pub service_id: SomeipServiceId,
+    pub service_name: String,
+    pub seen: bool,
+    #[serde(flatten)]
+    pub usage: Usage,
+    pub methods: Vec<ElementCoverage>,
+    pub eventgroups: Vec<ElementCoverage>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UnmatchedCoverage {
+    #[serde(flatten)]
+    pub key: UnmatchedKey,
+    #[serde(flatten)]
+    pub usage: Usage,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CoverageReport {
+    pub services: Vec<ServiceCoverage>,
+    pub unmatched: Vec<UnmatchedCoverage>,
+}
+
+pub struct CoverageAnalyzer<'a> {
+    matrix: &'a Matrix,
+    services: HashMap<SomeipServiceId, Usage>,
+    methods: HashMap<(SomeipServiceId, SomeipMethodId), Usage>,
+    eventgroups: HashMap<(SomeipServiceId, SomeipEventgroupId), Usage>,
+    unmatched: BTreeMap<UnmatchedKey, Usage>,
+}
+
+impl<'a> CoverageAnalyzer<'a> {
+    pub fn new(matrix: &'a Matrix) -> Self {
+        CoverageAnalyzer {
+            matrix,
+            services: HashMap::new(),
+            methods: HashMap::new(),
+            eventgroups: HashMap::new(),
+            unmatched: BTreeMap::new(),
+        }
+    }
+
+    fn host(&self, endpoint: &SomeipEndpoint) -> String {
+        self.matrix.role_name_or_ip(&endpoint.ip_addr)
+    }
+
+    fn direction(&self, msg: &SomeipMessage) -> String {
+        format!(
+            "{} -> {}",
+            self.host(&msg.source),
+            self.host(&msg.destination)
+        )
+    }
+
+    fn handle_sd_message(&mut self, msg: &SomeipMessage) {
+        let sd = match &msg.sd {
+            Some(sd) => sd,
+            None => return,
+        };
+        let direction = self.direction(msg);
+        for entry in &sd.entries {
+            let client = match entry.entry_type {
+                SomeipSdEntryType::FindService
+                | SomeipSdEntryType::Subscribe
+                | SomeipSdEntryType::StopSubscribe => Some(self.host(&msg.source)),
+                SomeipSdEntryType::SubscribeAck | SomeipSdEntryType::SubscribeNack => {
+                    Some(self.host(&msg.destination))
+                }
+                _ => None,
+            };
+            let service = match self.matrix.services.get(&entry.service_id) {
+                Some(service) => service,
+                None => {
+                    self.unmatched
+                        .entry(UnmatchedKey::Service {
+                            service_id: entry.service_id,
+                        })
+                        .or_default()
+                        .record(entry.entry_type.to_string(), direction.clone(), client);
+                    continue;
+                }
+            };
+            let usage = match entry.entry_type {
+                SomeipSdEntryType::Subscribe
+                | SomeipSdEntryType::StopSubscribe
+                | SomeipSdEntryType::SubscribeAck
+                | SomeipSdEntryType::SubscribeNack => {
+                    if service.eventgroups.contains_key(&entry.eventgroup_id) {
+                        self.eventgroups
+                            .entry((entry.service_id, entry.eventgroup_id))
+                            .or_default()
+                    } else {
+                        self.unmatched
+                            .entry(UnmatchedKey::Eventgroup {
+                                service_id: entry.service_id,
+                                eventgroup_id: entry.eventgroup_id,
+                            })
+                            .or_default()
+                    }
+                }
+                _ => self.services.entry(entry.service_id).or_default(),
+            };
+            usage.record(entry.entry_type.to_string(), direction.clone(), client);
+        }
+    }
+
+    pub fn report(&self) -> CoverageReport {
+        let mut services: Vec<ServiceCoverage> = self
+            .matrix
+            .services
+            .values()
+            .map(|service| {
+                let mut methods: Vec<ElementCoverage> = service
+                    .methods
+                    .values()
+                    .map(|method| {
+                        let usage = self
+                            .methods
+                            .get(&(service.service_id, method.method_id))
+                            .cloned()
+                            .unwrap_or_default();
+                        ElementCoverage {
+                            id: method.method_id,
+                            name: method.method_name.clone(),
+                            kind: method.method_type.kind_name(),
+                            seen: usage.count > 0,
+                            usage,
+                        }
+                    })
+                    .collect();
+                methods.sort_by_key(|method| method.id);
+
+                let mut eventgroups: Vec<ElementCoverage> = service
+                    .eventgroups
+                    .values()
+                    .map(|eventgroup| {
+                        let usage = self
+                            .eventgroups
+                            .get(&(service.service_id, eventgroup.eventgroup_id))
+                            .cloned()
+                            .unwrap_or_default();
+                        ElementCoverage {
+                            id: eventgroup.eventgroup_id,
+                            name: eventgroup.eventgroup_name.clone(),
+                            kind: "eventgroup",
+                            seen: usage.count > 0,
+                            usage,
+                        }
+                    })
+                    .collect();
+                eventgroups.sort_by_key(|eventgroup| eventgroup.id);
+
+                let usage = self
+                    .services
+                    .get(&service.service_id)
+                    .cloned()
+                    .unwrap_or_default();
+                ServiceCoverage {
+                    service_id: service.service_id,
+                    service_name: service.service_name.clone(),
+                    seen: usage.count > 0
+                        || methods.iter().any(|method| method.seen)
+                        || eventgroups.iter().any(|eventgroup| eventgroup.seen),
+                    usage,
+                    methods,
+                    eventgroups,
+                }
+            })
+            .collect();
+        services.sort_by_key(|service| service.service_id);
+
+        CoverageReport {
+            services,
+            unmatched: self
+                .unmatched
+                .iter()
+                .map(|(key, usage)| UnmatchedCoverage {
+                    key: *key,
+                    usage: usage.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl<'a> Analyzer for CoverageAnalyzer<'a> {
+    fn name(&self) -> &'static str {
+        "coverage"
+    }
+
+    fn handle_message(&mut self, msg: &SomeipMessage) {
+        if msg.sd.is_some() {
+            self.handle_sd_message(msg);
+            return;
+        }
+
+        let client = match msg.message_type {
+            SomeipMessageType::Request | SomeipMessageType::RequestWithoutResponse => {
+                Some(self.host(&msg.source))
+            }
+            SomeipMessageType::Response
+            | SomeipMessageType::ResponseWithError
+            | SomeipMessageType::Notification => Some(self.host(&msg.destination)),
+            SomeipMessageType::Unknown(_) => None,
+        };
+        let direction = self.direction(msg);
+        let usage = match self.matrix.services.get(&msg.service_id) {
+            Some(service) if service.methods.contains_key(&msg.method_id) => self
+                .methods
+                .entry((msg.service_id, msg.method_id))
+                .or_default(),
+            Some(_) => self
+                .unmatched
+                .entry(UnmatchedKey::Method {
+                    service_id: msg.service_id,
+                    method_id: msg.method_id,
+                })
+                .or_default(),
+            None => self
+                .unmatched
+                .entry(UnmatchedKey::Service {
+                    service_id: msg.service_id,
+                })
+                .or_default(),
+        };
+        usage.record(msg.message_type.to_string(), direction, client);
+    }
+
+    fn write_report(&self, w: &mut dyn Write) -> Result<(), MyError> {
+        let report = self.report();
+
+        let services_seen = report.services.iter().filter(|s| s.seen).count();
+        let (methods, methods_seen) = report.services.iter().fold((0, 0), |acc, s| {
+            (
+                acc.0 + s.methods.len(),
+                acc.1 + s.methods.iter().filter(|m| m.seen).count(),
+            )
+        });
+        let (eventgroups, eventgroups_seen) = report.services.iter().fold((0, 0), |acc, s| {
+            (
+                acc.0 + s.eventgroups.len(),
+                acc.1 + s.eventgroups.iter().filter(|e| e.seen).count(),
+            )
+        });
+        writeln!(
+            w,
+            "Matrix coverage: services {}/{}, methods {}/{}, eventgroups {}/{}",
+            services_seen,
+            report.services.len(),
+            methods_seen,
+            methods,
+            eventgroups_seen,
+            eventgroups
+        )?;
+
+        let write_element = |w: &mut dyn Write, element: &ElementCoverage| {
+            writeln!(
+                w,
+                "  [{}] {:>8}  {:<18} 0x{:04x} {:<32} {}",
+                if element.seen { "x" } else { " " },
+                element.usage.count,
+                element.kind,
+                element.id,
+                element.name,
+                element
+                    .usage
+                    .clients
+                    .iter()
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(",")
+            )
+        };
+        for service in &report.services {
+            writeln!(
+                w,
+                "[{}] 0x{:04x} {}  sd entries {} {}",
+                if service.seen { "x" } else { " " },
+                service.service_id,
+                service.service_name,
+                service.usage.count,
+                service
+                    .usage
+                    .message_types
+                    .iter()
+                    .map(|(message_type, count)| format!("{}:{}", message_type, count))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            )?;
+            for method in &service.methods {
+                write_element(w, method)?;
+            }
+            for eventgroup in &service.eventgroups {
+                write_element(w, eventgroup)?;
+            }
+        }
+
+        writeln!(w, "Unmatched traffic: {}", report.unmatched.len())?;
+        for unmatched in &report.unmatched {
+            let element = match unmatched.key {
+                UnmatchedKey::Service { service_id } => format!("service 0x{:04x}", service_id),
+                UnmatchedKey::Method {
+                    service_id,
+                    method_id,
+                } => format!("method 0x{:04x}.0x{:04x}", service_id, method_id),
+                UnmatchedKey::Eventgroup {
+                    service_id,
+                    eventgroup_id,
+                } => format!("eventgroup 0x{:04x}.0x{:04x}", service_id, eventgroup_id),
+            };
+            writeln!(
+                w,
+                "  {:>8}  {:<30} {}",
+                unmatched.usage.count,
+                element,
+                unmatched
+                    .usage
+                    .directions
+                    .iter()
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )?;
+        }
+        Ok(())
+    }
+
+    fn json_report(&self) -> Option<serde_json::Value> {
+        serde_json::to_value(self.report()).ok()
+    }
+}
+
+#[test]
+fn coverage_report() {
+    use crate::analyzers::{
+        test_event, test_eventgroup, test_ff_method, test_matrix, test_message, test_role,
+        test_sd_entry, test_sd_message,
+    };
+
+    let mut matrix = test_matrix(vec![
+        test_ff_method(0x0001, "SetTemp", &[]),
+        test_event(0x8001, "TempChanged", ""),
+    ]);
+    test_role(&mut matrix, "HU", "10.0.0.1");
+    test_eventgroup(&mut matrix, 1, "eg", vec![0x8001]);
+    let mut analyzer = CoverageAnalyzer::new(&matrix);
+
+    let client = "10.0.0.1:40000";
+    let server = "10.0.0.2:30501";
+    for _ in 0..2 {
+        analyzer.handle_message(&test_message(
+            0,
+            client,
+            server,
+            SomeipMessageType::RequestWithoutResponse,
+            0x1234,
+            0x0001,
+        ));
+    }
+    analyzer.handle_message(&test_message(
+        0,
+        server,
+        client,
+        SomeipMessageType::Notification,
+        0x1234,
+        0x8002,
+    ));
+    analyzer.handle_message(&test_sd_message(
+        0,
+        "10.0.0.1:30490",
+        "10.0.0.2:30490",
+        1,
+        false,
+        test_sd_entry(SomeipSdEntryType::Subscribe),
+    ));
+
+    let report = analyzer.report();
+    let service = &report.services[0];
+    assert!(service.seen);
+    assert_eq!(service.methods[0].usage.count, 2);
+    assert_eq!(
+        service.methods[0].usage.directions,
+        BTreeSet::from(["HU -> 10.0.0.2".to_owned()])
+    );
+    assert!(!service.methods[1].seen);
+    assert_eq!(
+        service.eventgroups[0].usage.clients,
+        BTreeSet::from(["HU".to_owned()])
+    );
+    assert_eq!(report.unmatched.len(), 1);
+    assert_eq!(
+        report.unmatched[0].key,
+        UnmatchedKey::Method {
+            service_id: 0x1234,
+            method_id: 0x8002
+        }
+    );
+
+    let json = analyzer.json_report().unwrap();
+    assert_eq!(json["services"][0]["methods"][0]["count"], 2);
+    assert_eq!(json["unmatched"][0]["kind"], "method");
+}
+
+#[test]
+fn sd_entry_coverage() {
+    use crate::analyzers::{
+        test_event, test_eventgroup, test_ff_method, test_matrix, test_report, test_role,
+        test_sd_entry, test_sd_message,
+    };
+    use crate::types::SomeipSdEntry;
+
+    let mut matrix = test_matrix(vec![
+        test_ff_method(0x0001, "SetTemp", &[]),
+        test_event(0x8001, "TempChanged", ""),
+    ]);
+    test_role(&mut matrix, "HU", "10.0.0.1");
+    test_eventgroup(&mut matrix, 1, "eg", vec![0x8001]);
+    let mut analyzer = CoverageAnalyzer::new(&matrix);
+    let server = "10.0.0.2:30490";
+    let client = "10.0.0.1:30490";
+
+    // Offer只计入服务，服务被看到但方法与Eventgroup没有
+    analyzer.handle_message(&test_sd_message(
+        0,
+        server,
+        "239.0.0.1:30490",
+        1,
+        false,
+        test_sd_entry(SomeipSdEntryType::OfferService),
+    ));
+    let report = analyzer.report();
+    assert!(report.services[0].seen);
+    assert_eq!(report.services[0].usage.count, 1);
+    assert!(report.services[0].usage.clients.is_empty());
+    assert!(!report.services[0].eventgroups[0].seen);
+
+    // SubscribeAck的客户端是接收方
+    analyzer.handle_message(&test_sd_message(
+        1,
+        server,
+        client,
+        2,
+        false,
+        test_sd_entry(SomeipSdEntryType::SubscribeAck),
+    ));
+    // 矩阵中没有的Eventgroup与服务
+    analyzer.handle_message(&test_sd_message(
+        2,
+        client,
+        server,
+        2,
+        false,
+        SomeipSdEntry {
+            eventgroup_id: 9,
+            ..test_sd_entry(SomeipSdEntryType::Subscribe)
+        },
+    ));
+    analyzer.handle_message(&test_sd_message(
+        3,
+        client,
+        "239.0.0.1:30490",
+        3,
+        false,
+        SomeipSdEntry {
+            service_id: 0x5678,
+            ..test_sd_entry(SomeipSdEntryType::FindService)
+        },
+    ));
+
+    let report = analyzer.report();
+    assert_eq!(
+        report.services[0].eventgroups[0].usage.clients,
+        BTreeSet::from(["HU".to_owned()])
+    );
+    let unmatched: Vec<(UnmatchedKey, &BTreeSet<String>)> = report
+        .unmatched
+        .iter()
+        .map(|unmatched| (unmatched.key, &unmatched.usage.clients))
+        .collect();
+    let hu = BTreeSet::from(["HU".to_owned()]);
+    assert_eq!(
+        unmatched,
+        vec![
+            (UnmatchedKey::Service { service_id: 0x5678 }, &hu),
+            (
+                UnmatchedKey::Eventgroup {
+                    service_id: 0x1234,
+                    eventgroup_id: 9
+                },
+                &hu
+            ),
+        ]
+    );
+    let report = test_report(&analyzer);
+    assert_eq!(
+        report.lines().nth(1),
+        Some("[x] 0x1234 Climate  sd entries 1 OfferService:1")
+    );
+}
+
+#[test]
+fn coverage_text_report() {
+    use crate::analyzers::{
+        test_event, test_eventgroup, test_ff_method, test_matrix, test_message, test_report,
+        test_role,
+    };
+
+    let mut matrix = test_matrix(vec![
+        test_ff_method(0x0001, "SetTemp", &[]),
+        test_event(0x8001, "TempChanged", ""),
+    ]);
+    test_role(&mut matrix, "HU", "10.0.0.1");
+    test_eventgroup(&mut matrix, 1, "eg", vec![0x8001]);
+    let mut analyzer = CoverageAnalyzer::new(&matrix);
+    analyzer.handle_message(&test_message(
+        0,
+        "10.0.0.1:40000",
+        "10.0.0.2:30501",
+        SomeipMessageType::RequestWithoutResponse,
+        0x1234,
+        0x0001,
+    ));
+    analyzer.handle_message(&test_message(
+        10,
+        "10.0.0.3:30501",
+        "10.0.0.1:40000",
+        SomeipMessageType::Notification,
+        0x5678,
+        0x8001,
+    ));
+
+    let report = test_report(&analyzer);
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(
+        lines[0],
+        "Matrix coverage: services 1/1, methods 1/2, eventgroups 0/1"
+    );
+    assert!(lines[1].starts_with("[x] 0x1234 Climate  sd entries 0"));
+    assert!(lines[2].starts_with("  [x]        1  fire&forget method 0x0001 SetTemp"));
+    assert!(lines[2].ends_with(" HU"));
+    assert!(lines[3].starts_with("  [ ]        0  event"));
+    assert!(lines[4].starts_with("  [ ]        0  eventgroup"));
+    assert_eq!(lines[5], "Unmatched traffic: 1");
+    assert_eq!(
+        lines[6].split_whitespace().collect::<Vec<_>>(),
+        ["1", "service", "0x5678", "10.0.0.3", "->", "HU"]
+    );
+}
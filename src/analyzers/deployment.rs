@@ -0,0 +1,676 @@
+/// 部署一致性检查：把报文的源、目的端点对应到矩阵中的角色，与Deployment表比较
+/// 普通报文：Request/RequestNoReturn的目的端是服务端，Response/Error/Notification的源端是服务端
+/// SD报文：Offer与SubscribeAck的发送方是服务端，Find与Subscribe的发送方是客户端
+/// 检查项：未知主机、MAC不一致、服务端不是矩阵中规定的角色、未声明的客户端、服务端端口不一致
+/// 服务端端口同时检查普通报文的端口与Offer中端点Option的端口，这样只提供服务而没有通信时也能发现端口错误
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Write;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use crate::errors::MyError;
+use crate::matrix::types::{Matrix, MatrixRole, RoleName};
+use crate::types::{
+    mac_to_string, MacAddr, Port, SomeipEndpoint, SomeipMessage, SomeipMessageType,
+    SomeipSdEntryType, SomeipServiceId,
+};
+
+use super::{Analyzer, FindingStats};
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DeploymentFinding {
+    UnknownHost {
+        ip_addr: IpAddr,
+        mac_addr: MacAddr,
+    },
+    WrongMac {
+        role: RoleName,
+        expected: MacAddr,
+        actual: MacAddr,
+    },
+    /// 服务由矩阵中没有规定的角色提供
+    WrongServer {
+        service_id: SomeipServiceId,
+        expected: Vec<RoleName>,
+    },
+    UnexpectedClient {
+        service_id: SomeipServiceId,
+    },
+    WrongPort {
+        service_id: SomeipServiceId,
+        expected: Vec<Port>,
+        actual: Port,
+    },
+}
+
+impl fmt::Display for DeploymentFinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeploymentFinding::UnknownHost { ip_addr, mac_addr } => {
+                write!(f, "unknown host {} ({})", ip_addr, mac_to_string(mac_addr))
+            }
+            DeploymentFinding::WrongMac {
+                role,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "wrong mac for {}: expected {}, got {}",
+                role,
+                mac_to_string(expected),
+                mac_to_string(actual)
+            ),
+            DeploymentFinding::WrongServer {
+                service_id,
+                expected,
+            } => write!(
+                f,
+                "service 0x{:04x} served by wrong ecu, expected {}",
+                service_id,
+                expected.join("/")
+            ),
+            DeploymentFinding::UnexpectedClient { service_id } => {
+                write!(f, "unexpected client of service 0x{:04x}", service_id)
+            }
+            DeploymentFinding::WrongPort {
+                service_id,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "service 0x{:04x} on wrong port {}, expected {}",
+                service_id,
+                actual,
+                expected
+                    .iter()
+                    .map(|p| p.to_string())
+                    .collect::<Vec<_>>()
+                    .join("/")
+            ),
+        }
+    }
+}
+
+/// (服务端, 客户端)，未知主机用IP表示，组播或不确定的客户端用"*"表示
+pub type RolePair = (String, String);
+
+pub struct DeploymentAnalyzer<'a> {
+    matrix: &'a Matrix,
+    first_timestamp: Option<Duration>,
+    findings: BTreeMap<RolePair, BTreeMap<DeploymentFinding, FindingStats>>,
+}
+
+impl<'a> DeploymentAnalyzer<'a> {
+    pub fn new(matrix: &'a Matrix) -> Self {
+        DeploymentAnalyzer {
+            matrix,
+            first_timestamp: None,
+            findings: BTreeMap::new(),
+        }
+    }
+
+    fn record(&mut self, timestamp: Duration, pair: &RolePair, finding: DeploymentFinding) {
+        self.findings
+            .entry(pair.clone())
+            .or_default()
+            .entry(finding)
+            .and_modify(|stats| stats.update(timestamp))
+            .or_insert(FindingStats::new(timestamp));
+    }
+
+    /// 检查端点对应的主机与MAC
+    fn check_host(&mut self, timestamp: Duration, pair: &RolePair, endpoint: &SomeipEndpoint) {
+        let roles = self.matrix.find_roles_by_ip(&endpoint.ip_addr);
+        if roles.is_empty() {
+            self.record(
+                timestamp,
+                pair,
+                DeploymentFinding::UnknownHost {
+                    ip_addr: endpoint.ip_addr,
+                    mac_addr: endpoint.mac_addr,
+                },
+            );
+            return;
+        }
+        // 抓包中没有MAC（如SLL的目的端），或矩阵中没有填写MAC时不检查
+        let known_macs: Vec<&MatrixRole> = roles
+            .iter()
+            .copied()
+            .filter(|role| role.mac_addr != MacAddr::default())
+            .collect();
+        if endpoint.mac_addr != MacAddr::default()
+            && !known_macs.is_empty()
+            && !known_macs
+                .iter()
+                .any(|role| role.mac_addr == endpoint.mac_addr)
+        {
+            self.record(
+                timestamp,
+                pair,
+                DeploymentFinding::WrongMac {
+                    role: known_macs[0].name.clone(),
+                    expected: known_macs[0].mac_addr,
+                    actual: endpoint.mac_addr,
+                },
+            );
+        }
+    }
+
+    /// server、client为None表示该方向上没有确定的端点（例如组播的Offer）
+    fn check(
+        &mut self,
+        timestamp: Duration,
+        service_id: SomeipServiceId,
+        server: Option<&SomeipEndpoint>,
+        client: Option<&SomeipEndpoint>,
+        check_port: bool,
+    ) {
+        let matrix = self.matrix;
+        let client = client.filter(|endpoint| !endpoint.ip_addr.is_multicast());
+        let service = matrix.services.get(&service_id);
+        let pairs = match service {
+            Some(service) => service
+                .server_client
+                .borrow()
+                .iter()
+                .map(|p| (p.server.clone(), p.client.clone(), p.server_port))
+                .collect(),
+            None => vec![],
+        };
+
+        let mut expected_servers: Vec<RoleName> =
+            pairs.iter().map(|(server, _, _)| server.clone()).collect();
+        expected_servers.sort();
+        expected_servers.dedup();
+
+        // 同一IP上有多个角色时，优先选择矩阵中规定的服务端、客户端角色
+        let server_roles = server
+            .map(|endpoint| matrix.find_roles_by_ip(&endpoint.ip_addr))
+            .unwrap_or_default();
+        let server_role = server_roles
+            .iter()
+            .find(|role| expected_servers.contains(&role.name))
+            .or(server_roles.first())
+            .copied();
+        let client_roles = client
+            .map(|endpoint| matrix.find_roles_by_ip(&endpoint.ip_addr))
+            .unwrap_or_default();
+        let client_role = client_roles
+            .iter()
+            .find(|role| {
+                pairs.iter().any(|(server, client, _)| {
+                    client == &role.name && server_role.map(|s| &s.name == server).unwrap_or(true)
+                })
+            })
+            .or(client_roles.first())
+            .copied();
+
+        let label =
+            |endpoint: Option<&SomeipEndpoint>, role: Option<&MatrixRole>| match (endpoint, role) {
+                (_, Some(role)) => role.name.clone(),
+                (Some(endpoint), None) => endpoint.ip_addr.to_string(),
+                (None, None) => "*".to_owned(),
+            };
+        let pair: RolePair = (label(server, server_role), label(client, client_role));
+
+        if let Some(endpoint) = server {
+            self.check_host(timestamp, &pair, endpoint);
+        }
+        if let Some(endpoint) = client {
+            self.check_host(timestamp, &pair, endpoint);
+        }
+        // 矩阵中没有的服务由覆盖率检查负责，这里只关心部署
+        if service.is_none() {
+            return;
+        }
+
+        if let (Some(endpoint), Some(role)) = (server, server_role) {
+            if !expected_servers.contains(&role.name) {
+                self.record(
+                    timestamp,
+                    &pair,
+                    DeploymentFinding::WrongServer {
+                        service_id,
+                        expected: expected_servers.clone(),
+                    },
+                );
+                return;
+            }
+            if check_port {
+                let mut expected: Vec<Port> = pairs
+                    .iter()
+                    .filter(|(server, _, _)| server == &role.name)
+                    .map(|(_, _, port)| *port)
+                    .collect();
+                expected.sort();
+                expected.dedup();
+                if !expected.contains(&endpoint.port) {
+                    self.record(
+                        timestamp,
+                        &pair,
+                        DeploymentFinding::WrongPort {
+                            service_id,
+                            expected,
+                            actual: endpoint.port,
+                        },
+                    );
+                }
+            }
+        }
+        if let Some(client_role) = client_role {
+            let allowed = pairs.iter().any(|(server, client, _)| {
+                client == &client_role.name
+                    && server_role.map(|role| &role.name == server).unwrap_or(true)
+            });
+            if !allowed {
+                self.record(
+                    timestamp,
+                    &pair,
+                    DeploymentFinding::UnexpectedClient { service_id },
+                );
+            }
+        }
+    }
+}
+
+impl<'a> Analyzer for DeploymentAnalyzer<'a> {
+    fn name(&self) -> &'static str {
+        "deployment"
+    }
+
+    fn handle_message(&mut self, msg: &SomeipMessage) {
+        self.first_timestamp.get_or_insert(msg.timestamp);
+
+        if let Some(sd) = &msg.sd {
+            for entry in &sd.entries {
+                match entry.entry_type {
+                    SomeipSdEntryType::OfferService | SomeipSdEntryType::StopOfferService => {
+                        // Offer中的单播端点就是服务端提供服务的端口
+                        let endpoints: Vec<SomeipEndpoint> = entry
+                            .endpoints
+                            .iter()
+                            .filter(|option| !option.multicast)
+                            .map(|option| SomeipEndpoint {
+                                mac_addr: match option.ip_addr == msg.source.ip_addr {
+                                    true => msg.source.mac_addr,
+                                    false => MacAddr::default(),
+                                },
+                                ip_addr: option.ip_addr,
+                                port: option.port,
+                            })
+                            .collect();
+                        if endpoints.is_empty() {
+                            self.check(
+                                msg.timestamp,
+                                entry.service_id,
+                                Some(&msg.source),
+                                None,
+                                false,
+                            );
+                        }
+                        for endpoint in &endpoints {
+                            self.check(msg.timestamp, entry.service_id, Some(endpoint), None, true);
+                        }
+                    }
+                    SomeipSdEntryType::SubscribeAck | SomeipSdEntryType::SubscribeNack => self
+                        .check(
+                            msg.timestamp,
+                            entry.service_id,
+                            Some(&msg.source),
+                            Some(&msg.destination),
+                            false,
+                        ),
+                    SomeipSdEntryType::Subscribe | SomeipSdEntryType::StopSubscribe => self.check(
+                        msg.timestamp,
+                        entry.service_id,
+                        Some(&msg.destination),
+                        Some(&msg.source),
+                        false,
+                    ),
+                    SomeipSdEntryType::FindService => self.check(
+                        msg.timestamp,
+                        entry.service_id,
+                        None,
+                        Some(&msg.source),
+                        false,
+                    ),
+                    SomeipSdEntryType::Unknown(_) => {}
+                }
+            }
+            return;
+        }
+
+        match msg.message_type {
+            SomeipMessageType::Request | SomeipMessageType::RequestWithoutResponse => self.check(
+                msg.timestamp,
+                msg.service_id,
+                Some(&msg.destination),
+                Some(&msg.source),
+                true,
+            ),
+            SomeipMessageType::Response
+            | SomeipMessageType::ResponseWithError
+            | SomeipMessageType::Notification => self.check(
+                msg.timestamp,
+                msg.service_id,
+                Some(&msg.source),
+                Some(&msg.destination),
+                true,
+            ),
+            SomeipMessageType::Unknown(_) => {}
+        }
+    }
+
+    fn write_report(&self, w: &mut dyn Write) -> Result<(), MyError> {
+        let first_timestamp = self.first_timestamp.unwrap_or_default();
+
+        writeln!(
+            w,
+            "Deployment conformance: {} role pairs with findings",
+            self.findings.len()
+        )?;
+        for ((server, client), findings) in &self.findings {
+            writeln!(w, "{} -> {}", client, server)?;
+            for (finding, stats) in findings {
+                writeln!(
+                    w,
+                    "  {:>8}x  {:>12.6}s .. {:>12.6}s  {}",
+                    stats.count,
+                    stats
+                        .first_timestamp
+                        .saturating_sub(first_timestamp)
+                        .as_secs_f64(),
+                    stats
+                        .last_timestamp
+                        .saturating_sub(first_timestamp)
+                        .as_secs_f64(),
+                    finding
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn deployment_findings() {
+    use crate::analyzers::{test_deployment, test_message, test_role};
+
+    let mut matrix = Matrix::default();
+    test_deployment(&mut matrix);
+    test_role(&mut matrix, "ADAS", "10.0.0.3");
+    let mut analyzer = DeploymentAnalyzer::new(&matrix);
+
+    // 符合矩阵
+    let ok = test_message(
+        0,
+        "10.0.0.1:40000",
+        "10.0.0.2:30501",
+        SomeipMessageType::Request,
+        0x1234,
+        1,
+    );
+    analyzer.handle_message(&ok);
+    assert!(analyzer.findings.is_empty());
+
+    // 端口错误
+    let msg = test_message(
+        1,
+        "10.0.0.1:40000",
+        "10.0.0.2:30600",
+        SomeipMessageType::Request,
+        0x1234,
+        1,
+    );
+    analyzer.handle_message(&msg);
+    // 非法客户端
+    let msg = test_message(
+        2,
+        "10.0.0.3:40000",
+        "10.0.0.2:30501",
+        SomeipMessageType::Request,
+        0x1234,
+        1,
+    );
+    analyzer.handle_message(&msg);
+    // 服务端错误
+    let msg = test_message(
+        3,
+        "10.0.0.3:30501",
+        "10.0.0.1:40000",
+        SomeipMessageType::Notification,
+        0x1234,
+        0x8001,
+    );
+    analyzer.handle_message(&msg);
+    // 未知主机与MAC错误
+    let mut msg = test_message(
+        4,
+        "10.0.0.9:40000",
+        "10.0.0.2:30501",
+        SomeipMessageType::Request,
+        0x1234,
+        1,
+    );
+    msg.destination.mac_addr = [9, 9, 9, 9, 9, 9];
+    analyzer.handle_message(&msg);
+
+    let findings = |server: &str, client: &str| {
+        analyzer.findings[&(server.to_owned(), client.to_owned())]
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        findings("TBOX", "HU"),
+        vec![DeploymentFinding::WrongPort {
+            service_id: 0x1234,
+            expected: vec![30501],
+            actual: 30600
+        }]
+    );
+    assert_eq!(
+        findings("TBOX", "ADAS"),
+        vec![DeploymentFinding::UnexpectedClient { service_id: 0x1234 }]
+    );
+    assert_eq!(
+        findings("ADAS", "HU"),
+        vec![DeploymentFinding::WrongServer {
+            service_id: 0x1234,
+            expected: vec!["TBOX".to_owned()]
+        }]
+    );
+    assert_eq!(findings("TBOX", "10.0.0.9").len(), 2);
+    assert_eq!(
+        analyzer.findings[&("TBOX".to_owned(), "HU".to_owned())]
+            .values()
+            .next()
+            .unwrap()
+            .first_timestamp,
+        Duration::from_millis(1)
+    );
+}
+
+#[test]
+fn offer_endpoint_port() {
+    use crate::analyzers::{test_deployment, test_sd_entry, test_sd_message};
+    use crate::types::{SomeipSdEndpointOption, SomeipSdEntry, SomeipTransportPortocol};
+
+    let mut matrix = Matrix::default();
+    test_deployment(&mut matrix);
+    let mut analyzer = DeploymentAnalyzer::new(&matrix);
+    let offer = |port| {
+        let entry = SomeipSdEntry {
+            endpoints: vec![SomeipSdEndpointOption {
+                ip_addr: "10.0.0.2".parse().unwrap(),
+                port,
+                transport_protocol: SomeipTransportPortocol::UDP,
+                multicast: false,
+            }],
+            ..test_sd_entry(SomeipSdEntryType::OfferService)
+        };
+        test_sd_message(
+            0,
+            "10.0.0.2:30490",
+            "224.224.224.245:30490",
+            1,
+            false,
+            entry,
+        )
+    };
+    analyzer.handle_message(&offer(30501));
+    assert!(analyzer.findings.is_empty());
+
+    analyzer.handle_message(&offer(30600));
+    assert_eq!(
+        analyzer.findings[&("TBOX".to_owned(), "*".to_owned())]
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>(),
+        vec![DeploymentFinding::WrongPort {
+            service_id: 0x1234,
+            expected: vec![30501],
+            actual: 30600
+        }]
+    );
+}
+
+#[test]
+fn sd_entry_roles() {
+    use crate::analyzers::{
+        test_deployment, test_message, test_role, test_sd_entry, test_sd_message,
+    };
+    use crate::types::{SomeipSdEndpointOption, SomeipSdEntry, SomeipTransportPortocol};
+
+    let mut matrix = Matrix::default();
+    test_deployment(&mut matrix);
+    test_role(&mut matrix, "ADAS", "10.0.0.3");
+    let mut analyzer = DeploymentAnalyzer::new(&matrix);
+    let entry = test_sd_entry;
+
+    // 符合矩阵的订阅与应答，以及矩阵中没有的服务
+    analyzer.handle_message(&test_sd_message(
+        0,
+        "10.0.0.1:30490",
+        "10.0.0.2:30490",
+        1,
+        false,
+        entry(SomeipSdEntryType::Subscribe),
+    ));
+    analyzer.handle_message(&test_sd_message(
+        1,
+        "10.0.0.2:30490",
+        "10.0.0.1:30490",
+        1,
+        false,
+        entry(SomeipSdEntryType::SubscribeAck),
+    ));
+    analyzer.handle_message(&test_message(
+        2,
+        "10.0.0.1:40000",
+        "10.0.0.2:30600",
+        SomeipMessageType::Request,
+        0x9999,
+        1,
+    ));
+    assert!(analyzer.findings.is_empty());
+
+    // 未声明的客户端订阅
+    analyzer.handle_message(&test_sd_message(
+        3,
+        "10.0.0.3:30490",
+        "10.0.0.2:30490",
+        1,
+        false,
+        entry(SomeipSdEntryType::Subscribe),
+    ));
+    // 未知主机查找服务
+    analyzer.handle_message(&test_sd_message(
+        4,
+        "10.0.0.9:30490",
+        "224.224.224.245:30490",
+        1,
+        false,
+        entry(SomeipSdEntryType::FindService),
+    ));
+    // 只有组播端点的Offer按照发送方检查服务端，不检查端口
+    let offer = SomeipSdEntry {
+        endpoints: vec![SomeipSdEndpointOption {
+            ip_addr: "239.0.0.1".parse().unwrap(),
+            port: 30600,
+            transport_protocol: SomeipTransportPortocol::UDP,
+            multicast: true,
+        }],
+        ..entry(SomeipSdEntryType::OfferService)
+    };
+    analyzer.handle_message(&test_sd_message(
+        5,
+        "10.0.0.3:30490",
+        "224.224.224.245:30490",
+        1,
+        false,
+        offer,
+    ));
+
+    let findings = |server: &str, client: &str| {
+        analyzer.findings[&(server.to_owned(), client.to_owned())]
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(analyzer.findings.len(), 3);
+    assert_eq!(
+        findings("TBOX", "ADAS"),
+        vec![DeploymentFinding::UnexpectedClient { service_id: 0x1234 }]
+    );
+    assert_eq!(
+        findings("*", "10.0.0.9"),
+        vec![DeploymentFinding::UnknownHost {
+            ip_addr: "10.0.0.9".parse().unwrap(),
+            mac_addr: MacAddr::default()
+        }]
+    );
+    assert_eq!(
+        findings("ADAS", "*"),
+        vec![DeploymentFinding::WrongServer {
+            service_id: 0x1234,
+            expected: vec!["TBOX".to_owned()]
+        }]
+    );
+}
+
+#[test]
+fn deployment_report() {
+    use crate::analyzers::{test_deployment, test_message, test_report};
+
+    let mut matrix = Matrix::default();
+    test_deployment(&mut matrix);
+    let mut analyzer = DeploymentAnalyzer::new(&matrix);
+    for ts_ms in [1000, 1500, 2000] {
+        let mut msg = test_message(
+            ts_ms,
+            "10.0.0.1:40000",
+            "10.0.0.2:30600",
+            SomeipMessageType::Request,
+            0x1234,
+            1,
+        );
+        msg.source.mac_addr = [9, 9, 9, 9, 9, 9];
+        analyzer.handle_message(&msg);
+    }
+
+    let report = test_report(&analyzer);
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(
+        lines[0],
+        "Deployment conformance: 1 role pairs with findings"
+    );
+    assert_eq!(lines[1], "HU -> TBOX");
+    assert!(
+        lines[2].ends_with("wrong mac for HU: expected 00:01:02:03:04:05, got 09:09:09:09:09:09")
+    );
+    assert!(lines[2].trim_start().starts_with("3x"));
+    assert!(lines[3].contains("1.000000s"));
+    assert!(lines[3].ends_with("service 0x1234 on wrong port 30600, expected 30501"));
+}
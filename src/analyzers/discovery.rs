@@ -0,0 +1,608 @@
+/// 未知流量发现：没有矩阵时，根据抓包推断一份矩阵骨架，作为没有文档的ECU的起点
+/// 服务与方法来自MessageID，方法类型来自MessageType：
+/// 出现Notification的是Event，只有RequestNoReturn的是FF方法，其余按RR方法处理（Field的Getter/Setter无法与RR方法区分）
+/// 角色按IP划分，MAC取抓包中看到的；服务端端口优先取SD Offer中的端点Option，没有Offer时取服务端的源端口
+/// 载荷只能推断长度，按uint8数组生成数据类型，长度固定的是Fixed，否则是Dynamic(最小,最大)
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::Write;
+use std::net::IpAddr;
+
+use log::warn;
+
+use crate::errors::MyError;
+use crate::matrix::types::{
+    Matrix, MatrixDataNode, MatrixEventgroup, MatrixMember, MatrixRole, MatrixRoleServerClientPair,
+    MatrixService, MatrixServiceMethod, MatrixServiceMethodType, MatrixType, NumberType, RoleName,
+    StringArrayLength,
+};
+use crate::types::{
+    MacAddr, Port, SomeipEndpoint, SomeipEventgroupId, SomeipMessage, SomeipMessageType,
+    SomeipMethodId, SomeipMinorVersion, SomeipSdEntryType, SomeipServiceId,
+    SomeipTransportPortocol,
+};
+
+use super::Analyzer;
+
+/// 载荷长度范围
+#[derive(Debug, Clone, Copy)]
+struct LengthRange {
+    min: usize,
+    max: usize,
+}
+
+impl LengthRange {
+    fn update(range: &mut Option<LengthRange>, length: usize) {
+        match range {
+            Some(range) => {
+                range.min = range.min.min(length);
+                range.max = range.max.max(length);
+            }
+            None => {
+                *range = Some(LengthRange {
+                    min: length,
+                    max: length,
+                })
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct DiscoveredMethod {
+    message_types: BTreeSet<SomeipMessageType>,
+    transport_protocol: Option<SomeipTransportPortocol>,
+    /// Request/RequestNoReturn的载荷长度
+    request_length: Option<LengthRange>,
+    /// Response/Notification的载荷长度
+    response_length: Option<LengthRange>,
+}
+
+#[derive(Debug, Default)]
+struct DiscoveredService {
+    instance_id: Option<u16>,
+    major_version: Option<u8>,
+    minor_version: Option<SomeipMinorVersion>,
+    methods: BTreeMap<SomeipMethodId, DiscoveredMethod>,
+    eventgroups: BTreeSet<SomeipEventgroupId>,
+    /// SD Offer中的服务端端点
+    offered: BTreeSet<(IpAddr, Port)>,
+    /// 普通报文中观察到的 (服务端IP, 服务端端口, 客户端IP)，组播的客户端为None
+    observed: BTreeSet<(IpAddr, Port, Option<IpAddr>)>,
+}
+
+pub struct DiscoveryAnalyzer {
+    hosts: BTreeMap<IpAddr, MacAddr>,
+    services: BTreeMap<SomeipServiceId, DiscoveredService>,
+}
+
+impl DiscoveryAnalyzer {
+    pub fn new() -> Self {
+        DiscoveryAnalyzer {
+            hosts: BTreeMap::new(),
+            services: BTreeMap::new(),
+        }
+    }
+
+    fn add_host(&mut self, endpoint: &SomeipEndpoint) {
+        if endpoint.ip_addr.is_multicast() || endpoint.ip_addr.is_unspecified() {
+            return;
+        }
+        let mac_addr = self.hosts.entry(endpoint.ip_addr).or_default();
+        if endpoint.mac_addr != MacAddr::default() {
+            *mac_addr = endpoint.mac_addr;
+        }
+    }
+
+    fn handle_sd_message(&mut self, msg: &SomeipMessage) {
+        let sd = match &msg.sd {
+            Some(sd) => sd,
+            None => return,
+        };
+        for entry in &sd.entries {
+            let service = self.services.entry(entry.service_id).or_default();
+            match entry.entry_type {
+                SomeipSdEntryType::OfferService => {
+                    service.instance_id = Some(entry.instance_id);
+                    service.major_version = Some(entry.major_version);
+                    // 矩阵中的次版本号只有16位，放不下的不采用
+                    match SomeipMinorVersion::try_from(entry.minor_version) {
+                        Ok(minor_version) => service.minor_version = Some(minor_version),
+                        Err(_) => warn!(
+                            "service 0x{:04x} offered with minor version {} beyond the matrix range",
+                            entry.service_id, entry.minor_version
+                        ),
+                    }
+                    for endpoint in entry.endpoints.iter().filter(|e| !e.multicast) {
+                        service.offered.insert((endpoint.ip_addr, endpoint.port));
+                    }
+                }
+                SomeipSdEntryType::Subscribe
+                | SomeipSdEntryType::SubscribeAck
+                | SomeipSdEntryType::SubscribeNack
+                | SomeipSdEntryType::StopSubscribe => {
+                    service.eventgroups.insert(entry.eventgroup_id);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn role_name(ip_addr: &IpAddr) -> RoleName {
+        format!("ECU_{}", ip_addr).replace(['.', ':'], "_")
+    }
+
+    /// 生成uint8数组类型，返回类型名，没有载荷时返回None
+    fn length_data_type(
+        data_types: &mut HashMap<String, MatrixDataNode>,
+        name: String,
+        range: Option<LengthRange>,
+    ) -> Option<String> {
+        let range = range.filter(|range| range.max > 0)?;
+        let length = match range.min == range.max {
+            true => StringArrayLength::FIXED(range.max),
+            false => StringArrayLength::DYNAMIC(range.min, range.max),
+        };
+        data_types.insert(
+            name.clone(),
+            MatrixDataNode {
+                name: name.clone(),
+                description: "discovered from capture".to_owned(),
+                data_type: MatrixType::Array {
+                    length,
+                    member: MatrixMember {
+                        member_name: "uint8".to_owned(),
+                        member_description: Default::default(),
+                        member_data_type: "uint8".to_owned(),
+                        member_ref: None,
+                    },
+                },
+                ..Default::default()
+            },
+        );
+        Some(name)
+    }
+
+    /// 根据已经收到的报文生成矩阵骨架
+    pub fn to_matrix(&self) -> Matrix {
+        let mut data_types = HashMap::new();
+        data_types.insert(
+            "uint8".to_owned(),
+            MatrixDataNode {
+                name: "uint8".to_owned(),
+                description: Default::default(),
+                data_type: MatrixType::Number {
+                    size: NumberType::Uint8,
+                },
+                ..Default::default()
+            },
+        );
+
+        let roles = self
+            .hosts
+            .iter()
+            .map(|(ip_addr, mac_addr)| {
+                let name = Self::role_name(ip_addr);
+                (
+                    name.clone(),
+                    MatrixRole {
+                        name,
+                        ip_addr: *ip_addr,
+                        mac_addr: *mac_addr,
+                    },
+                )
+            })
+            .collect();
+
+        let mut services = HashMap::new();
+        for (service_id, discovered) in &self.services {
+            let mut methods = HashMap::new();
+            for (method_id, method) in &discovered.methods {
+                let prefix = format!("Discovered_0x{:04x}_0x{:04x}", service_id, method_id);
+                let has = |t: SomeipMessageType| method.message_types.contains(&t);
+                let (method_name, method_type) = if has(SomeipMessageType::Notification) {
+                    (
+                        format!("Event_0x{:04x}", method_id),
+                        MatrixServiceMethodType::EVENT {
+                            data_out: Self::length_data_type(
+                                &mut data_types,
+                                format!("{}_Out", prefix),
+                                method.response_length,
+                            )
+                            .unwrap_or_default(),
+                            data_out_ref: None,
+                        },
+                    )
+                } else if has(SomeipMessageType::RequestWithoutResponse)
+                    && !has(SomeipMessageType::Request)
+                {
+                    (
+                        format!("Method_0x{:04x}", method_id),
+                        MatrixServiceMethodType::FFMethod {
+                            data_in: Self::length_data_type(
+                                &mut data_types,
+                                format!("{}_In", prefix),
+                                method.request_length,
+                            )
+                            .into_iter()
+                            .collect(),
+                            data_in_ref: vec![],
+                        },
+                    )
+                } else {
+                    (
+                        format!("Method_0x{:04x}", method_id),
+                        MatrixServiceMethodType::RRMethod {
+                            data_in: Self::length_data_type(
+                                &mut data_types,
+                                format!("{}_In", prefix),
+                                method.request_length,
+                            )
+                            .into_iter()
+                            .collect(),
+                            data_in_ref: vec![],
+                            data_out: Self::length_data_type(
+                                &mut data_types,
+                                format!("{}_Out", prefix),
+                                method.response_length,
+                            )
+                            .unwrap_or_default(),
+                            data_out_ref: None,
+                        },
+                    )
+                };
+                methods.insert(
+                    *method_id,
+                    MatrixServiceMethod::new(
+                        *method_id,
+                        method_name,
+                        method_type,
+                        method
+                            .transport_protocol
+                            .unwrap_or(SomeipTransportPortocol::UDP),
+                    ),
+                );
+            }
+
+            let eventgroups = discovered
+                .eventgroups
+                .iter()
+                .map(|eventgroup_id| {
+                    (
+                        *eventgroup_id,
+                        MatrixEventgroup {
+                            eventgroup_id: *eventgroup_id,
+                            eventgroup_name: format!("Eventgroup_0x{:04x}", eventgroup_id),
+                            methods: vec![],
+                        },
+                    )
+                })
+                .collect();
+
+            // Offer中的端口优先，观察到的客户端都挂在对应服务端上
+            let mut server_client = vec![];
+            for (server_ip, server_port, client_ip) in &discovered.observed {
+                let server_port = discovered
+                    .offered
+                    .iter()
+                    .find(|(ip, _)| ip == server_ip)
+                    .map(|(_, port)| *port)
+                    .unwrap_or(*server_port);
+                let pair = MatrixRoleServerClientPair {
+                    server: Self::role_name(server_ip),
+                    server_port,
+                    client: client_ip.map(|ip| Self::role_name(&ip)).unwrap_or_default(),
+                    ..Default::default()
+                };
+                if !server_client.contains(&pair) {
+                    server_client.push(pair);
+                }
+            }
+            for (server_ip, server_port) in &discovered.offered {
+                if !server_client
+                    .iter()
+                    .any(|pair| pair.server == Self::role_name(server_ip))
+                {
+                    server_client.push(MatrixRoleServerClientPair {
+                        server: Self::role_name(server_ip),
+                        server_port: *server_port,
+                        ..Default::default()
+                    });
+                }
+            }
+
+            services.insert(
+                *service_id,
+                MatrixService {
+                    service_id: *service_id,
+                    service_name: format!("Service_0x{:04x}", service_id),
+                    service_description: "discovered from capture".to_owned(),
+                    instance_id: discovered.instance_id.unwrap_or(0xFFFF),
+                    major_verison: discovered.major_version.unwrap_or_default() as u16,
+                    minor_version: discovered.minor_version.unwrap_or_default(),
+                    methods,
+                    eventgroups,
+                    server_client: server_client.into(),
+                },
+            );
+        }
+
+        Matrix {
+            version: "discovered".to_owned(),
+            services,
+            data_types,
+            roles,
+            ..Default::default()
+        }
+    }
+}
+
+impl Analyzer for DiscoveryAnalyzer {
+    fn name(&self) -> &'static str {
+        "discovery"
+    }
+
+    fn handle_message(&mut self, msg: &SomeipMessage) {
+        self.add_host(&msg.source);
+        self.add_host(&msg.destination);
+
+        if msg.sd.is_some() {
+            self.handle_sd_message(msg);
+            return;
+        }
+
+        let (server, client) = match msg.message_type {
+            SomeipMessageType::Request | SomeipMessageType::RequestWithoutResponse => {
+                (&msg.destination, &msg.source)
+            }
+            SomeipMessageType::Response
+            | SomeipMessageType::ResponseWithError
+            | SomeipMessageType::Notification => (&msg.source, &msg.destination),
+            SomeipMessageType::Unknown(_) => return,
+        };
+
+        let service = self.services.entry(msg.service_id).or_default();
+        service.major_version.get_or_insert(msg.interface_version);
+        service.observed.insert((
+            server.ip_addr,
+            server.port,
+            match client.ip_addr.is_multicast() {
+                true => None,
+                false => Some(client.ip_addr),
+            },
+        ));
+
+        let method = service.methods.entry(msg.method_id).or_default();
+        method.message_types.insert(msg.message_type);
+        method.transport_protocol = Some(msg.transport_protocol);
+        match msg.message_type {
+            SomeipMessageType::Request | SomeipMessageType::RequestWithoutResponse => {
+                LengthRange::update(&mut method.request_length, msg.payload.len())
+            }
+            // 带错误的应答载荷不可信，不参与长度推断
+            SomeipMessageType::Response | SomeipMessageType::Notification => {
+                LengthRange::update(&mut method.response_length, msg.payload.len())
+            }
+            _ => {}
+        }
+    }
+
+    fn write_report(&self, w: &mut dyn Write) -> Result<(), MyError> {
+        writeln!(
+            w,
+            "Discovery: {} hosts, {} services",
+            self.hosts.len(),
+            self.services.len()
+        )?;
+        for (service_id, service) in &self.services {
+            writeln!(
+                w,
+                "0x{:04x}  methods {}  eventgroups {}  servers {}",
+                service_id,
+                service.methods.len(),
+                service.eventgroups.len(),
+                service
+                    .observed
+                    .iter()
+                    .map(|(ip, port, _)| format!("{}:{}", ip, port))
+                    .chain(
+                        service
+                            .offered
+                            .iter()
+                            .map(|(ip, port)| format!("{}:{}", ip, port))
+                    )
+                    .collect::<BTreeSet<_>>()
+                    .into_iter()
+                    .collect::<Vec<_>>()
+                    .join(",")
+            )?;
+            for (method_id, method) in &service.methods {
+                writeln!(
+                    w,
+                    "  0x{:04x}  {}",
+                    method_id,
+                    method
+                        .message_types
+                        .iter()
+                        .map(|t| t.to_string())
+                        .collect::<Vec<_>>()
+                        .join(",")
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    fn json_report(&self) -> Option<serde_json::Value> {
+        serde_json::to_value(self.to_matrix()).ok()
+    }
+}
+
+#[test]
+fn discover_skeleton_matrix() {
+    use crate::analyzers::{test_message, test_sd_entry, test_sd_message};
+    use crate::types::{SomeipSdEndpointOption, SomeipSdEntry};
+
+    let mut analyzer = DiscoveryAnalyzer::new();
+    let client = "10.0.0.1:40000";
+    let server = "10.0.0.2:40001";
+
+    let mut offer = test_sd_message(
+        0,
+        "10.0.0.2:30490",
+        "239.0.0.1:30490",
+        1,
+        false,
+        SomeipSdEntry {
+            major_version: 2,
+            minor_version: 5,
+            endpoints: vec![SomeipSdEndpointOption {
+                ip_addr: "10.0.0.2".parse().unwrap(),
+                port: 30501,
+                transport_protocol: SomeipTransportPortocol::UDP,
+                multicast: false,
+            }],
+            ..test_sd_entry(SomeipSdEntryType::OfferService)
+        },
+    );
+    analyzer.handle_message(&offer);
+    offer.sd.as_mut().unwrap().entries[0].minor_version = 0x10000;
+    analyzer.handle_message(&offer);
+
+    let mut request = test_message(1, client, server, SomeipMessageType::Request, 0x1234, 1);
+    request.payload = vec![0; 4];
+    analyzer.handle_message(&request);
+    let mut response = test_message(2, server, client, SomeipMessageType::Response, 0x1234, 1);
+    response.payload = vec![0; 8];
+    analyzer.handle_message(&response);
+    response.payload = vec![0; 12];
+    analyzer.handle_message(&response);
+    analyzer.handle_message(&test_message(
+        3,
+        server,
+        client,
+        SomeipMessageType::Notification,
+        0x1234,
+        0x8001,
+    ));
+
+    let matrix = analyzer.to_matrix();
+    assert_eq!(matrix.roles.len(), 2);
+    let service = &matrix.services[&0x1234];
+    assert_eq!(service.instance_id, 1);
+    assert_eq!(service.major_verison, 2);
+    assert_eq!(service.minor_version, 5);
+    assert!(matches!(
+        service.methods[&1].method_type,
+        MatrixServiceMethodType::RRMethod { .. }
+    ));
+    assert!(matches!(
+        service.methods[&0x8001].method_type,
+        MatrixServiceMethodType::EVENT { .. }
+    ));
+    assert_eq!(
+        service.server_client.borrow()[0],
+        MatrixRoleServerClientPair {
+            server: "ECU_10_0_0_2".to_owned(),
+            server_port: 30501,
+            client: "ECU_10_0_0_1".to_owned(),
+            ..Default::default()
+        }
+    );
+    assert!(matches!(
+        matrix.data_types["Discovered_0x1234_0x0001_Out"].data_type,
+        MatrixType::Array {
+            length: StringArrayLength::DYNAMIC(8, 12),
+            ..
+        }
+    ));
+
+    // 骨架可以原样写出再读回
+    let json = serde_json::to_string(&matrix).unwrap();
+    let matrix: Matrix = serde_json::from_str(&json).unwrap();
+    assert_eq!(matrix.services[&0x1234].methods.len(), 2);
+}
+
+#[test]
+fn discover_without_offer() {
+    use crate::analyzers::{test_message, test_sd_entry, test_sd_message};
+    use crate::types::SomeipSdEntry;
+
+    let mut analyzer = DiscoveryAnalyzer::new();
+    // 只有RequestNoReturn，载荷长度固定，走TCP；没有Offer时版本取自报文头
+    for ts_ms in 0..3 {
+        let mut msg = test_message(
+            ts_ms,
+            "10.0.0.1:40000",
+            "10.0.0.2:30600",
+            SomeipMessageType::RequestWithoutResponse,
+            0x5678,
+            2,
+        );
+        msg.payload = vec![0; 4];
+        msg.transport_protocol = SomeipTransportPortocol::TCP;
+        msg.interface_version = 3;
+        analyzer.handle_message(&msg);
+    }
+    // 组播的事件没有载荷，客户端留空
+    analyzer.handle_message(&test_message(
+        3,
+        "10.0.0.2:30600",
+        "239.0.0.1:30600",
+        SomeipMessageType::Notification,
+        0x5678,
+        0x8001,
+    ));
+    analyzer.handle_message(&test_sd_message(
+        4,
+        "10.0.0.1:30490",
+        "10.0.0.2:30490",
+        1,
+        false,
+        SomeipSdEntry {
+            service_id: 0x5678,
+            eventgroup_id: 7,
+            ..test_sd_entry(SomeipSdEntryType::Subscribe)
+        },
+    ));
+
+    let matrix = analyzer.to_matrix();
+    let service = &matrix.services[&0x5678];
+    assert_eq!(service.instance_id, 0xFFFF);
+    assert_eq!(service.major_verison, 3);
+    let method = &service.methods[&2];
+    assert_eq!(method.transport_protocol, SomeipTransportPortocol::TCP);
+    assert!(matches!(
+        &method.method_type,
+        MatrixServiceMethodType::FFMethod { data_in, .. } if data_in == &["Discovered_0x5678_0x0002_In"]
+    ));
+    assert!(matches!(
+        matrix.data_types["Discovered_0x5678_0x0002_In"].data_type,
+        MatrixType::Array {
+            length: StringArrayLength::FIXED(4),
+            ..
+        }
+    ));
+    assert!(matches!(
+        &service.methods[&0x8001].method_type,
+        MatrixServiceMethodType::EVENT { data_out, .. } if data_out.is_empty()
+    ));
+    assert_eq!(service.eventgroups[&7].eventgroup_name, "Eventgroup_0x0007");
+    let pairs = service.server_client.borrow();
+    assert_eq!(
+        *pairs,
+        vec![
+            MatrixRoleServerClientPair {
+                server: "ECU_10_0_0_2".to_owned(),
+                server_port: 30600,
+                client: "".to_owned(),
+                ..Default::default()
+            },
+            MatrixRoleServerClientPair {
+                server: "ECU_10_0_0_2".to_owned(),
+                server_port: 30600,
+                client: "ECU_10_0_0_1".to_owned(),
+                ..Default::default()
+            },
+        ]
+    );
+}
@@ -0,0 +1,489 @@
+/// E2E保护检查：对矩阵中配置了E2E保护的元素，逐条校验CRC与计数器
+/// 按 发送端IP-PORT + MessageID + 请求/应答方向 划分序列，分别记录计数器
+/// 计数器不变为重复，跳变大于1为丢失，超过MaxDeltaCounter为顺序错误
+/// 配置了周期的元素，两条报文间隔超过两个周期认为没有新数据（对应E2E的NoNewData）
+/// 另外统计返回码为E_E2E_*的报文，这些是对端检查失败后回复的
+pub mod profiles;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Write;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use crate::errors::MyError;
+use crate::matrix::types::Matrix;
+use crate::parsers::pnet_packet_someip::{SomeipReturnCode, SomeipReturnCodes};
+use crate::types::{Port, SomeipMessage, SomeipMessageType, SomeipMethodId, SomeipServiceId};
+
+use self::profiles::E2eCheckError;
+
+use super::Analyzer;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct E2eStreamKey {
+    pub sender_ip: IpAddr,
+    pub sender_port: Port,
+    pub service_id: SomeipServiceId,
+    pub method_id: SomeipMethodId,
+    /// Response/Error与Request使用各自的计数器
+    pub response: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum E2eAnomalyKind {
+    Repeated,
+    /// 丢失的报文数，仍在MaxDeltaCounter之内
+    Skipped(u32),
+    /// 跳变超过了MaxDeltaCounter
+    WrongSequence(u32),
+    Check(E2eCheckError),
+    NoNewData(Duration),
+    /// 对端回复了E2E相关的错误返回码
+    ReturnCode(u8),
+}
+
+impl fmt::Display for E2eAnomalyKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            E2eAnomalyKind::Repeated => write!(f, "repeated"),
+            E2eAnomalyKind::Skipped(lost) => write!(f, "skipped, {} lost", lost),
+            E2eAnomalyKind::WrongSequence(lost) => write!(f, "wrong sequence, {} lost", lost),
+            E2eAnomalyKind::Check(e) => write!(f, "{}", e),
+            E2eAnomalyKind::NoNewData(gap) => {
+                write!(f, "no new data for {:.3}s", gap.as_secs_f64())
+            }
+            E2eAnomalyKind::ReturnCode(code) => write!(f, "{}", SomeipReturnCode(*code)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct E2eAnomaly {
+    pub timestamp: Duration,
+    pub key: E2eStreamKey,
+    pub kind: E2eAnomalyKind,
+}
+
+#[derive(Debug, Default)]
+struct E2eStream {
+    last_counter: Option<u32>,
+    last_timestamp: Duration,
+    messages: usize,
+    ok: usize,
+    repeated: usize,
+    lost: usize,
+    wrong_sequence: usize,
+    wrong_crc: usize,
+    no_new_data: usize,
+}
+
+pub struct E2eAnalyzer<'a> {
+    matrix: &'a Matrix,
+    first_timestamp: Option<Duration>,
+    streams: BTreeMap<E2eStreamKey, E2eStream>,
+    anomalies: Vec<E2eAnomaly>,
+}
+
+impl<'a> E2eAnalyzer<'a> {
+    pub fn new(matrix: &'a Matrix) -> Self {
+        E2eAnalyzer {
+            matrix,
+            first_timestamp: None,
+            streams: BTreeMap::new(),
+            anomalies: vec![],
+        }
+    }
+
+    fn describe_key(&self, key: &E2eStreamKey) -> String {
+        let service = self.matrix.services.get(&key.service_id);
+        format!(
+            "{}:{} 0x{:04x}.0x{:04x}{}{}",
+            self.matrix.role_name_or_ip(&key.sender_ip),
+            key.sender_port,
+            key.service_id,
+            key.method_id,
+            service
+                .map(|service| match service.methods.get(&key.method_id) {
+                    Some(method) => format!("({}.{})", service.service_name, method.method_name),
+                    None => format!("({})", service.service_name),
+                })
+                .unwrap_or_default(),
+            match key.response {
+                true => " response",
+                false => "",
+            }
+        )
+    }
+}
+
+impl<'a> Analyzer for E2eAnalyzer<'a> {
+    fn name(&self) -> &'static str {
+        "e2e"
+    }
+
+    fn handle_message(&mut self, msg: &SomeipMessage) {
+        self.first_timestamp.get_or_insert(msg.timestamp);
+        if msg.sd.is_some() {
+            return;
+        }
+
+        let key = E2eStreamKey {
+            sender_ip: msg.source.ip_addr,
+            sender_port: msg.source.port,
+            service_id: msg.service_id,
+            method_id: msg.method_id,
+            response: matches!(
+                msg.message_type,
+                SomeipMessageType::Response | SomeipMessageType::ResponseWithError
+            ),
+        };
+
+        if (SomeipReturnCodes::E_E2E_REPEATED.0..=SomeipReturnCodes::E_E2E_NO_NEW_DATA.0)
+            .contains(&msg.return_code)
+        {
+            self.anomalies.push(E2eAnomaly {
+                timestamp: msg.timestamp,
+                key,
+                kind: E2eAnomalyKind::ReturnCode(msg.return_code),
+            });
+        }
+
+        let method = match self
+            .matrix
+            .services
+            .get(&msg.service_id)
+            .and_then(|service| service.methods.get(&msg.method_id))
+        {
+            Some(method) => method,
+            None => return,
+        };
+        let config = match &method.e2e {
+            Some(config) => config,
+            None => return,
+        };
+        // 带错误的应答没有正常的载荷
+        if msg.message_type == SomeipMessageType::ResponseWithError {
+            return;
+        }
+
+        let stream = self.streams.entry(key).or_default();
+        let mut anomalies = vec![];
+        stream.messages += 1;
+
+        if let Some(cycle_time_ms) = method.cycle_time_ms {
+            let gap = msg.timestamp.saturating_sub(stream.last_timestamp);
+            if stream.messages > 1 && gap > Duration::from_millis(cycle_time_ms as u64 * 2) {
+                stream.no_new_data += 1;
+                anomalies.push(E2eAnomalyKind::NoNewData(gap));
+            }
+        }
+        stream.last_timestamp = msg.timestamp;
+
+        match profiles::check(config, &msg.payload) {
+            Ok(counter) => {
+                let modulus = profiles::counter_modulus(config.profile);
+                if let Some(last_counter) = stream.last_counter {
+                    let delta = ((counter as u64 + modulus - last_counter as u64 % modulus)
+                        % modulus) as u32;
+                    let max_delta = config.max_delta_counter.unwrap_or(1).max(1);
+                    if delta == 0 {
+                        stream.repeated += 1;
+                        anomalies.push(E2eAnomalyKind::Repeated);
+                    } else if delta > max_delta {
+                        stream.wrong_sequence += 1;
+                        stream.lost += delta as usize - 1;
+                        anomalies.push(E2eAnomalyKind::WrongSequence(delta - 1));
+                    } else if delta > 1 {
+                        stream.lost += delta as usize - 1;
+                        anomalies.push(E2eAnomalyKind::Skipped(delta - 1));
+                    } else {
+                        stream.ok += 1;
+                    }
+                } else {
+                    stream.ok += 1;
+                }
+                stream.last_counter = Some(counter);
+            }
+            Err(e) => {
+                stream.wrong_crc += 1;
+                anomalies.push(E2eAnomalyKind::Check(e));
+            }
+        }
+
+        for kind in anomalies {
+            self.anomalies.push(E2eAnomaly {
+                timestamp: msg.timestamp,
+                key,
+                kind,
+            });
+        }
+    }
+
+    fn write_report(&self, w: &mut dyn Write) -> Result<(), MyError> {
+        let first_timestamp = self.first_timestamp.unwrap_or_default();
+
+        writeln!(w, "E2E check: {} protected streams", self.streams.len())?;
+        writeln!(
+            w,
+            "{:>8} {:>8} {:>6} {:>6} {:>6} {:>6} {:>6}  stream",
+            "messages", "ok", "rep", "lost", "seq", "error", "nodata"
+        )?;
+        for (key, stream) in &self.streams {
+            writeln!(
+                w,
+                "{:>8} {:>8} {:>6} {:>6} {:>6} {:>6} {:>6}  {}",
+                stream.messages,
+                stream.ok,
+                stream.repeated,
+                stream.lost,
+                stream.wrong_sequence,
+                stream.wrong_crc,
+                stream.no_new_data,
+                self.describe_key(key)
+            )?;
+        }
+
+        writeln!(w, "E2E anomalies: {}", self.anomalies.len())?;
+        for anomaly in &self.anomalies {
+            writeln!(
+                w,
+                "{:>12.6}s  {}  {}",
+                anomaly
+                    .timestamp
+                    .saturating_sub(first_timestamp)
+                    .as_secs_f64(),
+                anomaly.kind,
+                self.describe_key(&anomaly.key)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+fn e2e_config(
+    profile: crate::matrix::types::MatrixE2eProfile,
+) -> crate::matrix::types::MatrixE2eConfig {
+    crate::matrix::types::MatrixE2eConfig {
+        profile,
+        data_id: 0x0123,
+        data_id_list: vec![],
+        offset: 0,
+        counter_offset: None,
+        max_delta_counter: Some(2),
+    }
+}
+
+/// 按照Profile生成受保护的载荷
+#[cfg(test)]
+fn protect(config: &crate::matrix::types::MatrixE2eConfig, counter: u32) -> Vec<u8> {
+    use crate::matrix::types::MatrixE2eProfile;
+
+    let mut data = vec![0x5A; 32];
+    let len = data.len() as u64;
+    let put = |data: &mut Vec<u8>, at: usize, value: u64, size: usize| {
+        data[at..at + size].copy_from_slice(&value.to_be_bytes()[8 - size..]);
+    };
+    match config.profile {
+        MatrixE2eProfile::P01 | MatrixE2eProfile::P11 | MatrixE2eProfile::P02 => {
+            data[1] = counter as u8
+        }
+        MatrixE2eProfile::P04 => {
+            put(&mut data, 0, len, 2);
+            put(&mut data, 2, counter as u64, 2);
+            put(&mut data, 4, config.data_id as u64, 4);
+        }
+        MatrixE2eProfile::P05 => data[2] = counter as u8,
+        MatrixE2eProfile::P06 => {
+            put(&mut data, 2, len, 2);
+            data[4] = counter as u8;
+        }
+        MatrixE2eProfile::P07 => {
+            put(&mut data, 8, len, 4);
+            put(&mut data, 12, counter as u64, 4);
+            put(&mut data, 16, config.data_id as u64, 4);
+        }
+    }
+    let crc = profiles::compute_crc(config, &data).unwrap();
+    match config.profile {
+        MatrixE2eProfile::P01 | MatrixE2eProfile::P11 | MatrixE2eProfile::P02 => {
+            data[0] = crc as u8
+        }
+        MatrixE2eProfile::P04 => put(&mut data, 8, crc, 4),
+        MatrixE2eProfile::P05 => data[0..2].copy_from_slice(&(crc as u16).to_le_bytes()),
+        MatrixE2eProfile::P06 => put(&mut data, 0, crc, 2),
+        MatrixE2eProfile::P07 => put(&mut data, 0, crc, 8),
+    }
+    data
+}
+
+#[test]
+fn e2e_counter_and_crc() {
+    use crate::analyzers::{test_event, test_matrix, test_message};
+    use crate::matrix::types::{MatrixE2eProfile, MatrixServiceMethod};
+
+    for profile in [
+        MatrixE2eProfile::P01,
+        MatrixE2eProfile::P02,
+        MatrixE2eProfile::P04,
+        MatrixE2eProfile::P05,
+        MatrixE2eProfile::P06,
+        MatrixE2eProfile::P07,
+        MatrixE2eProfile::P11,
+    ] {
+        let config = e2e_config(profile);
+        let matrix = test_matrix(vec![MatrixServiceMethod {
+            cycle_time_ms: Some(10),
+            e2e: Some(config.clone()),
+            ..test_event(0x8001, "Temperature", "")
+        }]);
+        let mut analyzer = E2eAnalyzer::new(&matrix);
+
+        // 计数器：1 2 2(重复) 4(丢1) 8(顺序错误) 9(间隔过长) 10(CRC错误)
+        for (ts, counter) in [
+            (0, 1),
+            (10, 2),
+            (20, 2),
+            (30, 4),
+            (40, 8),
+            (100, 9),
+            (110, 10),
+        ] {
+            let mut msg = test_message(
+                ts,
+                "10.0.0.2:30501",
+                "10.0.0.1:40000",
+                SomeipMessageType::Notification,
+                0x1234,
+                0x8001,
+            );
+            msg.payload = protect(&config, counter);
+            if counter == 10 {
+                *msg.payload.last_mut().unwrap() ^= 0xFF;
+            }
+            analyzer.handle_message(&msg);
+        }
+
+        let kinds: Vec<E2eAnomalyKind> = analyzer.anomalies.iter().map(|a| a.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                E2eAnomalyKind::Repeated,
+                E2eAnomalyKind::Skipped(1),
+                E2eAnomalyKind::WrongSequence(3),
+                E2eAnomalyKind::NoNewData(Duration::from_millis(60)),
+                E2eAnomalyKind::Check(E2eCheckError::WrongCrc),
+            ],
+            "{:?}",
+            profile
+        );
+    }
+}
+
+#[test]
+fn e2e_counter_wraps() {
+    use crate::analyzers::{test_event, test_matrix, test_message};
+    use crate::matrix::types::{MatrixE2eProfile, MatrixServiceMethod};
+
+    for (profile, counters) in [
+        (MatrixE2eProfile::P01, vec![13, 14, 0, 1]),
+        (MatrixE2eProfile::P02, vec![14, 15, 0]),
+        (MatrixE2eProfile::P05, vec![254, 255, 0]),
+        (MatrixE2eProfile::P04, vec![0xFFFE, 0xFFFF, 0]),
+    ] {
+        let config = e2e_config(profile);
+        let matrix = test_matrix(vec![MatrixServiceMethod {
+            cycle_time_ms: Some(10),
+            e2e: Some(config.clone()),
+            ..test_event(0x8001, "Temperature", "")
+        }]);
+        let mut analyzer = E2eAnalyzer::new(&matrix);
+        for (ts, counter) in (0..).step_by(10).zip(counters) {
+            let mut msg = test_message(
+                ts,
+                "10.0.0.2:30501",
+                "10.0.0.1:40000",
+                SomeipMessageType::Notification,
+                0x1234,
+                0x8001,
+            );
+            msg.payload = protect(&config, counter);
+            analyzer.handle_message(&msg);
+        }
+        assert!(analyzer.anomalies.is_empty(), "{:?}", profile);
+    }
+}
+
+#[test]
+fn e2e_request_response_and_return_codes() {
+    use crate::analyzers::{test_matrix, test_message, test_report, test_rr_method};
+    use crate::matrix::types::{MatrixE2eProfile, MatrixServiceMethod};
+
+    let config = e2e_config(MatrixE2eProfile::P05);
+    let matrix = test_matrix(vec![MatrixServiceMethod {
+        e2e: Some(config.clone()),
+        ..test_rr_method(1, "Calibrate", &[], "")
+    }]);
+    let mut analyzer = E2eAnalyzer::new(&matrix);
+    let client = "10.0.0.1:40000";
+    let server = "10.0.0.2:30501";
+
+    // Request与Response各自计数
+    for (ts, source, destination, message_type, counter) in [
+        (0, client, server, SomeipMessageType::Request, 1),
+        (5, server, client, SomeipMessageType::Response, 7),
+        (10, client, server, SomeipMessageType::Request, 2),
+        (15, server, client, SomeipMessageType::Response, 8),
+    ] {
+        let mut msg = test_message(ts, source, destination, message_type, 0x1234, 1);
+        msg.payload = protect(&config, counter);
+        analyzer.handle_message(&msg);
+    }
+    assert!(analyzer.anomalies.is_empty());
+    assert_eq!(analyzer.streams.len(), 2);
+
+    // 对端回复的E2E错误，带错误的应答不参与计数；矩阵中没有的方法也统计返回码
+    let mut error = test_message(
+        20,
+        server,
+        client,
+        SomeipMessageType::ResponseWithError,
+        0x1234,
+        1,
+    );
+    error.return_code = SomeipReturnCodes::E_E2E_REPEATED.0;
+    analyzer.handle_message(&error);
+    let mut unknown = test_message(
+        30,
+        server,
+        client,
+        SomeipMessageType::ResponseWithError,
+        0x1234,
+        2,
+    );
+    unknown.return_code = SomeipReturnCodes::E_E2E_NO_NEW_DATA.0;
+    analyzer.handle_message(&unknown);
+
+    let kinds: Vec<E2eAnomalyKind> = analyzer.anomalies.iter().map(|a| a.kind).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            E2eAnomalyKind::ReturnCode(0x0B),
+            E2eAnomalyKind::ReturnCode(0x0F)
+        ]
+    );
+    let response = E2eStreamKey {
+        sender_ip: "10.0.0.2".parse().unwrap(),
+        sender_port: 30501,
+        service_id: 0x1234,
+        method_id: 1,
+        response: true,
+    };
+    assert_eq!(analyzer.streams[&response].messages, 2);
+
+    let report = test_report(&analyzer);
+    assert!(report.starts_with("E2E check: 2 protected streams\n"));
+    assert!(report.contains("E2E anomalies: 2\n"));
+    assert!(report.contains("10.0.0.2:30501 0x1234.0x0001(Climate.Calibrate) response"));
+}
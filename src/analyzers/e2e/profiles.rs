@@ -0,0 +1,232 @@
+/// AUTOSAR E2E各Profile的报文头布局与CRC算法，只做接收端的检查
+/// P01/P11：CRC8，计数器4bit，CRC与计数器的位置可配置，DataIDMode只支持BOTH（DataID的低、高字节都参与CRC）
+/// P02：CRC8H2F，固定在载荷开头，DataID按计数器从DataIDList中选取
+/// P04：头部12字节 Length(16) Counter(16) DataID(32) CRC(32)，CRC32P4
+/// P05：头部3字节 CRC(16,小端) Counter(8)，CRC16
+/// P06：头部5字节 CRC(16) Length(16) Counter(8)，CRC16
+/// P07：头部20字节 CRC(64) Length(32) Counter(32) DataID(32)，CRC64
+use std::fmt;
+
+use crate::matrix::types::{MatrixE2eConfig, MatrixE2eProfile};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum E2eCheckError {
+    /// 载荷放不下E2E头
+    TooShort,
+    WrongCrc,
+    WrongLength,
+    WrongDataId,
+}
+
+impl fmt::Display for E2eCheckError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            E2eCheckError::TooShort => write!(f, "payload too short for e2e header"),
+            E2eCheckError::WrongCrc => write!(f, "wrong crc"),
+            E2eCheckError::WrongLength => write!(f, "wrong length field"),
+            E2eCheckError::WrongDataId => write!(f, "wrong data id"),
+        }
+    }
+}
+
+fn crc8(poly: u8, init: u8, xor_out: u8, chunks: &[&[u8]]) -> u8 {
+    let mut crc = init;
+    for byte in chunks.iter().flat_map(|c| c.iter()) {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = match crc & 0x80 != 0 {
+                true => (crc << 1) ^ poly,
+                false => crc << 1,
+            };
+        }
+    }
+    crc ^ xor_out
+}
+
+/// CRC-16/CCITT-FALSE
+fn crc16(chunks: &[&[u8]]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for byte in chunks.iter().flat_map(|c| c.iter()) {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = match crc & 0x8000 != 0 {
+                true => (crc << 1) ^ 0x1021,
+                false => crc << 1,
+            };
+        }
+    }
+    crc
+}
+
+/// CRC-32P4，多项式0xF4ACFB13，反射
+fn crc32p4(chunks: &[&[u8]]) -> u32 {
+    let mut crc: u32 = 0xFFFF_FFFF;
+    for byte in chunks.iter().flat_map(|c| c.iter()) {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = match crc & 1 != 0 {
+                true => (crc >> 1) ^ 0xC8DF_352F,
+                false => crc >> 1,
+            };
+        }
+    }
+    crc ^ 0xFFFF_FFFF
+}
+
+/// CRC-64/ECMA-182反射版本（CRC-64/XZ）
+fn crc64(chunks: &[&[u8]]) -> u64 {
+    let mut crc: u64 = u64::MAX;
+    for byte in chunks.iter().flat_map(|c| c.iter()) {
+        crc ^= *byte as u64;
+        for _ in 0..8 {
+            crc = match crc & 1 != 0 {
+                true => (crc >> 1) ^ 0xC96C_5795_D787_0F42,
+                false => crc >> 1,
+            };
+        }
+    }
+    crc ^ u64::MAX
+}
+
+fn be(data: &[u8]) -> u64 {
+    data.iter().fold(0, |acc, b| (acc << 8) | *b as u64)
+}
+
+/// 计数器取值个数，用于计算回绕后的差值
+pub fn counter_modulus(profile: MatrixE2eProfile) -> u64 {
+    match profile {
+        MatrixE2eProfile::P01 | MatrixE2eProfile::P11 => 15,
+        MatrixE2eProfile::P02 => 16,
+        MatrixE2eProfile::P04 => 1 << 16,
+        MatrixE2eProfile::P05 | MatrixE2eProfile::P06 => 1 << 8,
+        MatrixE2eProfile::P07 => 1 << 32,
+    }
+}
+
+/// 读取4bit计数器，位偏移不是8的整数倍时取高半字节
+fn nibble(data: &[u8], bit_offset: usize) -> Option<u8> {
+    let byte = *data.get(bit_offset / 8)?;
+    Some(match bit_offset % 8 {
+        0 => byte & 0x0F,
+        _ => byte >> 4,
+    })
+}
+
+/// 按照Profile计算CRC，返回值与报文中CRC字段的布局一致
+/// 报文中原有的CRC字段不参与计算，可以用于构造测试报文
+pub fn compute_crc(config: &MatrixE2eConfig, data: &[u8]) -> Result<u64, E2eCheckError> {
+    let offset = config.offset / 8;
+    let data_id = config.data_id;
+    let need = |len: usize| match data.len() >= offset + len {
+        true => Ok(()),
+        false => Err(E2eCheckError::TooShort),
+    };
+    Ok(match config.profile {
+        MatrixE2eProfile::P01 | MatrixE2eProfile::P11 => {
+            need(1)?;
+            let id = [data_id as u8, (data_id >> 8) as u8];
+            let rest = [&data[..offset], &data[offset + 1..]];
+            let crc = match config.profile {
+                MatrixE2eProfile::P01 => crc8(0x1D, 0x00, 0x00, &[&id, rest[0], rest[1]]),
+                _ => crc8(0x1D, 0xFF, 0xFF, &[&id, rest[0], rest[1]]),
+            };
+            crc as u64
+        }
+        MatrixE2eProfile::P02 => {
+            if data.len() < 2 {
+                return Err(E2eCheckError::TooShort);
+            }
+            let counter = (data[1] & 0x0F) as usize;
+            let id = config
+                .data_id_list
+                .get(counter)
+                .copied()
+                .unwrap_or(data_id as u8);
+            crc8(0x2F, 0xFF, 0xFF, &[&data[1..], &[id]]) as u64
+        }
+        MatrixE2eProfile::P04 => {
+            need(12)?;
+            crc32p4(&[&data[..offset + 8], &data[offset + 12..]]) as u64
+        }
+        MatrixE2eProfile::P05 => {
+            need(3)?;
+            crc16(&[
+                &data[..offset],
+                &data[offset + 2..],
+                &[data_id as u8, (data_id >> 8) as u8],
+            ]) as u64
+        }
+        MatrixE2eProfile::P06 => {
+            need(5)?;
+            crc16(&[
+                &data[..offset],
+                &data[offset + 2..],
+                &[(data_id >> 8) as u8, data_id as u8],
+            ]) as u64
+        }
+        MatrixE2eProfile::P07 => {
+            need(20)?;
+            crc64(&[&data[..offset], &data[offset + 8..]])
+        }
+    })
+}
+
+/// 检查一条受保护的报文，成功时返回计数器
+pub fn check(config: &MatrixE2eConfig, data: &[u8]) -> Result<u32, E2eCheckError> {
+    let computed = compute_crc(config, data)?;
+    let offset = config.offset / 8;
+    let length = data.len() as u64;
+    let (crc, counter) = match config.profile {
+        MatrixE2eProfile::P01 | MatrixE2eProfile::P11 => (
+            data[offset] as u64,
+            nibble(data, config.counter_offset.unwrap_or(8)).ok_or(E2eCheckError::TooShort)? as u32,
+        ),
+        MatrixE2eProfile::P02 => (data[0] as u64, (data[1] & 0x0F) as u32),
+        MatrixE2eProfile::P04 => {
+            let header = &data[offset..offset + 12];
+            if be(&header[0..2]) != length {
+                return Err(E2eCheckError::WrongLength);
+            }
+            if be(&header[4..8]) != config.data_id as u64 {
+                return Err(E2eCheckError::WrongDataId);
+            }
+            (be(&header[8..12]), be(&header[2..4]) as u32)
+        }
+        MatrixE2eProfile::P05 => (
+            u16::from_le_bytes([data[offset], data[offset + 1]]) as u64,
+            data[offset + 2] as u32,
+        ),
+        MatrixE2eProfile::P06 => {
+            let header = &data[offset..offset + 5];
+            if be(&header[2..4]) != length {
+                return Err(E2eCheckError::WrongLength);
+            }
+            (be(&header[0..2]), header[4] as u32)
+        }
+        MatrixE2eProfile::P07 => {
+            let header = &data[offset..offset + 20];
+            if be(&header[8..12]) != length {
+                return Err(E2eCheckError::WrongLength);
+            }
+            if be(&header[16..20]) != config.data_id as u64 {
+                return Err(E2eCheckError::WrongDataId);
+            }
+            (be(&header[0..8]), be(&header[12..16]) as u32)
+        }
+    };
+    match crc == computed {
+        true => Ok(counter),
+        false => Err(E2eCheckError::WrongCrc),
+    }
+}
+
+#[test]
+fn e2e_crc_check_values() {
+    // 各CRC算法的标准校验值，输入为"123456789"
+    let data: &[u8] = b"123456789";
+    assert_eq!(crc8(0x1D, 0xFF, 0xFF, &[data]), 0x4B);
+    assert_eq!(crc8(0x2F, 0xFF, 0xFF, &[data]), 0xDF);
+    assert_eq!(crc16(&[data]), 0x29B1);
+    assert_eq!(crc32p4(&[data]), 0x1697_D06A);
+    assert_eq!(crc64(&[data]), 0x995D_C9BB_DF19_39FA);
+}
@@ -0,0 +1,764 @@
+/// Excel分析报告：汇总、服务覆盖、请求应答时延、周期违例、SD时间线、错误与选中报文的解析结果各占一张表
+/// 服务、方法与角色的名字与输入的矩阵一致，时间为相对第一条报文的秒数
+/// 选中的报文由命令行最后的过滤表达式 (service).(method) 决定，没有过滤表达式时输出全部报文
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::Duration;
+
+use rust_xlsxwriter::{Format, Workbook};
+
+use crate::errors::MyError;
+use crate::matrix::types::Matrix;
+use crate::sinks::{anomalies, decode_message};
+use crate::types::{Port, SomeipMessage, SomeipMessageType, SomeipMethodId, SomeipServiceId};
+
+use super::compare::CaptureProfile;
+use super::return_code::return_code_name;
+use super::{Analyzer, FindingStats};
+
+/// 周期报文的间隔偏离矩阵中周期的比例超过该值认为违例
+const CYCLE_TOLERANCE: f64 = 0.1;
+/// Excel单张表的最大行数，不含表头
+const MAX_ROWS: usize = 1_048_575;
+
+type MethodKey = (SomeipServiceId, SomeipMethodId);
+
+#[derive(Clone)]
+enum Cell {
+    Text(String),
+    Number(f64),
+}
+
+impl From<String> for Cell {
+    fn from(value: String) -> Self {
+        Cell::Text(value)
+    }
+}
+
+impl From<&str> for Cell {
+    fn from(value: &str) -> Self {
+        Cell::Text(value.to_owned())
+    }
+}
+
+impl From<f64> for Cell {
+    fn from(value: f64) -> Self {
+        Cell::Number(value)
+    }
+}
+
+impl From<usize> for Cell {
+    fn from(value: usize) -> Self {
+        Cell::Number(value as f64)
+    }
+}
+
+impl From<u32> for Cell {
+    fn from(value: u32) -> Self {
+        Cell::Number(value as f64)
+    }
+}
+
+impl From<u16> for Cell {
+    fn from(value: u16) -> Self {
+        Cell::Number(value as f64)
+    }
+}
+
+type Row = Vec<Cell>;
+
+/// 逐条记录的一张表，超过MAX_ROWS之后只计数
+#[derive(Default)]
+struct Rows {
+    rows: Vec<Row>,
+    omitted: usize,
+}
+
+impl Rows {
+    fn push(&mut self, row: Row) {
+        match self.rows.len() < MAX_ROWS {
+            true => self.rows.push(row),
+            false => self.omitted += 1,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.rows.len() + self.omitted
+    }
+}
+
+struct CycleStats {
+    cycle_time_ms: u32,
+    intervals: usize,
+    violations: usize,
+    min_ms: f64,
+    max_ms: f64,
+    first_violation: Option<Duration>,
+}
+
+pub struct ExcelReportAnalyzer<'a> {
+    matrix: &'a Matrix,
+    /// 选中输出解析结果的方法，None表示全部
+    selected: Option<Vec<MethodKey>>,
+    profile: CaptureProfile,
+    first_timestamp: Option<Duration>,
+    last_timestamp: Duration,
+    messages: usize,
+    sd_messages: usize,
+    methods: BTreeMap<MethodKey, FindingStats>,
+    last_sent: HashMap<(IpAddr, Port, SomeipServiceId, SomeipMethodId), Duration>,
+    cycles: BTreeMap<(MethodKey, IpAddr), CycleStats>,
+    sd_timeline: Rows,
+    /// 未知服务的报文按服务汇总，不逐条记录
+    unknown_services: BTreeMap<SomeipServiceId, FindingStats>,
+    error_messages: usize,
+    errors: Rows,
+    decoded: Rows,
+}
+
+impl<'a> ExcelReportAnalyzer<'a> {
+    pub fn new(matrix: &'a Matrix, selected: Option<Vec<MethodKey>>) -> Self {
+        ExcelReportAnalyzer {
+            matrix,
+            selected,
+            profile: CaptureProfile::default(),
+            first_timestamp: None,
+            last_timestamp: Duration::ZERO,
+            messages: 0,
+            sd_messages: 0,
+            methods: BTreeMap::new(),
+            last_sent: HashMap::new(),
+            cycles: BTreeMap::new(),
+            sd_timeline: Rows::default(),
+            unknown_services: BTreeMap::new(),
+            error_messages: 0,
+            errors: Rows::default(),
+            decoded: Rows::default(),
+        }
+    }
+
+    fn relative(&self, timestamp: Duration) -> f64 {
+        timestamp
+            .saturating_sub(self.first_timestamp.unwrap_or_default())
+            .as_secs_f64()
+    }
+
+    fn service_name(&self, service_id: SomeipServiceId) -> String {
+        match self.matrix.service_name(service_id) {
+            Some(name) => name.to_owned(),
+            None => format!("0x{:04x}", service_id),
+        }
+    }
+
+    fn method_name(&self, service_id: SomeipServiceId, method_id: SomeipMethodId) -> String {
+        match self
+            .matrix
+            .services
+            .get(&service_id)
+            .and_then(|service| service.methods.get(&method_id))
+        {
+            Some(method) => method.method_name.clone(),
+            None => format!("0x{:04x}", method_id),
+        }
+    }
+
+    fn check_cycle(&mut self, msg: &SomeipMessage) {
+        let cycle_time_ms = match self
+            .matrix
+            .services
+            .get(&msg.service_id)
+            .and_then(|service| service.methods.get(&msg.method_id))
+            .and_then(|method| method.cycle_time_ms)
+        {
+            Some(cycle_time_ms) if cycle_time_ms > 0 => cycle_time_ms,
+            _ => return,
+        };
+        let sender = (
+            msg.source.ip_addr,
+            msg.source.port,
+            msg.service_id,
+            msg.method_id,
+        );
+        let last = match self.last_sent.insert(sender, msg.timestamp) {
+            Some(last) => last,
+            None => return,
+        };
+        let interval = msg.timestamp.saturating_sub(last).as_secs_f64() * 1000.0;
+        let stats = self
+            .cycles
+            .entry(((msg.service_id, msg.method_id), msg.source.ip_addr))
+            .or_insert(CycleStats {
+                cycle_time_ms,
+                intervals: 0,
+                violations: 0,
+                min_ms: f64::INFINITY,
+                max_ms: 0.0,
+                first_violation: None,
+            });
+        stats.intervals += 1;
+        stats.min_ms = stats.min_ms.min(interval);
+        stats.max_ms = stats.max_ms.max(interval);
+        if (interval - cycle_time_ms as f64).abs() > cycle_time_ms as f64 * CYCLE_TOLERANCE {
+            stats.violations += 1;
+            stats.first_violation.get_or_insert(msg.timestamp);
+        }
+    }
+
+    fn record_sd(&mut self, msg: &SomeipMessage, time: f64) {
+        let sd = match &msg.sd {
+            Some(sd) => sd,
+            None => return,
+        };
+        for entry in &sd.entries {
+            let endpoints: Vec<String> = entry
+                .endpoints
+                .iter()
+                .map(|endpoint| {
+                    format!(
+                        "{}:{}/{:?}",
+                        endpoint.ip_addr, endpoint.port, endpoint.transport_protocol
+                    )
+                })
+                .collect();
+            let row = vec![
+                time.into(),
+                self.matrix.role_name_or_ip(&msg.source.ip_addr).into(),
+                self.matrix.role_name_or_ip(&msg.destination.ip_addr).into(),
+                entry.entry_type.to_string().into(),
+                self.service_name(entry.service_id).into(),
+                entry.instance_id.into(),
+                (entry.major_version as u32).into(),
+                entry.eventgroup_id.into(),
+                entry.ttl.into(),
+                endpoints.join(" ").into(),
+            ];
+            self.sd_timeline.push(row);
+        }
+    }
+
+    fn record_error(&mut self, msg: &SomeipMessage, time: f64) {
+        let decoded = decode_message(self.matrix, msg);
+        let anomalies = anomalies(self.matrix, msg, matches!(decoded, Some(Err(_))));
+        if anomalies.is_empty() {
+            return;
+        }
+        self.error_messages += 1;
+        let problems: Vec<String> = anomalies
+            .into_iter()
+            .filter_map(|anomaly| match anomaly {
+                "unknown_service" => {
+                    self.unknown_services
+                        .entry(msg.service_id)
+                        .and_modify(|stats| stats.update(msg.timestamp))
+                        .or_insert(FindingStats::new(msg.timestamp));
+                    None
+                }
+                "error_return_code" => {
+                    let method = self
+                        .matrix
+                        .services
+                        .get(&msg.service_id)
+                        .and_then(|service| service.methods.get(&msg.method_id));
+                    Some(return_code_name(method, msg.return_code))
+                }
+                anomaly => Some(anomaly.to_owned()),
+            })
+            .collect();
+        if problems.is_empty() {
+            return;
+        }
+        let row = vec![
+            time.into(),
+            self.matrix.role_name_or_ip(&msg.source.ip_addr).into(),
+            self.matrix.role_name_or_ip(&msg.destination.ip_addr).into(),
+            self.service_name(msg.service_id).into(),
+            self.method_name(msg.service_id, msg.method_id).into(),
+            msg.message_type.to_string().into(),
+            msg.session_id.into(),
+            problems.join(", ").into(),
+        ];
+        self.errors.push(row);
+    }
+
+    fn record_decoded(&mut self, msg: &SomeipMessage, time: f64) {
+        let key = (msg.service_id, msg.method_id);
+        if msg.sd.is_some()
+            || self
+                .selected
+                .as_ref()
+                .is_some_and(|selected| !selected.contains(&key))
+        {
+            return;
+        }
+        // 表已满时不再解析
+        if self.decoded.rows.len() >= MAX_ROWS {
+            self.decoded.omitted += 1;
+            return;
+        }
+        let hex = || -> String { msg.payload.iter().map(|b| format!("{:02x}", b)).collect() };
+        let parameters = match decode_message(self.matrix, msg) {
+            Some(Ok(values)) => {
+                let values: Vec<serde_json::Value> =
+                    values.into_iter().map(|(_, value)| value).collect();
+                serde_json::Value::Array(values).to_string()
+            }
+            Some(Err(error)) => format!("{:?}: {}", error, hex()),
+            None => hex(),
+        };
+        let method = self
+            .matrix
+            .services
+            .get(&msg.service_id)
+            .and_then(|service| service.methods.get(&msg.method_id));
+        let row = vec![
+            time.into(),
+            self.matrix.role_name_or_ip(&msg.source.ip_addr).into(),
+            self.matrix.role_name_or_ip(&msg.destination.ip_addr).into(),
+            self.service_name(msg.service_id).into(),
+            self.method_name(msg.service_id, msg.method_id).into(),
+            msg.message_type.to_string().into(),
+            msg.session_id.into(),
+            return_code_name(method, msg.return_code).into(),
+            parameters.into(),
+        ];
+        self.decoded.push(row);
+    }
+
+    fn summary(&self) -> Vec<(&'static str, Cell)> {
+        let matrix_services = self.matrix.services.len();
+        let seen_services = self.profile.services.len();
+        let known_services = self
+            .profile
+            .services
+            .iter()
+            .filter(|service_id| self.matrix.services.contains_key(service_id))
+            .count();
+        let matrix_methods: usize = self
+            .matrix
+            .services
+            .values()
+            .map(|service| service.methods.len())
+            .sum();
+        let known_methods = self
+            .methods
+            .keys()
+            .filter(|(service_id, method_id)| {
+                self.matrix
+                    .services
+                    .get(service_id)
+                    .is_some_and(|service| service.methods.contains_key(method_id))
+            })
+            .count();
+        vec![
+            ("Matrix Version", self.matrix.version.as_str().into()),
+            (
+                "Capture Duration (s)",
+                self.relative(self.last_timestamp).into(),
+            ),
+            ("Messages", self.messages.into()),
+            ("SD Messages", self.sd_messages.into()),
+            ("Services In Matrix", matrix_services.into()),
+            ("Matrix Services Seen", known_services.into()),
+            (
+                "Unknown Services Seen",
+                (seen_services - known_services).into(),
+            ),
+            ("Methods In Matrix", matrix_methods.into()),
+            ("Matrix Methods Seen", known_methods.into()),
+            ("Messages With Errors", self.error_messages.into()),
+            (
+                "Cyclic Timing Violations",
+                self.cycles
+                    .values()
+                    .map(|stats| stats.violations)
+                    .sum::<usize>()
+                    .into(),
+            ),
+            ("Selected Messages", self.decoded.len().into()),
+        ]
+    }
+
+    fn services_rows(&self) -> Vec<Row> {
+        let mut keys: Vec<MethodKey> = self
+            .matrix
+            .services
+            .values()
+            .flat_map(|service| {
+                service
+                    .methods
+                    .keys()
+                    .map(|method_id| (service.service_id, *method_id))
+            })
+            .chain(self.methods.keys().copied())
+            .collect();
+        keys.sort();
+        keys.dedup();
+        keys.into_iter()
+            .map(|(service_id, method_id)| {
+                let method = self
+                    .matrix
+                    .services
+                    .get(&service_id)
+                    .and_then(|service| service.methods.get(&method_id));
+                let stats = self.methods.get(&(service_id, method_id));
+                vec![
+                    format!("0x{:04x}", service_id).into(),
+                    self.service_name(service_id).into(),
+                    format!("0x{:04x}", method_id).into(),
+                    self.method_name(service_id, method_id).into(),
+                    method
+                        .map(|method| method.method_type.kind_name())
+                        .unwrap_or("unknown")
+                        .into(),
+                    match method.is_some() {
+                        true => "yes",
+                        false => "no",
+                    }
+                    .into(),
+                    stats.map_or(0, |stats| stats.count).into(),
+                    match stats {
+                        Some(stats) => self.relative(stats.first_timestamp).into(),
+                        None => "".into(),
+                    },
+                    match stats {
+                        Some(stats) => self.relative(stats.last_timestamp).into(),
+                        None => "".into(),
+                    },
+                ]
+            })
+            .collect()
+    }
+
+    /// 每个未知服务一行，时间为第一次出现的时间
+    fn unknown_service_rows(&self) -> Vec<Row> {
+        self.unknown_services
+            .iter()
+            .map(|(service_id, stats)| {
+                vec![
+                    self.relative(stats.first_timestamp).into(),
+                    "".into(),
+                    "".into(),
+                    self.service_name(*service_id).into(),
+                    "".into(),
+                    "".into(),
+                    "".into(),
+                    format!(
+                        "unknown_service: {} messages, last at {:.6}s",
+                        stats.count,
+                        self.relative(stats.last_timestamp)
+                    )
+                    .into(),
+                ]
+            })
+            .collect()
+    }
+
+    fn latency_rows(&self) -> Vec<Row> {
+        self.profile
+            .latencies
+            .iter()
+            .map(|((service_id, method_id), latency)| {
+                vec![
+                    self.service_name(*service_id).into(),
+                    self.method_name(*service_id, *method_id).into(),
+                    latency.count().into(),
+                    latency.mean().into(),
+                    latency.percentile(0.5).into(),
+                    latency.percentile(0.95).into(),
+                    latency.percentile(1.0).into(),
+                ]
+            })
+            .collect()
+    }
+
+    fn cycle_rows(&self) -> Vec<Row> {
+        self.cycles
+            .iter()
+            .map(|(((service_id, method_id), sender), stats)| {
+                vec![
+                    self.service_name(*service_id).into(),
+                    self.method_name(*service_id, *method_id).into(),
+                    self.matrix.role_name_or_ip(sender).into(),
+                    stats.cycle_time_ms.into(),
+                    stats.intervals.into(),
+                    stats.violations.into(),
+                    stats.min_ms.into(),
+                    stats.max_ms.into(),
+                    match stats.first_violation {
+                        Some(timestamp) => self.relative(timestamp).into(),
+                        None => "".into(),
+                    },
+                ]
+            })
+            .collect()
+    }
+
+    fn workbook(&self) -> Result<Workbook, MyError> {
+        let mut workbook = Workbook::new();
+        let summary: Vec<Row> = self
+            .summary()
+            .into_iter()
+            .map(|(name, value)| vec![name.into(), value])
+            .collect();
+        write_sheet(&mut workbook, "Summary", &["Item", "Value"], &summary, 0)?;
+        write_sheet(
+            &mut workbook,
+            "Services",
+            &[
+                "Service ID",
+                "Service",
+                "Method ID",
+                "Method",
+                "Type",
+                "In Matrix",
+                "Messages",
+                "First Seen (s)",
+                "Last Seen (s)",
+            ],
+            &self.services_rows(),
+            0,
+        )?;
+        write_sheet(
+            &mut workbook,
+            "Latency",
+            &[
+                "Service",
+                "Method",
+                "Responses",
+                "Mean (ms)",
+                "P50 (ms)",
+                "P95 (ms)",
+                "Max (ms)",
+            ],
+            &self.latency_rows(),
+            0,
+        )?;
+        write_sheet(
+            &mut workbook,
+            "Cyclic Timing",
+            &[
+                "Service",
+                "Method",
+                "Sender",
+                "Cycle Time (ms)",
+                "Intervals",
+                "Violations",
+                "Min (ms)",
+                "Max (ms)",
+                "First Violation (s)",
+            ],
+            &self.cycle_rows(),
+            0,
+        )?;
+        write_sheet(
+            &mut workbook,
+            "SD Timeline",
+            &[
+                "Time (s)",
+                "Source",
+                "Destination",
+                "Entry",
+                "Service",
+                "Instance",
+                "Major Version",
+                "Eventgroup",
+                "TTL",
+                "Endpoints",
+            ],
+            &self.sd_timeline.rows,
+            self.sd_timeline.omitted,
+        )?;
+        let mut errors = self.unknown_service_rows();
+        errors.extend(self.errors.rows.iter().cloned());
+        write_sheet(
+            &mut workbook,
+            "Errors",
+            &[
+                "Time (s)",
+                "Source",
+                "Destination",
+                "Service",
+                "Method",
+                "Message Type",
+                "Session ID",
+                "Problems",
+            ],
+            &errors,
+            self.errors.omitted,
+        )?;
+        write_sheet(
+            &mut workbook,
+            "Messages",
+            &[
+                "Time (s)",
+                "Source",
+                "Destination",
+                "Service",
+                "Method",
+                "Message Type",
+                "Session ID",
+                "Return Code",
+                "Parameters",
+            ],
+            &self.decoded.rows,
+            self.decoded.omitted,
+        )?;
+        Ok(workbook)
+    }
+
+    pub fn to_xlsx_file<P: AsRef<Path>>(&self, path: P) -> Result<(), MyError> {
+        self.workbook()?.save(path)?;
+        Ok(())
+    }
+}
+
+/// 超过MAX_ROWS的部分不写入，最后一行说明省略了多少行
+fn write_sheet(
+    workbook: &mut Workbook,
+    name: &str,
+    headers: &[&str],
+    rows: &[Row],
+    omitted: usize,
+) -> Result<(), MyError> {
+    let (rows, omitted) = match rows.len() + omitted > MAX_ROWS {
+        true => {
+            let kept = rows.len().min(MAX_ROWS - 1);
+            (&rows[..kept], rows.len() - kept + omitted)
+        }
+        false => (rows, 0),
+    };
+    let bold = Format::new().set_bold();
+    let worksheet = workbook.add_worksheet();
+    worksheet.set_name(name)?;
+    for (col, header) in headers.iter().enumerate() {
+        worksheet.write_string_with_format(0, col as u16, *header, &bold)?;
+    }
+    if omitted > 0 {
+        let row = rows.len() as u32 + 1;
+        worksheet.write_string(row, 0, format!("{} rows omitted", omitted))?;
+    }
+    for (row, cells) in rows.iter().enumerate() {
+        for (col, cell) in cells.iter().enumerate() {
+            let (row, col) = (row as u32 + 1, col as u16);
+            match cell {
+                Cell::Text(text) => worksheet.write_string(row, col, text)?,
+                Cell::Number(number) => worksheet.write_number(row, col, *number)?,
+            };
+        }
+    }
+    worksheet.set_freeze_panes(1, 0)?;
+    worksheet.autofit();
+    Ok(())
+}
+
+impl<'a> Analyzer for ExcelReportAnalyzer<'a> {
+    fn name(&self) -> &'static str {
+        "excel-report"
+    }
+
+    fn handle_message(&mut self, msg: &SomeipMessage) {
+        self.first_timestamp.get_or_insert(msg.timestamp);
+        self.last_timestamp = msg.timestamp;
+        self.messages += 1;
+        let time = self.relative(msg.timestamp);
+        self.profile.record(self.matrix, msg);
+        self.record_error(msg, time);
+        match msg.sd {
+            Some(_) => {
+                self.sd_messages += 1;
+                self.record_sd(msg, time);
+            }
+            None => {
+                self.methods
+                    .entry((msg.service_id, msg.method_id))
+                    .and_modify(|stats| stats.update(msg.timestamp))
+                    .or_insert(FindingStats::new(msg.timestamp));
+                if matches!(
+                    msg.message_type,
+                    SomeipMessageType::Notification | SomeipMessageType::RequestWithoutResponse
+                ) {
+                    self.check_cycle(msg);
+                }
+                self.record_decoded(msg, time);
+            }
+        }
+    }
+
+    fn write_report(&self, w: &mut dyn Write) -> Result<(), MyError> {
+        writeln!(w, "Excel report summary:")?;
+        for (name, value) in self.summary() {
+            match value {
+                Cell::Text(text) => writeln!(w, "  {:<28}{}", name, text)?,
+                Cell::Number(number) => writeln!(w, "  {:<28}{}", name, number)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn excel_report_sheets() {
+    use super::test_message;
+    use calamine::{Data, Reader, Xlsx};
+
+    let matrix = Matrix::default();
+    let mut analyzer = ExcelReportAnalyzer::new(&matrix, None);
+    let mut request = test_message(
+        1000,
+        "10.0.0.1:40000",
+        "10.0.0.2:30501",
+        SomeipMessageType::Request,
+        0x1234,
+        1,
+    );
+    request.payload = vec![0xAB];
+    let mut response = test_message(
+        1002,
+        "10.0.0.2:30501",
+        "10.0.0.1:40000",
+        SomeipMessageType::ResponseWithError,
+        0x1234,
+        1,
+    );
+    response.return_code = 0x01;
+    analyzer.handle_message(&request);
+    analyzer.handle_message(&response);
+
+    let buffer = analyzer.workbook().unwrap().save_to_buffer().unwrap();
+    let mut workbook = Xlsx::new(std::io::Cursor::new(buffer)).unwrap();
+    assert_eq!(
+        workbook.sheet_names(),
+        [
+            "Summary",
+            "Services",
+            "Latency",
+            "Cyclic Timing",
+            "SD Timeline",
+            "Errors",
+            "Messages"
+        ]
+    );
+    let latency = workbook.worksheet_range("Latency").unwrap();
+    assert_eq!(
+        latency.get((1, 0)),
+        Some(&Data::String("0x1234".to_owned()))
+    );
+    assert_eq!(latency.get((1, 2)), Some(&Data::Float(1.0)));
+    let messages = workbook.worksheet_range("Messages").unwrap();
+    assert_eq!(messages.get((1, 8)), Some(&Data::String("ab".to_owned())));
+    // 未知服务的报文汇总成一行，其他问题逐条记录
+    let errors = workbook.worksheet_range("Errors").unwrap();
+    assert_eq!(errors.height(), 3);
+    assert_eq!(
+        errors.get((1, 7)),
+        Some(&Data::String(
+            "unknown_service: 2 messages, last at 0.002000s".to_owned()
+        ))
+    );
+    assert_eq!(
+        errors.get((2, 7)),
+        Some(&Data::String("0x01 E_NOK".to_owned()))
+    );
+}
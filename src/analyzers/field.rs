@@ -0,0 +1,510 @@
+/// 字段状态时间线：按照矩阵解析Field的Notifier、Getter应答、Setter请求与应答，记录每个字段的取值变化
+/// Setter请求只是期望的值，记入历史但不改变状态；其余三种报文中的值即为字段的当前值
+/// 支持两种查询：某一时刻所有字段的值（--field-at，相对抓包开始的秒数），某个字段的完整历史（--field-history）
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::time::Duration;
+
+use log::debug;
+use serde_json::{json, Value};
+
+use crate::errors::MyError;
+use crate::matrix::types::{Matrix, MatrixServiceMethodFieldType, MatrixServiceMethodType};
+use crate::types::{PacketIndex, SomeipMessage, SomeipMessageType, SomeipServiceId};
+
+use super::Analyzer;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldChangeSource {
+    Notifier,
+    GetterResponse,
+    SetterRequest,
+    SetterResponse,
+}
+
+impl FieldChangeSource {
+    fn from_message(
+        field_type: MatrixServiceMethodFieldType,
+        message_type: SomeipMessageType,
+    ) -> Option<Self> {
+        Some(match (field_type, message_type) {
+            (MatrixServiceMethodFieldType::Notifier, SomeipMessageType::Notification) => {
+                FieldChangeSource::Notifier
+            }
+            (MatrixServiceMethodFieldType::Getter, SomeipMessageType::Response) => {
+                FieldChangeSource::GetterResponse
+            }
+            (MatrixServiceMethodFieldType::Setter, SomeipMessageType::Request) => {
+                FieldChangeSource::SetterRequest
+            }
+            (MatrixServiceMethodFieldType::Setter, SomeipMessageType::Response) => {
+                FieldChangeSource::SetterResponse
+            }
+            _ => return None,
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            FieldChangeSource::Notifier => "notifier",
+            FieldChangeSource::GetterResponse => "getter response",
+            FieldChangeSource::SetterRequest => "setter request",
+            FieldChangeSource::SetterResponse => "setter response",
+        }
+    }
+
+    /// Setter请求还没有被服务端接受，不代表字段的当前值
+    fn changes_state(&self) -> bool {
+        *self != FieldChangeSource::SetterRequest
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FieldChange {
+    pub timestamp: Duration,
+    pub packet_index: PacketIndex,
+    pub source: FieldChangeSource,
+    pub sender: String,
+    pub value: Value,
+}
+
+/// 字段按 服务 + 元素名 区分，Getter、Setter、Notifier共用同一个元素名
+pub type FieldKey = (SomeipServiceId, String);
+
+pub struct FieldAnalyzer<'a> {
+    matrix: &'a Matrix,
+    first_timestamp: Option<Duration>,
+    /// 查询某一时刻的快照，相对抓包开始的时间
+    at: Option<Duration>,
+    /// 查询某个字段的历史，可以是 服务名.字段名 或者 字段名
+    history: Option<String>,
+    /// 只记录变化：值与上一条改变状态的记录相同的不记录，Setter请求总是记录
+    fields: BTreeMap<FieldKey, Vec<FieldChange>>,
+    decode_errors: usize,
+}
+
+impl<'a> FieldAnalyzer<'a> {
+    pub fn new(matrix: &'a Matrix, at: Option<Duration>, history: Option<String>) -> Self {
+        FieldAnalyzer {
+            matrix,
+            first_timestamp: None,
+            at,
+            history,
+            fields: BTreeMap::new(),
+            decode_errors: 0,
+        }
+    }
+
+    fn field_name(&self, key: &FieldKey) -> String {
+        match self.matrix.service_name(key.0) {
+            Some(service_name) => format!("{}.{}", service_name, key.1),
+            None => format!("0x{:04x}.{}", key.0, key.1),
+        }
+    }
+
+    fn relative(&self, timestamp: Duration) -> f64 {
+        timestamp
+            .saturating_sub(self.first_timestamp.unwrap_or_default())
+            .as_secs_f64()
+    }
+
+    /// 某一时刻每个字段的值，时间为相对抓包开始的时间
+    pub fn snapshot(&self, at: Duration) -> BTreeMap<&FieldKey, &FieldChange> {
+        let at = self
+            .first_timestamp
+            .unwrap_or_default()
+            .checked_add(at)
+            .unwrap_or(Duration::MAX);
+        self.fields
+            .iter()
+            .filter_map(|(key, changes)| {
+                changes
+                    .iter()
+                    .rev()
+                    .filter(|change| change.source.changes_state())
+                    .find(|change| change.timestamp <= at)
+                    .map(|change| (key, change))
+            })
+            .collect()
+    }
+
+    /// 按名字查找字段的历史，名字可以带服务名
+    pub fn history(&self, name: &str) -> Option<(&FieldKey, &Vec<FieldChange>)> {
+        self.fields
+            .iter()
+            .find(|(key, _)| self.field_name(key) == name || key.1 == name)
+    }
+
+    fn change_json(&self, change: &FieldChange) -> Value {
+        json!({
+            "time": self.relative(change.timestamp),
+            "packet_index": change.packet_index,
+            "source": change.source.name(),
+            "sender": change.sender,
+            "value": change.value,
+        })
+    }
+}
+
+impl<'a> Analyzer for FieldAnalyzer<'a> {
+    fn name(&self) -> &'static str {
+        "field"
+    }
+
+    fn handle_message(&mut self, msg: &SomeipMessage) {
+        self.first_timestamp.get_or_insert(msg.timestamp);
+        let method = match self
+            .matrix
+            .services
+            .get(&msg.service_id)
+            .and_then(|service| service.methods.get(&msg.method_id))
+        {
+            Some(method) => method,
+            None => return,
+        };
+        let source = match &method.method_type {
+            MatrixServiceMethodType::FIELD { field_type, .. } => {
+                match FieldChangeSource::from_message(*field_type, msg.message_type) {
+                    Some(source) => source,
+                    None => return,
+                }
+            }
+            _ => return,
+        };
+        let types = match method.method_type.parameter_types(msg.message_type) {
+            Some(types) => types,
+            None => return,
+        };
+        let value = match self.matrix.decode_parameters(&types, &msg.payload) {
+            Ok(mut values) if values.len() == 1 => values.remove(0).1,
+            Ok(_) => return,
+            Err(e) => {
+                debug!(
+                    "ts:{:?}, decode field {} error: {:?}",
+                    msg.timestamp, method.method_name, e
+                );
+                self.decode_errors += 1;
+                return;
+            }
+        };
+
+        let changes = self
+            .fields
+            .entry((msg.service_id, method.method_name.clone()))
+            .or_default();
+        let unchanged = changes
+            .iter()
+            .rev()
+            .find(|change| change.source.changes_state())
+            .is_some_and(|last| last.value == value);
+        if source.changes_state() && unchanged {
+            return;
+        }
+        changes.push(FieldChange {
+            timestamp: msg.timestamp,
+            packet_index: msg.packet_index,
+            source,
+            sender: self.matrix.role_name_or_ip(&msg.source.ip_addr),
+            value,
+        });
+    }
+
+    fn write_report(&self, w: &mut dyn Write) -> Result<(), MyError> {
+        if let Some(name) = &self.history {
+            let (key, changes) = match self.history(name) {
+                Some(history) => history,
+                None => {
+                    writeln!(w, "Field history: no values of {}", name)?;
+                    return Ok(());
+                }
+            };
+            writeln!(
+                w,
+                "Field history: {}, {} changes",
+                self.field_name(key),
+                changes.len()
+            )?;
+            for change in changes {
+                writeln!(
+                    w,
+                    "{:>12.6}s  {:<16}  {:<12}  {}",
+                    self.relative(change.timestamp),
+                    change.source.name(),
+                    change.sender,
+                    change.value
+                )?;
+            }
+            return Ok(());
+        }
+
+        let snapshot = match self.at {
+            Some(at) => {
+                writeln!(w, "Field values at {:.6}s:", at.as_secs_f64())?;
+                self.snapshot(at)
+            }
+            None => {
+                writeln!(
+                    w,
+                    "Field values at end of capture: {} fields, {} decode errors",
+                    self.fields.len(),
+                    self.decode_errors
+                )?;
+                self.snapshot(
+                    Duration::MAX.saturating_sub(self.first_timestamp.unwrap_or_default()),
+                )
+            }
+        };
+        for (key, change) in snapshot {
+            writeln!(
+                w,
+                "{:>12.6}s  {:<16}  {} = {}",
+                self.relative(change.timestamp),
+                change.source.name(),
+                self.field_name(key),
+                change.value
+            )?;
+        }
+        Ok(())
+    }
+
+    fn json_report(&self) -> Option<Value> {
+        if let Some(name) = &self.history {
+            let (key, changes) = self.history(name)?;
+            return Some(json!({
+                "field": self.field_name(key),
+                "history": changes.iter().map(|c| self.change_json(c)).collect::<Vec<_>>(),
+            }));
+        }
+        let at = self
+            .at
+            .unwrap_or(Duration::MAX.saturating_sub(self.first_timestamp.unwrap_or_default()));
+        let fields: serde_json::Map<String, Value> = self
+            .snapshot(at)
+            .into_iter()
+            .map(|(key, change)| (self.field_name(key), self.change_json(change)))
+            .collect();
+        Some(json!({
+            "at": self.at.map(|at| at.as_secs_f64()),
+            "fields": fields,
+        }))
+    }
+}
+
+/// 服务0x1234中字段Temperature（uint8）的Getter、Setter与Notifier
+#[cfg(test)]
+fn field_matrix() -> Matrix {
+    use crate::analyzers::{test_data_type, test_field, test_matrix};
+    use crate::matrix::types::{MatrixType, NumberType};
+
+    let mut matrix = test_matrix(vec![
+        test_field(
+            1,
+            "Temperature",
+            MatrixServiceMethodFieldType::Getter,
+            "uint8",
+        ),
+        test_field(
+            2,
+            "Temperature",
+            MatrixServiceMethodFieldType::Setter,
+            "uint8",
+        ),
+        test_field(
+            0x8001,
+            "Temperature",
+            MatrixServiceMethodFieldType::Notifier,
+            "uint8",
+        ),
+    ]);
+    test_data_type(
+        &mut matrix,
+        "uint8",
+        MatrixType::Number {
+            size: NumberType::Uint8,
+        },
+    );
+    matrix
+}
+
+/// TBOX发给HU的0x1234报文，载荷为字段值
+#[cfg(test)]
+fn field_message(
+    ts_ms: u64,
+    message_type: SomeipMessageType,
+    method_id: crate::types::SomeipMethodId,
+    payload: Vec<u8>,
+) -> SomeipMessage {
+    use crate::analyzers::{test_message, test_payload};
+
+    test_payload(
+        test_message(
+            ts_ms,
+            "10.0.0.2:30501",
+            "10.0.0.1:40000",
+            message_type,
+            0x1234,
+            method_id,
+        ),
+        &payload,
+    )
+}
+
+#[test]
+fn field_timeline() {
+    let matrix = field_matrix();
+    let mut analyzer = FieldAnalyzer::new(&matrix, None, None);
+    for (ts_ms, message_type, method_id, value) in [
+        (0, SomeipMessageType::Notification, 0x8001, 20),
+        (100, SomeipMessageType::Notification, 0x8001, 20),
+        (200, SomeipMessageType::Request, 2, 25),
+        (300, SomeipMessageType::Response, 2, 24),
+        (400, SomeipMessageType::Response, 1, 22),
+    ] {
+        analyzer.handle_message(&field_message(ts_ms, message_type, method_id, vec![value]));
+    }
+
+    let (_, history) = analyzer.history("Climate.Temperature").unwrap();
+    let sources: Vec<&str> = history.iter().map(|c| c.source.name()).collect();
+    assert_eq!(
+        sources,
+        vec![
+            "notifier",
+            "setter request",
+            "setter response",
+            "getter response"
+        ]
+    );
+
+    let value_at = |ms: u64| {
+        analyzer
+            .snapshot(Duration::from_millis(ms))
+            .values()
+            .next()
+            .unwrap()
+            .value
+            .clone()
+    };
+    assert_eq!(value_at(250), json!(20));
+    assert_eq!(value_at(350), json!(24));
+    assert_eq!(value_at(1000), json!(22));
+}
+
+#[test]
+fn field_ignored_messages() {
+    use crate::analyzers::test_report;
+
+    let matrix = field_matrix();
+    let mut analyzer = FieldAnalyzer::new(&matrix, None, None);
+    // Getter请求与Notifier方向的Request都不带字段值，未知服务不解析
+    analyzer.handle_message(&field_message(0, SomeipMessageType::Request, 1, vec![]));
+    analyzer.handle_message(&field_message(
+        1,
+        SomeipMessageType::Request,
+        0x8001,
+        vec![1],
+    ));
+    let mut msg = field_message(2, SomeipMessageType::Notification, 0x8001, vec![1]);
+    msg.service_id = 0x4321;
+    analyzer.handle_message(&msg);
+    assert!(analyzer.fields.is_empty());
+
+    // 值解析失败只计数
+    analyzer.handle_message(&field_message(
+        3,
+        SomeipMessageType::Notification,
+        0x8001,
+        vec![],
+    ));
+    assert!(analyzer.fields.is_empty());
+    assert_eq!(analyzer.decode_errors, 1);
+
+    // 第一条报文之前没有值
+    analyzer.handle_message(&field_message(
+        10,
+        SomeipMessageType::Notification,
+        0x8001,
+        vec![5],
+    ));
+    assert!(analyzer.snapshot(Duration::from_millis(5)).is_empty());
+    assert_eq!(analyzer.snapshot(Duration::from_millis(10)).len(), 1);
+
+    let report = test_report(&analyzer);
+    assert!(report.starts_with("Field values at end of capture: 1 fields, 1 decode errors\n"));
+    assert!(report.contains("notifier          Climate.Temperature = 5"));
+}
+
+#[test]
+fn field_history_report() {
+    use crate::analyzers::test_report;
+
+    let matrix = field_matrix();
+    let mut analyzer = FieldAnalyzer::new(&matrix, None, Some("Temperature".to_owned()));
+    analyzer.handle_message(&field_message(
+        1000,
+        SomeipMessageType::Notification,
+        0x8001,
+        vec![20],
+    ));
+    analyzer.handle_message(&field_message(
+        1500,
+        SomeipMessageType::Response,
+        1,
+        vec![21],
+    ));
+
+    let report = test_report(&analyzer);
+    assert!(report.starts_with("Field history: Climate.Temperature, 2 changes\n"));
+    assert!(report.contains("    0.500000s  getter response   10.0.0.2      21"));
+
+    let json = analyzer.json_report().unwrap();
+    assert_eq!(json["field"], json!("Climate.Temperature"));
+    assert_eq!(json["history"][1]["time"], json!(0.5));
+    assert_eq!(json["history"][1]["source"], json!("getter response"));
+
+    let analyzer = FieldAnalyzer::new(&matrix, None, Some("Humidity".to_owned()));
+    assert_eq!(
+        test_report(&analyzer),
+        "Field history: no values of Humidity\n"
+    );
+    assert!(analyzer.json_report().is_none());
+}
+
+#[test]
+fn field_snapshot_at() {
+    use crate::analyzers::test_report;
+
+    let matrix = field_matrix();
+    let mut analyzer = FieldAnalyzer::new(&matrix, Some(Duration::from_millis(150)), None);
+    analyzer.handle_message(&field_message(
+        1000,
+        SomeipMessageType::Notification,
+        0x8001,
+        vec![20],
+    ));
+    analyzer.handle_message(&field_message(
+        1100,
+        SomeipMessageType::Request,
+        2,
+        vec![30],
+    ));
+    analyzer.handle_message(&field_message(
+        1200,
+        SomeipMessageType::Notification,
+        0x8001,
+        vec![30],
+    ));
+
+    // 0.15s时Setter请求还没有生效
+    let json = analyzer.json_report().unwrap();
+    assert_eq!(json["at"], json!(0.15));
+    assert_eq!(json["fields"]["Climate.Temperature"]["value"], json!(20));
+    // 很大的时间不会溢出，取最后的值
+    assert_eq!(
+        analyzer.snapshot(Duration::MAX)[&(0x1234, "Temperature".to_owned())].value,
+        json!(30)
+    );
+
+    let report = test_report(&analyzer);
+    assert!(report.starts_with("Field values at 0.150000s:\n"));
+    assert!(report.contains("Climate.Temperature = 20"));
+}
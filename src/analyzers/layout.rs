@@ -0,0 +1,372 @@
+/// 报文长度与载荷布局检查
+/// 报文头：Length小于8、Length与实际收到的字节数不一致（截断或者后面有多余字节）、ProtocolVersion不是1
+/// 载荷：按照矩阵中的数据类型逐个解析参数（与解码使用同一个Decoder），检查载荷是否不足、解析完之后是否还有多余字节，
+/// 以及动态长度的字符串（字节数）、数组（元素个数）是否在 DYNAMIC(min, max) 范围内
+/// 数据类型不完整（未实现的类型、找不到的类型）时不检查载荷
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Write;
+use std::time::Duration;
+
+use crate::errors::MyError;
+use crate::matrix::decode::{DecodeError, Decoder};
+use crate::matrix::types::Matrix;
+use crate::types::{SomeipMessage, SomeipMethodId, SomeipServiceId};
+
+use super::{Analyzer, FindingStats};
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LayoutFinding {
+    LengthTooSmall,
+    Truncated,
+    ExtraBytes,
+    WrongProtocolVersion(u8),
+    /// 载荷比矩阵中定义的参数短
+    PayloadTooShort,
+    /// 按照矩阵解析完参数之后还有多余的字节
+    TrailingBytes,
+    LengthOutOfBounds {
+        data_type: String,
+        min: usize,
+        max: usize,
+    },
+}
+
+impl fmt::Display for LayoutFinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LayoutFinding::LengthTooSmall => write!(f, "length field less than 8"),
+            LayoutFinding::Truncated => write!(f, "truncated, fewer bytes than length field"),
+            LayoutFinding::ExtraBytes => write!(f, "extra bytes after length field"),
+            LayoutFinding::WrongProtocolVersion(version) => {
+                write!(f, "protocol version {}", version)
+            }
+            LayoutFinding::PayloadTooShort => write!(f, "payload shorter than parameters"),
+            LayoutFinding::TrailingBytes => write!(f, "trailing bytes after parameters"),
+            LayoutFinding::LengthOutOfBounds {
+                data_type,
+                min,
+                max,
+            } => write!(f, "length of {} out of {}..{}", data_type, min, max),
+        }
+    }
+}
+
+type MethodFindings =
+    BTreeMap<(SomeipServiceId, SomeipMethodId), BTreeMap<LayoutFinding, FindingStats>>;
+
+pub struct LayoutAnalyzer<'a> {
+    matrix: &'a Matrix,
+    first_timestamp: Option<Duration>,
+    findings: MethodFindings,
+}
+
+impl<'a> LayoutAnalyzer<'a> {
+    pub fn new(matrix: &'a Matrix) -> Self {
+        LayoutAnalyzer {
+            matrix,
+            first_timestamp: None,
+            findings: BTreeMap::new(),
+        }
+    }
+
+    fn record(&mut self, msg: &SomeipMessage, finding: LayoutFinding) {
+        self.findings
+            .entry((msg.service_id, msg.method_id))
+            .or_default()
+            .entry(finding)
+            .and_modify(|stats| stats.update(msg.timestamp))
+            .or_insert(FindingStats::new(msg.timestamp));
+    }
+
+    fn method_name(&self, service_id: SomeipServiceId, method_id: SomeipMethodId) -> String {
+        let service = match self.matrix.services.get(&service_id) {
+            Some(service) => service,
+            None => return "".to_owned(),
+        };
+        match service.methods.get(&method_id) {
+            Some(method) => format!("({}.{})", service.service_name, method.method_name),
+            None => format!("({})", service.service_name),
+        }
+    }
+
+    /// 返回载荷中的问题，数据类型不完整时返回空
+    fn check_payload(&self, msg: &SomeipMessage) -> Vec<LayoutFinding> {
+        let types = match self
+            .matrix
+            .services
+            .get(&msg.service_id)
+            .and_then(|service| service.methods.get(&msg.method_id))
+            .and_then(|method| method.method_type.parameter_types(msg.message_type))
+        {
+            Some(types) => types,
+            None => return vec![],
+        };
+
+        let mut decoder = Decoder::new(self.matrix, &msg.payload);
+        let mut findings = vec![];
+        match types
+            .iter()
+            .try_for_each(|name| decoder.decode_next(name).map(|_| ()))
+        {
+            Ok(()) if decoder.position() < msg.payload.len() => {
+                findings.push(LayoutFinding::TrailingBytes)
+            }
+            Ok(()) => {}
+            Err(DecodeError::TooShort) => findings.push(LayoutFinding::PayloadTooShort),
+            Err(DecodeError::UnknownType) => return vec![],
+        }
+        findings.extend(decoder.out_of_bounds().iter().map(|bounds| {
+            LayoutFinding::LengthOutOfBounds {
+                data_type: bounds.data_type.clone(),
+                min: bounds.min,
+                max: bounds.max,
+            }
+        }));
+        findings
+    }
+}
+
+impl<'a> Analyzer for LayoutAnalyzer<'a> {
+    fn name(&self) -> &'static str {
+        "layout"
+    }
+
+    fn handle_message(&mut self, msg: &SomeipMessage) {
+        self.first_timestamp.get_or_insert(msg.timestamp);
+
+        if msg.protocol_version != 1 {
+            self.record(
+                msg,
+                LayoutFinding::WrongProtocolVersion(msg.protocol_version),
+            );
+        }
+        if msg.length < 8 {
+            self.record(msg, LayoutFinding::LengthTooSmall);
+            return;
+        }
+        match msg.available_length.cmp(&(msg.length as usize)) {
+            std::cmp::Ordering::Less => {
+                self.record(msg, LayoutFinding::Truncated);
+                // 载荷不完整，不再按照矩阵检查
+                return;
+            }
+            std::cmp::Ordering::Greater => self.record(msg, LayoutFinding::ExtraBytes),
+            std::cmp::Ordering::Equal => {}
+        }
+        if msg.sd.is_some() {
+            return;
+        }
+
+        for finding in self.check_payload(msg) {
+            self.record(msg, finding);
+        }
+    }
+
+    fn write_report(&self, w: &mut dyn Write) -> Result<(), MyError> {
+        let first_timestamp = self.first_timestamp.unwrap_or_default();
+
+        writeln!(
+            w,
+            "Payload layout: {} methods with findings",
+            self.findings.len()
+        )?;
+        for ((service_id, method_id), findings) in &self.findings {
+            writeln!(
+                w,
+                "0x{:04x}.0x{:04x}{}",
+                service_id,
+                method_id,
+                self.method_name(*service_id, *method_id)
+            )?;
+            for (finding, stats) in findings {
+                writeln!(
+                    w,
+                    "  {:>8}x  {:>12.6}s .. {:>12.6}s  {}",
+                    stats.count,
+                    stats
+                        .first_timestamp
+                        .saturating_sub(first_timestamp)
+                        .as_secs_f64(),
+                    stats
+                        .last_timestamp
+                        .saturating_sub(first_timestamp)
+                        .as_secs_f64(),
+                    finding
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 服务0x1234的方法0x0001（SetName）按给定的参数类型发送，矩阵中有uint16与Name两个数据类型
+#[cfg(test)]
+fn layout_matrix(data_in: &[&str]) -> Matrix {
+    use crate::analyzers::{test_data_type, test_ff_method, test_matrix};
+    use crate::matrix::types::{MatrixType, NumberType, StringArrayLength, StringEncoding};
+
+    let mut matrix = test_matrix(vec![test_ff_method(1, "SetName", data_in)]);
+    test_data_type(
+        &mut matrix,
+        "uint16",
+        MatrixType::Number {
+            size: NumberType::Uint16,
+        },
+    );
+    test_data_type(
+        &mut matrix,
+        "Name",
+        MatrixType::String {
+            length: StringArrayLength::DYNAMIC(1, 4),
+            encoding: StringEncoding::UTF8,
+        },
+    );
+    matrix
+}
+
+/// 0x1234.0x0001的RequestNoReturn
+#[cfg(test)]
+fn layout_request() -> SomeipMessage {
+    use crate::analyzers::test_message;
+    use crate::types::SomeipMessageType;
+
+    test_message(
+        0,
+        "10.0.0.1:40000",
+        "10.0.0.2:30501",
+        SomeipMessageType::RequestWithoutResponse,
+        0x1234,
+        1,
+    )
+}
+
+#[test]
+fn layout_findings() {
+    use crate::analyzers::test_payload;
+
+    let matrix = layout_matrix(&["uint16", "Name"]);
+    let mut analyzer = LayoutAnalyzer::new(&matrix);
+
+    // 符合矩阵：uint16 + 长度字段(1字节) + "ab"
+    analyzer.handle_message(&test_payload(layout_request(), &[0, 1, 2, b'a', b'b']));
+    assert!(analyzer.findings.is_empty());
+
+    analyzer.handle_message(&test_payload(
+        layout_request(),
+        &[0, 1, 2, b'a', b'b', 0xFF],
+    ));
+    analyzer.handle_message(&test_payload(layout_request(), &[0, 1, 5, b'a']));
+    analyzer.handle_message(&test_payload(
+        layout_request(),
+        &[0, 1, 5, b'a', b'b', b'c', b'd', b'e'],
+    ));
+    let mut msg = test_payload(layout_request(), &[0, 1, 2, b'a', b'b']);
+    msg.protocol_version = 2;
+    msg.available_length -= 2;
+    analyzer.handle_message(&msg);
+    let mut msg = test_payload(layout_request(), &[]);
+    msg.length = 4;
+    analyzer.handle_message(&msg);
+
+    let findings: Vec<LayoutFinding> = analyzer.findings[&(0x1234, 1)].keys().cloned().collect();
+    assert_eq!(
+        findings,
+        vec![
+            LayoutFinding::LengthTooSmall,
+            LayoutFinding::Truncated,
+            LayoutFinding::WrongProtocolVersion(2),
+            LayoutFinding::PayloadTooShort,
+            LayoutFinding::TrailingBytes,
+            LayoutFinding::LengthOutOfBounds {
+                data_type: "Name".to_owned(),
+                min: 1,
+                max: 4
+            },
+        ]
+    );
+}
+
+#[test]
+fn empty_struct_element() {
+    use crate::analyzers::{test_data_type, test_payload};
+    use crate::matrix::types::{MatrixMember, MatrixType, StringArrayLength};
+
+    let mut matrix = layout_matrix(&["EmptyList", "uint16"]);
+    test_data_type(&mut matrix, "Empty", MatrixType::Struct { members: vec![] });
+    test_data_type(
+        &mut matrix,
+        "EmptyList",
+        MatrixType::Array {
+            length: StringArrayLength::DYNAMIC(0, 8),
+            member: MatrixMember {
+                member_data_type: "Empty".to_owned(),
+                ..Default::default()
+            },
+        },
+    );
+    let mut analyzer = LayoutAnalyzer::new(&matrix);
+    // 数组长度2字节，但元素不占字节，之后是uint16
+    analyzer.handle_message(&test_payload(layout_request(), &[2, 0xAA, 0xBB, 0, 1]));
+    assert!(analyzer.findings.is_empty());
+}
+
+#[test]
+fn payload_not_checked() {
+    use crate::analyzers::{test_payload, test_sd_entry, test_sd_message};
+    use crate::types::SomeipSdEntryType;
+
+    let matrix = layout_matrix(&["uint16", "Unknown"]);
+    let mut analyzer = LayoutAnalyzer::new(&matrix);
+
+    // 数据类型不完整、矩阵中没有的方法、SD报文都不检查载荷
+    analyzer.handle_message(&test_payload(layout_request(), &[0, 1]));
+    let mut msg = test_payload(layout_request(), &[0, 1]);
+    msg.method_id = 2;
+    analyzer.handle_message(&msg);
+    let mut msg = test_sd_message(
+        0,
+        "10.0.0.2:30490",
+        "239.0.0.1:30490",
+        1,
+        false,
+        test_sd_entry(SomeipSdEntryType::OfferService),
+    );
+    msg.length = 8 + 16;
+    msg.available_length = msg.length as usize;
+    analyzer.handle_message(&msg);
+    assert!(analyzer.findings.is_empty());
+
+    // 头部的检查不依赖矩阵
+    let mut msg = test_payload(layout_request(), &[0, 1]);
+    msg.method_id = 2;
+    msg.available_length += 3;
+    analyzer.handle_message(&msg);
+    assert_eq!(
+        analyzer.findings[&(0x1234, 2)]
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>(),
+        vec![LayoutFinding::ExtraBytes]
+    );
+}
+
+#[test]
+fn layout_report() {
+    use crate::analyzers::{test_payload, test_report};
+
+    let matrix = layout_matrix(&["uint16", "Name"]);
+    let mut analyzer = LayoutAnalyzer::new(&matrix);
+    for ts_ms in [1000, 1250] {
+        let mut msg = test_payload(layout_request(), &[0, 1, 2, b'a', b'b', 0xFF]);
+        msg.timestamp = Duration::from_millis(ts_ms);
+        analyzer.handle_message(&msg);
+    }
+
+    assert_eq!(
+        test_report(&analyzer),
+        "Payload layout: 1 methods with findings\n\
+         0x1234.0x0001(Climate.SetName)\n\
+         \x20        2x      0.000000s ..     0.250000s  trailing bytes after parameters\n"
+    );
+}
@@ -0,0 +1,575 @@
+/// 报文类型一致性检查：按照矩阵中方法的类型判断报文类型是否合法
+/// RR方法：Request -> Response/Error；FF方法：只有RequestNoReturn；Event：只有Notification
+/// Field：Getter与Setter同RR方法，Notifier同Event
+/// 另外检查传输层协议是否与矩阵一致，以及需要应答的Request在超时时间内是否收到了应答
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::Write;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use crate::errors::MyError;
+use crate::matrix::types::{Matrix, MatrixServiceMethodFieldType, MatrixServiceMethodType};
+use crate::types::{
+    Port, SomeipClientId, SomeipMessage, SomeipMessageType, SomeipMethodId, SomeipServiceId,
+    SomeipSessionId, SomeipTransportPortocol,
+};
+
+use super::{Analyzer, FindingStats};
+
+/// 超过该时间仍未收到应答的Request认为没有应答，抓包结束前不足该时间的不计入
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MessageTypeFinding {
+    /// 服务在矩阵中定义了方法，但没有该方法
+    UnknownMethod,
+    UnexpectedMessageType {
+        method_kind: &'static str,
+        message_type: SomeipMessageType,
+    },
+    WrongTransport {
+        expected: SomeipTransportPortocol,
+        actual: SomeipTransportPortocol,
+    },
+    MissingResponse {
+        method_kind: &'static str,
+    },
+}
+
+impl fmt::Display for MessageTypeFinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MessageTypeFinding::UnknownMethod => write!(f, "method not in matrix"),
+            MessageTypeFinding::UnexpectedMessageType {
+                method_kind,
+                message_type,
+            } => write!(f, "{} on {}", message_type, method_kind),
+            MessageTypeFinding::WrongTransport { expected, actual } => {
+                write!(f, "sent over {:?}, expected {:?}", actual, expected)
+            }
+            MessageTypeFinding::MissingResponse { method_kind } => {
+                write!(f, "request on {} without response", method_kind)
+            }
+        }
+    }
+}
+
+/// 该类型的方法允许出现的报文类型，以及Request是否需要应答
+fn allowed_message_types(
+    method_type: &MatrixServiceMethodType,
+) -> (&'static [SomeipMessageType], bool) {
+    const REQUEST_RESPONSE: &[SomeipMessageType] = &[
+        SomeipMessageType::Request,
+        SomeipMessageType::Response,
+        SomeipMessageType::ResponseWithError,
+    ];
+    match method_type {
+        MatrixServiceMethodType::RRMethod { .. } => (REQUEST_RESPONSE, true),
+        MatrixServiceMethodType::FFMethod { .. } => {
+            (&[SomeipMessageType::RequestWithoutResponse], false)
+        }
+        MatrixServiceMethodType::EVENT { .. } => (&[SomeipMessageType::Notification], false),
+        MatrixServiceMethodType::FIELD { field_type, .. } => match field_type {
+            MatrixServiceMethodFieldType::Getter | MatrixServiceMethodFieldType::Setter => {
+                (REQUEST_RESPONSE, true)
+            }
+            MatrixServiceMethodFieldType::Notifier => (&[SomeipMessageType::Notification], false),
+        },
+    }
+}
+
+/// 客户端端点 + MessageID + RequestID 唯一确定一次调用
+type CallKey = (
+    IpAddr,
+    Port,
+    SomeipServiceId,
+    SomeipMethodId,
+    SomeipClientId,
+    SomeipSessionId,
+);
+
+type MethodFindings =
+    BTreeMap<(SomeipServiceId, SomeipMethodId), BTreeMap<MessageTypeFinding, FindingStats>>;
+
+pub struct MessageTypeAnalyzer<'a> {
+    matrix: &'a Matrix,
+    first_timestamp: Option<Duration>,
+    last_timestamp: Duration,
+    /// 等待应答的Request，值为发出的时间
+    pending: HashMap<CallKey, Duration>,
+    findings: MethodFindings,
+}
+
+impl<'a> MessageTypeAnalyzer<'a> {
+    pub fn new(matrix: &'a Matrix) -> Self {
+        MessageTypeAnalyzer {
+            matrix,
+            first_timestamp: None,
+            last_timestamp: Duration::ZERO,
+            pending: HashMap::new(),
+            findings: BTreeMap::new(),
+        }
+    }
+
+    fn record(
+        findings: &mut MethodFindings,
+        timestamp: Duration,
+        key: (SomeipServiceId, SomeipMethodId),
+        finding: MessageTypeFinding,
+    ) {
+        findings
+            .entry(key)
+            .or_default()
+            .entry(finding)
+            .and_modify(|stats| stats.update(timestamp))
+            .or_insert(FindingStats::new(timestamp));
+    }
+
+    /// 把超时的Request记为没有应答
+    fn expire_pending(&mut self, now: Duration) {
+        let matrix = self.matrix;
+        let findings = &mut self.findings;
+        self.pending.retain(|key, sent| {
+            if now.saturating_sub(*sent) < RESPONSE_TIMEOUT {
+                return true;
+            }
+            if let Some(method) = matrix
+                .services
+                .get(&key.2)
+                .and_then(|service| service.methods.get(&key.3))
+            {
+                Self::record(
+                    findings,
+                    *sent,
+                    (key.2, key.3),
+                    MessageTypeFinding::MissingResponse {
+                        method_kind: method.method_type.kind_name(),
+                    },
+                );
+            }
+            false
+        });
+    }
+
+    fn method_name(&self, service_id: SomeipServiceId, method_id: SomeipMethodId) -> String {
+        let service = match self.matrix.services.get(&service_id) {
+            Some(service) => service,
+            None => return "".to_owned(),
+        };
+        match service.methods.get(&method_id) {
+            Some(method) => format!("({}.{})", service.service_name, method.method_name),
+            None => format!("({})", service.service_name),
+        }
+    }
+}
+
+impl<'a> Analyzer for MessageTypeAnalyzer<'a> {
+    fn name(&self) -> &'static str {
+        "message-type"
+    }
+
+    fn handle_message(&mut self, msg: &SomeipMessage) {
+        self.first_timestamp.get_or_insert(msg.timestamp);
+        self.last_timestamp = msg.timestamp;
+        self.expire_pending(msg.timestamp);
+
+        if msg.sd.is_some() {
+            return;
+        }
+        // 矩阵中没有的服务由部署检查负责，没有方法定义的服务无从检查
+        let service = match self.matrix.services.get(&msg.service_id) {
+            Some(service) if !service.methods.is_empty() => service,
+            _ => return,
+        };
+        let key = (msg.service_id, msg.method_id);
+        let method = match service.methods.get(&msg.method_id) {
+            Some(method) => method,
+            None => {
+                Self::record(
+                    &mut self.findings,
+                    msg.timestamp,
+                    key,
+                    MessageTypeFinding::UnknownMethod,
+                );
+                return;
+            }
+        };
+
+        if method.transport_protocol != msg.transport_protocol {
+            Self::record(
+                &mut self.findings,
+                msg.timestamp,
+                key,
+                MessageTypeFinding::WrongTransport {
+                    expected: method.transport_protocol,
+                    actual: msg.transport_protocol,
+                },
+            );
+        }
+
+        let (allowed, need_response) = allowed_message_types(&method.method_type);
+        if !allowed.contains(&msg.message_type) {
+            Self::record(
+                &mut self.findings,
+                msg.timestamp,
+                key,
+                MessageTypeFinding::UnexpectedMessageType {
+                    method_kind: method.method_type.kind_name(),
+                    message_type: msg.message_type,
+                },
+            );
+        }
+
+        match msg.message_type {
+            SomeipMessageType::Request if need_response => {
+                self.pending.insert(
+                    (
+                        msg.source.ip_addr,
+                        msg.source.port,
+                        msg.service_id,
+                        msg.method_id,
+                        msg.client_id,
+                        msg.session_id,
+                    ),
+                    msg.timestamp,
+                );
+            }
+            SomeipMessageType::Response | SomeipMessageType::ResponseWithError => {
+                self.pending.remove(&(
+                    msg.destination.ip_addr,
+                    msg.destination.port,
+                    msg.service_id,
+                    msg.method_id,
+                    msg.client_id,
+                    msg.session_id,
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    fn write_report(&self, w: &mut dyn Write) -> Result<(), MyError> {
+        let first_timestamp = self.first_timestamp.unwrap_or_default();
+
+        // 抓包结束时仍在等待的Request，超时的才计入
+        let mut findings = self.findings.clone();
+        for (key, sent) in &self.pending {
+            if self.last_timestamp.saturating_sub(*sent) < RESPONSE_TIMEOUT {
+                continue;
+            }
+            if let Some(method) = self
+                .matrix
+                .services
+                .get(&key.2)
+                .and_then(|service| service.methods.get(&key.3))
+            {
+                Self::record(
+                    &mut findings,
+                    *sent,
+                    (key.2, key.3),
+                    MessageTypeFinding::MissingResponse {
+                        method_kind: method.method_type.kind_name(),
+                    },
+                );
+            }
+        }
+
+        writeln!(
+            w,
+            "Message type conformance: {} methods with findings",
+            findings.len()
+        )?;
+        for ((service_id, method_id), findings) in &findings {
+            writeln!(
+                w,
+                "0x{:04x}.0x{:04x}{}",
+                service_id,
+                method_id,
+                self.method_name(*service_id, *method_id)
+            )?;
+            for (finding, stats) in findings {
+                writeln!(
+                    w,
+                    "  {:>8}x  {:>12.6}s .. {:>12.6}s  {}",
+                    stats.count,
+                    stats
+                        .first_timestamp
+                        .saturating_sub(first_timestamp)
+                        .as_secs_f64(),
+                    stats
+                        .last_timestamp
+                        .saturating_sub(first_timestamp)
+                        .as_secs_f64(),
+                    finding
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn message_type_findings() {
+    use crate::analyzers::{test_ff_method, test_field, test_matrix, test_message, test_rr_method};
+
+    let matrix = test_matrix(vec![
+        test_rr_method(1, "Calibrate", &[], ""),
+        test_ff_method(2, "Reset", &[]),
+        test_field(3, "Temperature", MatrixServiceMethodFieldType::Setter, ""),
+    ]);
+    let mut analyzer = MessageTypeAnalyzer::new(&matrix);
+
+    let client = "10.0.0.1:40000";
+    let server = "10.0.0.2:30501";
+    // 正常的一问一答
+    analyzer.handle_message(&test_message(
+        0,
+        client,
+        server,
+        SomeipMessageType::Request,
+        0x1234,
+        1,
+    ));
+    analyzer.handle_message(&test_message(
+        10,
+        server,
+        client,
+        SomeipMessageType::Response,
+        0x1234,
+        1,
+    ));
+    // RR方法上的Notification
+    analyzer.handle_message(&test_message(
+        20,
+        server,
+        client,
+        SomeipMessageType::Notification,
+        0x1234,
+        1,
+    ));
+    // FF方法的应答
+    analyzer.handle_message(&test_message(
+        30,
+        server,
+        client,
+        SomeipMessageType::Response,
+        0x1234,
+        2,
+    ));
+    // Setter没有应答
+    analyzer.handle_message(&test_message(
+        40,
+        client,
+        server,
+        SomeipMessageType::Request,
+        0x1234,
+        3,
+    ));
+    // 未定义的方法与错误的传输层协议
+    analyzer.handle_message(&test_message(
+        50,
+        server,
+        client,
+        SomeipMessageType::Notification,
+        0x1234,
+        0x8009,
+    ));
+    let mut msg = test_message(
+        60,
+        client,
+        server,
+        SomeipMessageType::RequestWithoutResponse,
+        0x1234,
+        2,
+    );
+    msg.transport_protocol = SomeipTransportPortocol::TCP;
+    analyzer.handle_message(&msg);
+    analyzer.handle_message(&test_message(
+        2000,
+        client,
+        server,
+        SomeipMessageType::RequestWithoutResponse,
+        0x1234,
+        2,
+    ));
+
+    let findings = |method_id: SomeipMethodId| {
+        analyzer.findings[&(0x1234, method_id)]
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        findings(1),
+        vec![MessageTypeFinding::UnexpectedMessageType {
+            method_kind: "method",
+            message_type: SomeipMessageType::Notification
+        }]
+    );
+    assert_eq!(
+        findings(2),
+        vec![
+            MessageTypeFinding::UnexpectedMessageType {
+                method_kind: "fire&forget method",
+                message_type: SomeipMessageType::Response
+            },
+            MessageTypeFinding::WrongTransport {
+                expected: SomeipTransportPortocol::UDP,
+                actual: SomeipTransportPortocol::TCP
+            }
+        ]
+    );
+    assert_eq!(
+        findings(3),
+        vec![MessageTypeFinding::MissingResponse {
+            method_kind: "field setter"
+        }]
+    );
+    assert_eq!(findings(0x8009), vec![MessageTypeFinding::UnknownMethod]);
+}
+
+#[test]
+fn response_timeout() {
+    use crate::analyzers::{
+        test_ff_method, test_field, test_matrix, test_message, test_report, test_rr_method,
+    };
+
+    let matrix = test_matrix(vec![
+        test_rr_method(1, "Calibrate", &[], ""),
+        test_ff_method(2, "Reset", &[]),
+        test_field(3, "Temperature", MatrixServiceMethodFieldType::Setter, ""),
+    ]);
+    let mut analyzer = MessageTypeAnalyzer::new(&matrix);
+    let client = "10.0.0.1:40000";
+    let server = "10.0.0.2:30501";
+
+    // 会话号不同的应答不算，错误应答也算作应答
+    let mut request = test_message(0, client, server, SomeipMessageType::Request, 0x1234, 1);
+    analyzer.handle_message(&request);
+    let mut response = test_message(10, server, client, SomeipMessageType::Response, 0x1234, 1);
+    response.session_id = 2;
+    analyzer.handle_message(&response);
+    request.session_id = 3;
+    analyzer.handle_message(&request);
+    let mut error = test_message(
+        20,
+        server,
+        client,
+        SomeipMessageType::ResponseWithError,
+        0x1234,
+        1,
+    );
+    error.session_id = 3;
+    analyzer.handle_message(&error);
+    assert!(analyzer.findings.is_empty());
+
+    // 抓包结束时不足超时时间的Request不计入
+    analyzer.handle_message(&test_message(
+        999,
+        client,
+        server,
+        SomeipMessageType::RequestWithoutResponse,
+        0x1234,
+        2,
+    ));
+    assert_eq!(
+        test_report(&analyzer),
+        "Message type conformance: 0 methods with findings\n"
+    );
+    analyzer.handle_message(&test_message(
+        1000,
+        client,
+        server,
+        SomeipMessageType::RequestWithoutResponse,
+        0x1234,
+        2,
+    ));
+    assert_eq!(
+        test_report(&analyzer),
+        "Message type conformance: 1 methods with findings\n\
+         0x1234.0x0001(Climate.Calibrate)\n\
+         \x20        1x      0.000000s ..     0.000000s  request on method without response\n"
+    );
+}
+
+#[test]
+fn notifications_and_sd() {
+    use crate::analyzers::{
+        test_ff_method, test_field, test_matrix, test_message, test_rr_method, test_sd_entry,
+        test_sd_message, test_service,
+    };
+    use crate::types::SomeipSdEntryType;
+
+    let mut matrix = test_matrix(vec![
+        test_rr_method(1, "Calibrate", &[], ""),
+        test_ff_method(2, "Reset", &[]),
+        test_field(3, "Temperature", MatrixServiceMethodFieldType::Setter, ""),
+        test_field(
+            0x8001,
+            "TemperatureChanged",
+            MatrixServiceMethodFieldType::Notifier,
+            "",
+        ),
+    ]);
+    // 没有方法定义的服务不检查
+    test_service(&mut matrix, 0x5678, "Empty", vec![]);
+    let mut analyzer = MessageTypeAnalyzer::new(&matrix);
+    let client = "10.0.0.1:40000";
+    let server = "10.0.0.2:30501";
+
+    analyzer.handle_message(&test_message(
+        0,
+        server,
+        client,
+        SomeipMessageType::Notification,
+        0x1234,
+        0x8001,
+    ));
+    analyzer.handle_message(&test_message(
+        1,
+        client,
+        server,
+        SomeipMessageType::Request,
+        0x5678,
+        1,
+    ));
+    analyzer.handle_message(&test_sd_message(
+        2,
+        "10.0.0.2:30490",
+        "239.0.0.1:30490",
+        1,
+        false,
+        test_sd_entry(SomeipSdEntryType::OfferService),
+    ));
+    assert!(analyzer.findings.is_empty());
+
+    // Notifier上的Request，Notifier不需要应答
+    analyzer.handle_message(&test_message(
+        3,
+        client,
+        server,
+        SomeipMessageType::Request,
+        0x1234,
+        0x8001,
+    ));
+    analyzer.handle_message(&test_message(
+        5000,
+        server,
+        client,
+        SomeipMessageType::Notification,
+        0x1234,
+        0x8001,
+    ));
+    assert_eq!(
+        analyzer.findings[&(0x1234, 0x8001)]
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>(),
+        vec![MessageTypeFinding::UnexpectedMessageType {
+            method_kind: "field notifier",
+            message_type: SomeipMessageType::Request
+        }]
+    );
+    assert_eq!(analyzer.findings.len(), 1);
+}
@@ -0,0 +1,429 @@
+pub mod bandwidth;
+pub mod compare;
+pub mod coverage;
+pub mod deployment;
+pub mod discovery;
+pub mod e2e;
+pub mod excel_report;
+pub mod field;
+pub mod layout;
+pub mod message_type;
+pub mod return_code;
+pub mod scenario;
+pub mod sd_timing;
+pub mod security;
+pub mod session;
+pub mod version;
+
+use std::io::Write;
+use std::time::Duration;
+
+use log::info;
+
+use crate::errors::MyError;
+use crate::matrix::types::Matrix;
+use crate::parsers::first_step_parser::PacketParser;
+use crate::sinks::Sink;
+use crate::sources::{RawPacket, Source};
+use crate::types::SomeipMessage;
+
+/// 所有分析功能都按照同样的方式工作：逐条接收解析出的SomeIP报文，全部处理完成后输出报告
+pub trait Analyzer {
+    /// 与命令行中--analyze的取值一致
+    fn name(&self) -> &'static str;
+    fn handle_message(&mut self, msg: &SomeipMessage);
+    fn write_report(&self, w: &mut dyn Write) -> Result<(), MyError>;
+    /// 供看板等程序读取的报告，不支持的分析器返回None
+    fn json_report(&self) -> Option<serde_json::Value> {
+        None
+    }
+}
+
+/// 按照--analyze中的名字创建分析器，需要额外参数的分析器使用缺省参数
+pub fn by_name<'a>(name: &str, matrix: &'a Matrix) -> Option<Box<dyn Analyzer + 'a>> {
+    Some(match name {
+        "session" => Box::new(session::SessionAnalyzer::new(matrix)),
+        "deployment" => Box::new(deployment::DeploymentAnalyzer::new(matrix)),
+        "message-type" => Box::new(message_type::MessageTypeAnalyzer::new(matrix)),
+        "coverage" => Box::new(coverage::CoverageAnalyzer::new(matrix)),
+        "e2e" => Box::new(e2e::E2eAnalyzer::new(matrix)),
+        "sd-timing" => Box::new(sd_timing::SdTimingAnalyzer::new(matrix)),
+        "layout" => Box::new(layout::LayoutAnalyzer::new(matrix)),
+        "version" => Box::new(version::VersionAnalyzer::new(matrix)),
+        "field" => Box::new(field::FieldAnalyzer::new(matrix, None, None)),
+        "return-code" => Box::new(return_code::ReturnCodeAnalyzer::new(matrix)),
+        "security" => Box::new(security::SecurityAnalyzer::new(matrix)),
+        "bandwidth" => Box::new(bandwidth::BandwidthAnalyzer::new(
+            matrix,
+            Duration::from_secs(1),
+            10,
+        )),
+        _ => return None,
+    })
+}
+
+/// 同一类问题只记录出现次数与首次、末次出现的时间
+#[derive(Debug, Clone, Copy)]
+pub struct FindingStats {
+    pub count: usize,
+    pub first_timestamp: Duration,
+    pub last_timestamp: Duration,
+}
+
+impl FindingStats {
+    pub fn new(timestamp: Duration) -> Self {
+        FindingStats {
+            count: 1,
+            first_timestamp: timestamp,
+            last_timestamp: timestamp,
+        }
+    }
+
+    pub fn update(&mut self, timestamp: Duration) {
+        self.count += 1;
+        self.last_timestamp = timestamp;
+    }
+}
+
+/// 从数据源读取全部报文，解析后依次交给每个分析器与输出
+/// 输出的finish由调用方在全部数据源处理完成后调用
+pub fn run(
+    source: Source,
+    analyzers: &mut [&mut dyn Analyzer],
+    sinks: &mut [&mut dyn Sink],
+) -> Result<(), MyError> {
+    let mut parser = PacketParser::new(source.channel_type());
+    for sink in sinks.iter_mut() {
+        sink.start(source.channel_type())?;
+    }
+    let (send_data, recv_data) = crossbeam_channel::bounded::<RawPacket>(1024);
+    let handle = source.start(send_data)?;
+
+    let mut count = 0;
+    for raw in recv_data {
+        for sink in sinks.iter_mut() {
+            sink.write_packet(&raw)?;
+        }
+        for msg in parser.parse(raw.packet_index, raw.timestamp, &raw.data) {
+            count += 1;
+            for analyzer in analyzers.iter_mut() {
+                analyzer.handle_message(&msg);
+            }
+            for sink in sinks.iter_mut() {
+                sink.write_message(&msg)?;
+            }
+        }
+    }
+    handle
+        .join()
+        .map_err(|_| MyError::Custom("source thread panicked".to_owned()))?;
+    info!("parsed {} someip messages", count);
+
+    Ok(())
+}
+
+/// 测试用：把分析器的文本报告写到字符串中
+#[cfg(test)]
+pub(crate) fn test_report(analyzer: &dyn Analyzer) -> String {
+    let mut report = vec![];
+    analyzer.write_report(&mut report).unwrap();
+    String::from_utf8(report).unwrap()
+}
+
+/// 测试用：按照 "ip:port" 构造一条普通报文
+#[cfg(test)]
+pub(crate) fn test_message(
+    ts_ms: u64,
+    source: &str,
+    destination: &str,
+    message_type: crate::types::SomeipMessageType,
+    service_id: crate::types::SomeipServiceId,
+    method_id: crate::types::SomeipMethodId,
+) -> SomeipMessage {
+    use crate::types::{SomeipEndpoint, SomeipTransportPortocol};
+    use std::net::SocketAddr;
+
+    let endpoint = |s: &str| {
+        let addr: SocketAddr = s.parse().unwrap();
+        SomeipEndpoint {
+            mac_addr: Default::default(),
+            ip_addr: addr.ip(),
+            port: addr.port(),
+        }
+    };
+    SomeipMessage {
+        packet_index: 0,
+        frames: vec![0],
+        timestamp: std::time::Duration::from_millis(ts_ms),
+        source: endpoint(source),
+        destination: endpoint(destination),
+        vlan_id: None,
+        message_type,
+        service_id,
+        method_id,
+        client_id: 0,
+        session_id: 1,
+        protocol_version: 1,
+        interface_version: 1,
+        length: 8,
+        available_length: 8,
+        return_code: 0,
+        transport_protocol: SomeipTransportPortocol::UDP,
+        sd: None,
+        payload: vec![],
+    }
+}
+
+/// 测试用：替换报文的载荷，Length与收到的字节数都与载荷一致
+#[cfg(test)]
+pub(crate) fn test_payload(mut msg: SomeipMessage, payload: &[u8]) -> SomeipMessage {
+    msg.payload = payload.to_vec();
+    msg.length = payload.len() as u32 + 8;
+    msg.available_length = msg.length as usize;
+    msg
+}
+
+/// 测试用：一条SD报文，只有给定的条目
+#[cfg(test)]
+pub(crate) fn test_sd_message(
+    ts_ms: u64,
+    source: &str,
+    destination: &str,
+    session_id: crate::types::SomeipSessionId,
+    reboot_flag: bool,
+    entry: crate::types::SomeipSdEntry,
+) -> SomeipMessage {
+    use crate::types::{SomeipMessageType, SomeipSdHeader};
+
+    let mut msg = test_message(
+        ts_ms,
+        source,
+        destination,
+        SomeipMessageType::Notification,
+        0xFFFF,
+        0x8100,
+    );
+    msg.session_id = session_id;
+    msg.sd = Some(SomeipSdHeader {
+        reboot_flag,
+        unicast_flag: true,
+        entries: vec![entry],
+    });
+    msg
+}
+
+/// 测试用：服务0x1234、实例1、版本1.0、事件组1、TTL 3秒的SD条目，没有端点
+#[cfg(test)]
+pub(crate) fn test_sd_entry(
+    entry_type: crate::types::SomeipSdEntryType,
+) -> crate::types::SomeipSdEntry {
+    crate::types::SomeipSdEntry {
+        entry_type,
+        service_id: 0x1234,
+        instance_id: 1,
+        major_version: 1,
+        ttl: 3,
+        minor_version: 0,
+        eventgroup_id: 1,
+        counter: 0,
+        endpoints: vec![],
+    }
+}
+
+/// 测试用：在矩阵中加入一个角色，MAC地址为 00:01:02:03:04:05
+#[cfg(test)]
+pub(crate) fn test_role(matrix: &mut Matrix, name: &str, ip_addr: &str) {
+    matrix.roles.insert(
+        name.to_owned(),
+        crate::matrix::types::MatrixRole {
+            name: name.to_owned(),
+            ip_addr: ip_addr.parse().unwrap(),
+            mac_addr: [0, 1, 2, 3, 4, 5],
+        },
+    );
+}
+
+/// 测试用：HU（10.0.0.1）作为客户端、TBOX（10.0.0.2）作为服务端在30501端口部署服务0x1234，
+/// 矩阵中还没有该服务时加入名为Climate的空服务
+#[cfg(test)]
+pub(crate) fn test_deployment(matrix: &mut Matrix) {
+    use crate::matrix::types::{MatrixRoleServerClientPair, MatrixService};
+
+    test_role(matrix, "HU", "10.0.0.1");
+    test_role(matrix, "TBOX", "10.0.0.2");
+    let service = matrix
+        .services
+        .entry(0x1234)
+        .or_insert_with(|| MatrixService {
+            service_id: 0x1234,
+            service_name: "Climate".to_owned(),
+            ..Default::default()
+        });
+    service.server_client = vec![MatrixRoleServerClientPair {
+        server: "TBOX".to_owned(),
+        server_port: 30501,
+        client: "HU".to_owned(),
+        ..Default::default()
+    }]
+    .into();
+}
+
+/// 测试用：UDP方法，没有周期、E2E与应用错误
+#[cfg(test)]
+pub(crate) fn test_method(
+    method_id: crate::types::SomeipMethodId,
+    method_name: &str,
+    method_type: crate::matrix::types::MatrixServiceMethodType,
+) -> crate::matrix::types::MatrixServiceMethod {
+    crate::matrix::types::MatrixServiceMethod {
+        method_id,
+        method_name: method_name.to_owned(),
+        method_type,
+        transport_protocol: crate::types::SomeipTransportPortocol::UDP,
+        cycle_time_ms: None,
+        e2e: None,
+        application_errors: vec![],
+        mother_service_ref: Default::default(),
+    }
+}
+
+/// 测试用：一问一答的方法
+#[cfg(test)]
+pub(crate) fn test_rr_method(
+    method_id: crate::types::SomeipMethodId,
+    method_name: &str,
+    data_in: &[&str],
+    data_out: &str,
+) -> crate::matrix::types::MatrixServiceMethod {
+    test_method(
+        method_id,
+        method_name,
+        crate::matrix::types::MatrixServiceMethodType::RRMethod {
+            data_in: data_in.iter().map(|data| data.to_string()).collect(),
+            data_in_ref: vec![],
+            data_out: data_out.to_owned(),
+            data_out_ref: None,
+        },
+    )
+}
+
+/// 测试用：没有应答的方法
+#[cfg(test)]
+pub(crate) fn test_ff_method(
+    method_id: crate::types::SomeipMethodId,
+    method_name: &str,
+    data_in: &[&str],
+) -> crate::matrix::types::MatrixServiceMethod {
+    test_method(
+        method_id,
+        method_name,
+        crate::matrix::types::MatrixServiceMethodType::FFMethod {
+            data_in: data_in.iter().map(|data| data.to_string()).collect(),
+            data_in_ref: vec![],
+        },
+    )
+}
+
+/// 测试用：字段的Getter、Setter或Notifier
+#[cfg(test)]
+pub(crate) fn test_field(
+    method_id: crate::types::SomeipMethodId,
+    method_name: &str,
+    field_type: crate::matrix::types::MatrixServiceMethodFieldType,
+    data: &str,
+) -> crate::matrix::types::MatrixServiceMethod {
+    test_method(
+        method_id,
+        method_name,
+        crate::matrix::types::MatrixServiceMethodType::FIELD {
+            field_type,
+            data: data.to_owned(),
+            data_ref: None,
+        },
+    )
+}
+
+/// 测试用：事件
+#[cfg(test)]
+pub(crate) fn test_event(
+    method_id: crate::types::SomeipMethodId,
+    method_name: &str,
+    data_out: &str,
+) -> crate::matrix::types::MatrixServiceMethod {
+    test_method(
+        method_id,
+        method_name,
+        crate::matrix::types::MatrixServiceMethodType::EVENT {
+            data_out: data_out.to_owned(),
+            data_out_ref: None,
+        },
+    )
+}
+
+/// 测试用：在矩阵中加入一个服务，其余属性为缺省值
+#[cfg(test)]
+pub(crate) fn test_service(
+    matrix: &mut Matrix,
+    service_id: crate::types::SomeipServiceId,
+    service_name: &str,
+    methods: Vec<crate::matrix::types::MatrixServiceMethod>,
+) {
+    matrix.services.insert(
+        service_id,
+        crate::matrix::types::MatrixService {
+            service_id,
+            service_name: service_name.to_owned(),
+            methods: methods
+                .into_iter()
+                .map(|method| (method.method_id, method))
+                .collect(),
+            ..Default::default()
+        },
+    );
+}
+
+/// 测试用：只有服务0x1234（Climate）的矩阵
+#[cfg(test)]
+pub(crate) fn test_matrix(methods: Vec<crate::matrix::types::MatrixServiceMethod>) -> Matrix {
+    let mut matrix = Matrix::default();
+    test_service(&mut matrix, 0x1234, "Climate", methods);
+    matrix
+}
+
+/// 测试用：在服务0x1234中加入一个事件组
+#[cfg(test)]
+pub(crate) fn test_eventgroup(
+    matrix: &mut Matrix,
+    eventgroup_id: crate::types::SomeipEventgroupId,
+    eventgroup_name: &str,
+    methods: Vec<crate::types::SomeipMethodId>,
+) {
+    if let Some(service) = matrix.services.get_mut(&0x1234) {
+        service.eventgroups.insert(
+            eventgroup_id,
+            crate::matrix::types::MatrixEventgroup {
+                eventgroup_id,
+                eventgroup_name: eventgroup_name.to_owned(),
+                methods,
+            },
+        );
+    }
+}
+
+/// 测试用：在矩阵中加入一个数据类型
+#[cfg(test)]
+pub(crate) fn test_data_type(
+    matrix: &mut Matrix,
+    name: &str,
+    data_type: crate::matrix::types::MatrixType,
+) {
+    matrix.data_types.insert(
+        name.to_owned(),
+        crate::matrix::types::MatrixDataNode {
+            name: name.to_owned(),
+            description: "".to_owned(),
+            data_type,
+            ..Default::default()
+        },
+    );
+}
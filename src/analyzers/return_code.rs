@@ -0,0 +1,312 @@
+/// 返回码统计：按方法统计应答的数量以及每种返回码出现的次数
+/// 0x00~0x0F为标准返回码，0x20~0x5E为矩阵中按方法定义的应用错误码，输出错误名
+/// 带错误的应答按照矩阵中的错误载荷类型解析，解析不了的保留十六进制
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::errors::MyError;
+use crate::matrix::types::{Matrix, MatrixServiceMethod};
+use crate::parsers::pnet_packet_someip;
+use crate::types::{
+    SomeipMessage, SomeipMessageType, SomeipMethodId, SomeipReturnCode, SomeipServiceId,
+};
+
+use super::{Analyzer, FindingStats};
+
+/// 每个返回码最多保留的错误载荷数量
+const MAX_PAYLOADS: usize = 20;
+
+#[derive(Debug, Clone)]
+pub struct ReturnCodeStats {
+    pub stats: FindingStats,
+    /// 带错误的应答的时间与载荷，只保留前MAX_PAYLOADS条
+    pub payloads: Vec<(Duration, Value)>,
+}
+
+impl ReturnCodeStats {
+    fn push_payload(&mut self, timestamp: Duration, payload: Option<Value>) {
+        if let Some(payload) = payload {
+            if self.payloads.len() < MAX_PAYLOADS {
+                self.payloads.push((timestamp, payload));
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MethodReturnCodes {
+    pub responses: usize,
+    pub codes: BTreeMap<SomeipReturnCode, ReturnCodeStats>,
+}
+
+impl MethodReturnCodes {
+    fn errors(&self) -> usize {
+        self.codes.values().map(|code| code.stats.count).sum()
+    }
+}
+
+/// 返回码的名字，应用错误码从矩阵中查找
+pub fn return_code_name(
+    method: Option<&MatrixServiceMethod>,
+    return_code: SomeipReturnCode,
+) -> String {
+    let name = match return_code {
+        0x00..=0x0F => pnet_packet_someip::SomeipReturnCode::new(return_code).to_string(),
+        0x10..=0x1F => "reserved".to_owned(),
+        0x20..=0x5E => match method.and_then(|method| method.application_error(return_code)) {
+            Some(error) => error.name.clone(),
+            None => "application error not in matrix".to_owned(),
+        },
+        _ => "invalid".to_owned(),
+    };
+    format!("0x{:02x} {}", return_code, name)
+}
+
+pub struct ReturnCodeAnalyzer<'a> {
+    matrix: &'a Matrix,
+    first_timestamp: Option<Duration>,
+    methods: BTreeMap<(SomeipServiceId, SomeipMethodId), MethodReturnCodes>,
+}
+
+impl<'a> ReturnCodeAnalyzer<'a> {
+    pub fn new(matrix: &'a Matrix) -> Self {
+        ReturnCodeAnalyzer {
+            matrix,
+            first_timestamp: None,
+            methods: BTreeMap::new(),
+        }
+    }
+
+    fn method(
+        &self,
+        service_id: SomeipServiceId,
+        method_id: SomeipMethodId,
+    ) -> Option<&MatrixServiceMethod> {
+        self.matrix
+            .services
+            .get(&service_id)
+            .and_then(|service| service.methods.get(&method_id))
+    }
+
+    fn method_name(&self, service_id: SomeipServiceId, method_id: SomeipMethodId) -> String {
+        self.method(service_id, method_id)
+            .map(|method| format!(" {}", method.method_name))
+            .unwrap_or_default()
+    }
+
+    /// 按照矩阵中的错误载荷类型解析，没有定义或者解析失败的输出十六进制
+    fn decode_error_payload(&self, msg: &SomeipMessage) -> Option<Value> {
+        let data = self
+            .method(msg.service_id, msg.method_id)
+            .and_then(|method| method.application_error(msg.return_code))
+            .map(|error| error.data.as_str())
+            .filter(|data| !data.is_empty());
+        if let Some(data) = data {
+            if let Ok(mut values) = self.matrix.decode_parameters(&[data], &msg.payload) {
+                return Some(values.remove(0).1);
+            }
+        }
+        match msg.payload.is_empty() {
+            true => None,
+            false => Some(Value::String(
+                msg.payload.iter().map(|b| format!("{:02x}", b)).collect(),
+            )),
+        }
+    }
+
+    fn relative(&self, timestamp: Duration) -> f64 {
+        timestamp
+            .saturating_sub(self.first_timestamp.unwrap_or_default())
+            .as_secs_f64()
+    }
+}
+
+impl<'a> Analyzer for ReturnCodeAnalyzer<'a> {
+    fn name(&self) -> &'static str {
+        "return-code"
+    }
+
+    fn handle_message(&mut self, msg: &SomeipMessage) {
+        self.first_timestamp.get_or_insert(msg.timestamp);
+        if !matches!(
+            msg.message_type,
+            SomeipMessageType::Response | SomeipMessageType::ResponseWithError
+        ) {
+            return;
+        }
+        let payload = match msg.message_type {
+            SomeipMessageType::ResponseWithError => self.decode_error_payload(msg),
+            _ => None,
+        };
+        let method = self
+            .methods
+            .entry((msg.service_id, msg.method_id))
+            .or_default();
+        method.responses += 1;
+        // 正常的应答不计入
+        if msg.return_code == 0 && msg.message_type == SomeipMessageType::Response {
+            return;
+        }
+        method
+            .codes
+            .entry(msg.return_code)
+            .and_modify(|code| code.stats.update(msg.timestamp))
+            .or_insert(ReturnCodeStats {
+                stats: FindingStats::new(msg.timestamp),
+                payloads: vec![],
+            })
+            .push_payload(msg.timestamp, payload);
+    }
+
+    fn write_report(&self, w: &mut dyn Write) -> Result<(), MyError> {
+        let methods: Vec<_> = self
+            .methods
+            .iter()
+            .filter(|(_, method)| !method.codes.is_empty())
+            .collect();
+        writeln!(w, "Return codes: {} methods with errors", methods.len())?;
+        for ((service_id, method_id), codes) in methods {
+            writeln!(
+                w,
+                "0x{:04x}.0x{:04x}{}: {} responses, {} errors",
+                service_id,
+                method_id,
+                self.method_name(*service_id, *method_id),
+                codes.responses,
+                codes.errors()
+            )?;
+            let method = self.method(*service_id, *method_id);
+            for (return_code, code) in &codes.codes {
+                writeln!(
+                    w,
+                    "  {:>8}x  {:>12.6}s .. {:>12.6}s  {}",
+                    code.stats.count,
+                    self.relative(code.stats.first_timestamp),
+                    self.relative(code.stats.last_timestamp),
+                    return_code_name(method, *return_code)
+                )?;
+                for (timestamp, payload) in &code.payloads {
+                    writeln!(
+                        w,
+                        "  {:>12.6}s  payload: {}",
+                        self.relative(*timestamp),
+                        payload
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn json_report(&self) -> Option<Value> {
+        let methods: Vec<Value> = self
+            .methods
+            .iter()
+            .map(|((service_id, method_id), codes)| {
+                let method = self.method(*service_id, *method_id);
+                let errors: Vec<Value> = codes
+                    .codes
+                    .iter()
+                    .map(|(return_code, code)| {
+                        json!({
+                            "return_code": return_code,
+                            "name": return_code_name(method, *return_code),
+                            "count": code.stats.count,
+                            "first": self.relative(code.stats.first_timestamp),
+                            "last": self.relative(code.stats.last_timestamp),
+                            "payloads": code
+                                .payloads
+                                .iter()
+                                .map(|(timestamp, payload)| {
+                                    json!({ "time": self.relative(*timestamp), "payload": payload })
+                                })
+                                .collect::<Vec<_>>(),
+                        })
+                    })
+                    .collect();
+                json!({
+                    "service_id": service_id,
+                    "method_id": method_id,
+                    "method_name": method.map(|method| method.method_name.as_str()),
+                    "responses": codes.responses,
+                    "errors": errors,
+                })
+            })
+            .collect();
+        Some(Value::Array(methods))
+    }
+}
+
+#[test]
+fn application_error_names() {
+    use crate::analyzers::{
+        test_data_type, test_matrix, test_message, test_payload, test_rr_method,
+    };
+    use crate::matrix::types::{
+        MatrixApplicationError, MatrixServiceMethod, MatrixType, NumberType,
+    };
+
+    let mut matrix = test_matrix(vec![MatrixServiceMethod {
+        application_errors: vec![MatrixApplicationError {
+            return_code: 0x21,
+            name: "SEAT_BLOCKED".to_owned(),
+            description: "".to_owned(),
+            data: "uint8".to_owned(),
+        }],
+        ..test_rr_method(1, "MoveSeat", &[], "")
+    }]);
+    test_data_type(
+        &mut matrix,
+        "uint8",
+        MatrixType::Number {
+            size: NumberType::Uint8,
+        },
+    );
+
+    let mut analyzer = ReturnCodeAnalyzer::new(&matrix);
+    for (ts_ms, message_type, return_code, payload) in [
+        (0, SomeipMessageType::Response, 0x00, vec![]),
+        (10, SomeipMessageType::ResponseWithError, 0x21, vec![7]),
+        (20, SomeipMessageType::ResponseWithError, 0x22, vec![1, 2]),
+        (30, SomeipMessageType::ResponseWithError, 0x21, vec![8]),
+    ] {
+        let mut msg = test_payload(
+            test_message(
+                ts_ms,
+                "10.0.0.1:30501",
+                "10.0.0.2:40000",
+                message_type,
+                0x1234,
+                1,
+            ),
+            &payload,
+        );
+        msg.return_code = return_code;
+        analyzer.handle_message(&msg);
+    }
+
+    let codes = &analyzer.methods[&(0x1234, 1)];
+    assert_eq!((codes.responses, codes.errors()), (4, 3));
+    assert_eq!(
+        codes.codes[&0x21].payloads,
+        vec![
+            (Duration::from_millis(10), json!(7)),
+            (Duration::from_millis(30), json!(8))
+        ]
+    );
+    assert_eq!(
+        codes.codes[&0x22].payloads,
+        vec![(Duration::from_millis(20), json!("0102"))]
+    );
+
+    let method = analyzer.method(0x1234, 1);
+    assert_eq!(return_code_name(method, 0x21), "0x21 SEAT_BLOCKED");
+    assert_eq!(return_code_name(method, 0x01), "0x01 E_NOK");
+    assert_eq!(
+        return_code_name(method, 0x22),
+        "0x22 application error not in matrix"
+    );
+}
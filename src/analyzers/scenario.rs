@@ -0,0 +1,612 @@
+/// 场景断言：从TOML文件读取断言，按照抓包与矩阵逐条检查，用于台架自动化测试
+/// 路径与命令行过滤表达式一致：(serviceid).(methodid) 或 (servicename).(methodname).(member).(member)
+/// 支持的断言：
+/// - event-after-subscribe：每次Eventgroup的SubscribeAck之后，within_ms内收到指定的事件
+/// - value-in-range：信号的取值始终在 min..max 之间
+/// - returns：方法的应答都带有指定的返回码，缺省为E_OK
+///
+/// 结果可以输出为JUnit XML，任何一条断言失败时程序以非0退出
+use std::fs;
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::errors::MyError;
+use crate::matrix::parse_hex_or_dec;
+use crate::matrix::types::Matrix;
+use crate::types::{
+    SomeipEventgroupId, SomeipMessage, SomeipMessageType, SomeipMethodId, SomeipReturnCode,
+    SomeipSdEntryType, SomeipServiceId,
+};
+
+use super::return_code::return_code_name;
+use super::Analyzer;
+
+/// 每条断言最多保留的失败描述条数
+const MAX_VIOLATION_MESSAGES: usize = 10;
+
+type MethodKey = (SomeipServiceId, SomeipMethodId);
+
+#[derive(Debug, Deserialize)]
+pub struct ScenarioSpec {
+    #[serde(default)]
+    pub name: String,
+    #[serde(rename = "assert", default)]
+    pub assertions: Vec<AssertionSpec>,
+}
+
+impl ScenarioSpec {
+    pub fn from_toml_file<P>(path: P) -> Result<ScenarioSpec, MyError>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+    }
+}
+
+fn default_return_code() -> String {
+    "E_OK".to_owned()
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum AssertionSpec {
+    EventAfterSubscribe {
+        name: Option<String>,
+        eventgroup: String,
+        event: String,
+        within_ms: u64,
+    },
+    ValueInRange {
+        name: Option<String>,
+        signal: String,
+        min: Option<f64>,
+        max: Option<f64>,
+    },
+    Returns {
+        name: Option<String>,
+        method: String,
+        #[serde(default = "default_return_code")]
+        return_code: String,
+    },
+}
+
+impl AssertionSpec {
+    fn name(&self) -> String {
+        match self {
+            AssertionSpec::EventAfterSubscribe {
+                name,
+                eventgroup,
+                event,
+                within_ms,
+            } => name.clone().unwrap_or(format!(
+                "{} within {}ms after subscribe to {}",
+                event, within_ms, eventgroup
+            )),
+            AssertionSpec::ValueInRange {
+                name,
+                signal,
+                min,
+                max,
+            } => name.clone().unwrap_or(format!(
+                "{} in {}..{}",
+                signal,
+                min.map(|min| min.to_string()).unwrap_or_default(),
+                max.map(|max| max.to_string()).unwrap_or_default()
+            )),
+            AssertionSpec::Returns {
+                name,
+                method,
+                return_code,
+            } => name
+                .clone()
+                .unwrap_or(format!("{} returns {}", method, return_code)),
+        }
+    }
+}
+
+/// 断言解析路径之后的运行状态
+#[derive(Debug)]
+enum AssertionState {
+    /// 路径在矩阵中找不到
+    Unresolved(String),
+    EventAfterSubscribe {
+        eventgroup: (SomeipServiceId, SomeipEventgroupId),
+        events: Vec<MethodKey>,
+        within: Duration,
+        /// 还在等待事件的 (客户端, SubscribeAck的时间)
+        pending: Vec<(IpAddr, Duration)>,
+    },
+    ValueInRange {
+        methods: Vec<MethodKey>,
+        members: Vec<String>,
+        min: f64,
+        max: f64,
+    },
+    Returns {
+        methods: Vec<MethodKey>,
+        return_code: String,
+    },
+}
+
+#[derive(Debug)]
+struct Assertion {
+    name: String,
+    state: AssertionState,
+    /// 检查过的次数，为0说明抓包中没有相关的报文
+    checked: usize,
+    violations: usize,
+    messages: Vec<String>,
+}
+
+impl Assertion {
+    fn new(matrix: &Matrix, spec: &AssertionSpec) -> Self {
+        let state = match spec {
+            AssertionSpec::EventAfterSubscribe {
+                eventgroup,
+                event,
+                within_ms,
+                ..
+            } => match (
+                matrix.find_eventgroup_by_path(eventgroup),
+                matrix.find_methods_by_path(event).0,
+            ) {
+                (None, _) => {
+                    AssertionState::Unresolved(format!("unknown eventgroup {}", eventgroup))
+                }
+                (_, events) if events.is_empty() => {
+                    AssertionState::Unresolved(format!("unknown event {}", event))
+                }
+                (Some(eventgroup), events) => AssertionState::EventAfterSubscribe {
+                    eventgroup,
+                    events,
+                    within: Duration::from_millis(*within_ms),
+                    pending: vec![],
+                },
+            },
+            AssertionSpec::ValueInRange {
+                signal, min, max, ..
+            } => match matrix.find_methods_by_path(signal) {
+                (methods, _) if methods.is_empty() => {
+                    AssertionState::Unresolved(format!("unknown signal {}", signal))
+                }
+                (methods, members) => AssertionState::ValueInRange {
+                    methods,
+                    members: members.into_iter().map(|m| m.to_owned()).collect(),
+                    min: min.unwrap_or(f64::NEG_INFINITY),
+                    max: max.unwrap_or(f64::INFINITY),
+                },
+            },
+            AssertionSpec::Returns {
+                method,
+                return_code,
+                ..
+            } => match matrix.find_methods_by_path(method) {
+                (methods, _) if methods.is_empty() => {
+                    AssertionState::Unresolved(format!("unknown method {}", method))
+                }
+                (methods, _) => AssertionState::Returns {
+                    methods,
+                    return_code: return_code.clone(),
+                },
+            },
+        };
+        Assertion {
+            name: spec.name(),
+            state,
+            checked: 0,
+            violations: 0,
+            messages: vec![],
+        }
+    }
+
+    fn violate(&mut self, message: String) {
+        self.violations += 1;
+        if self.messages.len() < MAX_VIOLATION_MESSAGES {
+            self.messages.push(message);
+        }
+    }
+
+    /// time为相对抓包开始的秒数，只用于失败描述
+    fn handle_message(&mut self, matrix: &Matrix, msg: &SomeipMessage, time: f64) {
+        let mut violations = vec![];
+        match &mut self.state {
+            AssertionState::Unresolved(_) => {}
+            AssertionState::EventAfterSubscribe {
+                eventgroup,
+                events,
+                within,
+                pending,
+            } => {
+                let start = pending.len();
+                pending.retain(|(_, acked)| msg.timestamp.saturating_sub(*acked) <= *within);
+                for _ in pending.len()..start {
+                    violations.push(format!("{:.6}s: event not received in time", time));
+                }
+                if let Some(sd) = &msg.sd {
+                    for entry in &sd.entries {
+                        if entry.entry_type == SomeipSdEntryType::SubscribeAck
+                            && entry.ttl > 0
+                            && (entry.service_id, entry.eventgroup_id) == *eventgroup
+                        {
+                            pending.push((msg.destination.ip_addr, msg.timestamp));
+                        }
+                    }
+                } else if msg.message_type == SomeipMessageType::Notification
+                    && events.contains(&(msg.service_id, msg.method_id))
+                {
+                    let destination = msg.destination.ip_addr;
+                    let start = pending.len();
+                    pending.retain(|(client, _)| {
+                        *client != destination && !destination.is_multicast()
+                    });
+                    self.checked += start - pending.len();
+                }
+            }
+            AssertionState::ValueInRange {
+                methods,
+                members,
+                min,
+                max,
+            } => {
+                if !methods.contains(&(msg.service_id, msg.method_id)) || msg.return_code != 0 {
+                    return;
+                }
+                let value = matrix
+                    .services
+                    .get(&msg.service_id)
+                    .and_then(|service| service.methods.get(&msg.method_id))
+                    .and_then(|method| method.method_type.parameter_types(msg.message_type))
+                    .filter(|types| types.len() == 1)
+                    .and_then(|types| matrix.decode_parameters(&types, &msg.payload).ok())
+                    .and_then(|mut values| {
+                        let mut value = values.remove(0).1;
+                        for member in members.iter() {
+                            value = value.get(member)?.clone();
+                        }
+                        value.as_f64()
+                    });
+                if let Some(value) = value {
+                    self.checked += 1;
+                    if value < *min || value > *max {
+                        violations.push(format!("{:.6}s: value {} out of range", time, value));
+                    }
+                }
+            }
+            AssertionState::Returns {
+                methods,
+                return_code,
+            } => {
+                if !matches!(
+                    msg.message_type,
+                    SomeipMessageType::Response | SomeipMessageType::ResponseWithError
+                ) || !methods.contains(&(msg.service_id, msg.method_id))
+                {
+                    return;
+                }
+                self.checked += 1;
+                let method = matrix
+                    .services
+                    .get(&msg.service_id)
+                    .and_then(|service| service.methods.get(&msg.method_id));
+                if !return_code_matches(method, msg.return_code, return_code) {
+                    violations.push(format!(
+                        "{:.6}s: returned {}",
+                        time,
+                        return_code_name(method, msg.return_code)
+                    ));
+                }
+            }
+        }
+        for violation in violations {
+            self.violate(violation);
+        }
+    }
+
+    /// 抓包结束：超时仍未收到事件的计为失败，抓包结束前还没到超时时间的不计
+    fn finish(&mut self, last_timestamp: Duration, first_timestamp: Duration) {
+        let mut violations = vec![];
+        if let AssertionState::EventAfterSubscribe {
+            within, pending, ..
+        } = &mut self.state
+        {
+            for (_, acked) in pending.drain(..) {
+                if last_timestamp.saturating_sub(acked) > *within {
+                    violations.push(format!(
+                        "{:.6}s: event not received in time",
+                        (acked + *within)
+                            .saturating_sub(first_timestamp)
+                            .as_secs_f64()
+                    ));
+                }
+            }
+        }
+        for violation in violations {
+            self.violate(violation);
+        }
+    }
+
+    /// 失败原因，通过时返回None
+    fn failure(&self) -> Option<String> {
+        if let AssertionState::Unresolved(reason) = &self.state {
+            return Some(reason.clone());
+        }
+        if self.violations > 0 {
+            return Some(format!("{} violations", self.violations));
+        }
+        if self.checked == 0 {
+            return Some("no matching messages in capture".to_owned());
+        }
+        None
+    }
+}
+
+/// 期望的返回码可以是名字（E_OK或矩阵中的应用错误名）也可以是数字
+fn return_code_matches(
+    method: Option<&crate::matrix::types::MatrixServiceMethod>,
+    return_code: SomeipReturnCode,
+    expected: &str,
+) -> bool {
+    match parse_hex_or_dec::<SomeipReturnCode>(expected) {
+        Some(expected_code) => expected_code == return_code,
+        None => {
+            return_code_name(method, return_code)
+                .split_once(' ')
+                .map(|(_, name)| name)
+                == Some(expected)
+        }
+    }
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+pub struct ScenarioAnalyzer<'a> {
+    matrix: &'a Matrix,
+    name: String,
+    assertions: Vec<Assertion>,
+    first_timestamp: Option<Duration>,
+    last_timestamp: Duration,
+    finished: bool,
+}
+
+impl<'a> ScenarioAnalyzer<'a> {
+    pub fn new(matrix: &'a Matrix, spec: &ScenarioSpec) -> Self {
+        ScenarioAnalyzer {
+            matrix,
+            name: spec.name.clone(),
+            assertions: spec
+                .assertions
+                .iter()
+                .map(|spec| Assertion::new(matrix, spec))
+                .collect(),
+            first_timestamp: None,
+            last_timestamp: Duration::ZERO,
+            finished: false,
+        }
+    }
+
+    /// 全部报文处理完后调用，处理抓包结束时仍在等待的断言
+    pub fn finish(&mut self) {
+        if self.finished {
+            return;
+        }
+        self.finished = true;
+        let first_timestamp = self.first_timestamp.unwrap_or_default();
+        for assertion in &mut self.assertions {
+            assertion.finish(self.last_timestamp, first_timestamp);
+        }
+    }
+
+    pub fn failed(&self) -> bool {
+        self.assertions
+            .iter()
+            .any(|assertion| assertion.failure().is_some())
+    }
+
+    pub fn junit_xml(&self) -> String {
+        let failures = self
+            .assertions
+            .iter()
+            .filter(|assertion| assertion.failure().is_some())
+            .count();
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml += &format!(
+            "<testsuites tests=\"{}\" failures=\"{}\">\n",
+            self.assertions.len(),
+            failures
+        );
+        xml += &format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\">\n",
+            xml_escape(&self.name),
+            self.assertions.len(),
+            failures
+        );
+        for assertion in &self.assertions {
+            let name = xml_escape(&assertion.name);
+            match assertion.failure() {
+                None => {
+                    xml += &format!("    <testcase name=\"{}\" classname=\"scenario\"/>\n", name)
+                }
+                Some(failure) => {
+                    xml += &format!("    <testcase name=\"{}\" classname=\"scenario\">\n", name);
+                    xml += &format!(
+                        "      <failure message=\"{}\">{}</failure>\n",
+                        xml_escape(&failure),
+                        xml_escape(&assertion.messages.join("\n"))
+                    );
+                    xml += "    </testcase>\n";
+                }
+            }
+        }
+        xml += "  </testsuite>\n</testsuites>\n";
+        xml
+    }
+}
+
+impl<'a> Analyzer for ScenarioAnalyzer<'a> {
+    fn name(&self) -> &'static str {
+        "scenario"
+    }
+
+    fn handle_message(&mut self, msg: &SomeipMessage) {
+        let first_timestamp = *self.first_timestamp.get_or_insert(msg.timestamp);
+        self.last_timestamp = self.last_timestamp.max(msg.timestamp);
+        let time = msg.timestamp.saturating_sub(first_timestamp).as_secs_f64();
+        for assertion in &mut self.assertions {
+            assertion.handle_message(self.matrix, msg, time);
+        }
+    }
+
+    fn write_report(&self, w: &mut dyn Write) -> Result<(), MyError> {
+        let failures = self
+            .assertions
+            .iter()
+            .filter(|assertion| assertion.failure().is_some())
+            .count();
+        writeln!(
+            w,
+            "Scenario {}: {} assertions, {} failed",
+            self.name,
+            self.assertions.len(),
+            failures
+        )?;
+        for assertion in &self.assertions {
+            match assertion.failure() {
+                None => writeln!(
+                    w,
+                    "  PASS  {} ({} checks)",
+                    assertion.name, assertion.checked
+                )?,
+                Some(failure) => {
+                    writeln!(w, "  FAIL  {}: {}", assertion.name, failure)?;
+                    for message in &assertion.messages {
+                        writeln!(w, "          {}", message)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn json_report(&self) -> Option<Value> {
+        let assertions: Vec<Value> = self
+            .assertions
+            .iter()
+            .map(|assertion| {
+                json!({
+                    "name": assertion.name,
+                    "passed": assertion.failure().is_none(),
+                    "failure": assertion.failure(),
+                    "checked": assertion.checked,
+                    "violations": assertion.messages,
+                })
+            })
+            .collect();
+        Some(json!({
+            "name": self.name,
+            "passed": !self.failed(),
+            "assertions": assertions,
+        }))
+    }
+}
+
+#[test]
+fn scenario_assertions() {
+    use crate::analyzers::{
+        test_data_type, test_event, test_eventgroup, test_matrix, test_message, test_payload,
+        test_rr_method, test_sd_entry, test_sd_message,
+    };
+    use crate::matrix::types::{MatrixType, NumberType};
+
+    let mut matrix = test_matrix(vec![
+        test_event(0x8001, "Temperature", "uint8"),
+        test_rr_method(1, "Reset", &[], ""),
+    ]);
+    test_eventgroup(&mut matrix, 1, "Values", vec![0x8001]);
+    test_data_type(
+        &mut matrix,
+        "uint8",
+        MatrixType::Number {
+            size: NumberType::Uint8,
+        },
+    );
+
+    let spec: ScenarioSpec = toml::from_str(
+        r#"
+        name = "climate"
+
+        [[assert]]
+        kind = "event-after-subscribe"
+        eventgroup = "Climate.Values"
+        event = "Climate.Temperature"
+        within_ms = 100
+
+        [[assert]]
+        kind = "value-in-range"
+        signal = "Climate.Temperature"
+        max = 80
+
+        [[assert]]
+        kind = "returns"
+        method = "0x1234.0x0001"
+        "#,
+    )
+    .unwrap();
+    let mut analyzer = ScenarioAnalyzer::new(&matrix, &spec);
+
+    analyzer.handle_message(&test_sd_message(
+        0,
+        "10.0.0.1:30490",
+        "10.0.0.2:30490",
+        1,
+        false,
+        test_sd_entry(SomeipSdEntryType::SubscribeAck),
+    ));
+    for (ts_ms, value) in [(50, 20), (150, 90)] {
+        let event = test_message(
+            ts_ms,
+            "10.0.0.1:30501",
+            "10.0.0.2:30501",
+            SomeipMessageType::Notification,
+            0x1234,
+            0x8001,
+        );
+        analyzer.handle_message(&test_payload(event, &[value]));
+    }
+    let mut response = test_message(
+        200,
+        "10.0.0.1:30501",
+        "10.0.0.2:40000",
+        SomeipMessageType::Response,
+        0x1234,
+        1,
+    );
+    analyzer.handle_message(&response);
+    response.return_code = 0x01;
+    analyzer.handle_message(&response);
+    analyzer.finish();
+
+    let failures: Vec<Option<String>> = analyzer.assertions.iter().map(|a| a.failure()).collect();
+    assert_eq!(
+        failures,
+        vec![
+            None,
+            Some("1 violations".to_owned()),
+            Some("1 violations".to_owned())
+        ]
+    );
+    assert!(analyzer.failed());
+    assert!(analyzer
+        .junit_xml()
+        .contains("<testsuites tests=\"3\" failures=\"2\">"));
+}
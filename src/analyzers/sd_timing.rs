@@ -0,0 +1,869 @@
+/// SD时间与协议行为检查，时间参数按角色从矩阵中读取，没有配置时使用缺省值
+/// 初始等待：只有抓到了发送端启动（Reboot=1且SessionID=1）才测量，从它的第一条SD报文算到某个服务的第一条Offer
+/// 重复阶段：组播Offer的间隔依次为 base、2base、4base...，共repetitions_max次，之后进入周期阶段
+/// 抓包中途出现的Offer无法判断所处阶段，一律按周期阶段检查
+/// 应答延迟：Find到单播Offer、组播Offer到Subscribe，都应在request_response_delay的范围内
+/// TTL：Offer的TTL与配置一致，Offer与Subscribe在TTL到期前续期
+/// 标志位：Unicast标志必须为1；组播SD报文的SessionID回退时Reboot标志必须为1
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::Write;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use crate::errors::MyError;
+use crate::matrix::types::{Matrix, MatrixSdTiming};
+use crate::types::{
+    SomeipEventgroupId, SomeipInstanceId, SomeipMessage, SomeipSdEntry, SomeipSdEntryType,
+    SomeipServiceId, SomeipSessionId,
+};
+
+use super::{Analyzer, FindingStats};
+
+/// TTL为0xFFFFFF表示一直有效
+const TTL_INFINITE: u32 = 0xFF_FFFF;
+const SERVICE_ID_ANY: SomeipServiceId = 0xFFFF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SdTimingFinding {
+    WrongInitialWait {
+        service_id: SomeipServiceId,
+        instance_id: SomeipInstanceId,
+        min_ms: u32,
+        max_ms: u32,
+    },
+    /// index从0开始
+    WrongRepetitionDelay {
+        service_id: SomeipServiceId,
+        instance_id: SomeipInstanceId,
+        index: u32,
+        expected_ms: u32,
+    },
+    WrongCyclicOfferDelay {
+        service_id: SomeipServiceId,
+        instance_id: SomeipInstanceId,
+        expected_ms: u32,
+    },
+    WrongOfferResponseDelay {
+        service_id: SomeipServiceId,
+        min_ms: u32,
+        max_ms: u32,
+    },
+    WrongSubscribeResponseDelay {
+        service_id: SomeipServiceId,
+        eventgroup_id: SomeipEventgroupId,
+        min_ms: u32,
+        max_ms: u32,
+    },
+    WrongTtl {
+        service_id: SomeipServiceId,
+        expected: u32,
+        actual: u32,
+    },
+    /// 续期时距离TTL到期不足误差范围，或者已经过期
+    TtlRenewalMargin {
+        service_id: SomeipServiceId,
+        eventgroup_id: Option<SomeipEventgroupId>,
+        ttl: u32,
+    },
+    UnicastFlagNotSet,
+    MissingRebootFlag,
+    Reboot,
+}
+
+impl fmt::Display for SdTimingFinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SdTimingFinding::WrongInitialWait {
+                service_id,
+                instance_id,
+                min_ms,
+                max_ms,
+            } => write!(
+                f,
+                "initial wait of 0x{:04x}.0x{:04x} not within {}..{}ms",
+                service_id, instance_id, min_ms, max_ms
+            ),
+            SdTimingFinding::WrongRepetitionDelay {
+                service_id,
+                instance_id,
+                index,
+                expected_ms,
+            } => write!(
+                f,
+                "repetition {} of 0x{:04x}.0x{:04x} not after {}ms",
+                index, service_id, instance_id, expected_ms
+            ),
+            SdTimingFinding::WrongCyclicOfferDelay {
+                service_id,
+                instance_id,
+                expected_ms,
+            } => write!(
+                f,
+                "cyclic offer of 0x{:04x}.0x{:04x} not after {}ms",
+                service_id, instance_id, expected_ms
+            ),
+            SdTimingFinding::WrongOfferResponseDelay {
+                service_id,
+                min_ms,
+                max_ms,
+            } => write!(
+                f,
+                "offer of 0x{:04x} not within {}..{}ms after find",
+                service_id, min_ms, max_ms
+            ),
+            SdTimingFinding::WrongSubscribeResponseDelay {
+                service_id,
+                eventgroup_id,
+                min_ms,
+                max_ms,
+            } => write!(
+                f,
+                "subscribe of 0x{:04x}.0x{:04x} not within {}..{}ms after offer",
+                service_id, eventgroup_id, min_ms, max_ms
+            ),
+            SdTimingFinding::WrongTtl {
+                service_id,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "offer of 0x{:04x} with ttl {}s, expected {}s",
+                service_id, actual, expected
+            ),
+            SdTimingFinding::TtlRenewalMargin {
+                service_id,
+                eventgroup_id,
+                ttl,
+            } => match eventgroup_id {
+                Some(eventgroup_id) => write!(
+                    f,
+                    "subscribe of 0x{:04x}.0x{:04x} not renewed within ttl {}s",
+                    service_id, eventgroup_id, ttl
+                ),
+                None => write!(
+                    f,
+                    "offer of 0x{:04x} not renewed within ttl {}s",
+                    service_id, ttl
+                ),
+            },
+            SdTimingFinding::UnicastFlagNotSet => write!(f, "unicast flag not set"),
+            SdTimingFinding::MissingRebootFlag => {
+                write!(f, "session id went back without reboot flag")
+            }
+            SdTimingFinding::Reboot => write!(f, "reboot"),
+        }
+    }
+}
+
+/// 报告中统计的时间测量项
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SdDelayKind {
+    InitialWait,
+    Repetition,
+    CyclicOffer,
+    OfferResponse,
+    SubscribeResponse,
+}
+
+impl fmt::Display for SdDelayKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SdDelayKind::InitialWait => write!(f, "initial wait"),
+            SdDelayKind::Repetition => write!(f, "repetition"),
+            SdDelayKind::CyclicOffer => write!(f, "cyclic offer"),
+            SdDelayKind::OfferResponse => write!(f, "find -> offer"),
+            SdDelayKind::SubscribeResponse => write!(f, "offer -> subscribe"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Measurement {
+    pub count: usize,
+    pub min: Duration,
+    pub max: Duration,
+    pub total: Duration,
+}
+
+impl Measurement {
+    fn new(delay: Duration) -> Self {
+        Measurement {
+            count: 1,
+            min: delay,
+            max: delay,
+            total: delay,
+        }
+    }
+
+    fn update(&mut self, delay: Duration) {
+        self.count += 1;
+        self.min = self.min.min(delay);
+        self.max = self.max.max(delay);
+        self.total += delay;
+    }
+}
+
+#[derive(Debug)]
+struct SenderState {
+    /// 抓到了启动过程，用于测量初始等待
+    startup: Option<Duration>,
+    reboot_flag: bool,
+    /// 组播SD报文的SessionID
+    last_session_id: Option<SomeipSessionId>,
+}
+
+#[derive(Debug)]
+struct OfferStream {
+    last_offer: Duration,
+    /// 已经出现的组播Offer数，超过repetitions_max之后进入周期阶段
+    multicast_offers: u32,
+    ttl: u32,
+}
+
+pub struct SdTimingAnalyzer<'a> {
+    matrix: &'a Matrix,
+    first_timestamp: Option<Duration>,
+    senders: HashMap<IpAddr, SenderState>,
+    /// (服务端IP, 服务, 实例)
+    offers: HashMap<(IpAddr, SomeipServiceId, SomeipInstanceId), OfferStream>,
+    /// (客户端IP, 服务)
+    pending_finds: HashMap<(IpAddr, SomeipServiceId), Duration>,
+    /// (客户端IP, 服务端IP, 服务, Eventgroup) -> (上一次订阅时间, TTL)
+    subscribes: HashMap<(IpAddr, IpAddr, SomeipServiceId, SomeipEventgroupId), (Duration, u32)>,
+    findings: BTreeMap<String, BTreeMap<SdTimingFinding, FindingStats>>,
+    measurements: BTreeMap<(String, SdDelayKind), Measurement>,
+}
+
+fn millis(delay: Duration) -> f64 {
+    delay.as_secs_f64() * 1000.0
+}
+
+/// 测量值是否在 [min, max] 加减误差的范围内
+fn within(delay: Duration, min_ms: u32, max_ms: u32, timing: &MatrixSdTiming) -> bool {
+    let delay = millis(delay);
+    delay + timing.tolerance_ms as f64 >= min_ms as f64
+        && delay <= max_ms as f64 + timing.tolerance_ms as f64
+}
+
+impl<'a> SdTimingAnalyzer<'a> {
+    pub fn new(matrix: &'a Matrix) -> Self {
+        SdTimingAnalyzer {
+            matrix,
+            first_timestamp: None,
+            senders: HashMap::new(),
+            offers: HashMap::new(),
+            pending_finds: HashMap::new(),
+            subscribes: HashMap::new(),
+            findings: BTreeMap::new(),
+            measurements: BTreeMap::new(),
+        }
+    }
+
+    fn record(&mut self, timestamp: Duration, ip_addr: &IpAddr, finding: SdTimingFinding) {
+        self.findings
+            .entry(self.matrix.role_name_or_ip(ip_addr))
+            .or_default()
+            .entry(finding)
+            .and_modify(|stats| stats.update(timestamp))
+            .or_insert(FindingStats::new(timestamp));
+    }
+
+    fn measure(&mut self, ip_addr: &IpAddr, kind: SdDelayKind, delay: Duration) {
+        self.measurements
+            .entry((self.matrix.role_name_or_ip(ip_addr), kind))
+            .and_modify(|m| m.update(delay))
+            .or_insert(Measurement::new(delay));
+    }
+
+    /// 检查标志位，发送端重启时清空它相关的状态
+    fn check_flags(&mut self, msg: &SomeipMessage, reboot_flag: bool, unicast_flag: bool) {
+        let ip_addr = msg.source.ip_addr;
+        if !unicast_flag {
+            self.record(msg.timestamp, &ip_addr, SdTimingFinding::UnicastFlagNotSet);
+        }
+
+        let multicast = msg.destination.ip_addr.is_multicast();
+        let mut rebooted = false;
+        match self.senders.get_mut(&ip_addr) {
+            None => {
+                self.senders.insert(
+                    ip_addr,
+                    SenderState {
+                        startup: (reboot_flag && msg.session_id == 1).then_some(msg.timestamp),
+                        reboot_flag,
+                        last_session_id: multicast.then_some(msg.session_id),
+                    },
+                );
+            }
+            Some(sender) => {
+                if reboot_flag && !sender.reboot_flag {
+                    rebooted = true;
+                } else if multicast {
+                    if let Some(last) = sender.last_session_id {
+                        // 0xFFFF之后回绕到1是正常的
+                        if msg.session_id <= last && last != 0xFFFF {
+                            match reboot_flag {
+                                true => rebooted = true,
+                                false => self.record(
+                                    msg.timestamp,
+                                    &ip_addr,
+                                    SdTimingFinding::MissingRebootFlag,
+                                ),
+                            }
+                        }
+                    }
+                }
+                let sender = self.senders.get_mut(&ip_addr).unwrap();
+                sender.reboot_flag = reboot_flag;
+                if multicast {
+                    sender.last_session_id = Some(msg.session_id);
+                }
+                if rebooted {
+                    sender.startup = Some(msg.timestamp);
+                }
+            }
+        }
+
+        if rebooted {
+            self.record(msg.timestamp, &ip_addr, SdTimingFinding::Reboot);
+            // 重启之后重新开始所有阶段
+            self.offers.retain(|(server, _, _), _| server != &ip_addr);
+            self.subscribes
+                .retain(|(client, server, _, _), _| client != &ip_addr && server != &ip_addr);
+        }
+    }
+
+    fn handle_offer(&mut self, msg: &SomeipMessage, entry: &SomeipSdEntry) {
+        let server = msg.source.ip_addr;
+        let timing = self.matrix.sd_timing_by_ip(&server);
+        let key = (server, entry.service_id, entry.instance_id);
+
+        if entry.ttl == 0 {
+            // StopOffer之后重新进入初始等待
+            self.offers.remove(&key);
+            return;
+        }
+        if entry.ttl != timing.ttl {
+            self.record(
+                msg.timestamp,
+                &server,
+                SdTimingFinding::WrongTtl {
+                    service_id: entry.service_id,
+                    expected: timing.ttl,
+                    actual: entry.ttl,
+                },
+            );
+        }
+
+        let multicast = msg.destination.ip_addr.is_multicast();
+        if multicast {
+            // 组播Offer同样可以应答Find，但无法对应到某一个客户端，不测量
+            self.pending_finds
+                .retain(|(_, service_id), _| service_id != &entry.service_id);
+        } else {
+            let client = msg.destination.ip_addr;
+            let find = self
+                .pending_finds
+                .remove(&(client, entry.service_id))
+                .or_else(|| self.pending_finds.remove(&(client, SERVICE_ID_ANY)));
+            if let Some(find) = find {
+                let delay = msg.timestamp.saturating_sub(find);
+                self.measure(&server, SdDelayKind::OfferResponse, delay);
+                if !within(
+                    delay,
+                    timing.request_response_delay_min_ms,
+                    timing.request_response_delay_max_ms,
+                    &timing,
+                ) {
+                    self.record(
+                        msg.timestamp,
+                        &server,
+                        SdTimingFinding::WrongOfferResponseDelay {
+                            service_id: entry.service_id,
+                            min_ms: timing.request_response_delay_min_ms,
+                            max_ms: timing.request_response_delay_max_ms,
+                        },
+                    );
+                }
+            }
+        }
+
+        let startup = self.senders.get(&server).and_then(|sender| sender.startup);
+        let stream = match self.offers.get_mut(&key) {
+            Some(stream) => stream,
+            None => {
+                let mut multicast_offers = timing.repetitions_max.saturating_add(1);
+                if let Some(startup) = startup {
+                    multicast_offers = 0;
+                    let delay = msg.timestamp.saturating_sub(startup);
+                    self.measure(&server, SdDelayKind::InitialWait, delay);
+                    let (min_ms, max_ms) =
+                        (timing.initial_delay_min_ms, timing.initial_delay_max_ms);
+                    if !within(delay, min_ms, max_ms, &timing) {
+                        self.record(
+                            msg.timestamp,
+                            &server,
+                            SdTimingFinding::WrongInitialWait {
+                                service_id: entry.service_id,
+                                instance_id: entry.instance_id,
+                                min_ms,
+                                max_ms,
+                            },
+                        );
+                    }
+                }
+                self.offers.insert(
+                    key,
+                    OfferStream {
+                        last_offer: msg.timestamp,
+                        multicast_offers: multicast_offers.saturating_add(multicast as u32),
+                        ttl: entry.ttl,
+                    },
+                );
+                return;
+            }
+        };
+
+        let interval = msg.timestamp.saturating_sub(stream.last_offer);
+        let ttl = stream.ttl;
+        let index = stream.multicast_offers.saturating_sub(1);
+        stream.last_offer = msg.timestamp;
+        stream.ttl = entry.ttl;
+        if multicast {
+            stream.multicast_offers = stream.multicast_offers.saturating_add(1);
+        }
+
+        if ttl != TTL_INFINITE
+            && millis(interval) + timing.tolerance_ms as f64 > ttl as f64 * 1000.0
+        {
+            self.record(
+                msg.timestamp,
+                &server,
+                SdTimingFinding::TtlRenewalMargin {
+                    service_id: entry.service_id,
+                    eventgroup_id: None,
+                    ttl,
+                },
+            );
+        }
+        if !multicast {
+            return;
+        }
+
+        let (kind, expected_ms, finding) = match index < timing.repetitions_max {
+            true => {
+                // repetitions_max来自矩阵，很大时间隔按u32饱和
+                let expected_ms = timing
+                    .repetitions_base_delay_ms
+                    .saturating_mul(2u32.saturating_pow(index));
+                (
+                    SdDelayKind::Repetition,
+                    expected_ms,
+                    SdTimingFinding::WrongRepetitionDelay {
+                        service_id: entry.service_id,
+                        instance_id: entry.instance_id,
+                        index,
+                        expected_ms,
+                    },
+                )
+            }
+            false => (
+                SdDelayKind::CyclicOffer,
+                timing.cyclic_offer_delay_ms,
+                SdTimingFinding::WrongCyclicOfferDelay {
+                    service_id: entry.service_id,
+                    instance_id: entry.instance_id,
+                    expected_ms: timing.cyclic_offer_delay_ms,
+                },
+            ),
+        };
+        self.measure(&server, kind, interval);
+        if !within(interval, expected_ms, expected_ms, &timing) {
+            self.record(msg.timestamp, &server, finding);
+        }
+    }
+
+    fn handle_subscribe(&mut self, msg: &SomeipMessage, entry: &SomeipSdEntry) {
+        let client = msg.source.ip_addr;
+        let server = msg.destination.ip_addr;
+        let key = (client, server, entry.service_id, entry.eventgroup_id);
+        if entry.ttl == 0 {
+            self.subscribes.remove(&key);
+            return;
+        }
+        let timing = self.matrix.sd_timing_by_ip(&client);
+
+        // 只有紧跟在Offer之后的订阅才认为是对Offer的应答
+        let offer = self
+            .offers
+            .iter()
+            .filter(|((ip_addr, service_id, _), _)| {
+                ip_addr == &server && service_id == &entry.service_id
+            })
+            .map(|(_, stream)| stream.last_offer)
+            .max();
+        if let Some(offer) = offer {
+            let delay = msg.timestamp.saturating_sub(offer);
+            if millis(delay) < timing.cyclic_offer_delay_ms as f64 {
+                self.measure(&client, SdDelayKind::SubscribeResponse, delay);
+                if !within(
+                    delay,
+                    timing.request_response_delay_min_ms,
+                    timing.request_response_delay_max_ms,
+                    &timing,
+                ) {
+                    self.record(
+                        msg.timestamp,
+                        &client,
+                        SdTimingFinding::WrongSubscribeResponseDelay {
+                            service_id: entry.service_id,
+                            eventgroup_id: entry.eventgroup_id,
+                            min_ms: timing.request_response_delay_min_ms,
+                            max_ms: timing.request_response_delay_max_ms,
+                        },
+                    );
+                }
+            }
+        }
+
+        if let Some((last, ttl)) = self.subscribes.insert(key, (msg.timestamp, entry.ttl)) {
+            let interval = msg.timestamp.saturating_sub(last);
+            if ttl != TTL_INFINITE
+                && millis(interval) + timing.tolerance_ms as f64 > ttl as f64 * 1000.0
+            {
+                self.record(
+                    msg.timestamp,
+                    &client,
+                    SdTimingFinding::TtlRenewalMargin {
+                        service_id: entry.service_id,
+                        eventgroup_id: Some(entry.eventgroup_id),
+                        ttl,
+                    },
+                );
+            }
+        }
+    }
+}
+
+impl<'a> Analyzer for SdTimingAnalyzer<'a> {
+    fn name(&self) -> &'static str {
+        "sd-timing"
+    }
+
+    fn handle_message(&mut self, msg: &SomeipMessage) {
+        self.first_timestamp.get_or_insert(msg.timestamp);
+        let sd = match &msg.sd {
+            Some(sd) => sd,
+            None => return,
+        };
+        self.check_flags(msg, sd.reboot_flag, sd.unicast_flag);
+
+        for entry in &sd.entries {
+            match entry.entry_type {
+                SomeipSdEntryType::FindService => {
+                    self.pending_finds
+                        .insert((msg.source.ip_addr, entry.service_id), msg.timestamp);
+                }
+                SomeipSdEntryType::OfferService | SomeipSdEntryType::StopOfferService => {
+                    self.handle_offer(msg, entry)
+                }
+                SomeipSdEntryType::Subscribe | SomeipSdEntryType::StopSubscribe => {
+                    self.handle_subscribe(msg, entry)
+                }
+                SomeipSdEntryType::SubscribeAck
+                | SomeipSdEntryType::SubscribeNack
+                | SomeipSdEntryType::Unknown(_) => {}
+            }
+        }
+    }
+
+    fn write_report(&self, w: &mut dyn Write) -> Result<(), MyError> {
+        let first_timestamp = self.first_timestamp.unwrap_or_default();
+
+        writeln!(w, "SD timing: {} measurements", self.measurements.len())?;
+        writeln!(
+            w,
+            "{:>8} {:>10} {:>10} {:>10}  measurement",
+            "count", "min(ms)", "avg(ms)", "max(ms)"
+        )?;
+        for ((sender, kind), m) in &self.measurements {
+            writeln!(
+                w,
+                "{:>8} {:>10.3} {:>10.3} {:>10.3}  {} {}",
+                m.count,
+                millis(m.min),
+                millis(m.total) / m.count as f64,
+                millis(m.max),
+                sender,
+                kind
+            )?;
+        }
+
+        writeln!(
+            w,
+            "SD timing: {} senders with findings",
+            self.findings.len()
+        )?;
+        for (sender, findings) in &self.findings {
+            writeln!(w, "{}", sender)?;
+            for (finding, stats) in findings {
+                writeln!(
+                    w,
+                    "  {:>8}x  {:>12.6}s .. {:>12.6}s  {}",
+                    stats.count,
+                    stats
+                        .first_timestamp
+                        .saturating_sub(first_timestamp)
+                        .as_secs_f64(),
+                    stats
+                        .last_timestamp
+                        .saturating_sub(first_timestamp)
+                        .as_secs_f64(),
+                    finding
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn sd_timing_phases() {
+    use crate::analyzers::{test_sd_entry, test_sd_message};
+
+    let matrix = Matrix::default();
+    let mut analyzer = SdTimingAnalyzer::new(&matrix);
+    let server = "10.0.0.2:30490";
+    let multicast = "239.0.0.1:30490";
+
+    // 启动：Find 0ms，初始等待50ms，重复阶段200/400/800，之后周期2000，最后一个周期晚了500ms
+    analyzer.handle_message(&test_sd_message(
+        0,
+        server,
+        multicast,
+        1,
+        true,
+        test_sd_entry(SomeipSdEntryType::FindService),
+    ));
+    for (session_id, ts) in (2..).zip([50, 250, 650, 1450, 3450, 5950]) {
+        analyzer.handle_message(&test_sd_message(
+            ts,
+            server,
+            multicast,
+            session_id,
+            true,
+            test_sd_entry(SomeipSdEntryType::OfferService),
+        ));
+    }
+    // 客户端Find之后的单播Offer晚了100ms
+    analyzer.handle_message(&test_sd_message(
+        6000,
+        "10.0.0.1:30490",
+        multicast,
+        1,
+        true,
+        test_sd_entry(SomeipSdEntryType::FindService),
+    ));
+    analyzer.handle_message(&test_sd_message(
+        6100,
+        server,
+        "10.0.0.1:30490",
+        1,
+        true,
+        test_sd_entry(SomeipSdEntryType::OfferService),
+    ));
+    // SessionID回退却没有Reboot标志
+    analyzer.handle_message(&test_sd_message(
+        7000,
+        server,
+        multicast,
+        1,
+        false,
+        test_sd_entry(SomeipSdEntryType::FindService),
+    ));
+
+    let findings: Vec<SdTimingFinding> = analyzer.findings["10.0.0.2"].keys().copied().collect();
+    assert_eq!(
+        findings,
+        vec![
+            SdTimingFinding::WrongCyclicOfferDelay {
+                service_id: 0x1234,
+                instance_id: 1,
+                expected_ms: 2000
+            },
+            SdTimingFinding::WrongOfferResponseDelay {
+                service_id: 0x1234,
+                min_ms: 10,
+                max_ms: 50
+            },
+            SdTimingFinding::MissingRebootFlag,
+        ]
+    );
+    let repetition = analyzer.measurements[&("10.0.0.2".to_owned(), SdDelayKind::Repetition)];
+    assert_eq!(repetition.count, 3);
+    assert_eq!(repetition.max, Duration::from_millis(800));
+    let initial = analyzer.measurements[&("10.0.0.2".to_owned(), SdDelayKind::InitialWait)];
+    assert_eq!(initial.max, Duration::from_millis(50));
+}
+
+#[test]
+fn sd_timing_limits() {
+    use crate::analyzers::{test_role, test_sd_entry, test_sd_message};
+
+    let mut matrix = Matrix::default();
+    test_role(&mut matrix, "TBOX", "10.0.0.2");
+    matrix.sd_timing.insert(
+        "TBOX".to_owned(),
+        MatrixSdTiming {
+            repetitions_base_delay_ms: 1,
+            repetitions_max: 40,
+            tolerance_ms: 0,
+            ..Default::default()
+        },
+    );
+    let mut analyzer = SdTimingAnalyzer::new(&matrix);
+    let server = "10.0.0.2:30490";
+    let multicast = "239.0.0.1:30490";
+
+    // 初始等待只有5ms，短于最小值10ms；重复次数超过32也不能溢出
+    analyzer.handle_message(&test_sd_message(
+        0,
+        server,
+        multicast,
+        1,
+        true,
+        test_sd_entry(SomeipSdEntryType::FindService),
+    ));
+    for (session_id, ts) in (2..).zip((0..40).map(|i| 5 + i * 10)) {
+        analyzer.handle_message(&test_sd_message(
+            ts,
+            server,
+            multicast,
+            session_id,
+            true,
+            test_sd_entry(SomeipSdEntryType::OfferService),
+        ));
+    }
+    assert!(
+        analyzer.findings["TBOX"].contains_key(&SdTimingFinding::WrongInitialWait {
+            service_id: 0x1234,
+            instance_id: 1,
+            min_ms: 10,
+            max_ms: 100,
+        })
+    );
+    assert!(
+        analyzer.findings["TBOX"].contains_key(&SdTimingFinding::WrongRepetitionDelay {
+            service_id: 0x1234,
+            instance_id: 1,
+            index: 38,
+            expected_ms: u32::MAX,
+        })
+    );
+
+    let timing = MatrixSdTiming {
+        tolerance_ms: u32::MAX,
+        ..Default::default()
+    };
+    assert!(within(Duration::from_secs(1), 0, u32::MAX, &timing));
+}
+
+#[test]
+fn sd_subscribe_ttl_and_flags() {
+    use crate::analyzers::{test_report, test_sd_entry, test_sd_message};
+
+    let matrix = Matrix::default();
+    let mut analyzer = SdTimingAnalyzer::new(&matrix);
+    let server = "10.0.0.2:30490";
+    let client = "10.0.0.1:30490";
+    let multicast = "239.0.0.1:30490";
+    let offer = |ttl| SomeipSdEntry {
+        ttl,
+        ..test_sd_entry(SomeipSdEntryType::OfferService)
+    };
+    let subscribe = || SomeipSdEntry {
+        ttl: 1,
+        ..test_sd_entry(SomeipSdEntryType::Subscribe)
+    };
+
+    // 抓包中途开始，Offer按周期阶段检查；订阅晚了100ms，第二次订阅在TTL到期时才续期
+    analyzer.handle_message(&test_sd_message(0, server, multicast, 1, false, offer(3)));
+    analyzer.handle_message(&test_sd_message(100, client, server, 1, false, subscribe()));
+    analyzer.handle_message(&test_sd_message(
+        1100,
+        client,
+        server,
+        2,
+        false,
+        subscribe(),
+    ));
+    // TTL与配置不一致，单播的Offer没有设置Unicast标志
+    analyzer.handle_message(&test_sd_message(
+        2000,
+        server,
+        multicast,
+        2,
+        false,
+        offer(5),
+    ));
+    let mut msg = test_sd_message(2500, server, client, 3, false, offer(5));
+    msg.sd.as_mut().unwrap().unicast_flag = false;
+    analyzer.handle_message(&msg);
+    // SessionID从0xFFFF回绕到1是正常的，之后回退需要Reboot标志
+    let find = || test_sd_entry(SomeipSdEntryType::FindService);
+    analyzer.handle_message(&test_sd_message(
+        2600,
+        server,
+        multicast,
+        0xFFFF,
+        false,
+        find(),
+    ));
+    analyzer.handle_message(&test_sd_message(2700, server, multicast, 1, false, find()));
+    analyzer.handle_message(&test_sd_message(2800, server, multicast, 1, false, find()));
+    analyzer.handle_message(&test_sd_message(3000, server, multicast, 1, true, find()));
+
+    let findings = |sender: &str| {
+        analyzer.findings[sender]
+            .keys()
+            .copied()
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        findings("10.0.0.2"),
+        vec![
+            SdTimingFinding::WrongTtl {
+                service_id: 0x1234,
+                expected: 3,
+                actual: 5
+            },
+            SdTimingFinding::UnicastFlagNotSet,
+            SdTimingFinding::MissingRebootFlag,
+            SdTimingFinding::Reboot,
+        ]
+    );
+    assert_eq!(
+        findings("10.0.0.1"),
+        vec![
+            SdTimingFinding::WrongSubscribeResponseDelay {
+                service_id: 0x1234,
+                eventgroup_id: 1,
+                min_ms: 10,
+                max_ms: 50
+            },
+            SdTimingFinding::TtlRenewalMargin {
+                service_id: 0x1234,
+                eventgroup_id: Some(1),
+                ttl: 1
+            },
+        ]
+    );
+    // 重启之后Offer从头开始
+    assert!(analyzer.offers.is_empty());
+
+    let report = test_report(&analyzer);
+    assert!(report.contains("SD timing: 2 senders with findings\n"));
+    assert!(report.contains("offer of 0x1234 with ttl 5s, expected 3s\n"));
+    assert!(report.contains("       1   2000.000   2000.000   2000.000  10.0.0.2 cyclic offer\n"));
+}
@@ -0,0 +1,577 @@
+/// 安全异常检测：以矩阵中的部署（roles与服务的服务端、客户端）为白名单，检测可疑的流量
+/// 检测项：未知主机、冒用角色IP的未知MAC、冒用其他IP的已知MAC、非服务端角色发出的Offer、
+/// 同一服务实例被多个主机同时Offer、未授权客户端的订阅、调用未声明的方法、报文洪泛
+/// 与部署检查不同，这里按时间顺序输出带严重等级的告警，同一告警重复出现只计数
+/// 设置了实时输出时，每个新告警在发现时立即写出一行JSON
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::Write;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use log::error;
+use serde_json::{json, Value};
+
+use crate::errors::MyError;
+use crate::matrix::types::{Matrix, RoleName};
+use crate::types::{
+    mac_to_string, MacAddr, PacketIndex, SomeipInstanceId, SomeipMessage, SomeipMessageType,
+    SomeipMethodId, SomeipSdEntryType, SomeipServiceId,
+};
+
+use super::Analyzer;
+
+/// 同一个源IP每秒发送的报文超过该数量认为是洪泛
+const FLOOD_MESSAGES_PER_SECOND: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Medium,
+    High,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Medium => write!(f, "medium"),
+            Severity::High => write!(f, "high"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AlertKind {
+    UnknownHost {
+        ip_addr: IpAddr,
+        mac_addr: MacAddr,
+    },
+    /// 使用角色IP的主机MAC与矩阵不一致
+    ImpersonatedRole {
+        role: RoleName,
+        mac_addr: MacAddr,
+    },
+    /// 已知角色的MAC使用了不属于该角色的IP
+    SpoofedSource {
+        role: RoleName,
+        ip_addr: IpAddr,
+    },
+    OfferFromNonServer {
+        service_id: SomeipServiceId,
+        ip_addr: IpAddr,
+    },
+    DuplicateOffer {
+        service_id: SomeipServiceId,
+        instance_id: SomeipInstanceId,
+        ip_addrs: (IpAddr, IpAddr),
+    },
+    UnauthorizedSubscribe {
+        service_id: SomeipServiceId,
+        ip_addr: IpAddr,
+    },
+    UndeclaredMethod {
+        service_id: SomeipServiceId,
+        method_id: SomeipMethodId,
+        ip_addr: IpAddr,
+    },
+    Flood {
+        ip_addr: IpAddr,
+    },
+}
+
+impl AlertKind {
+    pub fn severity(&self) -> Severity {
+        match self {
+            AlertKind::UnknownHost { .. } | AlertKind::UndeclaredMethod { .. } => Severity::Medium,
+            AlertKind::ImpersonatedRole { .. }
+            | AlertKind::SpoofedSource { .. }
+            | AlertKind::OfferFromNonServer { .. }
+            | AlertKind::DuplicateOffer { .. }
+            | AlertKind::UnauthorizedSubscribe { .. }
+            | AlertKind::Flood { .. } => Severity::High,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AlertKind::UnknownHost { .. } => "unknown host",
+            AlertKind::ImpersonatedRole { .. } => "impersonated role",
+            AlertKind::SpoofedSource { .. } => "spoofed source",
+            AlertKind::OfferFromNonServer { .. } => "offer from non-server",
+            AlertKind::DuplicateOffer { .. } => "duplicate offer",
+            AlertKind::UnauthorizedSubscribe { .. } => "unauthorized subscribe",
+            AlertKind::UndeclaredMethod { .. } => "undeclared method",
+            AlertKind::Flood { .. } => "flood",
+        }
+    }
+}
+
+impl fmt::Display for AlertKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AlertKind::UnknownHost { ip_addr, mac_addr } => {
+                write!(f, "unknown host {} ({})", ip_addr, mac_to_string(mac_addr))
+            }
+            AlertKind::ImpersonatedRole { role, mac_addr } => write!(
+                f,
+                "unknown mac {} claiming role {}",
+                mac_to_string(mac_addr),
+                role
+            ),
+            AlertKind::SpoofedSource { role, ip_addr } => {
+                write!(f, "mac of {} sending from {}", role, ip_addr)
+            }
+            AlertKind::OfferFromNonServer {
+                service_id,
+                ip_addr,
+            } => write!(
+                f,
+                "service 0x{:04x} offered by {}, not a server in matrix",
+                service_id, ip_addr
+            ),
+            AlertKind::DuplicateOffer {
+                service_id,
+                instance_id,
+                ip_addrs,
+            } => write!(
+                f,
+                "service 0x{:04x}.0x{:04x} offered by both {} and {}",
+                service_id, instance_id, ip_addrs.0, ip_addrs.1
+            ),
+            AlertKind::UnauthorizedSubscribe {
+                service_id,
+                ip_addr,
+            } => write!(
+                f,
+                "subscribe to service 0x{:04x} from unauthorized client {}",
+                service_id, ip_addr
+            ),
+            AlertKind::UndeclaredMethod {
+                service_id,
+                method_id,
+                ip_addr,
+            } => write!(
+                f,
+                "request to undeclared method 0x{:04x}.0x{:04x} from {}",
+                service_id, method_id, ip_addr
+            ),
+            AlertKind::Flood { ip_addr } => write!(
+                f,
+                "{} sent more than {} messages per second",
+                ip_addr, FLOOD_MESSAGES_PER_SECOND
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Alert {
+    pub timestamp: Duration,
+    pub packet_index: PacketIndex,
+    pub kind: AlertKind,
+    /// 同一告警出现的次数
+    pub count: usize,
+}
+
+pub struct SecurityAnalyzer<'a> {
+    matrix: &'a Matrix,
+    first_timestamp: Option<Duration>,
+    /// 按首次出现的顺序排列
+    alerts: Vec<Alert>,
+    alert_index: BTreeMap<AlertKind, usize>,
+    /// (服务, 实例) -> (提供服务的IP, Offer过期的时间)
+    offers: HashMap<(SomeipServiceId, SomeipInstanceId), (IpAddr, Duration)>,
+    /// 源IP -> (当前一秒窗口的序号, 窗口内的报文数)
+    rates: HashMap<IpAddr, (u64, usize)>,
+    live: Option<Box<dyn Write>>,
+}
+
+impl<'a> SecurityAnalyzer<'a> {
+    pub fn new(matrix: &'a Matrix) -> Self {
+        SecurityAnalyzer {
+            matrix,
+            first_timestamp: None,
+            alerts: vec![],
+            alert_index: BTreeMap::new(),
+            offers: HashMap::new(),
+            rates: HashMap::new(),
+            live: None,
+        }
+    }
+
+    /// 每个新告警写出一行JSON并立即刷新，写出失败后不再写出
+    pub fn with_live_output(mut self, writer: Box<dyn Write>) -> Self {
+        self.live = Some(writer);
+        self
+    }
+
+    fn alert_json(&self, alert: &Alert) -> Value {
+        json!({
+            "time": self.relative(alert.timestamp),
+            "packet_index": alert.packet_index,
+            "severity": alert.kind.severity().to_string(),
+            "kind": alert.kind.name(),
+            "description": alert.kind.to_string(),
+            "count": alert.count,
+        })
+    }
+
+    fn write_live(&mut self, alert: &Alert) {
+        let value = self.alert_json(alert);
+        if let Some(writer) = &mut self.live {
+            if let Err(e) = writeln!(writer, "{}", value).and_then(|_| writer.flush()) {
+                error!("write security alert failed: {}", e);
+                self.live = None;
+            }
+        }
+    }
+
+    fn alert(&mut self, msg: &SomeipMessage, kind: AlertKind) {
+        match self.alert_index.get(&kind) {
+            Some(index) => self.alerts[*index].count += 1,
+            None => {
+                self.alert_index.insert(kind.clone(), self.alerts.len());
+                let alert = Alert {
+                    timestamp: msg.timestamp,
+                    packet_index: msg.packet_index,
+                    kind,
+                    count: 1,
+                };
+                self.write_live(&alert);
+                self.alerts.push(alert);
+            }
+        }
+    }
+
+    /// 检查源端点的IP与MAC是否与矩阵中的角色一致
+    fn check_source(&mut self, msg: &SomeipMessage) {
+        let matrix = self.matrix;
+        let ip_addr = msg.source.ip_addr;
+        let mac_addr = msg.source.mac_addr;
+        let roles = matrix.find_roles_by_ip(&ip_addr);
+        // 抓包中没有MAC（如SLL）时只检查IP
+        let has_mac = mac_addr != MacAddr::default();
+        if roles.is_empty() {
+            let owner = matrix
+                .roles
+                .values()
+                .filter(|role| has_mac && role.mac_addr == mac_addr)
+                .min_by(|a, b| a.name.cmp(&b.name));
+            match owner {
+                Some(role) => self.alert(
+                    msg,
+                    AlertKind::SpoofedSource {
+                        role: role.name.clone(),
+                        ip_addr,
+                    },
+                ),
+                None => self.alert(msg, AlertKind::UnknownHost { ip_addr, mac_addr }),
+            }
+            return;
+        }
+        let known_macs: Vec<_> = roles
+            .iter()
+            .filter(|role| role.mac_addr != MacAddr::default())
+            .collect();
+        if has_mac
+            && !known_macs.is_empty()
+            && !known_macs.iter().any(|role| role.mac_addr == mac_addr)
+        {
+            self.alert(
+                msg,
+                AlertKind::ImpersonatedRole {
+                    role: known_macs[0].name.clone(),
+                    mac_addr,
+                },
+            );
+        }
+    }
+
+    fn check_flood(&mut self, msg: &SomeipMessage) {
+        let window = msg
+            .timestamp
+            .saturating_sub(self.first_timestamp.unwrap_or_default())
+            .as_secs();
+        let rate = self.rates.entry(msg.source.ip_addr).or_insert((window, 0));
+        if rate.0 != window {
+            *rate = (window, 0);
+        }
+        rate.1 += 1;
+        if rate.1 == FLOOD_MESSAGES_PER_SECOND + 1 {
+            self.alert(
+                msg,
+                AlertKind::Flood {
+                    ip_addr: msg.source.ip_addr,
+                },
+            );
+        }
+    }
+
+    /// 矩阵中该服务的服务端角色名与客户端角色名
+    fn deployment(&self, service_id: SomeipServiceId) -> Option<(Vec<RoleName>, Vec<RoleName>)> {
+        let service = self.matrix.services.get(&service_id)?;
+        let pairs = service.server_client.borrow();
+        Some((
+            pairs.iter().map(|pair| pair.server.clone()).collect(),
+            pairs.iter().map(|pair| pair.client.clone()).collect(),
+        ))
+    }
+
+    fn has_role(&self, ip_addr: &IpAddr, names: &[RoleName]) -> bool {
+        self.matrix
+            .find_roles_by_ip(ip_addr)
+            .iter()
+            .any(|role| names.contains(&role.name))
+    }
+
+    fn check_sd(&mut self, msg: &SomeipMessage) {
+        let sd = match &msg.sd {
+            Some(sd) => sd,
+            None => return,
+        };
+        let ip_addr = msg.source.ip_addr;
+        for entry in &sd.entries {
+            let deployment = self.deployment(entry.service_id);
+            match entry.entry_type {
+                SomeipSdEntryType::OfferService if entry.ttl > 0 => {
+                    if let Some((servers, _)) = &deployment {
+                        if !self.has_role(&ip_addr, servers) {
+                            self.alert(
+                                msg,
+                                AlertKind::OfferFromNonServer {
+                                    service_id: entry.service_id,
+                                    ip_addr,
+                                },
+                            );
+                        }
+                    }
+                    let key = (entry.service_id, entry.instance_id);
+                    let expire = msg.timestamp + Duration::from_secs(entry.ttl as u64);
+                    match self.offers.insert(key, (ip_addr, expire)) {
+                        Some((other, other_expire))
+                            if other != ip_addr && other_expire >= msg.timestamp =>
+                        {
+                            self.alert(
+                                msg,
+                                AlertKind::DuplicateOffer {
+                                    service_id: entry.service_id,
+                                    instance_id: entry.instance_id,
+                                    ip_addrs: (other.min(ip_addr), other.max(ip_addr)),
+                                },
+                            )
+                        }
+                        _ => {}
+                    }
+                }
+                SomeipSdEntryType::OfferService | SomeipSdEntryType::StopOfferService => {
+                    let key = (entry.service_id, entry.instance_id);
+                    if self.offers.get(&key).map(|(ip, _)| *ip) == Some(ip_addr) {
+                        self.offers.remove(&key);
+                    }
+                }
+                SomeipSdEntryType::Subscribe if entry.ttl > 0 => {
+                    if let Some((_, clients)) = &deployment {
+                        if !self.has_role(&ip_addr, clients) {
+                            self.alert(
+                                msg,
+                                AlertKind::UnauthorizedSubscribe {
+                                    service_id: entry.service_id,
+                                    ip_addr,
+                                },
+                            );
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn relative(&self, timestamp: Duration) -> f64 {
+        timestamp
+            .saturating_sub(self.first_timestamp.unwrap_or_default())
+            .as_secs_f64()
+    }
+}
+
+impl<'a> Analyzer for SecurityAnalyzer<'a> {
+    fn name(&self) -> &'static str {
+        "security"
+    }
+
+    fn handle_message(&mut self, msg: &SomeipMessage) {
+        self.first_timestamp.get_or_insert(msg.timestamp);
+        self.check_source(msg);
+        self.check_flood(msg);
+        if msg.sd.is_some() {
+            self.check_sd(msg);
+            return;
+        }
+        if matches!(
+            msg.message_type,
+            SomeipMessageType::Request | SomeipMessageType::RequestWithoutResponse
+        ) {
+            // 矩阵中没有的服务由覆盖率检查负责，这里只关心已声明服务上的未知方法
+            let undeclared = self
+                .matrix
+                .services
+                .get(&msg.service_id)
+                .is_some_and(|service| !service.methods.contains_key(&msg.method_id));
+            if undeclared {
+                self.alert(
+                    msg,
+                    AlertKind::UndeclaredMethod {
+                        service_id: msg.service_id,
+                        method_id: msg.method_id,
+                        ip_addr: msg.source.ip_addr,
+                    },
+                );
+            }
+        }
+    }
+
+    fn write_report(&self, w: &mut dyn Write) -> Result<(), MyError> {
+        writeln!(w, "Security alerts: {} alerts", self.alerts.len())?;
+        for alert in &self.alerts {
+            writeln!(
+                w,
+                "  {:>12.6}s  #{:<8}  {:<6}  {:>8}x  {}",
+                self.relative(alert.timestamp),
+                alert.packet_index,
+                alert.kind.severity(),
+                alert.count,
+                alert.kind
+            )?;
+        }
+        Ok(())
+    }
+
+    fn json_report(&self) -> Option<Value> {
+        let alerts: Vec<Value> = self
+            .alerts
+            .iter()
+            .map(|alert| self.alert_json(alert))
+            .collect();
+        Some(Value::Array(alerts))
+    }
+}
+
+#[test]
+fn security_alerts() {
+    use crate::analyzers::{test_deployment, test_message, test_sd_entry, test_sd_message};
+
+    let mut matrix = Matrix::default();
+    test_deployment(&mut matrix);
+    // 两个角色的MAC地址需要不同
+    for (name, mac) in [("HU", 1), ("TBOX", 2)] {
+        if let Some(role) = matrix.roles.get_mut(name) {
+            role.mac_addr = [0, 0, 0, 0, 0, mac];
+        }
+    }
+
+    let mut analyzer = SecurityAnalyzer::new(&matrix);
+    let sd = |ts_ms, source: &str, mac: u8, entry_type| {
+        let mut msg = test_sd_message(
+            ts_ms,
+            source,
+            "239.0.0.1:30490",
+            1,
+            false,
+            test_sd_entry(entry_type),
+        );
+        msg.source.mac_addr = [0, 0, 0, 0, 0, mac];
+        msg
+    };
+    // 合法的Offer与Subscribe
+    analyzer.handle_message(&sd(0, "10.0.0.2:30490", 2, SomeipSdEntryType::OfferService));
+    analyzer.handle_message(&sd(10, "10.0.0.1:30490", 1, SomeipSdEntryType::Subscribe));
+    assert!(analyzer.alerts.is_empty());
+
+    // HU冒充服务端，重复Offer；TBOX订阅自己的服务
+    analyzer.handle_message(&sd(
+        20,
+        "10.0.0.1:30490",
+        1,
+        SomeipSdEntryType::OfferService,
+    ));
+    analyzer.handle_message(&sd(30, "10.0.0.2:30490", 2, SomeipSdEntryType::Subscribe));
+    // 未知MAC冒用HU的IP，HU的MAC使用了其他IP
+    analyzer.handle_message(&sd(40, "10.0.0.1:30490", 9, SomeipSdEntryType::FindService));
+    analyzer.handle_message(&sd(50, "10.0.0.7:30490", 1, SomeipSdEntryType::FindService));
+    // 未声明的方法
+    let request = test_message(
+        60,
+        "10.0.0.1:40000",
+        "10.0.0.2:30501",
+        SomeipMessageType::Request,
+        0x1234,
+        9,
+    );
+    analyzer.handle_message(&request);
+    analyzer.handle_message(&request);
+
+    let kinds: Vec<&str> = analyzer.alerts.iter().map(|a| a.kind.name()).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            "offer from non-server",
+            "duplicate offer",
+            "unauthorized subscribe",
+            "impersonated role",
+            "spoofed source",
+            "undeclared method"
+        ]
+    );
+    assert_eq!(analyzer.alerts[5].count, 2);
+    assert_eq!(analyzer.alerts[0].kind.severity(), Severity::High);
+}
+
+#[test]
+fn security_flood() {
+    use crate::analyzers::{test_deployment, test_message};
+
+    let mut matrix = Matrix::default();
+    test_deployment(&mut matrix);
+    let path = std::env::temp_dir().join(format!("someip-alerts-{}.jsonl", std::process::id()));
+    let output = crate::sinks::open_output(path.to_str().unwrap()).unwrap();
+    let mut analyzer = SecurityAnalyzer::new(&matrix).with_live_output(output);
+    let notification = |ts_ms| {
+        test_message(
+            ts_ms,
+            "10.0.0.2:30501",
+            "10.0.0.1:30501",
+            SomeipMessageType::Notification,
+            0x1234,
+            0x8001,
+        )
+    };
+
+    // 第一秒内正好达到上限，不告警
+    for _ in 0..FLOOD_MESSAGES_PER_SECOND {
+        analyzer.handle_message(&notification(0));
+    }
+    assert!(analyzer.alerts.is_empty());
+    // 第二秒内超过上限，同一个窗口只告警一次
+    for _ in 0..FLOOD_MESSAGES_PER_SECOND + 10 {
+        analyzer.handle_message(&notification(1500));
+    }
+    assert_eq!(analyzer.alerts.len(), 1);
+    assert_eq!(analyzer.alerts[0].count, 1);
+    // 告警在发现时就已经写出
+    let lines = std::fs::read_to_string(&path).unwrap();
+    let alert: Value = serde_json::from_str(lines.trim_end()).unwrap();
+    assert_eq!(alert["kind"], "flood");
+    assert_eq!(alert["time"], 1.5);
+    assert_eq!(
+        alert["description"],
+        "10.0.0.2 sent more than 1000 messages per second"
+    );
+
+    // 之后的窗口再次超过上限只计数，不再写出
+    for _ in 0..FLOOD_MESSAGES_PER_SECOND + 1 {
+        analyzer.handle_message(&notification(3000));
+    }
+    assert_eq!(analyzer.alerts[0].count, 2);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), lines);
+    drop(analyzer);
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,446 @@
+/// SessionID序列检查
+/// 规范要求：SessionID从1开始，每发送一条报文加1，0xFFFF之后回绕到1；SessionID为0表示未启用会话处理
+/// 普通报文按照 发送端IP-PORT + MessageID + ClientID 划分序列，Response/Error回显Request的SessionID，不单独检查
+/// SD报文按照 发送端IP + 接收端（组播或单播对端）划分序列，并结合Reboot标志位判断发送端是否重启：
+/// 旧报文Reboot=0而新报文Reboot=1，或者两者Reboot都为1但新的SessionID不大于旧的SessionID
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Write;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use crate::errors::MyError;
+use crate::matrix::types::Matrix;
+use crate::types::{
+    Port, SomeipClientId, SomeipMessage, SomeipMessageType, SomeipMethodId, SomeipServiceId,
+    SomeipSessionId,
+};
+
+use super::Analyzer;
+
+/// 序列号前进超过半圈认为是迟到的旧报文，而不是丢了大量报文
+const SESSION_ID_WINDOW: u32 = 0x8000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SessionStreamKey {
+    Someip {
+        sender_ip: IpAddr,
+        sender_port: Port,
+        service_id: SomeipServiceId,
+        method_id: SomeipMethodId,
+        client_id: SomeipClientId,
+    },
+    Sd {
+        sender_ip: IpAddr,
+        /// None表示组播
+        receiver_ip: Option<IpAddr>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionAnomalyKind {
+    /// 中间缺失了若干条报文，多半是UDP丢包
+    Gap(u16),
+    Duplicate,
+    /// 普通报文SessionID重新从1开始，或SD报文根据Reboot标志判断出发送端重启
+    Reset,
+    OutOfOrder,
+}
+
+impl fmt::Display for SessionAnomalyKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SessionAnomalyKind::Gap(lost) => write!(f, "gap, {} lost", lost),
+            SessionAnomalyKind::Duplicate => write!(f, "duplicate"),
+            SessionAnomalyKind::Reset => write!(f, "reset"),
+            SessionAnomalyKind::OutOfOrder => write!(f, "out of order"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SessionAnomaly {
+    pub timestamp: Duration,
+    pub key: SessionStreamKey,
+    pub kind: SessionAnomalyKind,
+    pub last_session_id: SomeipSessionId,
+    pub session_id: SomeipSessionId,
+}
+
+#[derive(Debug, Default)]
+struct SessionStream {
+    last_session_id: SomeipSessionId,
+    last_reboot_flag: bool,
+    messages: usize,
+    lost: usize,
+    duplicates: usize,
+    resets: usize,
+    out_of_order: usize,
+}
+
+impl SessionStream {
+    fn record(&mut self, kind: SessionAnomalyKind) {
+        match kind {
+            SessionAnomalyKind::Gap(lost) => self.lost += lost as usize,
+            SessionAnomalyKind::Duplicate => self.duplicates += 1,
+            SessionAnomalyKind::Reset => self.resets += 1,
+            SessionAnomalyKind::OutOfOrder => self.out_of_order += 1,
+        }
+    }
+}
+
+/// SessionID取值1..=0xFFFF，计算从last到current前进了多少步
+fn session_id_distance(last: SomeipSessionId, current: SomeipSessionId) -> u32 {
+    let last = last as u32 - 1;
+    let current = current as u32 - 1;
+    (current + 0xFFFF - last) % 0xFFFF
+}
+
+/// 判断普通报文的SessionID变化，返回None表示正常递增
+pub(crate) fn check_someip_session_id(
+    last: SomeipSessionId,
+    current: SomeipSessionId,
+) -> Option<SessionAnomalyKind> {
+    let distance = session_id_distance(last, current);
+    if distance == 1 {
+        None
+    } else if distance == 0 {
+        Some(SessionAnomalyKind::Duplicate)
+    } else if current == 1 {
+        Some(SessionAnomalyKind::Reset)
+    } else if distance < SESSION_ID_WINDOW {
+        Some(SessionAnomalyKind::Gap((distance - 1) as u16))
+    } else {
+        Some(SessionAnomalyKind::OutOfOrder)
+    }
+}
+
+/// 判断SD报文的SessionID变化，重启以Reboot标志为准
+fn check_sd_session_id(
+    last: SomeipSessionId,
+    last_reboot_flag: bool,
+    current: SomeipSessionId,
+    reboot_flag: bool,
+) -> Option<SessionAnomalyKind> {
+    if reboot_flag && (!last_reboot_flag || current <= last) {
+        if current == last {
+            return Some(SessionAnomalyKind::Duplicate);
+        }
+        return Some(SessionAnomalyKind::Reset);
+    }
+    let distance = session_id_distance(last, current);
+    if distance == 1 {
+        None
+    } else if distance == 0 {
+        Some(SessionAnomalyKind::Duplicate)
+    } else if distance < SESSION_ID_WINDOW {
+        Some(SessionAnomalyKind::Gap((distance - 1) as u16))
+    } else {
+        Some(SessionAnomalyKind::OutOfOrder)
+    }
+}
+
+pub struct SessionAnalyzer<'a> {
+    matrix: &'a Matrix,
+    first_timestamp: Option<Duration>,
+    streams: BTreeMap<SessionStreamKey, SessionStream>,
+    anomalies: Vec<SessionAnomaly>,
+}
+
+impl<'a> SessionAnalyzer<'a> {
+    pub fn new(matrix: &'a Matrix) -> Self {
+        SessionAnalyzer {
+            matrix,
+            first_timestamp: None,
+            streams: BTreeMap::new(),
+            anomalies: vec![],
+        }
+    }
+
+    fn stream_key(msg: &SomeipMessage) -> Option<SessionStreamKey> {
+        if let Some(sd) = &msg.sd {
+            return Some(SessionStreamKey::Sd {
+                sender_ip: msg.source.ip_addr,
+                receiver_ip: match sd.unicast_flag && !msg.destination.ip_addr.is_multicast() {
+                    true => Some(msg.destination.ip_addr),
+                    false => None,
+                },
+            });
+        }
+        match msg.message_type {
+            SomeipMessageType::Request
+            | SomeipMessageType::RequestWithoutResponse
+            | SomeipMessageType::Notification => Some(SessionStreamKey::Someip {
+                sender_ip: msg.source.ip_addr,
+                sender_port: msg.source.port,
+                service_id: msg.service_id,
+                method_id: msg.method_id,
+                client_id: msg.client_id,
+            }),
+            _ => None,
+        }
+    }
+
+    fn describe_key(&self, key: &SessionStreamKey) -> String {
+        let role = |ip: &IpAddr| match self.matrix.find_role_by_ip(ip) {
+            Some(role) => format!("{}({})", role.name, ip),
+            None => ip.to_string(),
+        };
+        match key {
+            SessionStreamKey::Someip {
+                sender_ip,
+                sender_port,
+                service_id,
+                method_id,
+                client_id,
+            } => format!(
+                "{}:{} 0x{:04x}.0x{:04x}{} client 0x{:04x}",
+                role(sender_ip),
+                sender_port,
+                service_id,
+                method_id,
+                self.matrix
+                    .service_name(*service_id)
+                    .map(|name| format!("({})", name))
+                    .unwrap_or_default(),
+                client_id
+            ),
+            SessionStreamKey::Sd {
+                sender_ip,
+                receiver_ip,
+            } => format!(
+                "{} SD -> {}",
+                role(sender_ip),
+                receiver_ip
+                    .map(|ip| role(&ip))
+                    .unwrap_or_else(|| "multicast".to_owned())
+            ),
+        }
+    }
+}
+
+impl<'a> Analyzer for SessionAnalyzer<'a> {
+    fn name(&self) -> &'static str {
+        "session"
+    }
+
+    fn handle_message(&mut self, msg: &SomeipMessage) {
+        self.first_timestamp.get_or_insert(msg.timestamp);
+
+        // SessionID为0表示未启用会话处理
+        if msg.session_id == 0 {
+            return;
+        }
+        let key = match Self::stream_key(msg) {
+            Some(key) => key,
+            None => return,
+        };
+        let reboot_flag = msg.sd.as_ref().map(|sd| sd.reboot_flag).unwrap_or(false);
+
+        let stream = match self.streams.get_mut(&key) {
+            Some(stream) => stream,
+            None => {
+                self.streams.insert(
+                    key,
+                    SessionStream {
+                        last_session_id: msg.session_id,
+                        last_reboot_flag: reboot_flag,
+                        messages: 1,
+                        ..Default::default()
+                    },
+                );
+                return;
+            }
+        };
+        stream.messages += 1;
+
+        let anomaly = match key {
+            SessionStreamKey::Sd { .. } => check_sd_session_id(
+                stream.last_session_id,
+                stream.last_reboot_flag,
+                msg.session_id,
+                reboot_flag,
+            ),
+            SessionStreamKey::Someip { .. } => {
+                check_someip_session_id(stream.last_session_id, msg.session_id)
+            }
+        };
+
+        if let Some(kind) = anomaly {
+            stream.record(kind);
+            self.anomalies.push(SessionAnomaly {
+                timestamp: msg.timestamp,
+                key,
+                kind,
+                last_session_id: stream.last_session_id,
+                session_id: msg.session_id,
+            });
+        }
+
+        // 迟到的旧报文不更新序列的当前位置
+        if anomaly != Some(SessionAnomalyKind::OutOfOrder) {
+            stream.last_session_id = msg.session_id;
+            stream.last_reboot_flag = reboot_flag;
+        }
+    }
+
+    fn write_report(&self, w: &mut dyn Write) -> Result<(), MyError> {
+        let first_timestamp = self.first_timestamp.unwrap_or_default();
+
+        writeln!(w, "Session ID check: {} streams", self.streams.len())?;
+        writeln!(
+            w,
+            "{:>8} {:>8} {:>6} {:>6} {:>6}  stream",
+            "messages", "lost", "dup", "reset", "ooo"
+        )?;
+        for (key, stream) in &self.streams {
+            writeln!(
+                w,
+                "{:>8} {:>8} {:>6} {:>6} {:>6}  {}",
+                stream.messages,
+                stream.lost,
+                stream.duplicates,
+                stream.resets,
+                stream.out_of_order,
+                self.describe_key(key)
+            )?;
+        }
+
+        writeln!(w, "Session ID anomalies: {}", self.anomalies.len())?;
+        for anomaly in &self.anomalies {
+            writeln!(
+                w,
+                "{:>12.6}s  {}  0x{:04x} -> 0x{:04x}  {}",
+                anomaly
+                    .timestamp
+                    .saturating_sub(first_timestamp)
+                    .as_secs_f64(),
+                anomaly.kind,
+                anomaly.last_session_id,
+                anomaly.session_id,
+                self.describe_key(&anomaly.key)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn session_id_sequence() {
+    assert_eq!(check_someip_session_id(1, 2), None);
+    assert_eq!(check_someip_session_id(0xFFFF, 1), None);
+    assert_eq!(
+        check_someip_session_id(5, 5),
+        Some(SessionAnomalyKind::Duplicate)
+    );
+    assert_eq!(
+        check_someip_session_id(5, 9),
+        Some(SessionAnomalyKind::Gap(3))
+    );
+    assert_eq!(
+        check_someip_session_id(0xFFFE, 2),
+        Some(SessionAnomalyKind::Gap(2))
+    );
+    assert_eq!(
+        check_someip_session_id(0x1234, 1),
+        Some(SessionAnomalyKind::Reset)
+    );
+    assert_eq!(
+        check_someip_session_id(9, 7),
+        Some(SessionAnomalyKind::OutOfOrder)
+    );
+
+    assert_eq!(check_sd_session_id(3, true, 4, true), None);
+    assert_eq!(check_sd_session_id(0xFFFF, true, 1, false), None);
+    assert_eq!(
+        check_sd_session_id(0x20, false, 1, true),
+        Some(SessionAnomalyKind::Reset)
+    );
+    assert_eq!(
+        check_sd_session_id(0x20, true, 0x05, true),
+        Some(SessionAnomalyKind::Reset)
+    );
+    assert_eq!(
+        check_sd_session_id(0x20, false, 0x05, false),
+        Some(SessionAnomalyKind::OutOfOrder)
+    );
+}
+
+#[test]
+fn session_streams() {
+    use super::{test_message, test_report};
+    use crate::types::SomeipSdHeader;
+
+    let matrix = Matrix::default();
+    let mut analyzer = SessionAnalyzer::new(&matrix);
+    let mut feed = |source: &str, message_type, session_id, reboot_flag: Option<bool>| {
+        let (service_id, method_id) = match reboot_flag {
+            Some(_) => (0xFFFF, 0x8100),
+            None => (0x1234, 0x0001),
+        };
+        let destination = match reboot_flag {
+            Some(_) => "224.224.224.245:30490",
+            None => "10.0.0.2:30501",
+        };
+        let mut msg = test_message(0, source, destination, message_type, service_id, method_id);
+        msg.session_id = session_id;
+        msg.sd = reboot_flag.map(|reboot_flag| SomeipSdHeader {
+            reboot_flag,
+            unicast_flag: true,
+            entries: vec![],
+        });
+        analyzer.handle_message(&msg);
+    };
+    let request = SomeipMessageType::Request;
+    for session_id in [1, 2, 4, 4, 3, 5, 1] {
+        feed("10.0.0.1:40000", request, session_id, None);
+    }
+    // 同一个发送端的另一个端口是独立的序列
+    for session_id in [1, 2] {
+        feed("10.0.0.1:40001", request, session_id, None);
+    }
+    // Response回显Request的SessionID，不检查
+    feed("10.0.0.2:30501", SomeipMessageType::Response, 9, None);
+    for (session_id, reboot_flag) in [(0x10, true), (0x11, true), (0x01, true), (0x03, false)] {
+        feed(
+            "10.0.0.2:30490",
+            SomeipMessageType::Notification,
+            session_id,
+            Some(reboot_flag),
+        );
+    }
+
+    let kinds: Vec<_> = analyzer
+        .anomalies
+        .iter()
+        .map(|a| (a.kind, a.session_id))
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            (SessionAnomalyKind::Gap(1), 4),
+            (SessionAnomalyKind::Duplicate, 4),
+            (SessionAnomalyKind::OutOfOrder, 3),
+            (SessionAnomalyKind::Reset, 1),
+            (SessionAnomalyKind::Reset, 1),
+            (SessionAnomalyKind::Gap(1), 3),
+        ]
+    );
+    assert_eq!(analyzer.streams.len(), 3);
+    let stream = analyzer.streams.values().next().unwrap();
+    assert_eq!(
+        (
+            stream.messages,
+            stream.lost,
+            stream.duplicates,
+            stream.resets,
+            stream.out_of_order
+        ),
+        (7, 1, 1, 1, 1)
+    );
+
+    let report = test_report(&analyzer);
+    assert!(report.contains("Session ID check: 3 streams"));
+    assert!(report.contains("gap, 1 lost  0x0002 -> 0x0004  10.0.0.1:40000 0x1234.0x0001"));
+    assert!(report.contains("reset  0x0011 -> 0x0001  10.0.0.2 SD -> multicast"));
+}
@@ -0,0 +1,639 @@
+/// 接口版本兼容性检查，比较三处的版本：报文头的InterfaceVersion、SD中Offer/Find/Subscribe的版本、矩阵中服务的版本
+/// 报文头的InterfaceVersion对应服务的主版本号
+/// 客户端与服务端之间：按照服务端Offer的主版本号检查双方的报文与订阅
+/// Find中指定了次版本号（不是0xFFFFFFFF）却没有任何服务端Offer该版本，在报告时统计
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::io::Write;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use crate::errors::MyError;
+use crate::matrix::types::Matrix;
+use crate::types::{
+    SomeipInstanceId, SomeipMessage, SomeipMessageType, SomeipSdEntry, SomeipSdEntryType,
+    SomeipServiceId,
+};
+
+use super::{Analyzer, FindingStats};
+
+const MAJOR_VERSION_ANY: u8 = 0xFF;
+const MINOR_VERSION_ANY: u32 = 0xFFFF_FFFF;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum VersionFinding {
+    /// 报文头的InterfaceVersion与矩阵不一致
+    HeaderMajor {
+        sender: String,
+        expected: u8,
+        actual: u8,
+    },
+    OfferMajor {
+        server: String,
+        expected: u8,
+        actual: u8,
+    },
+    OfferMinor {
+        server: String,
+        expected: u32,
+        actual: u32,
+    },
+    FindMajor {
+        client: String,
+        expected: u8,
+        actual: u8,
+    },
+    /// 客户端使用的主版本号与服务端Offer的不一致
+    ClientServerMajor {
+        client: String,
+        server: String,
+        offered: u8,
+        used: u8,
+    },
+    UnofferedMinor {
+        client: String,
+        major: u8,
+        minor: u32,
+    },
+}
+
+impl fmt::Display for VersionFinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VersionFinding::HeaderMajor {
+                sender,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} sends interface version {}, matrix has {}",
+                sender, actual, expected
+            ),
+            VersionFinding::OfferMajor {
+                server,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} offers major version {}, matrix has {}",
+                server, actual, expected
+            ),
+            VersionFinding::OfferMinor {
+                server,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} offers minor version {}, matrix has {}",
+                server, actual, expected
+            ),
+            VersionFinding::FindMajor {
+                client,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} finds major version {}, matrix has {}",
+                client, actual, expected
+            ),
+            VersionFinding::ClientServerMajor {
+                client,
+                server,
+                offered,
+                used,
+            } => write!(
+                f,
+                "{} uses major version {} with {} offering {}",
+                client, used, server, offered
+            ),
+            VersionFinding::UnofferedMinor {
+                client,
+                major,
+                minor,
+            } => write!(
+                f,
+                "{} finds version {}.{} which nobody offers",
+                client, major, minor
+            ),
+        }
+    }
+}
+
+pub struct VersionAnalyzer<'a> {
+    matrix: &'a Matrix,
+    first_timestamp: Option<Duration>,
+    /// (服务端IP, 服务, 实例) -> Offer的主版本号
+    offered_major: HashMap<(IpAddr, SomeipServiceId, SomeipInstanceId), u8>,
+    /// 出现过的Offer (服务, 主版本号, 次版本号)
+    offered: BTreeSet<(SomeipServiceId, u8, u32)>,
+    /// 指定了次版本号的Find
+    finds: BTreeMap<(SomeipServiceId, String, u8, u32), FindingStats>,
+    findings: BTreeMap<SomeipServiceId, BTreeMap<VersionFinding, FindingStats>>,
+}
+
+impl<'a> VersionAnalyzer<'a> {
+    pub fn new(matrix: &'a Matrix) -> Self {
+        VersionAnalyzer {
+            matrix,
+            first_timestamp: None,
+            offered_major: HashMap::new(),
+            offered: BTreeSet::new(),
+            finds: BTreeMap::new(),
+            findings: BTreeMap::new(),
+        }
+    }
+
+    fn record(
+        findings: &mut BTreeMap<SomeipServiceId, BTreeMap<VersionFinding, FindingStats>>,
+        timestamp: Duration,
+        service_id: SomeipServiceId,
+        finding: VersionFinding,
+    ) {
+        findings
+            .entry(service_id)
+            .or_default()
+            .entry(finding)
+            .and_modify(|stats| stats.update(timestamp))
+            .or_insert(FindingStats::new(timestamp));
+    }
+
+    /// 服务端对该服务Offer的主版本号，不区分实例时取任意一个
+    fn find_offered_major(&self, server: &IpAddr, service_id: SomeipServiceId) -> Option<u8> {
+        self.offered_major
+            .iter()
+            .find(|((ip_addr, id, _), _)| ip_addr == server && id == &service_id)
+            .map(|(_, major)| *major)
+    }
+
+    fn handle_sd_entry(&mut self, msg: &SomeipMessage, entry: &SomeipSdEntry) {
+        let matrix = self.matrix;
+        let service = matrix.services.get(&entry.service_id);
+        let sender = matrix.role_name_or_ip(&msg.source.ip_addr);
+        match entry.entry_type {
+            SomeipSdEntryType::OfferService => {
+                self.offered_major.insert(
+                    (msg.source.ip_addr, entry.service_id, entry.instance_id),
+                    entry.major_version,
+                );
+                self.offered
+                    .insert((entry.service_id, entry.major_version, entry.minor_version));
+                if let Some(service) = service {
+                    if entry.major_version as u16 != service.major_verison {
+                        Self::record(
+                            &mut self.findings,
+                            msg.timestamp,
+                            entry.service_id,
+                            VersionFinding::OfferMajor {
+                                server: sender.clone(),
+                                expected: service.major_verison as u8,
+                                actual: entry.major_version,
+                            },
+                        );
+                    }
+                    if entry.minor_version != service.minor_version as u32 {
+                        Self::record(
+                            &mut self.findings,
+                            msg.timestamp,
+                            entry.service_id,
+                            VersionFinding::OfferMinor {
+                                server: sender,
+                                expected: service.minor_version as u32,
+                                actual: entry.minor_version,
+                            },
+                        );
+                    }
+                }
+            }
+            SomeipSdEntryType::StopOfferService => {
+                self.offered_major.remove(&(
+                    msg.source.ip_addr,
+                    entry.service_id,
+                    entry.instance_id,
+                ));
+            }
+            SomeipSdEntryType::FindService => {
+                if entry.major_version == MAJOR_VERSION_ANY {
+                    return;
+                }
+                if let Some(service) = service {
+                    if entry.major_version as u16 != service.major_verison {
+                        Self::record(
+                            &mut self.findings,
+                            msg.timestamp,
+                            entry.service_id,
+                            VersionFinding::FindMajor {
+                                client: sender.clone(),
+                                expected: service.major_verison as u8,
+                                actual: entry.major_version,
+                            },
+                        );
+                    }
+                }
+                if entry.minor_version != MINOR_VERSION_ANY {
+                    self.finds
+                        .entry((
+                            entry.service_id,
+                            sender,
+                            entry.major_version,
+                            entry.minor_version,
+                        ))
+                        .and_modify(|stats| stats.update(msg.timestamp))
+                        .or_insert(FindingStats::new(msg.timestamp));
+                }
+            }
+            SomeipSdEntryType::Subscribe => {
+                self.check_client_server(
+                    msg.timestamp,
+                    &msg.source.ip_addr,
+                    &msg.destination.ip_addr,
+                    entry.service_id,
+                    entry.major_version,
+                );
+            }
+            SomeipSdEntryType::StopSubscribe
+            | SomeipSdEntryType::SubscribeAck
+            | SomeipSdEntryType::SubscribeNack
+            | SomeipSdEntryType::Unknown(_) => {}
+        }
+    }
+
+    fn check_client_server(
+        &mut self,
+        timestamp: Duration,
+        client: &IpAddr,
+        server: &IpAddr,
+        service_id: SomeipServiceId,
+        used: u8,
+    ) {
+        if let Some(offered) = self.find_offered_major(server, service_id) {
+            if offered != used {
+                Self::record(
+                    &mut self.findings,
+                    timestamp,
+                    service_id,
+                    VersionFinding::ClientServerMajor {
+                        client: self.matrix.role_name_or_ip(client),
+                        server: self.matrix.role_name_or_ip(server),
+                        offered,
+                        used,
+                    },
+                );
+            }
+        }
+    }
+}
+
+impl<'a> Analyzer for VersionAnalyzer<'a> {
+    fn name(&self) -> &'static str {
+        "version"
+    }
+
+    fn handle_message(&mut self, msg: &SomeipMessage) {
+        self.first_timestamp.get_or_insert(msg.timestamp);
+
+        if let Some(sd) = &msg.sd {
+            for entry in &sd.entries {
+                self.handle_sd_entry(msg, entry);
+            }
+            return;
+        }
+
+        if let Some(service) = self.matrix.services.get(&msg.service_id) {
+            if msg.interface_version as u16 != service.major_verison {
+                Self::record(
+                    &mut self.findings,
+                    msg.timestamp,
+                    msg.service_id,
+                    VersionFinding::HeaderMajor {
+                        sender: self.matrix.role_name_or_ip(&msg.source.ip_addr),
+                        expected: service.major_verison as u8,
+                        actual: msg.interface_version,
+                    },
+                );
+            }
+        }
+
+        // 服务端发出的报文用的是自己的版本，只检查客户端发出的Request
+        if matches!(
+            msg.message_type,
+            SomeipMessageType::Request | SomeipMessageType::RequestWithoutResponse
+        ) {
+            self.check_client_server(
+                msg.timestamp,
+                &msg.source.ip_addr,
+                &msg.destination.ip_addr,
+                msg.service_id,
+                msg.interface_version,
+            );
+        }
+    }
+
+    fn write_report(&self, w: &mut dyn Write) -> Result<(), MyError> {
+        let first_timestamp = self.first_timestamp.unwrap_or_default();
+
+        let mut findings = self.findings.clone();
+        for ((service_id, client, major, minor), stats) in &self.finds {
+            let offered = self
+                .offered
+                .iter()
+                .any(|(id, offered_major, offered_minor)| {
+                    id == service_id && offered_major == major && offered_minor == minor
+                });
+            if offered {
+                continue;
+            }
+            let finding = VersionFinding::UnofferedMinor {
+                client: client.clone(),
+                major: *major,
+                minor: *minor,
+            };
+            findings
+                .entry(*service_id)
+                .or_default()
+                .insert(finding, *stats);
+        }
+
+        writeln!(
+            w,
+            "Interface versions: {} services with findings",
+            findings.len()
+        )?;
+        for (service_id, findings) in &findings {
+            writeln!(
+                w,
+                "0x{:04x}{}",
+                service_id,
+                self.matrix
+                    .service_name(*service_id)
+                    .map(|name| format!("({})", name))
+                    .unwrap_or_default()
+            )?;
+            for (finding, stats) in findings {
+                writeln!(
+                    w,
+                    "  {:>8}x  {:>12.6}s .. {:>12.6}s  {}",
+                    stats.count,
+                    stats
+                        .first_timestamp
+                        .saturating_sub(first_timestamp)
+                        .as_secs_f64(),
+                    stats
+                        .last_timestamp
+                        .saturating_sub(first_timestamp)
+                        .as_secs_f64(),
+                    finding
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 矩阵中服务0x1234的接口版本为1.2
+#[cfg(test)]
+fn version_matrix() -> Matrix {
+    use crate::analyzers::test_matrix;
+
+    let mut matrix = test_matrix(vec![]);
+    if let Some(service) = matrix.services.get_mut(&0x1234) {
+        service.major_verison = 1;
+        service.minor_version = 2;
+    }
+    matrix
+}
+
+/// 给定版本号的SD条目
+#[cfg(test)]
+fn version_entry(
+    entry_type: SomeipSdEntryType,
+    major_version: u8,
+    minor_version: u32,
+) -> SomeipSdEntry {
+    SomeipSdEntry {
+        major_version,
+        minor_version,
+        ..crate::analyzers::test_sd_entry(entry_type)
+    }
+}
+
+#[test]
+fn version_findings() {
+    use crate::analyzers::{test_message, test_report, test_sd_message};
+
+    let matrix = version_matrix();
+    let mut analyzer = VersionAnalyzer::new(&matrix);
+    let server = "10.0.0.2:30490";
+    let client = "10.0.0.1:30490";
+
+    // 服务端Offer 2.2，客户端按照1.x使用
+    analyzer.handle_message(&test_sd_message(
+        0,
+        server,
+        "239.0.0.1:30490",
+        1,
+        false,
+        version_entry(SomeipSdEntryType::OfferService, 2, 2),
+    ));
+    analyzer.handle_message(&test_sd_message(
+        1,
+        client,
+        "239.0.0.1:30490",
+        1,
+        false,
+        version_entry(SomeipSdEntryType::FindService, 1, 3),
+    ));
+    analyzer.handle_message(&test_sd_message(
+        2,
+        client,
+        server,
+        1,
+        false,
+        version_entry(SomeipSdEntryType::Subscribe, 1, 1),
+    ));
+    let msg = test_message(
+        3,
+        "10.0.0.1:40000",
+        "10.0.0.2:30501",
+        SomeipMessageType::Request,
+        0x1234,
+        1,
+    );
+    analyzer.handle_message(&msg);
+
+    let findings: Vec<VersionFinding> = analyzer.findings[&0x1234].keys().cloned().collect();
+    assert_eq!(
+        findings,
+        vec![
+            VersionFinding::OfferMajor {
+                server: "10.0.0.2".to_owned(),
+                expected: 1,
+                actual: 2
+            },
+            VersionFinding::ClientServerMajor {
+                client: "10.0.0.1".to_owned(),
+                server: "10.0.0.2".to_owned(),
+                offered: 2,
+                used: 1
+            },
+        ]
+    );
+
+    let report = test_report(&analyzer);
+    assert!(report.contains("10.0.0.1 finds version 1.3 which nobody offers"));
+}
+
+#[test]
+fn version_header_and_minor() {
+    use crate::analyzers::{test_message, test_sd_message};
+
+    let matrix = version_matrix();
+    let mut analyzer = VersionAnalyzer::new(&matrix);
+
+    // 主版本号正确、次版本号不一致；Find任意版本不检查
+    analyzer.handle_message(&test_sd_message(
+        0,
+        "10.0.0.2:30490",
+        "239.0.0.1:30490",
+        1,
+        false,
+        version_entry(SomeipSdEntryType::OfferService, 1, 3),
+    ));
+    analyzer.handle_message(&test_sd_message(
+        1,
+        "10.0.0.1:30490",
+        "239.0.0.1:30490",
+        1,
+        false,
+        version_entry(SomeipSdEntryType::FindService, MAJOR_VERSION_ANY, 7),
+    ));
+    analyzer.handle_message(&test_sd_message(
+        2,
+        "10.0.0.1:30490",
+        "239.0.0.1:30490",
+        2,
+        false,
+        version_entry(SomeipSdEntryType::FindService, 2, MINOR_VERSION_ANY),
+    ));
+    // 服务端的Response用了错误的InterfaceVersion，只记录报文头的问题
+    let mut msg = test_message(
+        3,
+        "10.0.0.2:30501",
+        "10.0.0.1:40000",
+        SomeipMessageType::Response,
+        0x1234,
+        1,
+    );
+    msg.interface_version = 3;
+    analyzer.handle_message(&msg);
+    analyzer.handle_message(&SomeipMessage {
+        timestamp: Duration::from_millis(5),
+        ..msg
+    });
+
+    let findings = &analyzer.findings[&0x1234];
+    assert_eq!(
+        findings.keys().cloned().collect::<Vec<_>>(),
+        vec![
+            VersionFinding::HeaderMajor {
+                sender: "10.0.0.2".to_owned(),
+                expected: 1,
+                actual: 3
+            },
+            VersionFinding::OfferMinor {
+                server: "10.0.0.2".to_owned(),
+                expected: 2,
+                actual: 3
+            },
+            VersionFinding::FindMajor {
+                client: "10.0.0.1".to_owned(),
+                expected: 1,
+                actual: 2
+            },
+        ]
+    );
+    let header = &findings[&VersionFinding::HeaderMajor {
+        sender: "10.0.0.2".to_owned(),
+        expected: 1,
+        actual: 3,
+    }];
+    assert_eq!(header.count, 2);
+    assert!(analyzer.finds.is_empty());
+}
+
+#[test]
+fn version_stop_offer() {
+    use crate::analyzers::{test_message, test_sd_message};
+
+    let matrix = version_matrix();
+    let mut analyzer = VersionAnalyzer::new(&matrix);
+    let server = "10.0.0.2:30490";
+
+    analyzer.handle_message(&test_sd_message(
+        0,
+        server,
+        "239.0.0.1:30490",
+        1,
+        false,
+        version_entry(SomeipSdEntryType::OfferService, 1, 2),
+    ));
+    analyzer.handle_message(&test_sd_message(
+        1,
+        server,
+        "239.0.0.1:30490",
+        2,
+        false,
+        version_entry(SomeipSdEntryType::StopOfferService, 1, 2),
+    ));
+    // 服务已停止Offer，客户端的版本无从比较
+    let mut msg = test_message(
+        2,
+        "10.0.0.1:40000",
+        "10.0.0.2:30501",
+        SomeipMessageType::RequestWithoutResponse,
+        0x1234,
+        1,
+    );
+    msg.interface_version = 2;
+    analyzer.handle_message(&msg);
+
+    assert_eq!(
+        analyzer.findings[&0x1234]
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>(),
+        vec![VersionFinding::HeaderMajor {
+            sender: "10.0.0.1".to_owned(),
+            expected: 1,
+            actual: 2
+        }]
+    );
+}
+
+#[test]
+fn version_offered_find() {
+    use crate::analyzers::{test_report, test_sd_message};
+
+    let matrix = version_matrix();
+    let mut analyzer = VersionAnalyzer::new(&matrix);
+
+    analyzer.handle_message(&test_sd_message(
+        0,
+        "10.0.0.1:30490",
+        "239.0.0.1:30490",
+        1,
+        false,
+        version_entry(SomeipSdEntryType::FindService, 1, 2),
+    ));
+    analyzer.handle_message(&test_sd_message(
+        1,
+        "10.0.0.2:30490",
+        "239.0.0.1:30490",
+        1,
+        false,
+        version_entry(SomeipSdEntryType::OfferService, 1, 2),
+    ));
+
+    assert!(analyzer.findings.is_empty());
+    let report = test_report(&analyzer);
+    assert_eq!(report, "Interface versions: 0 services with findings\n");
+}
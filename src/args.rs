@@ -1,16 +1,73 @@
 use clap::builder::NonEmptyStringValueParser;
 use clap::{crate_authors, crate_description, crate_name, crate_version};
-use clap::{Arg, Command};
-use log::{error, info};
-use std::error::Error;
-use std::path::Path;
-
+use clap::{Arg, ArgAction, Command};
 
 pub fn command() -> Command {
     Command::new(crate_name!())
         .version(crate_version!())
         .author(crate_authors!("\n"))
         .about(crate_description!())
+        .subcommand_negates_reqs(true)
+        .subcommand(
+            Command::new("serve")
+                .about("serve an http api to register matrices and captures, run analyses and page through decoded messages.")
+                .arg(
+                    Arg::new("listen")
+                        .help("address to listen on.")
+                        .long("listen")
+                        .value_parser(NonEmptyStringValueParser::new())
+                        .default_value("127.0.0.1:8080")
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new("data_dir")
+                        .help("directory where uploaded matrices and captures are stored.")
+                        .long("data-dir")
+                        .value_parser(NonEmptyStringValueParser::new())
+                        .default_value("./data")
+                        .num_args(1),
+                ),
+        )
+        .subcommand(
+            Command::new("query")
+                .about("query the messages saved by --store without parsing the capture again, print one json per line.")
+                .arg(
+                    Arg::new("store")
+                        .help("the sqlite file written by --store.")
+                        .long("store")
+                        .short('s')
+                        .value_parser(NonEmptyStringValueParser::new())
+                        .required(true)
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new("sql")
+                        .help("run this read-only sql instead, tables: messages, sd_entries, meta.")
+                        .long("sql")
+                        .value_parser(NonEmptyStringValueParser::new())
+                        .conflicts_with("filter")
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new("offset")
+                        .help("skip this many selected messages.")
+                        .long("offset")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("0")
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new("limit")
+                        .help("print at most this many messages.")
+                        .long("limit")
+                        .value_parser(clap::value_parser!(usize))
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new("filter")
+                        .help("filter expression, the same as the main command, resolved with the matrix saved in the store."),
+                ),
+        )
         .arg(
             Arg::new("matrix")
                 .help("the matrix file, json or xlsx.")
@@ -54,11 +111,12 @@ pub fn command() -> Command {
         )
         .arg(
             Arg::new("input_from_file")
-                .help("parse from pcap file")
+                .help("parse from pcap file, repeat to parse several captures in order.")
                 .long("file")
                 .value_parser(NonEmptyStringValueParser::new())
                 .short('f')
-                .num_args(1),
+                .num_args(1)
+                .action(ArgAction::Append),
         )
         .arg(
             Arg::new("input_from_local_interface")
@@ -77,16 +135,224 @@ pub fn command() -> Command {
         )
         .group(
             clap::ArgGroup::new("input")
-                .args(&["input_from_file", "input_from_local_interface", "input_from_adb"])
+                .args(["input_from_file", "input_from_local_interface", "input_from_adb"])
                 .required(true)
                 .multiple(false)
         )
+        .arg(
+            Arg::new("analyze")
+                .help("analyses to run on the parsed messages, separated by comma.")
+                .long("analyze")
+                .short('a')
+                .value_parser(["session", "deployment", "message-type", "coverage", "e2e", "sd-timing", "layout", "version", "field", "bandwidth", "return-code", "security"])
+                .value_delimiter(',')
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("discover")
+                .help("infer a skeleton matrix from the capture and write it to the json file, matrix is optional in this mode.")
+                .long("discover")
+                .value_parser(NonEmptyStringValueParser::new())
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("compare")
+                .help("compare the captures given by --file as baseline with this capture and report the behavioral differences.")
+                .long("compare")
+                .value_parser(NonEmptyStringValueParser::new())
+                .requires("input_from_file")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("scenario")
+                .help("check the assertions in the toml scenario file against the capture, exit with 1 if any of them fails.")
+                .long("scenario")
+                .value_parser(NonEmptyStringValueParser::new())
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("junit")
+                .help("with --scenario, write the assertion results to this JUnit XML file.")
+                .long("junit")
+                .value_parser(NonEmptyStringValueParser::new())
+                .requires("scenario")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("field_at")
+                .help("with --analyze field, print the value of every field at this time, in seconds from the start of the capture.")
+                .long("field-at")
+                .value_parser(clap::value_parser!(f64))
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("field_history")
+                .help("with --analyze field, print every change of this field, like: (servicename).(fieldname) or (fieldname).")
+                .long("field-history")
+                .value_parser(NonEmptyStringValueParser::new())
+                .conflicts_with("field_at")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("bandwidth_window")
+                .help("with --analyze bandwidth, length of the time window in milliseconds used for the peak rate.")
+                .long("bandwidth-window")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .default_value("1000")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("top")
+                .help("with --analyze bandwidth, number of top talkers printed for every group.")
+                .long("top")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("10")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("security_alerts")
+                .help("with --analyze security, write every new alert as one json line to this file as soon as it is found, - for stderr.")
+                .long("security-alerts")
+                .value_parser(NonEmptyStringValueParser::new())
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("jsonl")
+                .help("write every decoded message as one json line to this file, - for stdout.")
+                .long("jsonl")
+                .value_parser(NonEmptyStringValueParser::new())
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("pretty")
+                .help("print every decoded message as a tree to this file, - for stdout, colored on a terminal.")
+                .long("pretty")
+                .value_parser(NonEmptyStringValueParser::new())
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("csv")
+                .help("export the values of --csv-signal as csv to this file, - for stdout.")
+                .long("csv")
+                .requires("csv_signal")
+                .value_parser(NonEmptyStringValueParser::new())
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("csv_signal")
+                .help("with --csv, signal path like Climate.Status.temp, a struct is expanded into its members.")
+                .long("csv-signal")
+                .requires("csv")
+                .action(ArgAction::Append)
+                .value_parser(NonEmptyStringValueParser::new())
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("csv_layout")
+                .help("with --csv, wide: one column per signal, long: one row per signal value.")
+                .long("csv-layout")
+                .value_parser(["wide", "long"])
+                .default_value("wide")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("csv_fill")
+                .help("with --csv in wide layout, fill empty cells with the last value of the signal.")
+                .long("csv-fill")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("csv_time")
+                .help("with --csv, time relative to the first message or absolute capture time.")
+                .long("csv-time")
+                .value_parser(["relative", "absolute"])
+                .default_value("relative")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("xlsx")
+                .help("write an excel report to this file, the messages sheet only contains messages selected by the filter.")
+                .long("xlsx")
+                .value_parser(NonEmptyStringValueParser::new())
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("pcapng")
+                .help("write the frames of the messages selected by the filter to this pcapng file, with decoded comments.")
+                .long("pcapng")
+                .value_parser(NonEmptyStringValueParser::new())
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("websocket")
+                .help("push decoded messages and sd state changes to websocket clients connected to this address, like 0.0.0.0:9000, clients choose ?filter= and ?backpressure=drop|sample.")
+                .long("websocket")
+                .value_parser(NonEmptyStringValueParser::new())
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("metrics")
+                .help("serve prometheus metrics on http://<this address>/metrics, like 0.0.0.0:9100.")
+                .long("metrics")
+                .value_parser(NonEmptyStringValueParser::new())
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("store")
+                .help("save the decoded messages and sd entries to this sqlite file for the query command, an existing file is replaced.")
+                .long("store")
+                .value_parser(NonEmptyStringValueParser::new())
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("sequence")
+                .help("write a sequence diagram of the messages selected by the filter between matrix roles to this file, - for stdout.")
+                .long("sequence")
+                .value_parser(NonEmptyStringValueParser::new())
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("sequence_format")
+                .help("with --sequence, the diagram language.")
+                .long("sequence-format")
+                .value_parser(["plantuml", "mermaid"])
+                .default_value("plantuml")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("sequence_from")
+                .help("with --sequence, only draw messages after this time, in seconds from the start of the capture.")
+                .long("sequence-from")
+                .value_parser(clap::value_parser!(f64))
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("sequence_to")
+                .help("with --sequence, only draw messages before this time, in seconds from the start of the capture.")
+                .long("sequence-to")
+                .value_parser(clap::value_parser!(f64))
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("sequence_sd")
+                .help("with --sequence, draw sd messages as grey notes.")
+                .long("sequence-sd")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("format")
+                .help("report format, json only contains the analyses that support it.")
+                .long("format")
+                .value_parser(["text", "json"])
+                .default_value("text")
+                .num_args(1),
+        )
         // TODO: filter expression allow complex expression
         .arg(
             Arg::new("filter")
                 .help("filter expression, like: (serviceid).(methodid) or (servicename).(methodname).(datatypename).(datatypename).[time,subscribe,value,trend]")
                 .last(true)
-                .required(true)                
+                .required(false)
         )
 }
 
@@ -6,13 +6,32 @@ pub enum MyError {
     // TODO: convert xlsx error to myerror
     Xlsx(#[from] calamine::XlsxError),
     De(#[from] calamine::DeError),
+    Pcap(#[from] pcap::Error),
+    Json(#[from] serde_json::Error),
+    Toml(#[from] toml::de::Error),
+    Csv(#[from] csv::Error),
+    XlsxWrite(#[from] rust_xlsxwriter::XlsxError),
+    Sqlite(#[from] rusqlite::Error),
     ArgInputError(String),
     ParseMatrixFileError(String),
-    Custom(String)
+    Custom(String),
 }
 
 impl std::fmt::Display for MyError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        todo!()
+        match self {
+            MyError::Io(e) => write!(f, "io error: {}", e),
+            MyError::Xlsx(e) => write!(f, "xlsx error: {}", e),
+            MyError::De(e) => write!(f, "xlsx deserialize error: {}", e),
+            MyError::Pcap(e) => write!(f, "pcap error: {}", e),
+            MyError::Json(e) => write!(f, "json error: {}", e),
+            MyError::Toml(e) => write!(f, "toml error: {}", e),
+            MyError::Csv(e) => write!(f, "csv error: {}", e),
+            MyError::XlsxWrite(e) => write!(f, "xlsx write error: {}", e),
+            MyError::Sqlite(e) => write!(f, "sqlite error: {}", e),
+            MyError::ArgInputError(s) => write!(f, "arg input error: {}", s),
+            MyError::ParseMatrixFileError(s) => write!(f, "parse matrix file error: {}", s),
+            MyError::Custom(s) => write!(f, "{}", s),
+        }
     }
 }
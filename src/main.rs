@@ -1,19 +1,43 @@
+mod analyzers;
 mod args;
 mod errors;
 mod matrix;
 mod parsers;
-mod types;
+mod server;
+mod sinks;
 mod sources;
+mod store;
+mod types;
 
+use analyzers::bandwidth::BandwidthAnalyzer;
+use analyzers::compare::CompareAnalyzer;
+use analyzers::discovery::DiscoveryAnalyzer;
+use analyzers::excel_report::ExcelReportAnalyzer;
+use analyzers::field::FieldAnalyzer;
+use analyzers::scenario::{ScenarioAnalyzer, ScenarioSpec};
+use analyzers::security::SecurityAnalyzer;
+use analyzers::Analyzer;
 use args::command;
 use errors::MyError;
-use log::{debug, error, info, log_enabled, trace};
+use log::{debug, info};
 use matrix::types::Matrix;
+use sinks::jsonl::JsonLinesSink;
+use sinks::metrics::MetricsSink;
+use sinks::pcapng::PcapngSink;
+use sinks::pretty::PrettySink;
+use sinks::sequence::{DiagramFormat, SequenceSink};
+use sinks::signals::{CsvLayout, SignalCsvSink};
+use sinks::websocket::WebSocketSink;
+use sinks::Sink;
+use sources::adb_source::AdbSource;
+use sources::interface_source::InterfaceSource;
+use sources::pcap_source::PcapFileSource;
 use sources::Source;
 use std::env::set_var;
-use std::ffi::OsStr;
-use std::path::Path;
-use std::time::Instant;
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+use store::{Store, StoreSink};
 
 fn main() -> Result<(), MyError> {
     let matches = command().get_matches();
@@ -27,69 +51,301 @@ fn main() -> Result<(), MyError> {
         },
     );
     env_logger::init();
-    println!("test:{}", debug);
     debug!("in debug mode");
 
+    if let Some(("serve", serve)) = matches.subcommand() {
+        let listen = serve.get_one::<String>("listen").unwrap();
+        let data_dir = serve.get_one::<String>("data_dir").unwrap();
+        return server::serve(listen, PathBuf::from(data_dir));
+    }
+    if let Some(("query", query)) = matches.subcommand() {
+        let store = Store::open(query.get_one::<String>("store").unwrap())?;
+        let mut stdout = std::io::stdout().lock();
+        match query.get_one::<String>("sql") {
+            Some(sql) => {
+                let (columns, rows) = store.sql(sql)?;
+                for row in rows {
+                    let row: serde_json::Map<String, serde_json::Value> =
+                        columns.iter().cloned().zip(row).collect();
+                    serde_json::to_writer(&mut stdout, &row)?;
+                    writeln!(stdout)?;
+                }
+            }
+            None => {
+                let filter = query.get_one::<String>("filter").map(|s| s.as_str());
+                let offset = *query.get_one::<usize>("offset").unwrap();
+                let limit = query
+                    .get_one::<usize>("limit")
+                    .copied()
+                    .unwrap_or(usize::MAX);
+                let (_, messages) = store.messages(filter, offset, limit)?;
+                for message in messages {
+                    serde_json::to_writer(&mut stdout, &message)?;
+                    writeln!(stdout)?;
+                }
+            }
+        }
+        return Ok(());
+    }
+
     let matrix: Matrix;
 
     if let Some(matrix_file) = matches.get_one::<String>("matrix") {
         info!("matrix file:{}", matrix_file.to_string());
-        // 支持excel、json后缀名
-        match Path::new(matrix_file).canonicalize() {
-            Ok(path) => {
-                if let Some(ext) = path.extension() {
-                    match ext.to_ascii_lowercase().to_str() {
-                        Some("xlsx") | Some("xls") => {
-                            matrix = Matrix::from_excel_file(path)?
-                        },
-                        Some("json") => {
-                            matrix = Matrix::from_json_file(path)?
-                        },
-                        _ => {},
+        matrix = Matrix::from_file(matrix_file)?;
+    } else if matches.contains_id("discover") {
+        // 发现模式下可以没有矩阵
+        matrix = Matrix::default();
+    } else {
+        return Err(MyError::ArgInputError("arg matrix error".to_owned()));
+    }
+
+    // TODO: filter parse，目前只支持 (service).(method)
+    let selected = match matches.get_one::<String>("filter") {
+        Some(filter) => Some(sinks::select_methods(&matrix, filter)?),
+        None => None,
+    };
+
+    // parse data source
+    let sources: Vec<Source> = match (
+        matches.get_one::<String>("input_from_adb"),
+        matches.get_many::<String>("input_from_file"),
+        matches.get_one::<String>("input_from_local_interface"),
+    ) {
+        (None, Some(input_from_files), None) => {
+            let mut sources = vec![];
+            for input_from_file in input_from_files {
+                info!("input_from_file:{}", input_from_file);
+                sources.push(Source::PcapFile(PcapFileSource::new(&PathBuf::from(
+                    input_from_file,
+                ))?));
+            }
+            sources
+        }
+        (None, None, Some(input_from_local_interface)) => {
+            info!("input_from_local_interface:{}", input_from_local_interface);
+            vec![Source::Interface(InterfaceSource::new(
+                input_from_local_interface,
+            )?)]
+        }
+        (Some(input_from_adb), None, None) => {
+            info!("input_from_adb:{}", input_from_adb);
+            vec![Source::Adb(AdbSource::new(input_from_adb)?)]
+        }
+        _ => {
+            return Err(MyError::ArgInputError("data source".to_owned()));
+        }
+    };
+
+    let mut analyzers: Vec<Box<dyn Analyzer>> = vec![];
+    for name in matches.get_many::<String>("analyze").unwrap_or_default() {
+        match name.as_str() {
+            "field" => {
+                let at = seconds_arg(&matches, "field_at")?;
+                let history = matches.get_one::<String>("field_history").cloned();
+                analyzers.push(Box::new(FieldAnalyzer::new(&matrix, at, history)))
+            }
+            "security" => {
+                let mut analyzer = SecurityAnalyzer::new(&matrix);
+                if let Some(alerts) = matches.get_one::<String>("security_alerts") {
+                    let output: Box<dyn Write> = match alerts.as_str() {
+                        "-" => Box::new(std::io::stderr()),
+                        path => sinks::open_output(path)?,
+                    };
+                    analyzer = analyzer.with_live_output(output);
+                }
+                analyzers.push(Box::new(analyzer))
+            }
+            "bandwidth" => {
+                let window =
+                    Duration::from_millis(*matches.get_one::<u64>("bandwidth_window").unwrap());
+                let top = *matches.get_one::<usize>("top").unwrap();
+                analyzers.push(Box::new(BandwidthAnalyzer::new(&matrix, window, top)))
+            }
+            name => analyzers.push(analyzers::by_name(name, &matrix).unwrap()),
+        }
+    }
+
+    let discover = matches.get_one::<String>("discover");
+    let mut discovery = discover.map(|_| DiscoveryAnalyzer::new());
+    let compare = matches.get_one::<String>("compare");
+    let mut comparison = compare.map(|_| CompareAnalyzer::new(&matrix));
+    let scenario_spec = match matches.get_one::<String>("scenario") {
+        Some(scenario) => Some(ScenarioSpec::from_toml_file(scenario)?),
+        None => None,
+    };
+    let mut scenario = scenario_spec
+        .as_ref()
+        .map(|spec| ScenarioAnalyzer::new(&matrix, spec));
+    let xlsx = matches.get_one::<String>("xlsx");
+    let mut report = xlsx.map(|_| ExcelReportAnalyzer::new(&matrix, selected.clone()));
+
+    let mut sinks: Vec<Box<dyn Sink + '_>> = vec![];
+    if let Some(jsonl) = matches.get_one::<String>("jsonl") {
+        sinks.push(Box::new(JsonLinesSink::new(
+            &matrix,
+            sinks::open_output(jsonl)?,
+        )));
+    }
+    if let Some(pretty) = matches.get_one::<String>("pretty") {
+        let color = pretty == "-" && std::io::stdout().is_terminal();
+        sinks.push(Box::new(PrettySink::new(
+            &matrix,
+            sinks::open_output(pretty)?,
+            color,
+        )));
+    }
+    if let Some(pcapng) = matches.get_one::<String>("pcapng") {
+        let output = sinks::open_output(pcapng)?;
+        sinks.push(Box::new(PcapngSink::new(
+            &matrix,
+            output,
+            selected.clone(),
+        )?));
+    }
+    if let Some(websocket) = matches.get_one::<String>("websocket") {
+        sinks.push(Box::new(WebSocketSink::new(&matrix, websocket)?));
+    }
+    if let Some(metrics) = matches.get_one::<String>("metrics") {
+        sinks.push(Box::new(MetricsSink::new(&matrix, metrics)?));
+    }
+    if let Some(store) = matches.get_one::<String>("store") {
+        sinks.push(Box::new(StoreSink::new(&matrix, store)?));
+    }
+    if let Some(sequence) = matches.get_one::<String>("sequence") {
+        let format = match matches
+            .get_one::<String>("sequence_format")
+            .map(|s| s.as_str())
+        {
+            Some("mermaid") => DiagramFormat::Mermaid,
+            _ => DiagramFormat::PlantUml,
+        };
+        sinks.push(Box::new(SequenceSink::new(
+            &matrix,
+            sinks::open_output(sequence)?,
+            format,
+            selected.clone(),
+            seconds_arg(&matches, "sequence_from")?,
+            seconds_arg(&matches, "sequence_to")?,
+            matches.get_flag("sequence_sd"),
+        )));
+    }
+    if let Some(csv) = matches.get_one::<String>("csv") {
+        let signals: Vec<&str> = matches
+            .get_many::<String>("csv_signal")
+            .unwrap_or_default()
+            .map(|s| s.as_str())
+            .collect();
+        let layout = match matches.get_one::<String>("csv_layout").map(|s| s.as_str()) {
+            Some("long") => CsvLayout::Long,
+            _ => CsvLayout::Wide,
+        };
+        sinks.push(Box::new(SignalCsvSink::new(
+            &matrix,
+            sinks::open_output(csv)?,
+            &signals,
+            layout,
+            matches.get_flag("csv_fill"),
+            matches.get_one::<String>("csv_time").map(|s| s.as_str()) == Some("absolute"),
+        )?));
+    }
+
+    for source in sources {
+        let mut running: Vec<&mut dyn Analyzer> = analyzers
+            .iter_mut()
+            .map(|a| a.as_mut() as &mut dyn Analyzer)
+            .collect();
+        if let Some(discovery) = discovery.as_mut() {
+            running.push(discovery);
+        }
+        if let Some(comparison) = comparison.as_mut() {
+            running.push(comparison);
+        }
+        if let Some(scenario) = scenario.as_mut() {
+            running.push(scenario);
+        }
+        if let Some(report) = report.as_mut() {
+            running.push(report);
+        }
+        let mut writing: Vec<&mut dyn Sink> = sinks
+            .iter_mut()
+            .map(|s| s.as_mut() as &mut dyn Sink)
+            .collect();
+        analyzers::run(source, &mut running, &mut writing)?;
+    }
+    for sink in sinks.iter_mut() {
+        sink.finish()?;
+    }
+
+    let mut scenario_failed = false;
+    if let Some(mut scenario) = scenario {
+        scenario.finish();
+        if let Some(junit) = matches.get_one::<String>("junit") {
+            info!("write junit report to {}", junit);
+            std::fs::write(junit, scenario.junit_xml())?;
+        }
+        scenario_failed = scenario.failed();
+        analyzers.push(Box::new(scenario));
+    }
+
+    // 对比模式：其余分析器只分析基准抓包
+    if let (Some(compare), Some(mut comparison)) = (compare, comparison) {
+        info!("compare with {}", compare);
+        comparison.start_candidate();
+        let source = Source::PcapFile(PcapFileSource::new(&PathBuf::from(compare))?);
+        analyzers::run(source, &mut [&mut comparison], &mut [])?;
+        analyzers.push(Box::new(comparison));
+    }
+
+    if let (Some(xlsx), Some(report)) = (xlsx, report) {
+        info!("write excel report to {}", xlsx);
+        report.to_xlsx_file(xlsx)?;
+        analyzers.push(Box::new(report));
+    }
+
+    if let (Some(discover), Some(discovery)) = (discover, discovery) {
+        info!("write discovered matrix to {}", discover);
+        discovery.to_matrix().to_json_file(discover)?;
+        analyzers.push(Box::new(discovery));
+    }
+
+    let mut stdout = std::io::stdout().lock();
+    match matches.get_one::<String>("format").map(|s| s.as_str()) {
+        Some("json") => {
+            let mut reports = serde_json::Map::new();
+            for analyzer in &analyzers {
+                match analyzer.json_report() {
+                    Some(report) => {
+                        reports.insert(analyzer.name().to_owned(), report);
                     }
-                } else {
-                    return Err(MyError::ArgInputError("arg matrix file extension error".to_owned()));
+                    None => info!("{} has no json report, skip.", analyzer.name()),
                 }
             }
-            Err(_) => {
-                return Err(MyError::ArgInputError("arg matrix path error".to_owned()));
+            serde_json::to_writer_pretty(&mut stdout, &reports)?;
+            writeln!(stdout)?;
+        }
+        _ => {
+            for analyzer in &analyzers {
+                analyzer.write_report(&mut stdout)?;
             }
         }
-    } else {
-        return Err(MyError::ArgInputError("arg matrix error".to_owned()));
     }
 
-    let filter = matches.get_one::<String>("filter");
-    // TODO: filter parse
+    if scenario_failed {
+        stdout.flush()?;
+        std::process::exit(1);
+    }
 
-    // parse data source
-    let source: Source;
-    // match (
-    //     matches.get_one::<String>("input_from_adb"),
-    //     matches.get_one::<String>("input_from_file"),
-    //     matches.get_one::<String>("input_from_local_interface"),
-    // ) {
-    //     (None, None, None) => todo!(),
-    //     (None, None, Some(input_from_local_interface)) => {
-    //         info!(
-    //             "input_from_local_interface:{}",
-    //             input_from_local_interface.to_string()
-    //         );
-    //         todo!()
-    //     }
-    //     (None, Some(input_from_file), None) => {
-    //         info!("input_from_file:{}", input_from_file);
-    //         todo!()
-    //     }
-    //     (Some(input_from_adb), None, None) => {
-    //         info!("input_from_adb:{}", input_from_adb);
-    //         todo!()
-    //     }
-    //     _ => {
-    //         return Err(MyError::ArgInputError("data source".to_owned()));
-    //     }
-    // }
-
-    // Ok((matrix,))
     Ok(())
 }
+
+/// 以秒为单位的时间参数，负数或超出范围时报错
+fn seconds_arg(matches: &clap::ArgMatches, id: &str) -> Result<Option<Duration>, MyError> {
+    matches
+        .get_one::<f64>(id)
+        .map(|&seconds| {
+            Duration::try_from_secs_f64(seconds)
+                .map_err(|_| MyError::ArgInputError(format!("{} {}", id, seconds)))
+        })
+        .transpose()
+}
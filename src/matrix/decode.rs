@@ -0,0 +1,316 @@
+/// 按照矩阵中的数据类型把载荷解析成JSON值
+/// 数值按大端解析；字符串按编码解析，去掉BOM与结尾的\0；结构体解析成以成员名为键的对象
+/// 长度字段的大小、结构体是否带长度字段取自矩阵的序列化参数
+/// 解析的同时记录已经解析的字节数与超出 DYNAMIC(min, max) 范围的长度，载荷布局检查直接使用这些结果
+use serde_json::{Map, Number, Value};
+
+use super::types::{Matrix, MatrixType, NumberType, StringArrayLength, StringEncoding};
+
+/// 数据类型嵌套的最大深度，防止矩阵中的循环引用
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    TooShort,
+    /// 找不到或者未实现的数据类型
+    UnknownType,
+}
+
+/// 动态长度的字符串（字节数）或数组（元素个数）超出了矩阵中的范围
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LengthOutOfBounds {
+    pub data_type: String,
+    pub min: usize,
+    pub max: usize,
+}
+
+/// 按照数据类型依次解析载荷中的参数
+pub struct Decoder<'a> {
+    matrix: &'a Matrix,
+    data: &'a [u8],
+    pos: usize,
+    out_of_bounds: Vec<LengthOutOfBounds>,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(matrix: &'a Matrix, data: &'a [u8]) -> Self {
+        Decoder {
+            matrix,
+            data,
+            pos: 0,
+            out_of_bounds: vec![],
+        }
+    }
+
+    /// 解析下一个参数
+    pub fn decode_next(&mut self, name: &str) -> Result<Value, DecodeError> {
+        self.decode(name, self.data.len(), 0)
+    }
+
+    /// 已经解析的字节数，之后的字节不属于任何参数
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub fn out_of_bounds(&self) -> &[LengthOutOfBounds] {
+        &self.out_of_bounds
+    }
+
+    fn check_bounds(&mut self, name: &str, length: usize, min: usize, max: usize) {
+        if length < min || length > max {
+            self.out_of_bounds.push(LengthOutOfBounds {
+                data_type: name.to_owned(),
+                min,
+                max,
+            });
+        }
+    }
+
+    fn take(&mut self, n: usize, end: usize) -> Result<&'a [u8], DecodeError> {
+        if self.pos + n > end {
+            return Err(DecodeError::TooShort);
+        }
+        let data = &self.data[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(data)
+    }
+
+    fn unsigned(&mut self, n: usize, end: usize) -> Result<u64, DecodeError> {
+        let data = self.take(n, end)?;
+        Ok(data.iter().fold(0, |acc, b| (acc << 8) | *b as u64))
+    }
+
+    /// 读取长度字段，返回内容的结束位置
+    fn sub_end(&mut self, size: usize, end: usize) -> Result<usize, DecodeError> {
+        let length = self.unsigned(size, end)? as usize;
+        match self.pos + length <= end {
+            true => Ok(self.pos + length),
+            false => Err(DecodeError::TooShort),
+        }
+    }
+
+    fn number(&mut self, size: &NumberType, end: usize) -> Result<Value, DecodeError> {
+        let raw = self.unsigned(size.size(), end)?;
+        let bits = size.size() as u32 * 8;
+        // 有符号数需要从最高位扩展符号
+        let signed = |raw: u64| ((raw << (64 - bits)) as i64) >> (64 - bits);
+        Ok(match size {
+            NumberType::Boolean => Value::Bool(raw != 0),
+            NumberType::Uint8 | NumberType::Uint16 | NumberType::Uint32 | NumberType::Uint64 => {
+                Value::from(raw)
+            }
+            NumberType::Sint8 | NumberType::Sint16 | NumberType::Sint32 | NumberType::Sint64 => {
+                Value::from(signed(raw))
+            }
+            NumberType::Float32 => Number::from_f64(f32::from_bits(raw as u32) as f64)
+                .map(Value::Number)
+                .unwrap_or(Value::Null),
+            NumberType::Float64 => Number::from_f64(f64::from_bits(raw))
+                .map(Value::Number)
+                .unwrap_or(Value::Null),
+        })
+    }
+
+    fn string(data: &[u8], encoding: &StringEncoding) -> Value {
+        let text = match data {
+            [0xEF, 0xBB, 0xBF, rest @ ..] => String::from_utf8_lossy(rest).into_owned(),
+            [0xFF, 0xFE, rest @ ..] => utf16(rest, u16::from_le_bytes),
+            [0xFE, 0xFF, rest @ ..] => utf16(rest, u16::from_be_bytes),
+            _ => match encoding {
+                StringEncoding::UTF8 => String::from_utf8_lossy(data).into_owned(),
+                StringEncoding::UTF16LE => utf16(data, u16::from_le_bytes),
+                StringEncoding::UTF16BE => utf16(data, u16::from_be_bytes),
+            },
+        };
+        Value::String(text.trim_end_matches('\0').to_owned())
+    }
+
+    fn decode(&mut self, name: &str, end: usize, depth: usize) -> Result<Value, DecodeError> {
+        let matrix = self.matrix;
+        let node = match matrix.data_types.get(name) {
+            Some(node) if depth < MAX_DEPTH => node,
+            _ => return Err(DecodeError::UnknownType),
+        };
+        let parameter = &matrix.serialization_parameter;
+        Ok(match &node.data_type {
+            MatrixType::Number { size } => self.number(size, end)?,
+            MatrixType::String { length, encoding } => {
+                let data = match length {
+                    StringArrayLength::FIXED(0) => return Err(DecodeError::UnknownType),
+                    StringArrayLength::FIXED(n) => self.take(*n, end)?,
+                    StringArrayLength::DYNAMIC(min, max) => {
+                        let sub_end =
+                            self.sub_end(parameter.string_length_field_size.bytes(), end)?;
+                        self.check_bounds(name, sub_end - self.pos, *min, *max);
+                        self.take(sub_end - self.pos, sub_end)?
+                    }
+                };
+                Self::string(data, encoding)
+            }
+            MatrixType::Array { length, member } => {
+                let mut values = vec![];
+                match length {
+                    StringArrayLength::FIXED(0) => return Err(DecodeError::UnknownType),
+                    StringArrayLength::FIXED(n) => {
+                        for _ in 0..*n {
+                            values.push(self.decode(&member.member_data_type, end, depth + 1)?);
+                        }
+                    }
+                    StringArrayLength::DYNAMIC(min, max) => {
+                        let sub_end =
+                            self.sub_end(parameter.array_length_field_size.bytes(), end)?;
+                        while self.pos < sub_end {
+                            let pos = self.pos;
+                            values.push(self.decode(
+                                &member.member_data_type,
+                                sub_end,
+                                depth + 1,
+                            )?);
+                            // 元素不占字节（例如没有成员也没有长度字段的结构体）时无法继续，跳过剩下的内容
+                            if self.pos == pos {
+                                self.pos = sub_end;
+                            }
+                        }
+                        self.check_bounds(name, values.len(), *min, *max);
+                    }
+                }
+                Value::Array(values)
+            }
+            MatrixType::Struct { members } => {
+                let sub_end = match parameter.length_field_for_struct {
+                    true => self.sub_end(parameter.struct_length_field_size.bytes(), end)?,
+                    false => end,
+                };
+                let mut values = Map::new();
+                for member in members {
+                    let value = self.decode(&member.member_data_type, sub_end, depth + 1)?;
+                    values.insert(member.member_name.clone(), value);
+                }
+                if parameter.length_field_for_struct {
+                    self.pos = sub_end;
+                }
+                Value::Object(values)
+            }
+            MatrixType::Unimplemented => return Err(DecodeError::UnknownType),
+        })
+    }
+}
+
+fn utf16(data: &[u8], from_bytes: fn([u8; 2]) -> u16) -> String {
+    let units: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|c| from_bytes([c[0], c[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+impl Matrix {
+    /// 依次解析载荷中的参数，返回 (类型名, 值)，参数之后多余的字节忽略
+    pub fn decode_parameters(
+        &self,
+        types: &[&str],
+        payload: &[u8],
+    ) -> Result<Vec<(String, Value)>, DecodeError> {
+        let mut decoder = Decoder::new(self, payload);
+        types
+            .iter()
+            .map(|name| Ok((name.to_string(), decoder.decode_next(name)?)))
+            .collect()
+    }
+}
+
+#[test]
+fn decode_struct_parameters() {
+    use super::types::{MatrixDataNode, MatrixMember};
+
+    let mut matrix = Matrix::default();
+    let mut insert = |name: &str, data_type: MatrixType| {
+        matrix.data_types.insert(
+            name.to_owned(),
+            MatrixDataNode {
+                name: name.to_owned(),
+                description: "".to_owned(),
+                data_type,
+                ..Default::default()
+            },
+        );
+    };
+    insert(
+        "sint16",
+        MatrixType::Number {
+            size: NumberType::Sint16,
+        },
+    );
+    insert(
+        "Name",
+        MatrixType::String {
+            length: StringArrayLength::DYNAMIC(0, 8),
+            encoding: StringEncoding::UTF8,
+        },
+    );
+    let member = |name: &str, data_type: &str| MatrixMember {
+        member_name: name.to_owned(),
+        member_data_type: data_type.to_owned(),
+        ..Default::default()
+    };
+    insert(
+        "Seat",
+        MatrixType::Struct {
+            members: vec![member("angle", "sint16"), member("owner", "Name")],
+        },
+    );
+
+    // 缺省的序列化参数：长度字段1字节，结构体不带长度字段
+    let payload = [0xFF, 0xFE, 4, 0xEF, 0xBB, 0xBF, b'A', 0xFF];
+    let values = matrix.decode_parameters(&["Seat"], &payload).unwrap();
+    assert_eq!(
+        values,
+        vec![(
+            "Seat".to_owned(),
+            serde_json::json!({"angle": -2, "owner": "A"})
+        )]
+    );
+    assert_eq!(
+        matrix.decode_parameters(&["Seat"], &payload[..4]),
+        Err(DecodeError::TooShort)
+    );
+}
+
+#[test]
+fn decode_zero_size_elements() {
+    use super::types::{MatrixDataNode, MatrixMember};
+
+    let mut matrix = Matrix::default();
+    let mut insert = |name: &str, data_type: MatrixType| {
+        matrix.data_types.insert(
+            name.to_owned(),
+            MatrixDataNode {
+                name: name.to_owned(),
+                description: "".to_owned(),
+                data_type,
+                ..Default::default()
+            },
+        );
+    };
+    insert("Empty", MatrixType::Struct { members: vec![] });
+    insert(
+        "Empties",
+        MatrixType::Array {
+            length: StringArrayLength::DYNAMIC(0, 8),
+            member: MatrixMember {
+                member_name: "empty".to_owned(),
+                member_data_type: "Empty".to_owned(),
+                ..Default::default()
+            },
+        },
+    );
+
+    // 元素不占字节，解析一个后跳过数组剩下的内容
+    let values = matrix
+        .decode_parameters(&["Empties"], &[3, 1, 2, 3])
+        .unwrap();
+    assert_eq!(
+        values,
+        vec![("Empties".to_owned(), serde_json::json!([{}]))]
+    );
+}
@@ -1,4 +1,5 @@
 use std::borrow::BorrowMut;
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::net::{IpAddr, Ipv4Addr};
 use std::path::Path;
@@ -8,8 +9,9 @@ use log::{debug, error, info};
 use serde::{de, Deserialize, Deserializer};
 
 use crate::errors::MyError;
-use crate::types::SomeipServiceId;
+use crate::types::{mac_from_str, SomeipMethodId, SomeipServiceId, SomeipTransportPortocol};
 
+use super::parse_hex_or_dec;
 use super::types::*;
 
 fn deserialize_hex<'de, D>(deserializer: D) -> Result<u16, D::Error>
@@ -112,6 +114,62 @@ struct DataTypeDefinitionRecord {
     // discrete_value_defination: String,
 }
 
+/// 单元格可能是文本也可能是数字，统一转换为字符串，空单元格为None
+fn deserialize_cell_string<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct CellVisitor;
+
+    impl<'de> de::Visitor<'de> for CellVisitor {
+        type Value = Option<String>;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("a cell value")
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+            let v = v.trim();
+            Ok(match v.is_empty() {
+                true => None,
+                false => Some(v.to_owned()),
+            })
+        }
+
+        fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+            Ok(Some(v.to_string()))
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+            Ok(Some(v.to_string()))
+        }
+
+        fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+            Ok(Some(v.to_string()))
+        }
+
+        fn visit_bool<E: de::Error>(self, v: bool) -> Result<Self::Value, E> {
+            Ok(Some(v.to_string()))
+        }
+
+        fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_some<D: Deserializer<'de>>(self, d: D) -> Result<Self::Value, D::Error> {
+            d.deserialize_any(CellVisitor)
+        }
+    }
+
+    deserializer.deserialize_option(CellVisitor)
+}
+
+/// 该表不按表头解析，字段顺序必须与表格的列顺序一致
+/// 合并单元格只有第一行有值，后续行需要沿用上一行的服务与方法
 #[allow(dead_code)]
 #[derive(Deserialize)]
 struct ServiceInterfacesRecord {
@@ -121,36 +179,395 @@ struct ServiceInterfacesRecord {
     service_id: Option<u16>,
     #[serde(rename = "Service Description")]
     service_description: String,
-    // #[serde(rename = "Method/Event/Field")]
-    // method_event_field: String,
-    // #[serde(rename = "Setter/Getter/Notifier")]
-    // setter_getter_notifier: String,
-    // #[serde(rename = "Element Name")]
-    // element_name: String,
-    // #[serde(rename = "Element Description")]
-    // element_description: String,
-    // #[serde(rename = "Method ID/Event ID", deserialize_with = "deserialize_hex")]
-    // method_id: u16,
-    // #[serde(rename = "Eventgroup Name")]
-    // eventgroup_name: String,
-    // #[serde(rename = "Eventgroup ID")]
-    // eventgroup_id: String,
-    // #[serde(rename = "Send Strategy")]
-    // send_strategy: String,
-    // #[serde(rename = "Cyclic Time (ms)")]
-    // cyclic_time_ms: Option<usize>,
-    // #[serde(rename = "Parameter Name")]
-    // parameter_name: String,
-    // #[serde(rename = "IN/OUT")]
-    // in_out: String,
-    // #[serde(rename = "Parameter Description")]
-    // parameter_description: String,
-    // #[serde(rename = "Parameter Data Type")]
-    // parameter_data_type: String,
-    // #[serde(rename = "UDP/TCP")]
-    // udp_tcp: String,
-    // #[serde(rename = "AutoSAR E2E Protection (Profile 6)")]
-    // e2e_protection: String,
+    #[serde(
+        rename = "Method/Event/Field",
+        deserialize_with = "deserialize_cell_string"
+    )]
+    method_event_field: Option<String>,
+    #[serde(
+        rename = "Setter/Getter/Notifier",
+        deserialize_with = "deserialize_cell_string"
+    )]
+    setter_getter_notifier: Option<String>,
+    #[serde(rename = "Element Name", deserialize_with = "deserialize_cell_string")]
+    element_name: Option<String>,
+    #[serde(
+        rename = "Element Description",
+        deserialize_with = "deserialize_cell_string"
+    )]
+    element_description: Option<String>,
+    #[serde(
+        rename = "Method ID/Event ID",
+        deserialize_with = "deserialize_cell_string"
+    )]
+    method_id: Option<String>,
+    #[serde(
+        rename = "Eventgroup Name",
+        deserialize_with = "deserialize_cell_string"
+    )]
+    eventgroup_name: Option<String>,
+    #[serde(rename = "Eventgroup ID", deserialize_with = "deserialize_cell_string")]
+    eventgroup_id: Option<String>,
+    #[serde(rename = "Send Strategy", deserialize_with = "deserialize_cell_string")]
+    send_strategy: Option<String>,
+    #[serde(
+        rename = "Cyclic Time (ms)",
+        deserialize_with = "deserialize_cell_string"
+    )]
+    cyclic_time_ms: Option<String>,
+    #[serde(
+        rename = "Parameter Name",
+        deserialize_with = "deserialize_cell_string"
+    )]
+    parameter_name: Option<String>,
+    #[serde(rename = "IN/OUT", deserialize_with = "deserialize_cell_string")]
+    in_out: Option<String>,
+    #[serde(
+        rename = "Parameter Description",
+        deserialize_with = "deserialize_cell_string"
+    )]
+    parameter_description: Option<String>,
+    #[serde(
+        rename = "Parameter Data Type",
+        deserialize_with = "deserialize_cell_string"
+    )]
+    parameter_data_type: Option<String>,
+    #[serde(rename = "UDP/TCP", deserialize_with = "deserialize_cell_string")]
+    udp_tcp: Option<String>,
+    #[serde(
+        rename = "AutoSAR E2E Protection (Profile 6)",
+        deserialize_with = "deserialize_cell_string"
+    )]
+    e2e_protection: Option<String>,
+}
+
+/// 可选的SD Timing表，每行一个角色，空单元格使用缺省值
+#[derive(Deserialize)]
+struct SdTimingRecord {
+    #[serde(rename = "Role")]
+    role: String,
+    #[serde(
+        rename = "Initial Delay Min (ms)",
+        deserialize_with = "deserialize_cell_string"
+    )]
+    initial_delay_min_ms: Option<String>,
+    #[serde(
+        rename = "Initial Delay Max (ms)",
+        deserialize_with = "deserialize_cell_string"
+    )]
+    initial_delay_max_ms: Option<String>,
+    #[serde(
+        rename = "Repetitions Base Delay (ms)",
+        deserialize_with = "deserialize_cell_string"
+    )]
+    repetitions_base_delay_ms: Option<String>,
+    #[serde(
+        rename = "Repetitions Max",
+        deserialize_with = "deserialize_cell_string"
+    )]
+    repetitions_max: Option<String>,
+    #[serde(
+        rename = "Cyclic Offer Delay (ms)",
+        deserialize_with = "deserialize_cell_string"
+    )]
+    cyclic_offer_delay_ms: Option<String>,
+    #[serde(
+        rename = "Request Response Delay Min (ms)",
+        deserialize_with = "deserialize_cell_string"
+    )]
+    request_response_delay_min_ms: Option<String>,
+    #[serde(
+        rename = "Request Response Delay Max (ms)",
+        deserialize_with = "deserialize_cell_string"
+    )]
+    request_response_delay_max_ms: Option<String>,
+    #[serde(rename = "TTL (s)", deserialize_with = "deserialize_cell_string")]
+    ttl: Option<String>,
+}
+
+impl SdTimingRecord {
+    fn to_sd_timing(&self) -> MatrixSdTiming {
+        // 单元格可能是小数形式的数字，无效的值使用缺省值
+        let parse = |cell: &Option<String>, default: u32| match cell.as_deref() {
+            Some(s) => match s.trim().parse::<f64>() {
+                Ok(v) if (0.0..=u32::MAX as f64).contains(&v) => v as u32,
+                _ => {
+                    error!("invalid sd timing of role {}: {}", self.role, s);
+                    default
+                }
+            },
+            None => default,
+        };
+        let default = MatrixSdTiming::default();
+        MatrixSdTiming {
+            initial_delay_min_ms: parse(&self.initial_delay_min_ms, default.initial_delay_min_ms),
+            initial_delay_max_ms: parse(&self.initial_delay_max_ms, default.initial_delay_max_ms),
+            repetitions_base_delay_ms: parse(
+                &self.repetitions_base_delay_ms,
+                default.repetitions_base_delay_ms,
+            ),
+            repetitions_max: parse(&self.repetitions_max, default.repetitions_max),
+            cyclic_offer_delay_ms: parse(
+                &self.cyclic_offer_delay_ms,
+                default.cyclic_offer_delay_ms,
+            ),
+            request_response_delay_min_ms: parse(
+                &self.request_response_delay_min_ms,
+                default.request_response_delay_min_ms,
+            ),
+            request_response_delay_max_ms: parse(
+                &self.request_response_delay_max_ms,
+                default.request_response_delay_max_ms,
+            ),
+            ttl: parse(&self.ttl, default.ttl),
+            tolerance_ms: default.tolerance_ms,
+        }
+    }
+}
+
+/// 可选的Application Errors表，每行一个方法的一个应用错误码
+#[derive(Deserialize)]
+struct ApplicationErrorRecord {
+    #[serde(rename = "Service ID", deserialize_with = "deserialize_empty_or_hex")]
+    service_id: Option<u16>,
+    #[serde(rename = "Method ID", deserialize_with = "deserialize_cell_string")]
+    method_id: Option<String>,
+    #[serde(rename = "Return Code", deserialize_with = "deserialize_cell_string")]
+    return_code: Option<String>,
+    #[serde(rename = "Error Name", deserialize_with = "deserialize_cell_string")]
+    name: Option<String>,
+    #[serde(
+        rename = "Error Description",
+        deserialize_with = "deserialize_cell_string"
+    )]
+    description: Option<String>,
+    #[serde(
+        rename = "Error Data Type",
+        deserialize_with = "deserialize_cell_string"
+    )]
+    data: Option<String>,
+}
+
+/// DataTypeDefinition表中数值类型的单位与枚举定义，旧版本的矩阵没有这两列
+#[derive(Deserialize)]
+struct DataTypeValueRecord {
+    #[serde(
+        rename = "Parameter Data Type Name",
+        deserialize_with = "deserialize_cell_string"
+    )]
+    parameter_data_type_name: Option<String>,
+    #[serde(rename = "Data Category", deserialize_with = "deserialize_cell_string")]
+    data_category: Option<String>,
+    #[serde(rename = "Unit", deserialize_with = "deserialize_cell_string")]
+    unit: Option<String>,
+    #[serde(
+        rename = "Discrete Value Defination",
+        deserialize_with = "deserialize_cell_string"
+    )]
+    discrete_value_defination: Option<String>,
+}
+
+/// 枚举定义每行一个值，如 "0x0: Off" 或 "1=On"
+fn parse_enum_values(defination: &str) -> Vec<(u64, String)> {
+    defination
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            let split = line.find(|c: char| c == ':' || c == '=' || c.is_whitespace())?;
+            let (value, label) = line.split_at(split);
+            let value = parse_hex_or_dec(value)?;
+            let label = label.trim_start().trim_start_matches([':', '=']).trim();
+            Some((value, label.to_owned()))
+        })
+        .collect()
+}
+
+/// 根据 Method/Event/Field 与 Setter/Getter/Notifier 两列确定方法类型
+/// Method默认是RR，只有明确写了Fire&Forget的才是FF
+fn parse_method_type(record: &ServiceInterfacesRecord) -> Option<MatrixServiceMethodType> {
+    let kind = record.method_event_field.clone()?.to_lowercase();
+    let sub_kind = record
+        .setter_getter_notifier
+        .clone()
+        .unwrap_or_default()
+        .to_lowercase();
+    let is_ff = |s: &str| s.contains("fire") || s.contains("forget") || s == "ff";
+
+    if kind.contains("method") {
+        if is_ff(&kind) || is_ff(&sub_kind) {
+            Some(MatrixServiceMethodType::FFMethod {
+                data_in: vec![],
+                data_in_ref: vec![],
+            })
+        } else {
+            Some(MatrixServiceMethodType::RRMethod {
+                data_in: vec![],
+                data_in_ref: vec![],
+                data_out: Default::default(),
+                data_out_ref: None,
+            })
+        }
+    } else if kind.contains("event") {
+        Some(MatrixServiceMethodType::EVENT {
+            data_out: Default::default(),
+            data_out_ref: None,
+        })
+    } else if kind.contains("field") {
+        let field_type = if sub_kind.contains("getter") {
+            MatrixServiceMethodFieldType::Getter
+        } else if sub_kind.contains("setter") {
+            MatrixServiceMethodFieldType::Setter
+        } else if sub_kind.contains("notifier") {
+            MatrixServiceMethodFieldType::Notifier
+        } else {
+            return None;
+        };
+        Some(MatrixServiceMethodType::FIELD {
+            field_type,
+            data: Default::default(),
+            data_ref: None,
+        })
+    } else {
+        None
+    }
+}
+
+/// 解析E2E保护列，列名默认是Profile 6，内容可以是：
+/// 1. 空、"/"、"No"：没有保护
+/// 2. 单独的DataID，如 0x1234
+/// 3. 键值对，用 ; , 或换行分隔，如 Profile=4;DataID=0x1234;Offset=64;CounterOffset=8;MaxDeltaCounter=2;DataIDList=1 2 3
+fn parse_e2e_config(cell: &str) -> Option<MatrixE2eConfig> {
+    let cell = cell.trim();
+    if cell.is_empty() || cell == "/" || cell == "-" || cell.eq_ignore_ascii_case("no") {
+        return None;
+    }
+
+    let mut config = MatrixE2eConfig {
+        profile: MatrixE2eProfile::P06,
+        data_id: 0,
+        data_id_list: vec![],
+        offset: 0,
+        counter_offset: None,
+        max_delta_counter: None,
+    };
+    if let Some(data_id) = parse_hex_or_dec::<u32>(cell) {
+        config.data_id = data_id;
+        return Some(config);
+    }
+
+    let mut has_data_id = false;
+    for item in cell.split([';', ',', '\n']) {
+        let (key, value) = match item.split_once(['=', ':']) {
+            Some((key, value)) => (key, value.trim()),
+            None => continue,
+        };
+        let key = key.to_lowercase().replace([' ', '_'], "");
+        match key.as_str() {
+            "profile" => {
+                config.profile = match value.trim_start_matches(|c: char| !c.is_ascii_digit()) {
+                    "1" | "01" => MatrixE2eProfile::P01,
+                    "2" | "02" => MatrixE2eProfile::P02,
+                    "4" | "04" => MatrixE2eProfile::P04,
+                    "5" | "05" => MatrixE2eProfile::P05,
+                    "6" | "06" => MatrixE2eProfile::P06,
+                    "7" | "07" => MatrixE2eProfile::P07,
+                    "11" => MatrixE2eProfile::P11,
+                    _ => {
+                        error!("unsupported e2e profile:{}", value);
+                        return None;
+                    }
+                }
+            }
+            "dataid" => {
+                config.data_id = parse_hex_or_dec::<u32>(value)?;
+                has_data_id = true;
+            }
+            "dataidlist" => {
+                config.data_id_list = value
+                    .split_whitespace()
+                    .filter_map(|s| parse_hex_or_dec::<u32>(s).map(|v| v as u8))
+                    .collect();
+                has_data_id = true;
+            }
+            "offset" => config.offset = value.parse().ok()?,
+            "counteroffset" => config.counter_offset = Some(value.parse().ok()?),
+            "maxdeltacounter" => config.max_delta_counter = Some(parse_hex_or_dec::<u32>(value)?),
+            _ => debug!("unknown e2e config key:{}", key),
+        }
+    }
+    if !has_data_id {
+        error!("e2e config without data id:{}", cell);
+        return None;
+    }
+    Some(config)
+}
+
+/// 把参数行的数据类型填入方法中
+fn fill_method_parameter(method: &mut MatrixServiceMethod, record: &ServiceInterfacesRecord) {
+    let data_type = match &record.parameter_data_type {
+        Some(data_type) if data_type != "/" => data_type.clone(),
+        _ => return,
+    };
+    let is_out = record
+        .in_out
+        .as_ref()
+        .is_some_and(|s| s.to_lowercase().contains("out"));
+    match &mut method.method_type {
+        MatrixServiceMethodType::RRMethod {
+            data_in, data_out, ..
+        } => {
+            if is_out {
+                if data_out.is_empty() {
+                    *data_out = data_type;
+                }
+            } else {
+                data_in.push(data_type);
+            }
+        }
+        MatrixServiceMethodType::FFMethod { data_in, .. } => data_in.push(data_type),
+        MatrixServiceMethodType::EVENT { data_out, .. } => *data_out = data_type,
+        MatrixServiceMethodType::FIELD { data, .. } => *data = data_type,
+    }
+}
+
+/// 把Application Errors表的一行加入对应的方法，同一个方法重复的错误码只保留第一个
+fn add_application_error(
+    services: &mut HashMap<SomeipServiceId, MatrixService>,
+    record: &ApplicationErrorRecord,
+) {
+    let method = record.service_id.and_then(|service_id| {
+        let method_id = parse_hex_or_dec(record.method_id.as_deref()?)?;
+        services.get_mut(&service_id)?.methods.get_mut(&method_id)
+    });
+    let return_code = record
+        .return_code
+        .as_deref()
+        .and_then(parse_hex_or_dec::<u8>);
+    match (method, return_code) {
+        (Some(method), Some(return_code))
+            if method
+                .application_errors
+                .iter()
+                .any(|error| error.return_code == return_code) =>
+        {
+            error!(
+                "Duplicate Application Error:{:?}.{:?} {:?}",
+                record.service_id, record.method_id, record.return_code
+            )
+        }
+        (Some(method), Some(return_code)) => {
+            method.application_errors.push(MatrixApplicationError {
+                return_code,
+                name: record.name.clone().unwrap_or_default(),
+                description: record.description.clone().unwrap_or_default(),
+                data: record.data.clone().unwrap_or_default(),
+            })
+        }
+        _ => error!(
+            "Invalid Application Error:{:?}.{:?} {:?}",
+            record.service_id, record.method_id, record.return_code
+        ),
+    }
 }
 
 impl Matrix {
@@ -179,16 +596,16 @@ impl Matrix {
 
         fn get_or_insert_role_for_roles(
             roles: &mut HashMap<String, MatrixRole>,
-            role_name: &String,
-            role_ip: &String,
-            role_mac: &String,
+            role_name: &str,
+            role_ip: &str,
+            role_mac: &str,
         ) -> MatrixRoleRef {
-            roles.entry(role_name.clone()).or_insert(MatrixRole {
-                name: role_name.clone(),
+            roles.entry(role_name.to_owned()).or_insert(MatrixRole {
+                name: role_name.to_owned(),
                 ip_addr: role_ip
                     .parse()
                     .unwrap_or(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))),
-                mac_addr: role_mac.as_bytes().try_into().unwrap_or([0, 0, 0, 0, 0, 0]),
+                mac_addr: mac_from_str(role_mac).unwrap_or([0, 0, 0, 0, 0, 0]),
             })
         }
 
@@ -225,6 +642,7 @@ impl Matrix {
                 major_verison: record.major_version,
                 minor_version: record.minor_version,
                 methods: HashMap::new(),
+                eventgroups: HashMap::new(),
                 server_client: vec![].into(),
             });
 
@@ -273,9 +691,9 @@ impl Matrix {
             )
         }
 
-        fn parse_number_data_type(record_data_type: &String) -> Result<MatrixType, MyError> {
+        fn parse_number_data_type(record_data_type: &str) -> Result<MatrixType, MyError> {
             Ok(MatrixType::Number {
-                size: NumberType::try_from(record_data_type.clone())?,
+                size: NumberType::try_from(record_data_type.to_owned())?,
             })
         }
 
@@ -294,6 +712,23 @@ impl Matrix {
             })
         }
 
+        // 数值类型的成员按类型名登记到data_types中，成员通过类型名引用
+        fn number_member(
+            data_types: &mut HashMap<String, MatrixDataNode>,
+            record_data_type: &str,
+        ) -> Result<(String, *const MatrixDataNode), MyError> {
+            let data_type = parse_number_data_type(record_data_type)?;
+            let ptr: *const MatrixDataNode = data_types
+                .entry(record_data_type.to_owned())
+                .or_insert(MatrixDataNode {
+                    name: record_data_type.to_owned(),
+                    description: Default::default(),
+                    data_type,
+                    ..Default::default()
+                });
+            Ok((record_data_type.to_owned(), ptr))
+        }
+
         let range = wb.worksheet_range("DataTypeDefinition").unwrap();
         let iter_records =
             RangeDeserializerBuilder::with_deserialize_headers::<DataTypeDefinitionRecord>()
@@ -325,7 +760,7 @@ impl Matrix {
             let record_data_type_description = record
                 .data_type_description
                 .clone()
-                .unwrap_or_else(|| "".to_string())
+                .unwrap_or_default()
                 .to_lowercase();
 
             if record.data_category.is_none() {
@@ -345,22 +780,19 @@ impl Matrix {
                         name: last_key.clone(),
                         description: record_data_type_description.clone(),
                         data_type: Default::default(),
+                        ..Default::default()
                     });
 
             last_node.description = record.data_type_description.clone().unwrap_or_default();
 
-            let record_data_type = record
-                .data_type
-                .clone()
-                .unwrap_or_else(|| "".to_string())
-                .to_lowercase();
+            let record_data_type = record.data_type.clone().unwrap_or_default().to_lowercase();
 
             // if let Some(ref mut last_node_mut) = last_node {
             // if let last_node_mut = last_node {
             match last_record_data_category.as_str() {
                 "struct" => {
                     // 首次确定类型需初始化
-                    if let MatrixType::Unimplemented {} = last_node.data_type {
+                    if let MatrixType::Unimplemented = last_node.data_type {
                         last_node.borrow_mut().data_type = MatrixType::Struct {
                             members: Default::default(),
                         };
@@ -376,12 +808,10 @@ impl Matrix {
                         }
                     };
 
-                    let record_member_description = record
-                        .member_description
-                        .clone()
-                        .unwrap_or_else(|| "".to_string());
+                    let record_member_description =
+                        record.member_description.clone().unwrap_or_default();
 
-                    let ptr: *const MatrixDataNode = match record_data_type.as_str() {
+                    let (member_data_type, ptr) = match record_data_type.as_str() {
                         "struct" | "array" | "/" | "" | "union" | "string" | "utf-8" => {
                             // 先按顺序猜测信息
                             let record_member_data_type_reference = &record
@@ -399,19 +829,17 @@ impl Matrix {
                                     record_member_data_type_reference
                                 };
 
-                            data_types
+                            let ptr: *const MatrixDataNode = data_types
                                 .entry(struct_array_union_in_struct_key_name.clone())
                                 .or_insert(MatrixDataNode {
                                     name: struct_array_union_in_struct_key_name.clone(),
                                     description: record_member_description.clone(),
                                     data_type: Default::default(),
-                                })
+                                    ..Default::default()
+                                });
+                            (struct_array_union_in_struct_key_name.clone(), ptr)
                         }
-                        _ => &MatrixDataNode {
-                            name: record_member_name.clone(),
-                            description: record_member_description.clone(),
-                            data_type: parse_number_data_type(&record_data_type)?,
-                        },
+                        _ => number_member(&mut data_types, &record_data_type)?,
                     };
 
                     let last_node_mut = data_types.get_mut(&last_key.clone()).unwrap();
@@ -421,13 +849,14 @@ impl Matrix {
                         (*members).push(MatrixMember {
                             member_name: record_member_name.clone(),
                             member_description: record_member_description.clone(),
+                            member_data_type,
                             member_ref: Some(ptr),
                         })
                     }
                 }
                 "array" => {
                     // 首次确定类型需初始化
-                    if let MatrixType::Unimplemented {} = last_node.data_type {
+                    if let MatrixType::Unimplemented = last_node.data_type {
                         last_node.borrow_mut().data_type = MatrixType::Array {
                             length: Default::default(),
                             member: Default::default(),
@@ -442,7 +871,7 @@ impl Matrix {
                         .clone()
                         .unwrap_or_default();
 
-                    let ptr: *const MatrixDataNode = match record_data_type.as_str() {
+                    let (member_data_type, ptr) = match record_data_type.as_str() {
                         "struct" | "array" | "/" | "" | "union" | "string" | "utf-8" => {
                             // Member Datatype Reference 优先级高于 Member Name
                             // 且member_name一定不为空
@@ -461,22 +890,17 @@ impl Matrix {
                                     record_member_data_type_reference
                                 };
 
-                            data_types
+                            let ptr: *const MatrixDataNode = data_types
                                 .entry(struct_array_union_in_struct_key_name.clone())
                                 .or_insert(MatrixDataNode {
                                     name: struct_array_union_in_struct_key_name.clone(),
                                     description: record_member_description.clone(),
                                     data_type: Default::default(),
-                                })
-                        }
-                        _ => {
-                            // 对于数组中的数值类型，无名、无描述、仅有数据类型
-                            &MatrixDataNode {
-                                name: Default::default(),
-                                description: Default::default(),
-                                data_type: parse_number_data_type(&record_data_type)?,
-                            }
+                                    ..Default::default()
+                                });
+                            (struct_array_union_in_struct_key_name.clone(), ptr)
                         }
+                        _ => number_member(&mut data_types, &record_data_type)?,
                     };
 
                     let last_node_mut = data_types.get_mut(&last_key.clone()).unwrap();
@@ -489,6 +913,7 @@ impl Matrix {
                         (*member) = MatrixMember {
                             member_name: record_member_name.clone(),
                             member_description: record_member_description.clone(),
+                            member_data_type,
                             member_ref: Some(ptr),
                         };
                     }
@@ -514,6 +939,37 @@ impl Matrix {
             // }
         }
 
+        // Fill Units and Enumerations，只对整个类型是数值的行有效
+        if let Ok(iter_records) =
+            RangeDeserializerBuilder::with_deserialize_headers::<DataTypeValueRecord>()
+                .from_range(&range)
+        {
+            for result in iter_records {
+                let record: DataTypeValueRecord = result?;
+                let is_number = matches!(
+                    record
+                        .data_category
+                        .as_deref()
+                        .map(str::to_lowercase)
+                        .as_deref(),
+                    Some("integer" | "enumeration" | "float" | "double")
+                );
+                let node = match record.parameter_data_type_name {
+                    Some(name) if is_number => match data_types.get_mut(&name) {
+                        Some(node) => node,
+                        None => continue,
+                    },
+                    _ => continue,
+                };
+                if let Some(unit) = record.unit.filter(|unit| unit != "/") {
+                    node.unit = unit;
+                }
+                if let Some(defination) = record.discrete_value_defination {
+                    node.enum_values = parse_enum_values(&defination);
+                }
+            }
+        }
+
         // Fill Methods
         let range = wb.worksheet_range("ServiceInterfaces")?;
         let iter_records =
@@ -523,27 +979,132 @@ impl Matrix {
                 .skip(2);
 
         // 同一个服务的方法必然连续
-        let mut last_service: &mut MatrixService;
         let mut last_service_id = 0;
+        let mut last_method_id: Option<SomeipMethodId> = None;
 
         for result in iter_records {
             let record: ServiceInterfacesRecord = result?;
             // 遇到空行跳过当前行
-            if record.service_id.is_none() {
+            if record.service_id.is_none()
+                && record.method_id.is_none()
+                && record.parameter_data_type.is_none()
+            {
                 continue;
             }
             debug!("{:?}", record.service_interface_name);
 
-            if record.service_id.unwrap() != last_service_id {
-                last_service_id = record.service_id.unwrap();
-                if let Some(service) = services.get_mut(&last_service_id) {
-                    last_service = service;
-                    last_service.service_description = record.service_description;
-                } else {
+            if let Some(service_id) = record.service_id {
+                if service_id != last_service_id {
+                    last_service_id = service_id;
+                    last_method_id = None;
+                    if let Some(service) = services.get_mut(&last_service_id) {
+                        service.service_description = record.service_description.clone();
+                    }
+                }
+            }
+            let service = match services.get_mut(&last_service_id) {
+                Some(service) => service,
+                None => {
                     // 读取错误也跳过当前行
                     error!("Invalid Service ID");
                     continue;
                 }
+            };
+
+            if let Some(method_id) = &record.method_id {
+                let method_id = match parse_hex_or_dec(method_id) {
+                    Some(method_id) => method_id,
+                    None => {
+                        error!("Invalid Method ID:{}", method_id);
+                        last_method_id = None;
+                        continue;
+                    }
+                };
+                last_method_id = Some(method_id);
+                if let Entry::Vacant(entry) = service.methods.entry(method_id) {
+                    let method_type = match parse_method_type(&record) {
+                        Some(method_type) => method_type,
+                        None => {
+                            error!(
+                                "Invalid Method Type:{:?} {:?}",
+                                record.method_event_field, record.setter_getter_notifier
+                            );
+                            last_method_id = None;
+                            continue;
+                        }
+                    };
+                    entry.insert(MatrixServiceMethod {
+                        method_id,
+                        method_name: record.element_name.clone().unwrap_or_default(),
+                        method_type,
+                        transport_protocol: match record.udp_tcp.as_deref() {
+                            Some(s) if s.eq_ignore_ascii_case("tcp") => {
+                                SomeipTransportPortocol::TCP
+                            }
+                            _ => SomeipTransportPortocol::UDP,
+                        },
+                        cycle_time_ms: record
+                            .cyclic_time_ms
+                            .as_deref()
+                            .and_then(|s| s.parse::<f64>().ok())
+                            .filter(|ms| *ms > 0.0)
+                            .map(|ms| ms as u32),
+                        e2e: record.e2e_protection.as_deref().and_then(parse_e2e_config),
+                        application_errors: vec![],
+                        mother_service_ref: Default::default(),
+                    });
+                }
+            }
+
+            if let (Some(method_id), Some(eventgroup_id)) = (
+                last_method_id,
+                record.eventgroup_id.as_deref().and_then(parse_hex_or_dec),
+            ) {
+                let eventgroup =
+                    service
+                        .eventgroups
+                        .entry(eventgroup_id)
+                        .or_insert(MatrixEventgroup {
+                            eventgroup_id,
+                            eventgroup_name: record.eventgroup_name.clone().unwrap_or_default(),
+                            methods: vec![],
+                        });
+                if !eventgroup.methods.contains(&method_id) {
+                    eventgroup.methods.push(method_id);
+                }
+            }
+
+            if let Some(method) = last_method_id.and_then(|id| service.methods.get_mut(&id)) {
+                fill_method_parameter(method, &record);
+            }
+        }
+
+        // Fill SD Timing，旧版本的矩阵没有这张表
+        let mut sd_timing = HashMap::new();
+        if let Ok(range) = wb.worksheet_range("SD Timing") {
+            let iter_records =
+                RangeDeserializerBuilder::with_deserialize_headers::<SdTimingRecord>()
+                    .from_range(&range)?;
+            for result in iter_records {
+                let record: SdTimingRecord = result?;
+                if record.role.is_empty() {
+                    continue;
+                }
+                sd_timing.insert(record.role.clone(), record.to_sd_timing());
+            }
+        }
+
+        // Fill Application Errors，旧版本的矩阵没有这张表
+        if let Ok(range) = wb.worksheet_range("Application Errors") {
+            let iter_records =
+                RangeDeserializerBuilder::with_deserialize_headers::<ApplicationErrorRecord>()
+                    .from_range(&range)?;
+            for result in iter_records {
+                let record: ApplicationErrorRecord = result?;
+                if record.service_id.is_none() && record.return_code.is_none() {
+                    continue;
+                }
+                add_application_error(&mut services, &record);
             }
         }
 
@@ -569,6 +1130,7 @@ impl Matrix {
             services,
             services_map_by_name: HashMap::new(),
             data_types,
+            sd_timing,
         })
     }
 }
@@ -578,8 +1140,7 @@ fn test() -> Result<(), Box<dyn std::error::Error>> {
     use std::env;
     use std::env::set_var;
     use std::fs::File;
-    use std::io::{self, Read, Write};
-    use std::path::Path;
+    use std::io::Write;
 
     set_var("RUST_LOG", "debug");
     env_logger::init();
@@ -594,21 +1155,15 @@ fn test() -> Result<(), Box<dyn std::error::Error>> {
         "{:?}",
         &matrix
             .data_types
-            .get(&"Struct_PickUpPointDetailInfo".to_string())
+            .get("Struct_PickUpPointDetailInfo")
             .unwrap()
     );
+    info!("{:?}", &matrix.data_types.get("Struct_GPSPoint").unwrap());
     info!(
         "{:?}",
         &matrix
             .data_types
-            .get(&"Struct_GPSPoint".to_string())
-            .unwrap()
-    );
-    info!(
-        "{:?}",
-        &matrix
-            .data_types
-            .get(&"String_DynamicStringData200".to_string())
+            .get("String_DynamicStringData200")
             .unwrap()
     );
 
@@ -624,3 +1179,341 @@ fn test() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+/// 测试用：ServiceInterfaces表的一行，没有给出的单元格为空
+#[cfg(test)]
+fn interface_record(
+    cells: serde_json::Value,
+) -> Result<ServiceInterfacesRecord, serde_json::Error> {
+    let mut record = serde_json::json!({
+        "Service InterFace Name": "Climate",
+        "Service ID": "0x1234",
+        "Service Description": "",
+    });
+    for column in [
+        "Method/Event/Field",
+        "Setter/Getter/Notifier",
+        "Element Name",
+        "Element Description",
+        "Method ID/Event ID",
+        "Eventgroup Name",
+        "Eventgroup ID",
+        "Send Strategy",
+        "Cyclic Time (ms)",
+        "Parameter Name",
+        "IN/OUT",
+        "Parameter Description",
+        "Parameter Data Type",
+        "UDP/TCP",
+        "AutoSAR E2E Protection (Profile 6)",
+    ] {
+        record[column] = serde_json::Value::Null;
+    }
+    if let serde_json::Value::Object(cells) = cells {
+        for (column, value) in cells {
+            record[column] = value;
+        }
+    }
+    serde_json::from_value(record)
+}
+
+#[test]
+fn excel_method_type() {
+    use serde_json::json;
+
+    let kind = |kind: serde_json::Value, sub_kind: serde_json::Value| {
+        let record = interface_record(json!({
+            "Method/Event/Field": kind,
+            "Setter/Getter/Notifier": sub_kind,
+        }))
+        .unwrap();
+        parse_method_type(&record).map(|method_type| method_type.kind_name())
+    };
+    assert_eq!(kind(json!("Method"), json!(null)), Some("method"));
+    assert_eq!(
+        kind(json!("Method"), json!("Fire&Forget")),
+        Some("fire&forget method")
+    );
+    assert_eq!(
+        kind(json!("Fire&Forget Method"), json!(null)),
+        Some("fire&forget method")
+    );
+    assert_eq!(
+        kind(json!("method"), json!("FF")),
+        Some("fire&forget method")
+    );
+    assert_eq!(kind(json!("Event"), json!("Notifier")), Some("event"));
+    assert_eq!(kind(json!("Field"), json!("Getter")), Some("field getter"));
+    assert_eq!(kind(json!("FIELD"), json!("setter")), Some("field setter"));
+    assert_eq!(
+        kind(json!("Field"), json!("Notifier")),
+        Some("field notifier")
+    );
+    // 字段没有写明类型、空单元格与不认识的类型都不是方法
+    assert_eq!(kind(json!("Field"), json!(null)), None);
+    assert_eq!(kind(json!(" "), json!(null)), None);
+    assert_eq!(kind(json!(null), json!("Getter")), None);
+    assert_eq!(kind(json!("Signal"), json!(null)), None);
+}
+
+#[test]
+fn excel_method_parameters() {
+    use serde_json::json;
+
+    let parameter = |in_out: &str, data_type: &str| {
+        interface_record(json!({ "IN/OUT": in_out, "Parameter Data Type": data_type })).unwrap()
+    };
+    let mut method = MatrixServiceMethod::new(
+        1,
+        "SetTemp".to_owned(),
+        MatrixServiceMethodType::RRMethod {
+            data_in: vec![],
+            data_in_ref: vec![],
+            data_out: "".to_owned(),
+            data_out_ref: None,
+        },
+        SomeipTransportPortocol::UDP,
+    );
+    // 输入参数按顺序排列，只取第一个输出参数，"/" 表示没有参数
+    for (in_out, data_type) in [
+        ("IN", "uint8"),
+        ("in", "uint16"),
+        ("OUT", "Status"),
+        ("out", "uint32"),
+        ("IN", "/"),
+    ] {
+        fill_method_parameter(&mut method, &parameter(in_out, data_type));
+    }
+    match &method.method_type {
+        MatrixServiceMethodType::RRMethod {
+            data_in, data_out, ..
+        } => {
+            assert_eq!(data_in, &["uint8", "uint16"]);
+            assert_eq!(data_out, "Status");
+        }
+        method_type => panic!("{:?}", method_type),
+    }
+
+    // 事件与字段只有一个参数，不区分方向
+    let mut method = MatrixServiceMethod::new(
+        0x8001,
+        "Temperature".to_owned(),
+        MatrixServiceMethodType::FIELD {
+            field_type: MatrixServiceMethodFieldType::Notifier,
+            data: "".to_owned(),
+            data_ref: None,
+        },
+        SomeipTransportPortocol::UDP,
+    );
+    fill_method_parameter(&mut method, &parameter("IN", "uint8"));
+    let record = interface_record(json!({})).unwrap();
+    fill_method_parameter(&mut method, &record);
+    match &method.method_type {
+        MatrixServiceMethodType::FIELD { data, .. } => assert_eq!(data, "uint8"),
+        method_type => panic!("{:?}", method_type),
+    }
+}
+
+#[test]
+fn excel_malformed_cells() {
+    use serde_json::json;
+
+    // 数字单元格转换为字符串，空白单元格为空
+    let record = interface_record(json!({
+        "Method ID/Event ID": 32769,
+        "Cyclic Time (ms)": 100.5,
+        "Eventgroup ID": " ",
+        "UDP/TCP": " UDP ",
+        "Send Strategy": true,
+    }))
+    .unwrap();
+    assert_eq!(record.method_id.as_deref(), Some("32769"));
+    assert_eq!(record.cyclic_time_ms.as_deref(), Some("100.5"));
+    assert_eq!(record.eventgroup_id, None);
+    assert_eq!(record.udp_tcp.as_deref(), Some("UDP"));
+    assert_eq!(record.send_strategy.as_deref(), Some("true"));
+
+    // 服务ID可以是十六进制、十进制或空，其他内容是错误
+    for (cell, service_id) in [("0x1234", Some(0x1234)), ("4660", Some(0x1234)), ("", None)] {
+        let record = interface_record(json!({ "Service ID": cell })).unwrap();
+        assert_eq!(record.service_id, service_id);
+    }
+    for cell in ["0xZZ", "0x12345", "70000", "Climate"] {
+        assert!(interface_record(json!({ "Service ID": cell })).is_err());
+    }
+    assert!(interface_record(json!({ "Method ID/Event ID": [1] })).is_err());
+}
+
+#[test]
+fn excel_e2e_config() {
+    // 没有保护
+    for cell in ["", " ", "/", "-", "No", "no"] {
+        assert_eq!(parse_e2e_config(cell), None);
+    }
+
+    // 单独的DataID缺省为Profile 6
+    let config = parse_e2e_config(" 0x1234 ").unwrap();
+    assert_eq!(config.profile, MatrixE2eProfile::P06);
+    assert_eq!(config.data_id, 0x1234);
+    assert_eq!(parse_e2e_config("4660").unwrap().data_id, 0x1234);
+
+    for (profile, expected) in [
+        ("1", MatrixE2eProfile::P01),
+        ("02", MatrixE2eProfile::P02),
+        ("P4", MatrixE2eProfile::P04),
+        ("Profile 5", MatrixE2eProfile::P05),
+        ("6", MatrixE2eProfile::P06),
+        ("07", MatrixE2eProfile::P07),
+        ("11", MatrixE2eProfile::P11),
+    ] {
+        let config = parse_e2e_config(&format!("Profile={};DataID=1", profile)).unwrap();
+        assert_eq!(config.profile, expected);
+    }
+
+    // 键值对可以用 ; , 或换行分隔，键不区分大小写、空格与下划线
+    let config = parse_e2e_config(
+        "Profile: 4, Data_ID=0x12345678\nOffset=64;Counter Offset=8;MaxDeltaCounter=0x2;Unknown=1",
+    )
+    .unwrap();
+    assert_eq!(
+        config,
+        MatrixE2eConfig {
+            profile: MatrixE2eProfile::P04,
+            data_id: 0x12345678,
+            data_id_list: vec![],
+            offset: 64,
+            counter_offset: Some(8),
+            max_delta_counter: Some(2),
+        }
+    );
+    // Profile 2的DataIDList代替DataID
+    let config = parse_e2e_config("Profile=2;DataIDList=0x01 2 0x03").unwrap();
+    assert_eq!(config.data_id_list, [1, 2, 3]);
+    // Profile 1/11的Offset是CRC的位置，计数器单独配置
+    let config = parse_e2e_config("Profile=11;DataID=0x10;Offset=16;CounterOffset=24").unwrap();
+    assert_eq!(config.profile, MatrixE2eProfile::P11);
+    assert_eq!((config.offset, config.counter_offset), (16, Some(24)));
+
+    // 不支持的Profile、没有DataID与无效的数值都当作没有配置
+    for cell in [
+        "Profile=3;DataID=1",
+        "Profile=4",
+        "Profile=4;Offset=8",
+        "Profile=4;DataID=0xZZ",
+        "Profile=4;DataID=1;Offset=x",
+        "Profile=4;DataID=1;CounterOffset=-1",
+        "Profile=4;DataID=1;MaxDeltaCounter=many",
+    ] {
+        assert_eq!(parse_e2e_config(cell), None, "{}", cell);
+    }
+}
+
+#[test]
+fn excel_sd_timing() {
+    let cell = |s: &str| Some(s.to_owned());
+    let record = SdTimingRecord {
+        role: "TBOX".to_owned(),
+        initial_delay_min_ms: None,
+        initial_delay_max_ms: cell("100.0"),
+        repetitions_base_delay_ms: cell(" 30.9 "),
+        repetitions_max: cell("0"),
+        cyclic_offer_delay_ms: cell("2000 ms"),
+        request_response_delay_min_ms: cell("-10"),
+        request_response_delay_max_ms: cell("NaN"),
+        ttl: cell("5"),
+    };
+    let default = MatrixSdTiming::default();
+    assert_eq!(
+        record.to_sd_timing(),
+        MatrixSdTiming {
+            // 空单元格、带单位的文本、负数与NaN都使用缺省值
+            initial_delay_min_ms: default.initial_delay_min_ms,
+            initial_delay_max_ms: 100,
+            repetitions_base_delay_ms: 30,
+            repetitions_max: 0,
+            cyclic_offer_delay_ms: default.cyclic_offer_delay_ms,
+            request_response_delay_min_ms: default.request_response_delay_min_ms,
+            request_response_delay_max_ms: default.request_response_delay_max_ms,
+            // TTL的单位是秒
+            ttl: 5,
+            tolerance_ms: default.tolerance_ms,
+        }
+    );
+}
+
+#[test]
+fn excel_application_errors() {
+    let mut services = HashMap::from([(
+        0x1234,
+        MatrixService {
+            service_id: 0x1234,
+            methods: HashMap::from([(
+                1,
+                MatrixServiceMethod::new(
+                    1,
+                    "MoveSeat".to_owned(),
+                    MatrixServiceMethodType::FFMethod {
+                        data_in: vec![],
+                        data_in_ref: vec![],
+                    },
+                    SomeipTransportPortocol::UDP,
+                ),
+            )]),
+            ..Default::default()
+        },
+    )]);
+    let record =
+        |service_id, method_id: &str, return_code: &str, name: &str| ApplicationErrorRecord {
+            service_id,
+            method_id: Some(method_id.to_owned()),
+            return_code: Some(return_code.to_owned()),
+            name: Some(name.to_owned()),
+            description: None,
+            data: None,
+        };
+    for record in [
+        // 方法ID与错误码可以是十六进制或十进制
+        record(Some(0x1234), "0x0001", "0x21", "SEAT_BLOCKED"),
+        record(Some(0x1234), "1", "34", "SEAT_BUSY"),
+        // 重复的错误码保留第一个
+        record(Some(0x1234), "0x1", "33", "DUPLICATE"),
+        // 未知的服务或方法、无效的错误码都被忽略
+        record(None, "1", "0x23", "NO_SERVICE"),
+        record(Some(0x4321), "1", "0x23", "UNKNOWN_SERVICE"),
+        record(Some(0x1234), "2", "0x23", "UNKNOWN_METHOD"),
+        record(Some(0x1234), "1", "0x100", "OUT_OF_RANGE"),
+        record(Some(0x1234), "1", "E_BLOCKED", "NOT_A_NUMBER"),
+    ] {
+        add_application_error(&mut services, &record);
+    }
+
+    let errors: Vec<(u8, &str)> = services[&0x1234].methods[&1]
+        .application_errors
+        .iter()
+        .map(|error| (error.return_code, error.name.as_str()))
+        .collect();
+    assert_eq!(errors, [(0x21, "SEAT_BLOCKED"), (0x22, "SEAT_BUSY")]);
+}
+
+#[test]
+fn excel_enum_values() {
+    let values = parse_enum_values(
+        "0x0: Off\n1=On\n  0x02 = Auto  \n3 Manual\n0XFF:Invalid\n\n4\nfive: Five\n0x100000000000000000: Big",
+    );
+    assert_eq!(
+        values,
+        [
+            (0, "Off".to_owned()),
+            (1, "On".to_owned()),
+            (2, "Auto".to_owned()),
+            (3, "Manual".to_owned()),
+            (0xFF, "Invalid".to_owned()),
+        ]
+    );
+    // Windows换行与没有名字的值
+    assert_eq!(
+        parse_enum_values("0x1:\r\n2=Two\r\n"),
+        [(1, "".to_owned()), (2, "Two".to_owned())]
+    );
+    assert!(parse_enum_values("").is_empty());
+}
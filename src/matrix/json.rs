@@ -4,8 +4,6 @@ use std::{
     path::Path,
 };
 
-use log::{debug, info};
-
 use crate::errors::MyError;
 
 use super::types::Matrix;
@@ -46,11 +44,9 @@ impl Matrix {
 
 #[test]
 fn test() -> Result<(), MyError> {
+    use log::debug;
     use std::env;
     use std::env::set_var;
-    use std::fs::File;
-    use std::io::{self, Read, Write};
-    use std::path::Path;
 
     set_var("RUST_LOG", "debug");
     env_logger::init();
@@ -59,7 +55,10 @@ fn test() -> Result<(), MyError> {
 
     let tmp_dir = env::temp_dir();
     let tmp_file_path = tmp_dir.join("temp_data.txt").canonicalize().unwrap();
-    debug!("write json matrix file to {}.", tmp_file_path.to_string_lossy());
+    debug!(
+        "write json matrix file to {}.",
+        tmp_file_path.to_string_lossy()
+    );
 
     matrix.to_json_file(tmp_file_path)
 }
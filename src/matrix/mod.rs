@@ -1,3 +1,51 @@
+pub mod decode;
 pub mod excel;
 pub mod json;
 pub mod types;
+
+use std::path::Path;
+
+use crate::errors::MyError;
+use types::Matrix;
+
+/// 解析十进制或者0x、0X开头的十六进制数，超出目标类型范围时返回None
+pub fn parse_hex_or_dec<T: TryFrom<u64>>(s: &str) -> Option<T> {
+    let value = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok()?,
+        None => s.parse::<u64>().ok()?,
+    };
+    T::try_from(value).ok()
+}
+
+impl Matrix {
+    /// 按照后缀名读取excel或json格式的矩阵文件
+    pub fn from_file<P>(path: P) -> Result<Matrix, MyError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path
+            .as_ref()
+            .canonicalize()
+            .map_err(|_| MyError::ArgInputError("arg matrix path error".to_owned()))?;
+        let ext = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+        match ext.as_deref() {
+            Some("xlsx") | Some("xls") => Matrix::from_excel_file(path),
+            Some("json") => Matrix::from_json_file(path),
+            _ => Err(MyError::ArgInputError(
+                "arg matrix file extension error".to_owned(),
+            )),
+        }
+    }
+}
+
+#[test]
+fn hex_or_dec() {
+    assert_eq!(parse_hex_or_dec::<u16>("0x1234"), Some(0x1234));
+    assert_eq!(parse_hex_or_dec::<u16>("0XFFFF"), Some(0xFFFF));
+    assert_eq!(parse_hex_or_dec::<u8>("33"), Some(33));
+    assert_eq!(parse_hex_or_dec::<u8>("0x100"), None);
+    assert_eq!(parse_hex_or_dec::<u32>("SEAT_BLOCKED"), None);
+}
@@ -7,18 +7,20 @@ use serde::{Deserialize, Serialize};
 
 use crate::errors::MyError;
 use crate::types::{
-    ServerPort, SomeipInstanceId, SomeipMajorVersion, SomeipMethodId, SomeipMinorVersion,
-    SomeipServiceId, SomeipTransportPortocol,
+    ServerPort, SomeipEventgroupId, SomeipInstanceId, SomeipMajorVersion, SomeipMessageType,
+    SomeipMethodId, SomeipMinorVersion, SomeipReturnCode, SomeipServiceId, SomeipTransportPortocol,
 };
 
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(untagged)]
+use super::parse_hex_or_dec;
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
 pub enum MatrixServiceMethodFieldType {
     Getter,
     Setter,
     Notifier,
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum MatrixServiceMethodType {
@@ -48,8 +50,64 @@ pub enum MatrixServiceMethodType {
     },
 }
 
+impl MatrixServiceMethodType {
+    /// 该类型的报文载荷中依次出现的参数类型名，None表示该报文类型没有定义载荷（例如带错误的应答）
+    pub fn parameter_types(&self, message_type: SomeipMessageType) -> Option<Vec<&str>> {
+        let types: Vec<&String> = match (self, message_type) {
+            (MatrixServiceMethodType::RRMethod { data_in, .. }, SomeipMessageType::Request) => {
+                data_in.iter().collect()
+            }
+            (MatrixServiceMethodType::RRMethod { data_out, .. }, SomeipMessageType::Response) => {
+                vec![data_out]
+            }
+            (
+                MatrixServiceMethodType::FFMethod { data_in, .. },
+                SomeipMessageType::RequestWithoutResponse,
+            ) => data_in.iter().collect(),
+            (MatrixServiceMethodType::EVENT { data_out, .. }, SomeipMessageType::Notification) => {
+                vec![data_out]
+            }
+            (
+                MatrixServiceMethodType::FIELD {
+                    field_type, data, ..
+                },
+                message_type,
+            ) => match (field_type, message_type) {
+                (MatrixServiceMethodFieldType::Getter, SomeipMessageType::Request) => vec![],
+                (MatrixServiceMethodFieldType::Getter, SomeipMessageType::Response)
+                | (MatrixServiceMethodFieldType::Setter, SomeipMessageType::Request)
+                | (MatrixServiceMethodFieldType::Setter, SomeipMessageType::Response)
+                | (MatrixServiceMethodFieldType::Notifier, SomeipMessageType::Notification) => {
+                    vec![data]
+                }
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(
+            types
+                .into_iter()
+                .filter(|name| !name.is_empty())
+                .map(|name| name.as_str())
+                .collect(),
+        )
+    }
+
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            MatrixServiceMethodType::RRMethod { .. } => "method",
+            MatrixServiceMethodType::FFMethod { .. } => "fire&forget method",
+            MatrixServiceMethodType::EVENT { .. } => "event",
+            MatrixServiceMethodType::FIELD { field_type, .. } => match field_type {
+                MatrixServiceMethodFieldType::Getter => "field getter",
+                MatrixServiceMethodFieldType::Setter => "field setter",
+                MatrixServiceMethodFieldType::Notifier => "field notifier",
+            },
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
-#[serde(untagged)]
 pub enum NumberType {
     Boolean,
     Uint8,
@@ -64,6 +122,18 @@ pub enum NumberType {
     Float64,
 }
 
+impl NumberType {
+    /// 序列化后的字节数
+    pub fn size(&self) -> usize {
+        match self {
+            NumberType::Boolean | NumberType::Uint8 | NumberType::Sint8 => 1,
+            NumberType::Uint16 | NumberType::Sint16 => 2,
+            NumberType::Uint32 | NumberType::Sint32 | NumberType::Float32 => 4,
+            NumberType::Uint64 | NumberType::Sint64 | NumberType::Float64 => 8,
+        }
+    }
+}
+
 impl TryFrom<String> for NumberType {
     type Error = MyError;
 
@@ -86,7 +156,6 @@ impl TryFrom<String> for NumberType {
 }
 
 #[derive(Debug, Deserialize, Serialize, Default)]
-#[serde(untagged)]
 pub enum StringEncoding {
     #[default]
     UTF8,
@@ -98,6 +167,7 @@ pub type StringArrayLengthFixed = usize;
 pub type StringArrayLengthMin = usize;
 pub type StringArrayLengthMax = usize;
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(untagged)]
 pub enum StringArrayLength {
@@ -132,10 +202,10 @@ pub enum MatrixType {
     },
     Array {
         length: StringArrayLength,
-        member: MatrixMember
+        member: MatrixMember,
     },
     Struct {
-        members: Vec<MatrixMember>
+        members: Vec<MatrixMember>,
     },
     #[default]
     Unimplemented,
@@ -146,30 +216,130 @@ pub enum MatrixType {
 pub struct MatrixMember {
     pub member_name: String,
     pub member_description: String,
+    /// 成员类型在data_types中的名字，数值类型直接使用类型名，如uint16
+    #[serde(default)]
+    pub member_data_type: String,
+    #[allow(dead_code)]
     #[serde(skip)]
     pub member_ref: Option<MatrixDataNodeConstRef>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Default)]
 pub struct MatrixDataNode {
     pub name: String,
     pub description: String,
     #[serde(flatten)]
     pub data_type: MatrixType,
+    /// 数值的物理单位，如km/h，没有定义时为空
+    #[serde(default)]
+    pub unit: String,
+    /// 枚举类型的 (值, 名字)，来自矩阵的Discrete Value Defination列
+    #[serde(default)]
+    pub enum_values: Vec<(u64, String)>,
 }
 
+impl MatrixDataNode {
+    pub fn enum_label(&self, value: u64) -> Option<&str> {
+        self.enum_values
+            .iter()
+            .find(|(v, _)| *v == value)
+            .map(|(_, label)| label.as_str())
+    }
+}
+
+#[allow(dead_code)]
 pub type MatrixDataNodeRef<'a> = Option<&'a mut MatrixDataNode>;
 pub type MatrixDataNodeConstRef = *const MatrixDataNode;
 // pub type MatrixDataNodeWeakRef = Weak<RefCell<MatrixDataNode>>;
 
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MatrixE2eProfile {
+    P01,
+    P02,
+    P04,
+    P05,
+    P06,
+    P07,
+    P11,
+}
+
+/// 元素的E2E保护配置，偏移量与AUTOSAR一致，单位是bit
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct MatrixE2eConfig {
+    pub profile: MatrixE2eProfile,
+    pub data_id: u32,
+    /// 仅P02：按计数器取用的DataID
+    #[serde(default)]
+    pub data_id_list: Vec<u8>,
+    /// E2E头在载荷中的位置，P01/P11为CRC的位置
+    #[serde(default)]
+    pub offset: usize,
+    /// 仅P01/P11：计数器的位置，默认为8
+    #[serde(default)]
+    pub counter_offset: Option<usize>,
+    /// 允许的计数器最大跳变，超过时认为顺序错误，默认为1
+    #[serde(default)]
+    pub max_delta_counter: Option<u32>,
+}
+
+/// 方法自定义的应用错误码，取值范围0x20~0x5E，带错误的应答载荷可以是矩阵中定义的数据类型
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct MatrixApplicationError {
+    pub return_code: SomeipReturnCode,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// 错误载荷的数据类型名，空表示没有载荷
+    #[serde(default)]
+    pub data: String,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct MatrixServiceMethod {
-    method_id: SomeipMethodId,
-    method_name: String,
-    method_type: MatrixServiceMethodType,
-    transport_protocol: SomeipTransportPortocol,
+    pub method_id: SomeipMethodId,
+    pub method_name: String,
+    pub method_type: MatrixServiceMethodType,
+    pub transport_protocol: SomeipTransportPortocol,
+    /// 周期发送的元素才有，单位毫秒
+    #[serde(default)]
+    pub cycle_time_ms: Option<u32>,
+    #[serde(default)]
+    pub e2e: Option<MatrixE2eConfig>,
+    #[serde(default)]
+    pub application_errors: Vec<MatrixApplicationError>,
+    #[allow(dead_code)]
     #[serde(skip)]
-    mother_service_ref: Weak<MatrixService>,
+    pub mother_service_ref: Weak<MatrixService>,
+}
+
+impl MatrixServiceMethod {
+    /// 没有周期、E2E配置与应用错误的方法
+    pub fn new(
+        method_id: SomeipMethodId,
+        method_name: String,
+        method_type: MatrixServiceMethodType,
+        transport_protocol: SomeipTransportPortocol,
+    ) -> Self {
+        MatrixServiceMethod {
+            method_id,
+            method_name,
+            method_type,
+            transport_protocol,
+            cycle_time_ms: None,
+            e2e: None,
+            application_errors: vec![],
+            mother_service_ref: Default::default(),
+        }
+    }
+
+    pub fn application_error(
+        &self,
+        return_code: SomeipReturnCode,
+    ) -> Option<&MatrixApplicationError> {
+        self.application_errors
+            .iter()
+            .find(|error| error.return_code == return_code)
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -194,10 +364,12 @@ impl Default for MatrixRole {
 #[derive(Debug, Deserialize, Serialize, Default)]
 pub struct MatrixRoleServerClientPair {
     pub server: String,
+    #[allow(dead_code)]
     #[serde(skip)]
     pub server_ref: Option<MatrixRoleRef>,
     pub server_port: ServerPort,
     pub client: String,
+    #[allow(dead_code)]
     #[serde(skip)]
     pub client_ref: Option<MatrixRoleRef>,
 }
@@ -218,7 +390,47 @@ impl PartialEq for MatrixRoleServerClientPair {
 
 pub type RoleName = String;
 
-#[derive(Debug, Deserialize, Serialize)]
+/// 服务发现的时间参数，单位毫秒，TTL单位秒
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MatrixSdTiming {
+    pub initial_delay_min_ms: u32,
+    pub initial_delay_max_ms: u32,
+    pub repetitions_base_delay_ms: u32,
+    pub repetitions_max: u32,
+    pub cyclic_offer_delay_ms: u32,
+    pub request_response_delay_min_ms: u32,
+    pub request_response_delay_max_ms: u32,
+    pub ttl: u32,
+    /// 测量值允许的误差，抓包时间戳本身有抖动
+    pub tolerance_ms: u32,
+}
+
+impl Default for MatrixSdTiming {
+    fn default() -> Self {
+        Self {
+            initial_delay_min_ms: 10,
+            initial_delay_max_ms: 100,
+            repetitions_base_delay_ms: 200,
+            repetitions_max: 3,
+            cyclic_offer_delay_ms: 2000,
+            request_response_delay_min_ms: 10,
+            request_response_delay_max_ms: 50,
+            ttl: 3,
+            tolerance_ms: 20,
+        }
+    }
+}
+
+/// Event与Field Notifier按照Eventgroup订阅
+#[derive(Debug, Deserialize, Serialize, Default)]
+pub struct MatrixEventgroup {
+    pub eventgroup_id: SomeipEventgroupId,
+    pub eventgroup_name: String,
+    pub methods: Vec<SomeipMethodId>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default)]
 pub struct MatrixService {
     pub service_id: SomeipServiceId,
     pub service_name: String,
@@ -227,6 +439,8 @@ pub struct MatrixService {
     pub major_verison: SomeipMajorVersion,
     pub minor_version: SomeipMinorVersion,
     pub methods: HashMap<SomeipMethodId, MatrixServiceMethod>,
+    #[serde(default)]
+    pub eventgroups: HashMap<SomeipEventgroupId, MatrixEventgroup>,
     pub server_client: RefCell<Vec<MatrixRoleServerClientPair>>,
 }
 
@@ -241,6 +455,17 @@ pub enum MatrixSerializationParameterSize {
     B64,
 }
 
+impl MatrixSerializationParameterSize {
+    pub fn bytes(&self) -> usize {
+        match self {
+            MatrixSerializationParameterSize::B8 => 1,
+            MatrixSerializationParameterSize::B16 => 2,
+            MatrixSerializationParameterSize::B32 => 4,
+            MatrixSerializationParameterSize::B64 => 8,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Default)]
 pub struct MatrixSerializationParameter {
     pub alignment: MatrixSerializationParameterSize,
@@ -256,13 +481,109 @@ pub struct MatrixSerializationParameter {
     pub union_null: bool,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Default)]
 pub struct Matrix {
     pub version: String,
     pub services: HashMap<SomeipServiceId, MatrixService>,
+    #[allow(dead_code)]
     #[serde(skip)]
     pub services_map_by_name: HashMap<String, MatrixServiceRef>,
     pub data_types: HashMap<String, MatrixDataNode>,
     pub serialization_parameter: MatrixSerializationParameter,
     pub roles: HashMap<RoleName, MatrixRole>,
+    /// 按角色配置的SD时间参数，没有配置的角色使用缺省值
+    #[serde(default)]
+    pub sd_timing: HashMap<RoleName, MatrixSdTiming>,
+}
+
+impl Matrix {
+    /// 同一个IP上可能部署了多个角色，按名字排序保证结果稳定
+    pub fn find_roles_by_ip(&self, ip_addr: &IpAddr) -> Vec<&MatrixRole> {
+        let mut roles: Vec<&MatrixRole> = self
+            .roles
+            .values()
+            .filter(|role| &role.ip_addr == ip_addr)
+            .collect();
+        roles.sort_by(|a, b| a.name.cmp(&b.name));
+        roles
+    }
+
+    pub fn find_role_by_ip(&self, ip_addr: &IpAddr) -> Option<&MatrixRole> {
+        self.find_roles_by_ip(ip_addr).into_iter().next()
+    }
+
+    /// 报告中用于显示的主机名，矩阵中没有的主机显示IP
+    pub fn role_name_or_ip(&self, ip_addr: &IpAddr) -> String {
+        match self.find_role_by_ip(ip_addr) {
+            Some(role) => role.name.clone(),
+            None => ip_addr.to_string(),
+        }
+    }
+
+    /// 角色的SD时间参数，同一IP上有多个角色时取第一个配置了参数的角色
+    pub fn sd_timing_by_ip(&self, ip_addr: &IpAddr) -> MatrixSdTiming {
+        self.find_roles_by_ip(ip_addr)
+            .iter()
+            .find_map(|role| self.sd_timing.get(&role.name))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn service_name(&self, service_id: SomeipServiceId) -> Option<&str> {
+        self.services
+            .get(&service_id)
+            .map(|service| service.service_name.as_str())
+    }
+
+    /// 按照过滤路径 (serviceid).(methodid) 或 (servicename).(methodname) 查找方法，ID可以是十六进制或十进制
+    /// 同名的Getter、Setter、Notifier都会返回，同时返回路径中剩余的部分
+    pub fn find_methods_by_path<'p>(
+        &self,
+        path: &'p str,
+    ) -> (Vec<(SomeipServiceId, SomeipMethodId)>, Vec<&'p str>) {
+        let mut parts = path.split('.');
+        let (service, method) = match (parts.next(), parts.next()) {
+            (Some(service), Some(method)) => (service, method),
+            _ => return (vec![], vec![]),
+        };
+        let mut methods: Vec<(SomeipServiceId, SomeipMethodId)> = self
+            .services
+            .values()
+            .filter(|s| {
+                s.service_name == service || parse_hex_or_dec(service) == Some(s.service_id)
+            })
+            .flat_map(|s| {
+                s.methods
+                    .values()
+                    .filter(|m| {
+                        m.method_name == method || parse_hex_or_dec(method) == Some(m.method_id)
+                    })
+                    .map(|m| (s.service_id, m.method_id))
+            })
+            .collect();
+        methods.sort();
+        (methods, parts.collect())
+    }
+
+    /// 按照 (serviceid).(eventgroupid) 或 (servicename).(eventgroupname) 查找Eventgroup
+    pub fn find_eventgroup_by_path(
+        &self,
+        path: &str,
+    ) -> Option<(SomeipServiceId, SomeipEventgroupId)> {
+        let (service, eventgroup) = path.split_once('.')?;
+        self.services
+            .values()
+            .filter(|s| {
+                s.service_name == service || parse_hex_or_dec(service) == Some(s.service_id)
+            })
+            .find_map(|s| {
+                s.eventgroups
+                    .values()
+                    .find(|e| {
+                        e.eventgroup_name == eventgroup
+                            || parse_hex_or_dec(eventgroup) == Some(e.eventgroup_id)
+                    })
+                    .map(|e| (s.service_id, e.eventgroup_id))
+            })
+    }
 }
@@ -1,97 +1,671 @@
-
 /// 用户不关心用TCP、UDP传输，还是用SomeIP-TP传输
 /// 用户只关心，是不是正常的包，是不是SD包（有没有订阅过程等等）
 /// 其中，ClientID、SessionID、Length等字段，用户是不会关心的
 /// 甚至Return Code也不关心——对于RR类型的操作，只关心什么时候发了Request，什么时候Response（Field的Getter、Setter同理）
 /// 但是，在没有提供原始矩阵表的情况下，只能按照MessageType字段区分上述类型了
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::time::Duration;
+
+use log::{debug, error};
+use pnet::datalink;
+use pnet::packet::ethernet::{EtherTypes, EthernetPacket};
+use pnet::packet::ip::IpNextHeaderProtocols::{self};
+use pnet::packet::sll::SLLPacket;
+use pnet::packet::tcp::TcpPacket;
+use pnet::packet::udp::UdpPacket;
+use pnet::packet::vlan::VlanPacket;
+use pnet::packet::Packet;
+
+use crate::types::{
+    MacAddr, PacketIndex, Port, SomeipClientId, SomeipEndpoint, SomeipMessage, SomeipMessageType,
+    SomeipMethodId, SomeipSdEndpointOption, SomeipSdEntry, SomeipSdEntryType, SomeipSdHeader,
+    SomeipServiceId, SomeipSessionId, SomeipTransportPortocol, VlanId,
+};
+
+use super::pnet_packet_someip::{SomeipIterable, SomeipPacket, SomeipSdPacket};
+use super::tcp_parser::{TcpStreamKey, TcpStreams, SOMEIP_HEADER_LENGTH};
+
+/// 一帧原始报文在解析过程中逐层填充的信息
+struct FrameContext {
+    packet_index: PacketIndex,
+    timestamp: Duration,
+    src_mac: MacAddr,
+    dst_mac: MacAddr,
+    vlan_id: Option<VlanId>,
+    src_ip: IpAddr,
+    dst_ip: IpAddr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct TpKey {
+    source: SomeipEndpoint,
+    destination: SomeipEndpoint,
+    service_id: SomeipServiceId,
+    method_id: SomeipMethodId,
+    client_id: SomeipClientId,
+    session_id: SomeipSessionId,
+    message_type: u8,
+}
+
+/// 未完成的TP报文超过这个时间没有收到新的分段就丢弃，丢包时缓存不会一直增长
+const TP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// TP分段缓存，分段按offset排列，收到最后一段并且连续时才拼接
+struct TpBuffer {
+    /// 最近一次收到分段的时间
+    updated: Duration,
+    segments: Vec<(usize, Vec<u8>)>,
+    last_received: bool,
+    frames: Vec<PacketIndex>,
+}
+
+pub struct PacketParser {
+    pub pnet_config: datalink::Config,
+    tp_buffers: HashMap<TpKey, TpBuffer>,
+    tcp_streams: TcpStreams,
+    /// 出现过合法SomeIP报文的IP-PORT
+    someip_ports: HashSet<(IpAddr, Port)>,
+    messages: Vec<SomeipMessage>,
+}
+
+impl PacketParser {
+    pub fn new(channel_type: datalink::ChannelType) -> Self {
+        PacketParser {
+            pnet_config: datalink::Config {
+                channel_type,
+                ..Default::default()
+            },
+            tp_buffers: HashMap::new(),
+            tcp_streams: TcpStreams::new(),
+            someip_ports: HashSet::new(),
+            messages: vec![],
+        }
+    }
+
+    /// 解析一帧原始报文，返回从中得到的完整SomeIP报文
+    pub fn parse(
+        &mut self,
+        packet_index: PacketIndex,
+        ts: Duration,
+        pkt: &[u8],
+    ) -> Vec<SomeipMessage> {
+        raw_packet_parser(self, packet_index, ts, pkt);
+        std::mem::take(&mut self.messages)
+    }
+}
 
 fn check_is_sd(pkt: &SomeipPacket) -> bool {
     (pkt.get_service_id() == 0xFFFF) && (pkt.get_method_id() == 0x8100)
 }
 
-fn handle_someip_sd_packet<'a>(pp: &'a PacketParser, ts: &'a Duration, pkt: &'a SomeipPacket) {}
+/// 只看MessageType判断是否可能是SomeIP报文
+fn check_is_someip_like(pkt: &SomeipPacket, available: usize) -> bool {
+    let message_type = pkt.get_message_type().without_tp_flag().0;
+    available >= SOMEIP_HEADER_LENGTH
+        && matches!(
+            message_type,
+            0x00 | 0x01 | 0x02 | 0x40 | 0x41 | 0x80 | 0x81 | 0xc0 | 0xc1
+        )
+}
+
+/// UDP中可能混有其他协议，按照报文头的固定字段粗略判断是否是SomeIP报文
+fn check_is_valid_someip(pkt: &SomeipPacket, available: usize) -> bool {
+    check_is_someip_like(pkt, available)
+        && pkt.get_length() >= 8
+        && pkt.get_protocol_version().0 == 0x01
+}
+
+fn build_message(
+    ctx: &FrameContext,
+    transport_protocol: SomeipTransportPortocol,
+    src_port: u16,
+    dst_port: u16,
+    pkt: &SomeipPacket,
+    payload: Vec<u8>,
+) -> SomeipMessage {
+    SomeipMessage {
+        packet_index: ctx.packet_index,
+        frames: vec![ctx.packet_index],
+        timestamp: ctx.timestamp,
+        source: SomeipEndpoint {
+            mac_addr: ctx.src_mac,
+            ip_addr: ctx.src_ip,
+            port: src_port,
+        },
+        destination: SomeipEndpoint {
+            mac_addr: ctx.dst_mac,
+            ip_addr: ctx.dst_ip,
+            port: dst_port,
+        },
+        vlan_id: ctx.vlan_id,
+        message_type: SomeipMessageType::from(pkt.get_message_type().without_tp_flag().0),
+        service_id: pkt.get_service_id(),
+        method_id: pkt.get_method_id(),
+        client_id: pkt.get_client_id(),
+        session_id: pkt.get_session_id(),
+        protocol_version: pkt.get_protocol_version().0,
+        interface_version: pkt.get_interface_version(),
+        length: pkt.get_length(),
+        // 迭代UDP中的多条报文时，packet()包含了之后所有的字节，只计入Length覆盖的部分
+        available_length: pkt
+            .packet()
+            .len()
+            .saturating_sub(8)
+            .min(pkt.get_length().max(8) as usize),
+        return_code: pkt.get_return_code().0,
+        transport_protocol,
+        sd: None,
+        payload,
+    }
+}
+
+/// Option格式：Length(2) Type(1) Reserved(1) 之后Length-1字节内容
+/// 只展开端点类型的Option，其他Option占位为None，保证Entry中的Option序号仍然有效
+fn parse_sd_options(mut data: &[u8]) -> Vec<Option<SomeipSdEndpointOption>> {
+    let mut options = vec![];
+    while data.len() >= 4 {
+        let length = u16::from_be_bytes([data[0], data[1]]) as usize;
+        let option_type = data[2];
+        let end = 3 + length;
+        if length == 0 || end > data.len() {
+            break;
+        }
+        let content = &data[4..end];
+        let endpoint = |ip_len: usize| -> Option<SomeipSdEndpointOption> {
+            if content.len() < ip_len + 4 {
+                return None;
+            }
+            let ip_addr = match ip_len {
+                4 => IpAddr::from(<[u8; 4]>::try_from(&content[..4]).ok()?),
+                _ => IpAddr::from(<[u8; 16]>::try_from(&content[..16]).ok()?),
+            };
+            Some(SomeipSdEndpointOption {
+                ip_addr,
+                port: u16::from_be_bytes([content[ip_len + 2], content[ip_len + 3]]),
+                transport_protocol: match content[ip_len + 1] {
+                    0x06 => SomeipTransportPortocol::TCP,
+                    _ => SomeipTransportPortocol::UDP,
+                },
+                multicast: option_type & 0x10 != 0,
+            })
+        };
+        options.push(match option_type {
+            0x04 | 0x14 => endpoint(4),
+            0x06 | 0x16 => endpoint(16),
+            _ => None,
+        });
+        data = &data[end..];
+    }
+    options
+}
+
+fn parse_sd_header(payload: &[u8]) -> Option<SomeipSdHeader> {
+    let sd = SomeipSdPacket::new(payload)?;
+    let options = parse_sd_options(sd.payload());
+    let entries = sd
+        .get_entries()
+        .iter()
+        .map(|entry| {
+            let ttl = entry.ttl;
+            let entry_type = match (entry.sd_entry_type.0, ttl) {
+                (0x00, _) => SomeipSdEntryType::FindService,
+                (0x01, 0) => SomeipSdEntryType::StopOfferService,
+                (0x01, _) => SomeipSdEntryType::OfferService,
+                (0x06, 0) => SomeipSdEntryType::StopSubscribe,
+                (0x06, _) => SomeipSdEntryType::Subscribe,
+                (0x07, 0) => SomeipSdEntryType::SubscribeNack,
+                (0x07, _) => SomeipSdEntryType::SubscribeAck,
+                (v, _) => SomeipSdEntryType::Unknown(v),
+            };
+            SomeipSdEntry {
+                entry_type,
+                service_id: entry.service_id,
+                instance_id: entry.instance_id,
+                major_version: entry.major_version,
+                ttl,
+                minor_version: entry.minor_version,
+                eventgroup_id: (entry.minor_version & 0xFFFF) as u16,
+                counter: ((entry.minor_version >> 16) & 0x0F) as u8,
+                endpoints: [
+                    (entry.index_of_1st_options_run, entry.number_of_options_1),
+                    (entry.index_of_2nd_options_run, entry.number_of_options_2),
+                ]
+                .iter()
+                .flat_map(|&(index, count)| {
+                    options.iter().skip(index as usize).take(count as usize)
+                })
+                .flatten()
+                .cloned()
+                .collect(),
+            }
+        })
+        .collect();
+    Some(SomeipSdHeader {
+        reboot_flag: sd.get_flags() & 0x80 != 0,
+        unicast_flag: sd.get_flags() & 0x40 != 0,
+        entries,
+    })
+}
+
+fn handle_someip_sd_packet(pp: &mut PacketParser, mut msg: SomeipMessage) {
+    match parse_sd_header(&msg.payload) {
+        Some(sd) => {
+            msg.sd = Some(sd);
+            pp.messages.push(msg);
+        }
+        None => {
+            error!("ts:{:?}, malformed someip sd packet", msg.timestamp);
+        }
+    }
+}
+
+/// TP报文头之后有4字节：Offset(28bit，单位16字节)、Reserved(3bit)、MoreSegmentsFlag(1bit)
+fn handle_someip_tp_packet(
+    pp: &mut PacketParser,
+    ctx: &FrameContext,
+    src_port: u16,
+    dst_port: u16,
+    pkt: &SomeipPacket,
+) {
+    let payload = pkt.payload();
+    if payload.len() < 4 {
+        error!("ts:{:?}, someip tp packet too short", ctx.timestamp);
+        return;
+    }
+    let tp_header = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]);
+    let offset = (tp_header & 0xFFFF_FFF0) as usize;
+    let more_segments = tp_header & 0x01 != 0;
+
+    let msg = build_message(
+        ctx,
+        SomeipTransportPortocol::UDP,
+        src_port,
+        dst_port,
+        pkt,
+        vec![],
+    );
+    let key = TpKey {
+        source: msg.source,
+        destination: msg.destination,
+        service_id: msg.service_id,
+        method_id: msg.method_id,
+        client_id: msg.client_id,
+        session_id: msg.session_id,
+        message_type: pkt.get_message_type().0,
+    };
+
+    pp.tp_buffers.retain(|key, buffer| {
+        let expired = ctx.timestamp.saturating_sub(buffer.updated) > TP_TIMEOUT;
+        if expired {
+            debug!(
+                "ts:{:?}, someip tp {:?} timeout, drop frames {:?}",
+                ctx.timestamp, key, buffer.frames
+            );
+        }
+        !expired
+    });
+    // 同一个键又收到第一段，说明之前的报文丢了分段，重新开始拼接
+    let restart = offset == 0
+        && pp
+            .tp_buffers
+            .get(&key)
+            .is_some_and(|buffer| buffer.segments.iter().any(|(o, _)| *o == 0));
+    if restart {
+        if let Some(buffer) = pp.tp_buffers.remove(&key) {
+            debug!(
+                "ts:{:?}, someip tp {:?} restarted, drop frames {:?}",
+                ctx.timestamp, key, buffer.frames
+            );
+        }
+    }
+    let buffer = pp.tp_buffers.entry(key).or_insert_with(|| TpBuffer {
+        updated: ctx.timestamp,
+        segments: vec![],
+        last_received: false,
+        frames: vec![],
+    });
+    buffer.updated = ctx.timestamp;
+    if !buffer.segments.iter().any(|(o, _)| *o == offset) {
+        buffer.segments.push((offset, payload[4..].to_vec()));
+    }
+    buffer.frames.push(ctx.packet_index);
+    buffer.last_received |= !more_segments;
+    if !buffer.last_received {
+        return;
+    }
+
+    buffer.segments.sort_by_key(|(o, _)| *o);
+    let mut data: Vec<u8> = vec![];
+    for (o, segment) in &buffer.segments {
+        if *o != data.len() {
+            // 中间还有分段没有收到
+            return;
+        }
+        data.extend_from_slice(segment);
+    }
+
+    let mut msg = msg;
+    msg.length = data.len() as u32 + 8;
+    msg.available_length = msg.length as usize;
+    msg.payload = data;
+    msg.frames = std::mem::take(&mut buffer.frames);
+    pp.tp_buffers.remove(&key);
+    pp.messages.push(msg);
+}
 
 // 确保只有1个原始的someip包的时候才到这里，pkt是一个原始的someip包——可以不完整，可以是一个TP包
-fn handle_raw_someip_packet<'a>(pp: &'a PacketParser, ts: &'a Duration, pkt: &'a SomeipPacket) {
+fn handle_raw_someip_packet(
+    pp: &mut PacketParser,
+    ctx: &FrameContext,
+    transport_protocol: SomeipTransportPortocol,
+    src_port: u16,
+    dst_port: u16,
+    pkt: &SomeipPacket,
+) {
+    if pkt.get_message_type().check_is_tp() {
+        handle_someip_tp_packet(pp, ctx, src_port, dst_port, pkt);
+        return;
+    }
+    let msg = build_message(
+        ctx,
+        transport_protocol,
+        src_port,
+        dst_port,
+        pkt,
+        pkt.payload().to_vec(),
+    );
     if check_is_sd(pkt) {
-        handle_someip_sd_packet(pp, ts, pkt);
+        handle_someip_sd_packet(pp, msg);
     } else {
-        if pkt.get_message_type().check_is_tp() {
-            // TODO:
-            error!("Unhandled TP Message.");
-        } else {
-            // handle_someip_packet(pp, ts, pkt);
-        }
+        pp.messages.push(msg);
     }
 }
 
-fn handle_tcp_packet<'a>(pp: &'a PacketParser, ts: &'a Duration, pkt: &'a [u8]) {
+fn handle_tcp_packet(pp: &mut PacketParser, ctx: &FrameContext, pkt: &[u8]) {
     // 这里确定收到了一个TCP包，TCP包大概率不是SomeIP包，但是需要对TCP数据流进行判断，试图找出里面的TCP-SOMEIP包
-    // 将所有TCP连接按照IP-PORT组合进行划分，不同类型的包设置缓冲区？
-    // TODO: 还没想好怎么写tcp中筛选someip包
+    // 将所有TCP连接按照IP-PORT组合进行划分，每个连接单独缓存，见tcp_parser
+    let pkt = match TcpPacket::new(pkt) {
+        Some(pkt) => pkt,
+        None => {
+            error!("ts:{:?}, malformed tcp packet", ctx.timestamp);
+            return;
+        }
+    };
+    let key = TcpStreamKey {
+        src_ip: ctx.src_ip,
+        src_port: pkt.get_source(),
+        dst_ip: ctx.dst_ip,
+        dst_port: pkt.get_destination(),
+    };
+    for (raw, frames) in
+        pp.tcp_streams
+            .push_segment(key, pkt.get_sequence(), ctx.packet_index, pkt.payload())
+    {
+        if let Some(someip) = SomeipPacket::new(&raw) {
+            let first = pp.messages.len();
+            handle_raw_someip_packet(
+                pp,
+                ctx,
+                SomeipTransportPortocol::TCP,
+                key.src_port,
+                key.dst_port,
+                &someip,
+            );
+            for msg in &mut pp.messages[first..] {
+                msg.frames = frames.clone();
+            }
+        }
+    }
 }
 
-fn handle_udp_packet<'a>(pp: &'a PacketParser, ts: &'a Duration, pkt: &'a [u8]) {
-    let pkt = UdpPacket::new(pkt).unwrap();
-    let mut iter = SomeipIterable::new(pkt.payload());
+fn handle_udp_packet(pp: &mut PacketParser, ctx: &FrameContext, pkt: &[u8]) {
+    let pkt = match UdpPacket::new(pkt) {
+        Some(pkt) => pkt,
+        None => {
+            error!("ts:{:?}, malformed udp packet", ctx.timestamp);
+            return;
+        }
+    };
+    let mut remain = pkt.payload().len();
+    let iter = SomeipIterable::new(pkt.payload());
+    let source = (ctx.src_ip, pkt.get_source());
+    let destination = (ctx.dst_ip, pkt.get_destination());
+    let first = pp.messages.len();
     // 这里确定收到了一个UDP包，UDP包可能不是SomeIP包，需要先判断合法性
     // 而且，规范中还认为，通过PDU的方式，一条UDP包中可以有多条Someip包，也需要对当前收到的包进行判断，是否可能是一个子包
     // 尽可能从里面筛选出单独的SomeIP包出来，包括SD包
-    while let Some(pkt) = iter.next() {
-        handle_raw_someip_packet(pp, ts, &pkt);
+    for someip in iter {
+        if !check_is_valid_someip(&someip, remain) {
+            // 已经出现过SomeIP报文的端口上，Length或ProtocolVersion不合法的报文也交给分析器检查
+            // Length不可信，之后的字节无法再切分
+            if check_is_someip_like(&someip, remain)
+                && (pp.someip_ports.contains(&source) || pp.someip_ports.contains(&destination))
+            {
+                let mut msg = build_message(
+                    ctx,
+                    SomeipTransportPortocol::UDP,
+                    source.1,
+                    destination.1,
+                    &someip,
+                    someip.payload().to_vec(),
+                );
+                msg.available_length = remain - 8;
+                pp.messages.push(msg);
+                remain = 0;
+            }
+            debug!("ts:{:?}, not a someip packet in udp payload", ctx.timestamp);
+            break;
+        }
+        pp.someip_ports.insert(source);
+        pp.someip_ports.insert(destination);
+        remain = remain.saturating_sub(someip.get_length() as usize + 8);
+        handle_raw_someip_packet(
+            pp,
+            ctx,
+            SomeipTransportPortocol::UDP,
+            source.1,
+            destination.1,
+            &someip,
+        );
+    }
+    // 剩余的字节不是SomeIP报文，算在最后一条报文上
+    if remain > 0 && pp.messages.len() > first {
+        pp.messages.last_mut().unwrap().available_length += remain;
     }
 }
 
-fn raw_packet_parser<'a>(pp: &'a PacketParser, ts: &'a Duration, pkt: &'a [u8]) {
-    let handle_ipv4_packet = |packet: &[u8]| {
-        let pkt = pnet::packet::ipv4::Ipv4Packet::new(packet).unwrap();
-        match pkt.get_next_level_protocol() {
-            IpNextHeaderProtocols::Udp => handle_udp_packet(pp, ts, pkt.payload()),
-            IpNextHeaderProtocols::Tcp => handle_tcp_packet(pp, ts, pkt.payload()),
-            _ => {
-                error!("ts:{:?}, unknown layer3 packet", ts);
-            }
+fn handle_ipv4_packet(pp: &mut PacketParser, ctx: &mut FrameContext, packet: &[u8]) {
+    let pkt = match pnet::packet::ipv4::Ipv4Packet::new(packet) {
+        Some(pkt) => pkt,
+        None => {
+            error!("ts:{:?}, malformed ipv4 packet", ctx.timestamp);
+            return;
         }
     };
+    ctx.src_ip = IpAddr::V4(pkt.get_source());
+    ctx.dst_ip = IpAddr::V4(pkt.get_destination());
+    match pkt.get_next_level_protocol() {
+        IpNextHeaderProtocols::Udp => handle_udp_packet(pp, ctx, pkt.payload()),
+        IpNextHeaderProtocols::Tcp => handle_tcp_packet(pp, ctx, pkt.payload()),
+        _ => {
+            debug!("ts:{:?}, unknown layer3 packet", ctx.timestamp);
+        }
+    }
+}
 
-    let handle_ipv6_packet = |packet: &[u8]| {
-        let pkt = pnet::packet::ipv6::Ipv6Packet::new(packet).unwrap();
-        match pkt.get_next_header() {
-            IpNextHeaderProtocols::Udp => handle_udp_packet(pp, ts, pkt.payload()),
-            IpNextHeaderProtocols::Tcp => handle_tcp_packet(pp, ts, pkt.payload()),
-            _ => {
-                error!("ts:{:?}, unknown layer3 packet", ts);
-            }
+fn handle_ipv6_packet(pp: &mut PacketParser, ctx: &mut FrameContext, packet: &[u8]) {
+    let pkt = match pnet::packet::ipv6::Ipv6Packet::new(packet) {
+        Some(pkt) => pkt,
+        None => {
+            error!("ts:{:?}, malformed ipv6 packet", ctx.timestamp);
+            return;
         }
     };
+    ctx.src_ip = IpAddr::V6(pkt.get_source());
+    ctx.dst_ip = IpAddr::V6(pkt.get_destination());
+    match pkt.get_next_header() {
+        IpNextHeaderProtocols::Udp => handle_udp_packet(pp, ctx, pkt.payload()),
+        IpNextHeaderProtocols::Tcp => handle_tcp_packet(pp, ctx, pkt.payload()),
+        _ => {
+            debug!("ts:{:?}, unknown layer3 packet", ctx.timestamp);
+        }
+    }
+}
 
-    let handle_layer2_packet = |packet: &[u8]| {
-        let pkt = EthernetPacket::new(&packet).unwrap();
+fn handle_vlan_packet(pp: &mut PacketParser, ctx: &mut FrameContext, packet: &[u8]) {
+    let pkt = match VlanPacket::new(packet) {
+        Some(pkt) => pkt,
+        None => {
+            error!("ts:{:?}, malformed vlan packet", ctx.timestamp);
+            return;
+        }
+    };
+    // QinQ时保留最内层的VLAN ID
+    ctx.vlan_id = Some(pkt.get_vlan_identifier());
+    match pkt.get_ethertype() {
+        EtherTypes::Ipv4 => handle_ipv4_packet(pp, ctx, pkt.payload()),
+        EtherTypes::Ipv6 => handle_ipv6_packet(pp, ctx, pkt.payload()),
+        EtherTypes::Vlan | EtherTypes::QinQ => handle_vlan_packet(pp, ctx, pkt.payload()),
+        _ => {
+            debug!("ts:{:?}, unknown vlan packet", ctx.timestamp);
+        }
+    }
+}
+
+fn raw_packet_parser(pp: &mut PacketParser, packet_index: PacketIndex, ts: Duration, pkt: &[u8]) {
+    let mut ctx = FrameContext {
+        packet_index,
+        timestamp: ts,
+        src_mac: Default::default(),
+        dst_mac: Default::default(),
+        vlan_id: None,
+        src_ip: IpAddr::V4(std::net::Ipv4Addr::UNSPECIFIED),
+        dst_ip: IpAddr::V4(std::net::Ipv4Addr::UNSPECIFIED),
+    };
+
+    let handle_layer2_packet = |pp: &mut PacketParser, ctx: &mut FrameContext, packet: &[u8]| {
+        let pkt = match EthernetPacket::new(packet) {
+            Some(pkt) => pkt,
+            None => {
+                error!("ts:{:?}, malformed layer2 packet", ctx.timestamp);
+                return;
+            }
+        };
+        ctx.src_mac = pkt.get_source().octets();
+        ctx.dst_mac = pkt.get_destination().octets();
         match pkt.get_ethertype() {
-            EtherTypes::Ipv4 => handle_ipv4_packet(pkt.payload()),
-            EtherTypes::Ipv6 => handle_ipv6_packet(pkt.payload()),
+            EtherTypes::Ipv4 => handle_ipv4_packet(pp, ctx, pkt.payload()),
+            EtherTypes::Ipv6 => handle_ipv6_packet(pp, ctx, pkt.payload()),
+            EtherTypes::Vlan | EtherTypes::QinQ => handle_vlan_packet(pp, ctx, pkt.payload()),
             _ => {
-                error!("ts:{:?}, unknown layer2 packet", ts);
+                debug!("ts:{:?}, unknown layer2 packet", ctx.timestamp);
             }
         }
     };
 
-    let handle_sll_packet = |packet: &[u8]| {
-        let pkt = SLLPacket::new(&packet).unwrap();
+    let handle_sll_packet = |pp: &mut PacketParser, ctx: &mut FrameContext, packet: &[u8]| {
+        let pkt = match SLLPacket::new(packet) {
+            Some(pkt) => pkt,
+            None => {
+                error!("ts:{:?}, malformed sll packet", ctx.timestamp);
+                return;
+            }
+        };
+        // SLL只记录了发送方的链路层地址
+        let link_addr = pkt.get_link_layer_address();
+        if pkt.get_link_layer_address_len() == 6 && link_addr.len() >= 6 {
+            ctx.src_mac.copy_from_slice(&link_addr[..6]);
+        }
         match pkt.get_protocol() {
-            EtherTypes::Ipv4 => handle_ipv4_packet(pkt.payload()),
-            EtherTypes::Ipv6 => handle_ipv6_packet(pkt.payload()),
+            EtherTypes::Ipv4 => handle_ipv4_packet(pp, ctx, pkt.payload()),
+            EtherTypes::Ipv6 => handle_ipv6_packet(pp, ctx, pkt.payload()),
             _ => {
-                error!("ts:{:?}, unknown sll packet", ts);
+                debug!("ts:{:?}, unknown sll packet", ctx.timestamp);
             }
         }
     };
 
     match pp.pnet_config.channel_type {
-        datalink::ChannelType::Layer2 => handle_layer2_packet(pkt),
-        datalink::ChannelType::Layer3(_) => handle_sll_packet(pkt),
-        _ => {
-            error!("ts:{:?}, unknown channel type", ts);
-        }
+        datalink::ChannelType::Layer2 => handle_layer2_packet(pp, &mut ctx, pkt),
+        datalink::ChannelType::Layer3(_) => handle_sll_packet(pp, &mut ctx, pkt),
     }
 }
+
+#[test]
+fn parse_sll_udp_someip_frame() {
+    // test1.pcap中的第一帧，SLL封装的UDP-SOMEIP报文
+    let frame = std::fs::read("./test1.pcap").unwrap()[40..101].to_vec();
+    let mut pp = PacketParser::new(datalink::ChannelType::Layer3(0));
+    let messages = pp.parse(0, Duration::ZERO, &frame);
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].transport_protocol, SomeipTransportPortocol::UDP);
+    assert_eq!(messages[0].source.port, 0x772a);
+}
+
+#[test]
+fn parse_sd_entry_endpoints() {
+    // 一条OfferService Entry，引用第2个Option（第1个是Configuration Option）
+    #[rustfmt::skip]
+    let payload = [
+        0xc0, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x10,
+        0x01, 0x01, 0x00, 0x10, 0x12, 0x34, 0x00, 0x01, 0x01, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x11,
+        0x00, 0x02, 0x01, 0x00, 0x00,
+        0x00, 0x09, 0x04, 0x00, 0xac, 0x10, 0x42, 0x4f, 0x00, 0x06, 0x77, 0x45,
+    ];
+    let sd = parse_sd_header(&payload).unwrap();
+    assert!(sd.reboot_flag && sd.unicast_flag);
+    assert_eq!(sd.entries[0].entry_type, SomeipSdEntryType::OfferService);
+    assert_eq!(
+        sd.entries[0].endpoints,
+        vec![SomeipSdEndpointOption {
+            ip_addr: "172.16.66.79".parse().unwrap(),
+            port: 0x7745,
+            transport_protocol: SomeipTransportPortocol::TCP,
+            multicast: false,
+        }]
+    );
+}
+
+#[test]
+fn tp_buffer_eviction() {
+    let ctx = |ts_ms: u64| FrameContext {
+        packet_index: 0,
+        timestamp: Duration::from_millis(ts_ms),
+        src_mac: Default::default(),
+        dst_mac: Default::default(),
+        vlan_id: None,
+        src_ip: "10.0.0.1".parse().unwrap(),
+        dst_ip: "10.0.0.2".parse().unwrap(),
+    };
+    // 报文头中TP标志位置位的Notification，后接TP头与16字节数据
+    let segment = |session_id: u8, offset: u32, more: bool, fill: u8| {
+        let mut data = vec![0x12, 0x34, 0x80, 0x01, 0x00, 0x00, 0x00, 0x1c];
+        data.extend_from_slice(&[0x00, 0x01, 0x00, session_id, 0x01, 0x01, 0x22, 0x00]);
+        data.extend_from_slice(&(offset | more as u32).to_be_bytes());
+        data.extend_from_slice(&[fill; 16]);
+        data
+    };
+    let mut pp = PacketParser::new(datalink::ChannelType::Layer3(0));
+    let feed = |pp: &mut PacketParser, ts_ms, data: Vec<u8>| {
+        let pkt = SomeipPacket::new(&data).unwrap();
+        handle_someip_tp_packet(pp, &ctx(ts_ms), 30501, 30501, &pkt);
+    };
+
+    // 第2段丢失，同一个键又从第一段开始
+    feed(&mut pp, 0, segment(1, 0, true, 0xaa));
+    feed(&mut pp, 10, segment(1, 0, true, 0xbb));
+    feed(&mut pp, 20, segment(1, 16, false, 0xcc));
+    let messages = std::mem::take(&mut pp.messages);
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].payload, [[0xbb; 16], [0xcc; 16]].concat());
+    assert!(pp.tp_buffers.is_empty());
+
+    // 一直没有收到后续分段的报文超时后丢弃
+    feed(&mut pp, 100, segment(2, 0, true, 0xaa));
+    feed(&mut pp, 5000, segment(3, 0, true, 0xaa));
+    assert_eq!(pp.tp_buffers.len(), 1);
+    assert!(pp.messages.is_empty());
+}
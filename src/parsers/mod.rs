@@ -0,0 +1,3 @@
+pub mod first_step_parser;
+pub mod pnet_packet_someip;
+pub mod tcp_parser;
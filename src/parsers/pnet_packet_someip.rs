@@ -12,7 +12,7 @@ use core::fmt;
 
 use pnet::packet::PrimitiveValues;
 use pnet_macros::{packet, Packet};
-use pnet_macros_support::{packet::*, types::*};
+use pnet_macros_support::types::*;
 
 /// -----------------------------------------------------------------------------------------------
 /// Someip PROTOCOL VERSION (only 1)
@@ -53,9 +53,9 @@ impl PrimitiveValues for SomeipProtocolVersion {
 //     }
 // }
 
-/// -----------------------------------------------------------------------------------------------
-/// Someip Message Type
-/// -----------------------------------------------------------------------------------------------
+// / -----------------------------------------------------------------------------------------------
+// / Someip Message Type
+// / -----------------------------------------------------------------------------------------------
 
 #[allow(non_snake_case)]
 #[allow(non_upper_case_globals)]
@@ -76,6 +76,16 @@ impl SomeipMessageType {
     pub fn new(value: u8) -> SomeipMessageType {
         SomeipMessageType(value)
     }
+
+    /// TP报文在MessageType上置0x20标志位
+    pub fn check_is_tp(&self) -> bool {
+        self.0 & 0x20 != 0
+    }
+
+    /// 去掉TP标志位后的MessageType
+    pub fn without_tp_flag(&self) -> SomeipMessageType {
+        SomeipMessageType(self.0 & !0x20)
+    }
 }
 
 impl PrimitiveValues for SomeipMessageType {
@@ -103,21 +113,21 @@ impl fmt::Display for SomeipMessageType {
         write!(
             f,
             "{}",
-            match self {
-                &SomeipMessageTypes::Request => "Request",
-                &SomeipMessageTypes::RequestNoReturn => "RequestNoReturn",
-                &SomeipMessageTypes::Notification => "Notication",
-                &SomeipMessageTypes::Response => "Response",
-                &SomeipMessageTypes::Error => "Error",
+            match *self {
+                SomeipMessageTypes::Request => "Request",
+                SomeipMessageTypes::RequestNoReturn => "RequestNoReturn",
+                SomeipMessageTypes::Notification => "Notication",
+                SomeipMessageTypes::Response => "Response",
+                SomeipMessageTypes::Error => "Error",
                 _ => "unknown",
             }
         )
     }
 }
 
-/// -----------------------------------------------------------------------------------------------
-/// Someip Return Code
-/// -----------------------------------------------------------------------------------------------
+// / -----------------------------------------------------------------------------------------------
+// / Someip Return Code
+// / -----------------------------------------------------------------------------------------------
 
 #[allow(non_snake_case)]
 #[allow(non_upper_case_globals)]
@@ -163,34 +173,35 @@ impl fmt::Display for SomeipReturnCode {
         write!(
             f,
             "{}",
-            match self {
-                &SomeipReturnCodes::E_OK => "E_OK",
-                &SomeipReturnCodes::E_NOK => "E_NOK",
-                &SomeipReturnCodes::E_UNKNOWN_SERVICE => "E_UNKNOWN_SERVICE",
-                &SomeipReturnCodes::E_UNKNOWN_METHOD => "E_UNKNOWN_METHOD",
-                &SomeipReturnCodes::E_NOT_READY => "E_NOT_READY",
-                &SomeipReturnCodes::E_NOT_REACHABLE => "E_NOT_REACHABLE",
-                &SomeipReturnCodes::E_TIMEOUT => "E_TIMEOUT",
-                &SomeipReturnCodes::E_WRONG_PROTOCOL_VERSION => "E_WRONG_PROTOCOL_VERSION",
-                &SomeipReturnCodes::E_WRONG_INTERFACE_VERSION => "E_WRONG_INTERFACE_VERSION",
-                &SomeipReturnCodes::E_MALFORMED_MESSAGE => "E_MALFORMED_MESSAGE",
-                &SomeipReturnCodes::E_WRONG_MESSAGE_TYPE => "E_WRONG_MESSAGE_TYPE",
-                &SomeipReturnCodes::E_E2E_REPEATED => "E_E2E_REPEATED",
-                &SomeipReturnCodes::E_E2E_WRONG_SEQUENCE => "E_E2E_WRONG_SEQUENCE",
-                &SomeipReturnCodes::E_E2E => "E_E2E",
-                &SomeipReturnCodes::E_E2E_NOT_AVAILABLE => "E_E2E_NOT_AVAILABLE",
-                &SomeipReturnCodes::E_E2E_NO_NEW_DATA => "E_E2E_NO_NEW_DATA",
+            match *self {
+                SomeipReturnCodes::E_OK => "E_OK",
+                SomeipReturnCodes::E_NOK => "E_NOK",
+                SomeipReturnCodes::E_UNKNOWN_SERVICE => "E_UNKNOWN_SERVICE",
+                SomeipReturnCodes::E_UNKNOWN_METHOD => "E_UNKNOWN_METHOD",
+                SomeipReturnCodes::E_NOT_READY => "E_NOT_READY",
+                SomeipReturnCodes::E_NOT_REACHABLE => "E_NOT_REACHABLE",
+                SomeipReturnCodes::E_TIMEOUT => "E_TIMEOUT",
+                SomeipReturnCodes::E_WRONG_PROTOCOL_VERSION => "E_WRONG_PROTOCOL_VERSION",
+                SomeipReturnCodes::E_WRONG_INTERFACE_VERSION => "E_WRONG_INTERFACE_VERSION",
+                SomeipReturnCodes::E_MALFORMED_MESSAGE => "E_MALFORMED_MESSAGE",
+                SomeipReturnCodes::E_WRONG_MESSAGE_TYPE => "E_WRONG_MESSAGE_TYPE",
+                SomeipReturnCodes::E_E2E_REPEATED => "E_E2E_REPEATED",
+                SomeipReturnCodes::E_E2E_WRONG_SEQUENCE => "E_E2E_WRONG_SEQUENCE",
+                SomeipReturnCodes::E_E2E => "E_E2E",
+                SomeipReturnCodes::E_E2E_NOT_AVAILABLE => "E_E2E_NOT_AVAILABLE",
+                SomeipReturnCodes::E_E2E_NO_NEW_DATA => "E_E2E_NO_NEW_DATA",
                 _ => "unknown",
             }
         )
     }
 }
 
-/// -----------------------------------------------------------------------------------------------
-/// Someip Packet
-/// -----------------------------------------------------------------------------------------------
+// / -----------------------------------------------------------------------------------------------
+// / Someip Packet
+// / -----------------------------------------------------------------------------------------------
 
 // #[derive(Packet, Debug)]
+#[allow(dead_code)]
 #[packet]
 pub struct Someip {
     #[construct_with(u16)]
@@ -227,13 +238,14 @@ fn someip_length(pkt: &SomeipPacket) -> usize {
 }
 
 impl<'p> SomeipIterable<'p> {
-    pub fn new(buf: &[u8]) -> SomeipIterable {
-        SomeipIterable { buf: buf }
+    pub fn new(buf: &[u8]) -> SomeipIterable<'_> {
+        SomeipIterable { buf }
     }
 }
 
 #[test]
 fn one_someip_packet_detect() {
+    use pnet_macros_support::packet::{Packet, PacketSize};
     use std::println;
     let packet = [
         0x10, 0x49, 0x90, 0x0d, 0x00, 0x00, 0x00, 0x09, 0x00, 0x00, 0x4e, 0xcd, 0x01, 0x01, 0x02,
@@ -253,9 +265,9 @@ fn one_someip_packet_detect() {
     println!("packet_size:{:?}", someippkt.packet_size());
 }
 
-/// -----------------------------------------------------------------------------------------------
-/// Someip SD Entry Type
-/// -----------------------------------------------------------------------------------------------
+// / -----------------------------------------------------------------------------------------------
+// / Someip SD Entry Type
+// / -----------------------------------------------------------------------------------------------
 
 #[allow(non_snake_case)]
 #[allow(non_upper_case_globals)]
@@ -319,6 +331,7 @@ impl PrimitiveValues for SomeipSdEntryType {
 // / Someip SD Entry
 // / -----------------------------------------------------------------------------------------------
 
+#[allow(dead_code)]
 #[derive(Packet, Debug)]
 pub struct SomeipSdEntry {
     #[construct_with(u8)]
@@ -356,6 +369,7 @@ pub struct SomeipSdEntry {
 // / Someip SD Packet (Without Someip Packet Header)
 // / -----------------------------------------------------------------------------------------------
 
+#[allow(dead_code)]
 #[derive(Packet, Debug)]
 pub struct SomeipSd {
     pub flags: u8,
@@ -373,7 +387,7 @@ pub struct SomeipSd {
 
 #[test]
 fn one_someip_sd_packet_detect() {
-    // use pnet_macros_support::packet::Packet;
+    use pnet_macros_support::packet::PacketSize;
     use std::println;
     let packet = [
         // 0xff, 0xff, 0x81, 0x00, 0x00, 0x00, 0x05, 0x74, 0x00, 0x00, 0x0c, 0xbe, 0x01, 0x01, 0x02,
@@ -0,0 +1,180 @@
+/// TCP-SOMEIP的数据流重组
+/// TCP是流式传输，一条SomeIP消息可能跨越多个TCP段，一个TCP段也可能包含多条SomeIP消息
+/// 这里按照IP-PORT四元组划分TCP连接，每条连接维护一个缓冲区，按序号拼接后再按SomeIP的Length字段切分
+/// 不处理乱序重传：遇到序号不连续的段，直接丢弃缓冲区并从下一个合法报文头重新同步
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use log::debug;
+
+use crate::types::{PacketIndex, Port};
+
+/// SomeIP报文头长度，Length字段之前的8字节加上Length覆盖的8字节
+pub const SOMEIP_HEADER_LENGTH: usize = 16;
+
+/// Length字段的上限，报文头错位或者损坏时Length可能接近4GiB，超过上限就认为不是合法的报文头，
+/// 不再等待数据而是丢弃缓冲区重新同步
+pub const SOMEIP_MAX_LENGTH: usize = 0x10_0000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TcpStreamKey {
+    pub src_ip: IpAddr,
+    pub src_port: Port,
+    pub dst_ip: IpAddr,
+    pub dst_port: Port,
+}
+
+#[derive(Default)]
+struct TcpStreamBuffer {
+    next_seq: Option<u32>,
+    buf: Vec<u8>,
+    /// 缓冲区中每一帧数据的结束位置与帧序号，用于找出一条报文由哪些帧组成
+    frames: Vec<(usize, PacketIndex)>,
+}
+
+#[derive(Default)]
+pub struct TcpStreams {
+    streams: HashMap<TcpStreamKey, TcpStreamBuffer>,
+}
+
+impl TcpStreams {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// 送入一个TCP段的负载，返回当前已经完整的SomeIP报文（每个元素为一条完整的原始报文与组成它的帧序号）
+    pub fn push_segment(
+        &mut self,
+        key: TcpStreamKey,
+        seq: u32,
+        packet_index: PacketIndex,
+        payload: &[u8],
+    ) -> Vec<(Vec<u8>, Vec<PacketIndex>)> {
+        if payload.is_empty() {
+            return vec![];
+        }
+
+        let stream = self.streams.entry(key).or_default();
+
+        let mut data = payload;
+        match stream.next_seq {
+            Some(next_seq) if next_seq != seq => {
+                let ahead = seq.wrapping_sub(next_seq);
+                let behind = next_seq.wrapping_sub(seq);
+                if (behind as usize) < payload.len() {
+                    // 部分重传，只取新数据
+                    data = &payload[behind as usize..];
+                } else if behind < 0x8000_0000 {
+                    // 完整的重传，忽略
+                    return vec![];
+                } else {
+                    debug!("tcp stream {:?} lost {} bytes, resync.", key, ahead);
+                    stream.buf.clear();
+                    stream.frames.clear();
+                }
+            }
+            _ => {}
+        }
+        stream.next_seq = Some(seq.wrapping_add(payload.len() as u32));
+        stream.buf.extend_from_slice(data);
+        stream.frames.push((stream.buf.len(), packet_index));
+
+        let mut messages = vec![];
+        loop {
+            if stream.buf.len() < SOMEIP_HEADER_LENGTH {
+                break;
+            }
+            let length =
+                u32::from_be_bytes([stream.buf[4], stream.buf[5], stream.buf[6], stream.buf[7]])
+                    as usize;
+            if !(8..=SOMEIP_MAX_LENGTH).contains(&length) || stream.buf[12] != 0x01 {
+                // 不是合法的SomeIP报文头，丢弃缓冲区等待下一次同步
+                debug!(
+                    "tcp stream {:?} is not someip, drop {} bytes.",
+                    key,
+                    stream.buf.len()
+                );
+                stream.buf.clear();
+                stream.frames.clear();
+                break;
+            }
+            let total = length + 8;
+            if stream.buf.len() < total {
+                break;
+            }
+            // 结束位置在报文之后的帧还有数据留在缓冲区中，继续保留
+            let mut frames = vec![];
+            let mut start = 0;
+            for (end, index) in &stream.frames {
+                if start >= total {
+                    break;
+                }
+                frames.push(*index);
+                start = *end;
+            }
+            stream.frames.retain_mut(|(end, _)| {
+                *end = end.saturating_sub(total);
+                *end > 0
+            });
+            messages.push((stream.buf.drain(..total).collect(), frames));
+        }
+        messages
+    }
+}
+
+#[test]
+fn tcp_stream_split_and_join() {
+    use std::net::Ipv4Addr;
+
+    let key = TcpStreamKey {
+        src_ip: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+        src_port: 30501,
+        dst_ip: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+        dst_port: 40000,
+    };
+    let msg = [
+        0x10, 0x49, 0x90, 0x0d, 0x00, 0x00, 0x00, 0x09, 0x00, 0x00, 0x4e, 0xcd, 0x01, 0x01, 0x02,
+        0x00, 0xaa,
+    ];
+    let mut streams = TcpStreams::new();
+
+    // 一条报文拆成两个段
+    assert!(streams.push_segment(key, 100, 0, &msg[..10]).is_empty());
+    let out = streams.push_segment(key, 110, 1, &msg[10..]);
+    assert_eq!(out, vec![(msg.to_vec(), vec![0, 1])]);
+
+    // 两条报文合并在一个段，并带有一次重传
+    let mut two = msg.to_vec();
+    two.extend_from_slice(&msg);
+    assert!(streams.push_segment(key, 100, 2, &msg).is_empty());
+    let out = streams.push_segment(key, 117, 3, &two);
+    assert_eq!(out.len(), 2);
+    assert_eq!(out[1].1, vec![3]);
+}
+
+#[test]
+fn tcp_stream_resync_after_bad_length() {
+    use std::net::Ipv4Addr;
+
+    let key = TcpStreamKey {
+        src_ip: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+        src_port: 30501,
+        dst_ip: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+        dst_port: 40000,
+    };
+    let msg = [
+        0x10, 0x49, 0x90, 0x0d, 0x00, 0x00, 0x00, 0x09, 0x00, 0x00, 0x4e, 0xcd, 0x01, 0x01, 0x02,
+        0x00, 0xaa,
+    ];
+    let mut streams = TcpStreams::new();
+
+    // Length接近4GiB的报文头直接丢弃，不会一直缓存后续数据
+    let mut bad = msg;
+    bad[4..8].copy_from_slice(&0xFFFF_FFF0u32.to_be_bytes());
+    assert!(streams.push_segment(key, 100, 0, &bad).is_empty());
+    assert!(streams.streams[&key].buf.is_empty());
+
+    // 下一个段从合法的报文头开始，重新同步
+    let out = streams.push_segment(key, 117, 1, &msg);
+    assert_eq!(out, vec![(msg.to_vec(), vec![1])]);
+}
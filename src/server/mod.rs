@@ -0,0 +1,681 @@
+/// HTTP分析服务：serve子命令启动，通过REST接口注册矩阵与抓包、运行分析、分页查看解析出的报文
+/// 矩阵中保存了裸指针，不能在线程间共享，所以所有请求在同一个线程中依次处理；每个请求都重新读取抓包
+///
+/// 接口，返回值都是JSON，出错时为 {"error": "..."}：
+/// - GET  /api/matrices                  已注册的矩阵
+/// - POST /api/matrices?path=<file>      注册服务器上的矩阵文件，excel或json
+/// - POST /api/matrices?name=<file>      上传矩阵文件，请求体为文件内容，保存到数据目录后按后缀名读取，重名时不覆盖
+/// - GET  /api/captures                  已注册的抓包
+/// - POST /api/captures?path=|name=      注册或上传抓包，与矩阵相同
+/// - POST /api/analyses                  请求体为 {"capture": id, "matrix": id, "analyses": [...]}，名字与--analyze一致
+/// - GET  /api/captures/<id>/messages?matrix=&filter=&offset=&limit=  分页查看解析出的报文，过滤表达式与命令行一致
+/// - GET  /api/captures/<id>/statistics?matrix=                       按报文类型与方法统计报文数
+/// - GET  /api/captures/<id>/sd-timeline?matrix=&filter=              按时间顺序列出SD条目
+/// - POST /api/captures/<id>/store?matrix=                            解析一次并保存到数据目录中的SQLite数据库
+/// - GET  /api/captures/<id>/sql?q=<sql>                              对保存的数据库执行只读SQL
+///
+/// 保存过的抓包分页查看报文时直接查询数据库，过滤表达式按照保存时的矩阵解析，matrix参数不起作用
+///
+/// matrix参数可以省略，此时使用空矩阵，只能看到报文头
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use log::{debug, info};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::analyzers::{self, Analyzer};
+use crate::errors::MyError;
+use crate::matrix::types::Matrix;
+use crate::sinks::{is_selected, message_json, select_methods, Sink};
+use crate::sources::pcap_source::PcapFileSource;
+use crate::sources::Source;
+use crate::store::{Store, StoreSink};
+use crate::types::{SomeipMessage, SomeipMethodId, SomeipServiceId};
+
+/// 分页查看报文时每页的缺省数量与最大数量
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+
+/// 可以上传的矩阵与抓包文件的后缀名
+const MATRIX_EXTENSIONS: [&str; 3] = ["xlsx", "xls", "json"];
+const CAPTURE_EXTENSIONS: [&str; 3] = ["pcap", "pcapng", "cap"];
+
+/// 返回给客户端的错误，带HTTP状态码
+#[derive(Debug)]
+struct ApiError {
+    status: u16,
+    message: String,
+}
+
+impl ApiError {
+    fn bad_request(message: String) -> Self {
+        ApiError {
+            status: 400,
+            message,
+        }
+    }
+
+    fn not_found(message: String) -> Self {
+        ApiError {
+            status: 404,
+            message,
+        }
+    }
+}
+
+impl From<MyError> for ApiError {
+    fn from(error: MyError) -> Self {
+        let status = match error {
+            MyError::ArgInputError(_) | MyError::ParseMatrixFileError(_) => 400,
+            _ => 500,
+        };
+        ApiError {
+            status,
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<std::io::Error> for ApiError {
+    fn from(error: std::io::Error) -> Self {
+        MyError::from(error).into()
+    }
+}
+
+#[derive(Deserialize)]
+struct AnalysisRequest {
+    capture: String,
+    matrix: Option<String>,
+    analyses: Vec<String>,
+}
+
+/// 注册的矩阵与抓包，id是文件名去掉后缀，重名时加上序号
+pub struct Server {
+    data_dir: PathBuf,
+    empty: Matrix,
+    matrices: BTreeMap<String, Matrix>,
+    captures: BTreeMap<String, PathBuf>,
+    /// 抓包 -> 保存的数据库
+    stores: BTreeMap<String, PathBuf>,
+}
+
+/// 解码URL中的%XX与+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => match text
+                .get(i + 1..i + 3)
+                .map(|hex| u8::from_str_radix(hex, 16))
+            {
+                Some(Ok(byte)) => {
+                    decoded.push(byte);
+                    i += 2;
+                }
+                _ => decoded.push(b'%'),
+            },
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+pub(crate) fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect()
+}
+
+fn query_usize(
+    query: &HashMap<String, String>,
+    key: &str,
+    default: usize,
+) -> Result<usize, ApiError> {
+    match query.get(key) {
+        Some(value) => value
+            .parse()
+            .map_err(|_| ApiError::bad_request(format!("invalid {} {}", key, value))),
+        None => Ok(default),
+    }
+}
+
+/// 分页收集选中的报文
+struct PageSink<'a> {
+    matrix: &'a Matrix,
+    selected: Option<Vec<(SomeipServiceId, SomeipMethodId)>>,
+    offset: usize,
+    limit: usize,
+    total: usize,
+    messages: Vec<Value>,
+}
+
+impl<'a> Sink for PageSink<'a> {
+    fn write_message(&mut self, msg: &SomeipMessage) -> Result<(), MyError> {
+        if !is_selected(self.selected.as_deref(), msg) {
+            return Ok(());
+        }
+        if self.total >= self.offset && self.messages.len() < self.limit {
+            self.messages.push(message_json(self.matrix, msg));
+        }
+        self.total += 1;
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct StatisticsSink {
+    messages: usize,
+    first_timestamp: Option<Duration>,
+    last_timestamp: Duration,
+    by_type: BTreeMap<String, usize>,
+    by_method: BTreeMap<(SomeipServiceId, SomeipMethodId), usize>,
+}
+
+impl Sink for StatisticsSink {
+    fn write_message(&mut self, msg: &SomeipMessage) -> Result<(), MyError> {
+        self.messages += 1;
+        self.first_timestamp.get_or_insert(msg.timestamp);
+        self.last_timestamp = msg.timestamp;
+        *self
+            .by_type
+            .entry(msg.message_type.to_string())
+            .or_default() += 1;
+        *self
+            .by_method
+            .entry((msg.service_id, msg.method_id))
+            .or_default() += 1;
+        Ok(())
+    }
+}
+
+/// SD报文中的条目按时间顺序排成一列，选中时只保留选中服务的条目
+struct SdTimelineSink<'a> {
+    matrix: &'a Matrix,
+    selected: Option<Vec<(SomeipServiceId, SomeipMethodId)>>,
+    entries: Vec<Value>,
+}
+
+impl<'a> Sink for SdTimelineSink<'a> {
+    fn write_message(&mut self, msg: &SomeipMessage) -> Result<(), MyError> {
+        let sd = match &msg.sd {
+            Some(sd) => sd,
+            None => return Ok(()),
+        };
+        for entry in &sd.entries {
+            if let Some(selected) = &self.selected {
+                if !selected
+                    .iter()
+                    .any(|(service_id, _)| *service_id == entry.service_id)
+                {
+                    continue;
+                }
+            }
+            self.entries.push(json!({
+                "timestamp": msg.timestamp.as_secs_f64(),
+                "source": self.matrix.role_name_or_ip(&msg.source.ip_addr),
+                "destination": self.matrix.role_name_or_ip(&msg.destination.ip_addr),
+                "type": entry.entry_type.to_string(),
+                "service_id": entry.service_id,
+                "service_name": self.matrix.service_name(entry.service_id),
+                "instance_id": entry.instance_id,
+                "eventgroup_id": entry.eventgroup_id,
+                "major_version": entry.major_version,
+                "ttl": entry.ttl,
+                "endpoints": entry
+                    .endpoints
+                    .iter()
+                    .map(|endpoint| format!(
+                        "{}:{}/{:?}",
+                        endpoint.ip_addr, endpoint.port, endpoint.transport_protocol
+                    ))
+                    .collect::<Vec<_>>(),
+            }));
+        }
+        Ok(())
+    }
+}
+
+impl Server {
+    pub fn new(data_dir: PathBuf) -> Self {
+        Server {
+            data_dir,
+            empty: Matrix::default(),
+            matrices: BTreeMap::new(),
+            captures: BTreeMap::new(),
+            stores: BTreeMap::new(),
+        }
+    }
+
+    /// 处理一个请求，返回状态码与JSON
+    pub fn handle(&mut self, method: &str, url: &str, body: &[u8]) -> (u16, Value) {
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        let query = parse_query(query);
+        let segments: Vec<String> = path
+            .trim_matches('/')
+            .split('/')
+            .map(percent_decode)
+            .collect();
+        let segments: Vec<&str> = segments.iter().map(|s| s.as_str()).collect();
+        let result = match (method, segments.as_slice()) {
+            ("GET", ["api", "matrices"]) => Ok(self.list_matrices()),
+            ("POST", ["api", "matrices"]) => self.add_matrix(&query, body),
+            ("GET", ["api", "captures"]) => Ok(self.list_captures()),
+            ("POST", ["api", "captures"]) => self.add_capture(&query, body),
+            ("POST", ["api", "analyses"]) => self.analyze(body),
+            ("GET", ["api", "captures", id, "messages"]) => self.messages(id, &query),
+            ("GET", ["api", "captures", id, "statistics"]) => self.statistics(id, &query),
+            ("GET", ["api", "captures", id, "sd-timeline"]) => self.sd_timeline(id, &query),
+            ("POST", ["api", "captures", id, "store"]) => self.save_store(id, &query),
+            ("GET", ["api", "captures", id, "sql"]) => self.sql(id, &query),
+            _ => Err(ApiError::not_found(format!(
+                "no route for {} {}",
+                method, path
+            ))),
+        };
+        match result {
+            Ok(value) => (200, value),
+            Err(error) => (error.status, json!({ "error": error.message })),
+        }
+    }
+
+    fn unique_id<T>(ids: &BTreeMap<String, T>, path: &Path) -> String {
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut id = stem.clone();
+        let mut n = 1;
+        while ids.contains_key(&id) {
+            n += 1;
+            id = format!("{}-{}", stem, n);
+        }
+        id
+    }
+
+    /// path参数注册服务器上已有的文件，name参数把请求体保存到数据目录中
+    /// 上传的文件只接受给定的后缀名，不覆盖已有的文件，重名时与id一样加上序号
+    fn upload(
+        &self,
+        query: &HashMap<String, String>,
+        body: &[u8],
+        extensions: &[&str],
+    ) -> Result<PathBuf, ApiError> {
+        match (query.get("path"), query.get("name")) {
+            (Some(path), _) => Path::new(path)
+                .canonicalize()
+                .map_err(|_| ApiError::bad_request(format!("file not found {}", path))),
+            (None, Some(name)) => {
+                // 只取文件名，防止写到数据目录之外
+                let file_name = Path::new(name)
+                    .file_name()
+                    .map(Path::new)
+                    .ok_or_else(|| ApiError::bad_request(format!("invalid name {}", name)))?;
+                let ext = file_name
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .map(|ext| ext.to_ascii_lowercase())
+                    .filter(|ext| extensions.contains(&ext.as_str()))
+                    .ok_or_else(|| {
+                        ApiError::bad_request(format!(
+                            "invalid name {}, expected extension {}",
+                            name,
+                            extensions.join("/")
+                        ))
+                    })?;
+                let stem = file_name
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                std::fs::create_dir_all(&self.data_dir)?;
+                let mut n = 1;
+                loop {
+                    let path = match n {
+                        1 => self.data_dir.join(format!("{}.{}", stem, ext)),
+                        _ => self.data_dir.join(format!("{}-{}.{}", stem, n, ext)),
+                    };
+                    let file = std::fs::OpenOptions::new()
+                        .write(true)
+                        .create_new(true)
+                        .open(&path);
+                    match file {
+                        Ok(mut file) => {
+                            file.write_all(body)?;
+                            info!("stored {} bytes to {}", body.len(), path.display());
+                            return Ok(path);
+                        }
+                        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => n += 1,
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+            }
+            (None, None) => Err(ApiError::bad_request("path or name is required".to_owned())),
+        }
+    }
+
+    fn matrix(&self, query: &HashMap<String, String>) -> Result<&Matrix, ApiError> {
+        match query.get("matrix") {
+            Some(id) => self.matrix_by_id(id),
+            None => Ok(&self.empty),
+        }
+    }
+
+    fn matrix_by_id(&self, id: &str) -> Result<&Matrix, ApiError> {
+        self.matrices
+            .get(id)
+            .ok_or_else(|| ApiError::not_found(format!("unknown matrix {}", id)))
+    }
+
+    fn selected(
+        matrix: &Matrix,
+        query: &HashMap<String, String>,
+    ) -> Result<Option<Vec<(SomeipServiceId, SomeipMethodId)>>, ApiError> {
+        match query.get("filter").filter(|filter| !filter.is_empty()) {
+            Some(filter) => Ok(Some(select_methods(matrix, filter)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// 从头读取一个抓包，交给分析器与输出
+    fn run(
+        &self,
+        capture: &str,
+        analyzers: &mut [&mut dyn Analyzer],
+        sinks: &mut [&mut dyn Sink],
+    ) -> Result<(), ApiError> {
+        let path = self
+            .captures
+            .get(capture)
+            .ok_or_else(|| ApiError::not_found(format!("unknown capture {}", capture)))?;
+        let source = Source::PcapFile(PcapFileSource::new(path)?);
+        analyzers::run(source, analyzers, sinks)?;
+        for sink in sinks.iter_mut() {
+            sink.finish()?;
+        }
+        Ok(())
+    }
+
+    fn list_matrices(&self) -> Value {
+        let matrices: Vec<Value> = self
+            .matrices
+            .iter()
+            .map(|(id, matrix)| {
+                json!({
+                    "id": id,
+                    "version": matrix.version,
+                    "services": matrix.services.len(),
+                    "roles": matrix.roles.len(),
+                })
+            })
+            .collect();
+        json!({ "matrices": matrices })
+    }
+
+    fn add_matrix(
+        &mut self,
+        query: &HashMap<String, String>,
+        body: &[u8],
+    ) -> Result<Value, ApiError> {
+        let path = self.upload(query, body, &MATRIX_EXTENSIONS)?;
+        let matrix = Matrix::from_file(&path)?;
+        let id = Self::unique_id(&self.matrices, &path);
+        info!("register matrix {} from {}", id, path.display());
+        let value = json!({
+            "id": id,
+            "version": matrix.version,
+            "services": matrix.services.len(),
+            "roles": matrix.roles.len(),
+        });
+        self.matrices.insert(id, matrix);
+        Ok(value)
+    }
+
+    fn list_captures(&self) -> Value {
+        let captures: Vec<Value> = self
+            .captures
+            .iter()
+            .map(|(id, path)| {
+                json!({
+                    "id": id,
+                    "path": path.display().to_string(),
+                    "stored": self.stores.contains_key(id),
+                })
+            })
+            .collect();
+        json!({ "captures": captures })
+    }
+
+    fn add_capture(
+        &mut self,
+        query: &HashMap<String, String>,
+        body: &[u8],
+    ) -> Result<Value, ApiError> {
+        let path = self.upload(query, body, &CAPTURE_EXTENSIONS)?;
+        // 打开一次，确认是可以读取的抓包文件
+        PcapFileSource::new(&path)?;
+        let id = Self::unique_id(&self.captures, &path);
+        info!("register capture {} from {}", id, path.display());
+        let value = json!({ "id": id, "path": path.display().to_string() });
+        self.captures.insert(id, path);
+        Ok(value)
+    }
+
+    /// 没有JSON报告的分析器返回文本报告
+    fn analyze(&self, body: &[u8]) -> Result<Value, ApiError> {
+        let request: AnalysisRequest = serde_json::from_slice(body)
+            .map_err(|e| ApiError::bad_request(format!("invalid analysis request: {}", e)))?;
+        let matrix = match &request.matrix {
+            Some(id) => self.matrix_by_id(id)?,
+            None => &self.empty,
+        };
+        let mut analyzers = vec![];
+        for name in &request.analyses {
+            match analyzers::by_name(name, matrix) {
+                Some(analyzer) => analyzers.push(analyzer),
+                None => return Err(ApiError::bad_request(format!("unknown analysis {}", name))),
+            }
+        }
+        let mut running: Vec<&mut dyn Analyzer> = analyzers
+            .iter_mut()
+            .map(|a| a.as_mut() as &mut dyn Analyzer)
+            .collect();
+        self.run(&request.capture, &mut running, &mut [])?;
+
+        let mut reports = serde_json::Map::new();
+        for analyzer in &analyzers {
+            let report = match analyzer.json_report() {
+                Some(report) => report,
+                None => {
+                    let mut text = vec![];
+                    analyzer.write_report(&mut text)?;
+                    json!({ "text": String::from_utf8_lossy(&text) })
+                }
+            };
+            reports.insert(analyzer.name().to_owned(), report);
+        }
+        Ok(json!({ "capture": request.capture, "reports": reports }))
+    }
+
+    fn opened_store(&self, id: &str) -> Result<Option<Store>, ApiError> {
+        match self.stores.get(id) {
+            Some(path) => Ok(Some(Store::open(path)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn save_store(&mut self, id: &str, query: &HashMap<String, String>) -> Result<Value, ApiError> {
+        if !self.captures.contains_key(id) {
+            return Err(ApiError::not_found(format!("unknown capture {}", id)));
+        }
+        let matrix = self.matrix(query)?;
+        std::fs::create_dir_all(&self.data_dir)?;
+        let path = self.data_dir.join(format!("{}.sqlite", id));
+        let mut store = StoreSink::new(matrix, &path)?;
+        self.run(id, &mut [], &mut [&mut store])?;
+        drop(store);
+        let (_, rows) = Store::open(&path)?.sql("SELECT COUNT(*) FROM messages")?;
+        info!("store capture {} to {}", id, path.display());
+        self.stores.insert(id.to_owned(), path);
+        Ok(json!({ "id": id, "messages": rows[0][0] }))
+    }
+
+    fn sql(&self, id: &str, query: &HashMap<String, String>) -> Result<Value, ApiError> {
+        let store = self
+            .opened_store(id)?
+            .ok_or_else(|| ApiError::not_found(format!("capture {} is not stored", id)))?;
+        let sql = query
+            .get("q")
+            .ok_or_else(|| ApiError::bad_request("q is required".to_owned()))?;
+        let (columns, rows) = store
+            .sql(sql)
+            .map_err(|e| ApiError::bad_request(e.to_string()))?;
+        Ok(json!({ "columns": columns, "rows": rows }))
+    }
+
+    fn messages(&self, id: &str, query: &HashMap<String, String>) -> Result<Value, ApiError> {
+        let offset = query_usize(query, "offset", 0)?;
+        let limit = query_usize(query, "limit", DEFAULT_PAGE_SIZE)?.min(MAX_PAGE_SIZE);
+        if let Some(store) = self.opened_store(id)? {
+            let filter = query.get("filter").filter(|filter| !filter.is_empty());
+            let (total, messages) = store.messages(filter.map(|s| s.as_str()), offset, limit)?;
+            return Ok(json!({
+                "total": total,
+                "offset": offset,
+                "limit": limit,
+                "messages": messages,
+            }));
+        }
+        let matrix = self.matrix(query)?;
+        let mut page = PageSink {
+            matrix,
+            selected: Self::selected(matrix, query)?,
+            offset,
+            limit,
+            total: 0,
+            messages: vec![],
+        };
+        self.run(id, &mut [], &mut [&mut page])?;
+        Ok(json!({
+            "total": page.total,
+            "offset": page.offset,
+            "limit": page.limit,
+            "messages": page.messages,
+        }))
+    }
+
+    fn statistics(&self, id: &str, query: &HashMap<String, String>) -> Result<Value, ApiError> {
+        let matrix = self.matrix(query)?;
+        let mut statistics = StatisticsSink::default();
+        self.run(id, &mut [], &mut [&mut statistics])?;
+        let duration = match statistics.first_timestamp {
+            Some(first) => statistics.last_timestamp.saturating_sub(first),
+            None => Duration::ZERO,
+        };
+        let by_method: Vec<Value> = statistics
+            .by_method
+            .iter()
+            .map(|((service_id, method_id), count)| {
+                let service = matrix.services.get(service_id);
+                let method = service.and_then(|service| service.methods.get(method_id));
+                json!({
+                    "service_id": service_id,
+                    "service_name": service.map(|service| service.service_name.as_str()),
+                    "method_id": method_id,
+                    "method_name": method.map(|method| method.method_name.as_str()),
+                    "count": count,
+                })
+            })
+            .collect();
+        Ok(json!({
+            "messages": statistics.messages,
+            "duration": duration.as_secs_f64(),
+            "by_type": statistics.by_type,
+            "by_method": by_method,
+        }))
+    }
+
+    fn sd_timeline(&self, id: &str, query: &HashMap<String, String>) -> Result<Value, ApiError> {
+        let matrix = self.matrix(query)?;
+        let mut timeline = SdTimelineSink {
+            matrix,
+            selected: Self::selected(matrix, query)?,
+            entries: vec![],
+        };
+        self.run(id, &mut [], &mut [&mut timeline])?;
+        Ok(json!({ "entries": timeline.entries }))
+    }
+}
+
+/// 监听地址，依次处理请求直到进程退出
+pub fn serve(listen: &str, data_dir: PathBuf) -> Result<(), MyError> {
+    let http = tiny_http::Server::http(listen)
+        .map_err(|e| MyError::Custom(format!("listen on {} failed: {}", listen, e)))?;
+    println!("listening on http://{}", listen);
+    let mut server = Server::new(data_dir);
+    let content_type =
+        tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+    for mut request in http.incoming_requests() {
+        let mut body = vec![];
+        let (status, value) = match request.as_reader().read_to_end(&mut body) {
+            Ok(_) => server.handle(request.method().as_str(), request.url(), &body),
+            Err(e) => (400, json!({ "error": e.to_string() })),
+        };
+        debug!("{} {} -> {}", request.method(), request.url(), status);
+        let response = tiny_http::Response::from_string(value.to_string())
+            .with_status_code(status)
+            .with_header(content_type.clone());
+        if let Err(e) = request.respond(response) {
+            debug!("respond failed: {}", e);
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn register_matrix() {
+    let data_dir = std::env::temp_dir().join(format!("someip-serve-{}", std::process::id()));
+    let mut server = Server::new(data_dir.clone());
+    let body = serde_json::to_vec(&Matrix::default()).unwrap();
+    for _ in 0..2 {
+        let (status, value) = server.handle("POST", "/api/matrices?name=car%20a.json", &body);
+        assert_eq!(status, 200, "{}", value);
+    }
+    let (_, value) = server.handle("GET", "/api/matrices", &[]);
+    let ids: Vec<&str> = value["matrices"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|matrix| matrix["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(ids, ["car a", "car a-2"]);
+    // 第二次上传没有覆盖第一次的文件
+    assert!(data_dir.join("car a.json").exists());
+    assert!(data_dir.join("car a-2.json").exists());
+
+    let (status, value) = server.handle("POST", "/api/matrices?name=car.sqlite", &body);
+    assert_eq!(status, 400);
+    assert_eq!(
+        value["error"],
+        "invalid name car.sqlite, expected extension xlsx/xls/json"
+    );
+    let (status, _) = server.handle("POST", "/api/captures?name=capture", &body);
+    assert_eq!(status, 400);
+
+    let (status, value) = server.handle("GET", "/api/captures/none/messages?limit=10", &[]);
+    assert_eq!(status, 404);
+    assert_eq!(value["error"], "unknown capture none");
+    let (status, _) = server.handle("GET", "/api/captures/x/statistics?matrix=nope", &[]);
+    assert_eq!(status, 404);
+    std::fs::remove_dir_all(data_dir).unwrap();
+}
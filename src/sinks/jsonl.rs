@@ -0,0 +1,70 @@
+/// JSON Lines输出：每条报文一行JSON，字段见message_json，供Python、Grafana等工具直接读取
+use std::io::Write;
+
+use crate::errors::MyError;
+use crate::matrix::types::Matrix;
+use crate::types::SomeipMessage;
+
+use super::{message_json, Sink};
+
+pub struct JsonLinesSink<'a, W: Write> {
+    matrix: &'a Matrix,
+    writer: W,
+}
+
+impl<'a, W: Write> JsonLinesSink<'a, W> {
+    pub fn new(matrix: &'a Matrix, writer: W) -> Self {
+        JsonLinesSink { matrix, writer }
+    }
+}
+
+impl<'a, W: Write> Sink for JsonLinesSink<'a, W> {
+    fn write_message(&mut self, msg: &SomeipMessage) -> Result<(), MyError> {
+        serde_json::to_writer(&mut self.writer, &message_json(self.matrix, msg))?;
+        writeln!(self.writer)?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), MyError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+#[test]
+fn json_lines() {
+    use crate::analyzers::test_message;
+    use crate::types::SomeipMessageType;
+
+    let matrix = Matrix::default();
+    let mut sink = JsonLinesSink::new(&matrix, vec![]);
+    for session_id in [1, 2] {
+        let mut msg = test_message(
+            1500,
+            "10.0.0.1:40000",
+            "10.0.0.2:30501",
+            SomeipMessageType::Request,
+            0x1234,
+            1,
+        );
+        msg.session_id = session_id;
+        sink.write_message(&msg).unwrap();
+    }
+    sink.finish().unwrap();
+
+    let output = String::from_utf8(sink.writer).unwrap();
+    let lines: Vec<serde_json::Value> = output
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[1]["session_id"], 2);
+    assert_eq!(lines[0]["timestamp"], 1.5);
+    assert_eq!(lines[0]["source"]["port"], 40000);
+    assert_eq!(lines[0]["message_type"], "Request");
+    assert_eq!(lines[0]["service_name"], serde_json::Value::Null);
+    assert_eq!(
+        lines[0]["anomalies"],
+        serde_json::json!(["unknown_service"])
+    );
+}
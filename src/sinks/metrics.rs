@@ -0,0 +1,472 @@
+/// Prometheus指标：--metrics <地址> 启动HTTP服务，GET /metrics 返回文本格式的指标，用于长时间运行的实时抓包
+/// 标签中的服务名、方法名与角色来自矩阵，矩阵中没有的显示十六进制ID或IP
+/// 矩阵不能跨线程，指标在处理报文的线程中计算，HTTP线程只读取计算好的值
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::{debug, info};
+
+use crate::analyzers::return_code::return_code_name;
+use crate::analyzers::session::{check_someip_session_id, SessionAnomalyKind};
+use crate::errors::MyError;
+use crate::matrix::types::Matrix;
+use crate::types::{
+    Port, SomeipClientId, SomeipInstanceId, SomeipMessage, SomeipMessageType, SomeipMethodId,
+    SomeipSdEntryType, SomeipServiceId, SomeipSessionId,
+};
+
+use super::Sink;
+
+/// 指标名、类型与说明
+const METRICS: [(&str, &str, &str); 8] = [
+    (
+        "someip_messages_total",
+        "counter",
+        "SOME/IP messages by sender.",
+    ),
+    (
+        "someip_bytes_total",
+        "counter",
+        "SOME/IP message bytes including the header.",
+    ),
+    (
+        "someip_request_latency_seconds",
+        "histogram",
+        "Request to response latency by responder.",
+    ),
+    (
+        "someip_request_timeouts_total",
+        "counter",
+        "Requests without a response within the timeout.",
+    ),
+    (
+        "someip_error_responses_total",
+        "counter",
+        "Responses with a non-zero return code.",
+    ),
+    (
+        "someip_session_lost_messages_total",
+        "counter",
+        "Messages missing from the session id sequence.",
+    ),
+    (
+        "someip_cycle_violations_total",
+        "counter",
+        "Cyclic messages deviating from the matrix cycle time.",
+    ),
+    (
+        "someip_sd_service_available",
+        "gauge",
+        "Whether the service instance is offered and its ttl has not expired.",
+    ),
+];
+/// 时延直方图的上界，单位秒
+const LATENCY_BUCKETS: [f64; 10] = [0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0];
+/// 超过该时间仍未收到应答的Request计为超时
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(1);
+/// 周期与矩阵中的周期相差超过该比例认为违反周期
+const CYCLE_TOLERANCE: f64 = 0.1;
+/// SD中表示永久有效的TTL
+const TTL_INFINITE: u32 = 0xFFFFFF;
+
+type Labels = Vec<(&'static str, String)>;
+type RequestKey = (
+    SomeipServiceId,
+    SomeipMethodId,
+    SomeipClientId,
+    SomeipSessionId,
+);
+type SenderKey = (IpAddr, Port, SomeipServiceId, SomeipMethodId);
+
+#[derive(Debug, Default)]
+struct Histogram {
+    /// 每个上界内的样本数，不累加
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+/// 计算好的指标，可以在线程间共享
+#[derive(Debug, Default)]
+pub struct Metrics {
+    counters: BTreeMap<&'static str, BTreeMap<Labels, u64>>,
+    histograms: BTreeMap<&'static str, BTreeMap<Labels, Histogram>>,
+    gauges: BTreeMap<&'static str, BTreeMap<Labels, f64>>,
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn format_labels(labels: &Labels, extra: Option<(&str, &str)>) -> String {
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(name, value)| (*name, value.as_str()))
+        .chain(extra)
+        .map(|(name, value)| format!("{}=\"{}\"", name, escape(value)))
+        .collect();
+    match labels.is_empty() {
+        true => String::new(),
+        false => format!("{{{}}}", labels.join(",")),
+    }
+}
+
+impl Metrics {
+    fn inc(&mut self, name: &'static str, labels: Labels, by: u64) {
+        *self
+            .counters
+            .entry(name)
+            .or_default()
+            .entry(labels)
+            .or_default() += by;
+    }
+
+    fn observe(&mut self, name: &'static str, labels: Labels, value: f64) {
+        let histogram = self
+            .histograms
+            .entry(name)
+            .or_default()
+            .entry(labels)
+            .or_default();
+        if let Some(i) = LATENCY_BUCKETS.iter().position(|bound| value <= *bound) {
+            histogram.buckets[i] += 1;
+        }
+        histogram.sum += value;
+        histogram.count += 1;
+    }
+
+    fn set(&mut self, name: &'static str, labels: Labels, value: f64) {
+        self.gauges.entry(name).or_default().insert(labels, value);
+    }
+
+    /// Prometheus文本格式
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        for (name, metric_type, help) in METRICS {
+            let _ = writeln!(text, "# HELP {} {}", name, help);
+            let _ = writeln!(text, "# TYPE {} {}", name, metric_type);
+            for (labels, value) in self.counters.get(name).into_iter().flatten() {
+                let _ = writeln!(text, "{}{} {}", name, format_labels(labels, None), value);
+            }
+            for (labels, value) in self.gauges.get(name).into_iter().flatten() {
+                let _ = writeln!(text, "{}{} {}", name, format_labels(labels, None), value);
+            }
+            for (labels, histogram) in self.histograms.get(name).into_iter().flatten() {
+                let mut cumulative = 0;
+                for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+                    cumulative += count;
+                    let le = bound.to_string();
+                    let labels = format_labels(labels, Some(("le", &le)));
+                    let _ = writeln!(text, "{}_bucket{} {}", name, labels, cumulative);
+                }
+                let inf = format_labels(labels, Some(("le", "+Inf")));
+                let _ = writeln!(text, "{}_bucket{} {}", name, inf, histogram.count);
+                let labels = format_labels(labels, None);
+                let _ = writeln!(text, "{}_sum{} {}", name, labels, histogram.sum);
+                let _ = writeln!(text, "{}_count{} {}", name, labels, histogram.count);
+            }
+        }
+        text
+    }
+}
+
+pub struct MetricsSink<'a> {
+    matrix: &'a Matrix,
+    metrics: Arc<Mutex<Metrics>>,
+    /// 请求 -> (发送时间, 服务名, 方法名)
+    pending: HashMap<RequestKey, (Duration, String, String)>,
+    last_sent: HashMap<SenderKey, Duration>,
+    sessions: HashMap<(SenderKey, SomeipClientId), SomeipSessionId>,
+    /// 提供的服务实例 -> 过期时间
+    offers: HashMap<(IpAddr, SomeipServiceId, SomeipInstanceId), Duration>,
+}
+
+/// 监听地址，在单独的线程中响应 GET /metrics
+fn serve(listen: &str, metrics: Arc<Mutex<Metrics>>) -> Result<(), MyError> {
+    let http = tiny_http::Server::http(listen)
+        .map_err(|e| MyError::Custom(format!("listen on {} failed: {}", listen, e)))?;
+    let content_type =
+        tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"text/plain; version=0.0.4"[..])
+            .unwrap();
+    std::thread::Builder::new()
+        .name("metrics-server".to_string())
+        .spawn(move || {
+            for request in http.incoming_requests() {
+                let response = match request.url().split('?').next() {
+                    Some("/metrics") => {
+                        let text = metrics.lock().unwrap().render();
+                        tiny_http::Response::from_string(text).with_header(content_type.clone())
+                    }
+                    _ => tiny_http::Response::from_string("not found").with_status_code(404),
+                };
+                if let Err(e) = request.respond(response) {
+                    debug!("respond failed: {}", e);
+                }
+            }
+        })?;
+    info!("metrics on http://{}/metrics", listen);
+    Ok(())
+}
+
+impl<'a> MetricsSink<'a> {
+    pub fn new(matrix: &'a Matrix, listen: &str) -> Result<Self, MyError> {
+        let metrics = Arc::new(Mutex::new(Metrics::default()));
+        serve(listen, metrics.clone())?;
+        Ok(MetricsSink {
+            matrix,
+            metrics,
+            pending: HashMap::new(),
+            last_sent: HashMap::new(),
+            sessions: HashMap::new(),
+            offers: HashMap::new(),
+        })
+    }
+
+    fn service_name(&self, service_id: SomeipServiceId) -> String {
+        match self.matrix.service_name(service_id) {
+            Some(name) => name.to_owned(),
+            None => format!("0x{:04x}", service_id),
+        }
+    }
+
+    fn method_name(&self, service_id: SomeipServiceId, method_id: SomeipMethodId) -> String {
+        match self
+            .matrix
+            .services
+            .get(&service_id)
+            .and_then(|service| service.methods.get(&method_id))
+        {
+            Some(method) => method.method_name.clone(),
+            None => format!("0x{:04x}", method_id),
+        }
+    }
+
+    /// 超时的请求计数后丢弃，避免长时间运行时占用的内存一直增长
+    fn expire_requests(&mut self, metrics: &mut Metrics, now: Duration) {
+        self.pending.retain(|_, (sent, service, method)| {
+            if now.saturating_sub(*sent) < RESPONSE_TIMEOUT {
+                return true;
+            }
+            let labels = vec![("service", service.clone()), ("method", method.clone())];
+            metrics.inc("someip_request_timeouts_total", labels, 1);
+            false
+        });
+    }
+
+    fn record_sd(&mut self, metrics: &mut Metrics, msg: &SomeipMessage) {
+        let sd = match &msg.sd {
+            Some(sd) => sd,
+            None => return,
+        };
+        for entry in &sd.entries {
+            let key = (msg.source.ip_addr, entry.service_id, entry.instance_id);
+            let available = match entry.entry_type {
+                SomeipSdEntryType::OfferService => {
+                    let expires = match entry.ttl {
+                        TTL_INFINITE => Duration::MAX,
+                        ttl => msg.timestamp + Duration::from_secs(ttl as u64),
+                    };
+                    self.offers.insert(key, expires);
+                    1.0
+                }
+                SomeipSdEntryType::StopOfferService => {
+                    self.offers.remove(&key);
+                    0.0
+                }
+                _ => continue,
+            };
+            metrics.set(
+                "someip_sd_service_available",
+                self.offer_labels(&key),
+                available,
+            );
+        }
+    }
+
+    fn offer_labels(
+        &self,
+        (ip_addr, service_id, instance_id): &(IpAddr, SomeipServiceId, SomeipInstanceId),
+    ) -> Labels {
+        vec![
+            ("service", self.service_name(*service_id)),
+            ("instance", format!("0x{:04x}", instance_id)),
+            ("role", self.matrix.role_name_or_ip(ip_addr)),
+        ]
+    }
+
+    fn expire_offers(&mut self, metrics: &mut Metrics, now: Duration) {
+        let expired: Vec<_> = self
+            .offers
+            .iter()
+            .filter(|(_, expires)| **expires < now)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in expired {
+            self.offers.remove(&key);
+            metrics.set("someip_sd_service_available", self.offer_labels(&key), 0.0);
+        }
+    }
+
+    fn check_cycle(&mut self, metrics: &mut Metrics, msg: &SomeipMessage, labels: &Labels) {
+        let cycle_time_ms = match self
+            .matrix
+            .services
+            .get(&msg.service_id)
+            .and_then(|service| service.methods.get(&msg.method_id))
+            .and_then(|method| method.cycle_time_ms)
+        {
+            Some(cycle_time_ms) if cycle_time_ms > 0 => cycle_time_ms as f64,
+            _ => return,
+        };
+        let sender = (
+            msg.source.ip_addr,
+            msg.source.port,
+            msg.service_id,
+            msg.method_id,
+        );
+        if let Some(last) = self.last_sent.insert(sender, msg.timestamp) {
+            let interval = msg.timestamp.saturating_sub(last).as_secs_f64() * 1000.0;
+            if (interval - cycle_time_ms).abs() > cycle_time_ms * CYCLE_TOLERANCE {
+                metrics.inc("someip_cycle_violations_total", labels.clone(), 1);
+            }
+        }
+    }
+
+    /// 与session分析相同，只检查发送方自己编号的报文
+    fn check_session(&mut self, metrics: &mut Metrics, msg: &SomeipMessage, labels: &Labels) {
+        let sender = (
+            msg.source.ip_addr,
+            msg.source.port,
+            msg.service_id,
+            msg.method_id,
+        );
+        if msg.session_id == 0 {
+            return;
+        }
+        let last = match self
+            .sessions
+            .insert((sender, msg.client_id), msg.session_id)
+        {
+            Some(last) => last,
+            None => return,
+        };
+        match check_someip_session_id(last, msg.session_id) {
+            Some(SessionAnomalyKind::Gap(lost)) => metrics.inc(
+                "someip_session_lost_messages_total",
+                labels.clone(),
+                lost as u64,
+            ),
+            // 迟到的旧报文不更新序列的当前位置
+            Some(SessionAnomalyKind::OutOfOrder) => {
+                self.sessions.insert((sender, msg.client_id), last);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<'a> Sink for MetricsSink<'a> {
+    fn write_message(&mut self, msg: &SomeipMessage) -> Result<(), MyError> {
+        let metrics = self.metrics.clone();
+        let mut metrics = metrics.lock().unwrap();
+        self.expire_requests(&mut metrics, msg.timestamp);
+        self.expire_offers(&mut metrics, msg.timestamp);
+
+        let service = match msg.sd.is_some() {
+            true => "SD".to_owned(),
+            false => self.service_name(msg.service_id),
+        };
+        let method = self.method_name(msg.service_id, msg.method_id);
+        let role = self.matrix.role_name_or_ip(&msg.source.ip_addr);
+        let labels: Labels = vec![
+            ("service", service.clone()),
+            ("method", method.clone()),
+            ("role", role),
+        ];
+        let mut typed = labels.clone();
+        typed.push(("message_type", msg.message_type.to_string()));
+        metrics.inc("someip_messages_total", typed, 1);
+        metrics.inc("someip_bytes_total", labels.clone(), msg.length as u64 + 8);
+
+        if msg.sd.is_some() {
+            self.record_sd(&mut metrics, msg);
+            return Ok(());
+        }
+        let request = (msg.service_id, msg.method_id, msg.client_id, msg.session_id);
+        match msg.message_type {
+            SomeipMessageType::Request => {
+                self.pending
+                    .insert(request, (msg.timestamp, service, method));
+                self.check_session(&mut metrics, msg, &labels);
+            }
+            SomeipMessageType::RequestWithoutResponse | SomeipMessageType::Notification => {
+                self.check_session(&mut metrics, msg, &labels);
+                self.check_cycle(&mut metrics, msg, &labels);
+            }
+            SomeipMessageType::Response | SomeipMessageType::ResponseWithError => {
+                if let Some((sent, ..)) = self.pending.remove(&request) {
+                    let latency = msg.timestamp.saturating_sub(sent).as_secs_f64();
+                    metrics.observe("someip_request_latency_seconds", labels.clone(), latency);
+                }
+                if msg.return_code != 0 {
+                    let service = self.matrix.services.get(&msg.service_id);
+                    let method = service.and_then(|service| service.methods.get(&msg.method_id));
+                    let mut labels = labels;
+                    labels.push(("return_code", return_code_name(method, msg.return_code)));
+                    metrics.inc("someip_error_responses_total", labels, 1);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn render_metrics() {
+    use crate::analyzers::test_message;
+
+    let matrix = Matrix::default();
+    let mut sink = MetricsSink::new(&matrix, "127.0.0.1:0").unwrap();
+    // 最后一条请求之前的请求在超时之后仍没有应答
+    for (ts_ms, message_type, session_id) in [
+        (1000, SomeipMessageType::Request, 1),
+        (1003, SomeipMessageType::Response, 1),
+        (1100, SomeipMessageType::Request, 4),
+        (3000, SomeipMessageType::Request, 5),
+    ] {
+        let (source, destination) = match message_type {
+            SomeipMessageType::Request => ("10.0.0.1:40000", "10.0.0.2:30501"),
+            _ => ("10.0.0.2:30501", "10.0.0.1:40000"),
+        };
+        let mut msg = test_message(ts_ms, source, destination, message_type, 0x1234, 1);
+        msg.session_id = session_id;
+        sink.write_message(&msg).unwrap();
+    }
+
+    let text = sink.metrics.lock().unwrap().render();
+    let labels = r#"service="0x1234",method="0x0001""#;
+    for line in [
+        format!(
+            r#"someip_messages_total{{{},role="10.0.0.1",message_type="Request"}} 3"#,
+            labels
+        ),
+        format!(
+            r#"someip_request_latency_seconds_bucket{{{},role="10.0.0.2",le="0.005"}} 1"#,
+            labels
+        ),
+        format!(
+            r#"someip_session_lost_messages_total{{{},role="10.0.0.1"}} 2"#,
+            labels
+        ),
+        format!(r#"someip_request_timeouts_total{{{}}} 1"#, labels),
+    ] {
+        assert!(text.lines().any(|l| l == line), "{} not in\n{}", line, text);
+    }
+}
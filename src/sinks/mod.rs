@@ -0,0 +1,206 @@
+/// 报文输出：与分析器不同，每条报文解析完成后立即输出，不等全部报文处理完
+pub mod jsonl;
+pub mod metrics;
+pub mod pcapng;
+pub mod pretty;
+pub mod sequence;
+pub mod signals;
+pub mod websocket;
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use pnet::datalink;
+use serde_json::{json, Value};
+
+use crate::analyzers::return_code::return_code_name;
+use crate::errors::MyError;
+use crate::matrix::types::Matrix;
+use crate::sources::RawPacket;
+use crate::types::{
+    mac_to_string, SomeipEndpoint, SomeipMessage, SomeipMessageType, SomeipMethodId,
+    SomeipServiceId,
+};
+
+pub trait Sink {
+    /// 一个数据源开始读取之前调用
+    fn start(&mut self, _channel_type: datalink::ChannelType) -> Result<(), MyError> {
+        Ok(())
+    }
+    /// 每一帧原始报文，在解析出其中的SomeIP报文之前调用
+    fn write_packet(&mut self, _raw: &RawPacket) -> Result<(), MyError> {
+        Ok(())
+    }
+    fn write_message(&mut self, msg: &SomeipMessage) -> Result<(), MyError>;
+    /// 全部数据源处理完成后调用一次
+    fn finish(&mut self) -> Result<(), MyError> {
+        Ok(())
+    }
+}
+
+/// "-"表示标准输出，否则创建文件
+pub fn open_output(path: &str) -> Result<Box<dyn Write>, MyError> {
+    Ok(match path {
+        "-" => Box::new(BufWriter::new(std::io::stdout())),
+        path => Box::new(BufWriter::new(File::create(path)?)),
+    })
+}
+
+/// 按照过滤表达式找到选中的方法，找不到时报错
+pub fn select_methods(
+    matrix: &Matrix,
+    filter: &str,
+) -> Result<Vec<(SomeipServiceId, SomeipMethodId)>, MyError> {
+    match matrix.find_methods_by_path(filter).0 {
+        methods if methods.is_empty() => {
+            Err(MyError::ArgInputError(format!("unknown filter {}", filter)))
+        }
+        methods => Ok(methods),
+    }
+}
+
+/// 报文是否被选中，None表示全部选中；SD报文中有一个条目属于选中的服务即选中
+pub fn is_selected(
+    selected: Option<&[(SomeipServiceId, SomeipMethodId)]>,
+    msg: &SomeipMessage,
+) -> bool {
+    let selected = match selected {
+        Some(selected) => selected,
+        None => return true,
+    };
+    match &msg.sd {
+        Some(sd) => sd.entries.iter().any(|entry| {
+            selected
+                .iter()
+                .any(|(service_id, _)| *service_id == entry.service_id)
+        }),
+        None => selected.contains(&(msg.service_id, msg.method_id)),
+    }
+}
+
+fn endpoint_json(matrix: &Matrix, endpoint: &SomeipEndpoint) -> Value {
+    json!({
+        "ip": endpoint.ip_addr.to_string(),
+        "port": endpoint.port,
+        "mac": mac_to_string(&endpoint.mac_addr),
+        "role": matrix.find_role_by_ip(&endpoint.ip_addr).map(|role| role.name.as_str()),
+    })
+}
+
+/// 按照矩阵解析报文的参数，返回 [(类型名, 值)]
+/// 带错误的应答按照应用错误码定义的错误载荷类型解析；没有定义载荷的报文返回None
+pub fn decode_message(
+    matrix: &Matrix,
+    msg: &SomeipMessage,
+) -> Option<Result<Vec<(String, Value)>, crate::matrix::decode::DecodeError>> {
+    let method = matrix
+        .services
+        .get(&msg.service_id)
+        .and_then(|service| service.methods.get(&msg.method_id))?;
+    let types = match msg.message_type {
+        SomeipMessageType::ResponseWithError => vec![method
+            .application_error(msg.return_code)
+            .map(|error| error.data.as_str())
+            .filter(|data| !data.is_empty())?],
+        message_type => method.method_type.parameter_types(message_type)?,
+    };
+    Some(matrix.decode_parameters(&types, &msg.payload))
+}
+
+/// 单条报文能够直接看出的问题，名字是固定的，供脚本过滤
+pub fn anomalies(matrix: &Matrix, msg: &SomeipMessage, decode_failed: bool) -> Vec<&'static str> {
+    let mut anomalies = vec![];
+    if msg.sd.is_none() {
+        match matrix.services.get(&msg.service_id) {
+            None => anomalies.push("unknown_service"),
+            Some(service) if !service.methods.contains_key(&msg.method_id) => {
+                anomalies.push("unknown_method")
+            }
+            _ => {}
+        }
+    }
+    if msg.protocol_version != 1 {
+        anomalies.push("wrong_protocol_version");
+    }
+    if msg.available_length < msg.length as usize {
+        anomalies.push("truncated");
+    } else if msg.available_length > msg.length as usize {
+        anomalies.push("extra_bytes");
+    }
+    if decode_failed {
+        anomalies.push("decode_error");
+    }
+    if msg.return_code != 0 {
+        anomalies.push("error_return_code");
+    }
+    anomalies
+}
+
+/// 报文的JSON表示，字段固定，没有的值为null
+pub fn message_json(matrix: &Matrix, msg: &SomeipMessage) -> Value {
+    let service = matrix.services.get(&msg.service_id);
+    let method = service.and_then(|service| service.methods.get(&msg.method_id));
+    let decoded = decode_message(matrix, msg);
+    let parameters = match &decoded {
+        Some(Ok(values)) => Value::Array(
+            values
+                .iter()
+                .map(|(data_type, value)| json!({"type": data_type, "value": value}))
+                .collect(),
+        ),
+        _ => Value::Null,
+    };
+    let sd = msg.sd.as_ref().map(|sd| {
+        let entries: Vec<Value> = sd
+            .entries
+            .iter()
+            .map(|entry| {
+                json!({
+                    "type": entry.entry_type.to_string(),
+                    "service_id": entry.service_id,
+                    "instance_id": entry.instance_id,
+                    "eventgroup_id": entry.eventgroup_id,
+                    "major_version": entry.major_version,
+                    "minor_version": entry.minor_version,
+                    "ttl": entry.ttl,
+                    "endpoints": entry
+                        .endpoints
+                        .iter()
+                        .map(|endpoint| format!(
+                            "{}:{}/{:?}",
+                            endpoint.ip_addr, endpoint.port, endpoint.transport_protocol
+                        ))
+                        .collect::<Vec<_>>(),
+                })
+            })
+            .collect();
+        json!({
+            "reboot": sd.reboot_flag,
+            "unicast": sd.unicast_flag,
+            "entries": entries,
+        })
+    });
+    json!({
+        "timestamp": msg.timestamp.as_secs_f64(),
+        "packet_index": msg.packet_index,
+        "source": endpoint_json(matrix, &msg.source),
+        "destination": endpoint_json(matrix, &msg.destination),
+        "vlan_id": msg.vlan_id,
+        "transport": format!("{:?}", msg.transport_protocol),
+        "service_id": msg.service_id,
+        "service_name": service.map(|service| service.service_name.as_str()),
+        "method_id": msg.method_id,
+        "method_name": method.map(|method| method.method_name.as_str()),
+        "client_id": msg.client_id,
+        "session_id": msg.session_id,
+        "message_type": msg.message_type.to_string(),
+        "return_code": msg.return_code,
+        "return_code_name": return_code_name(method, msg.return_code),
+        "protocol_version": msg.protocol_version,
+        "interface_version": msg.interface_version,
+        "length": msg.length,
+        "parameters": parameters,
+        "sd": sd,
+        "anomalies": anomalies(matrix, msg, matches!(decoded, Some(Err(_)))),
+    })
+}
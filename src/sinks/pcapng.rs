@@ -0,0 +1,343 @@
+/// 过滤后的pcapng导出：只保留选中报文所在的原始帧，每帧附带注释说明解析结果
+/// 注释包含 角色、服务与方法名、报文类型、解析出的参数与异常，一帧中有多条报文时每条一行
+/// TP与TCP重组的报文保留组成它的所有原始帧；原始帧先缓存，超出保留时间或数据源结束时按顺序写出
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::time::Duration;
+
+use log::debug;
+use pnet::datalink;
+
+use crate::analyzers::return_code::return_code_name;
+use crate::errors::MyError;
+use crate::matrix::types::Matrix;
+use crate::sources::RawPacket;
+use crate::types::{PacketIndex, SomeipMessage, SomeipMethodId, SomeipServiceId};
+
+use super::{anomalies, decode_message, is_selected, Sink};
+
+/// 原始帧的保留时间，TP与TCP重组的报文的所有帧应在该时间内到达
+const FRAME_RETENTION: Duration = Duration::from_secs(5);
+/// 注释中参数部分的最大字符数
+const MAX_PARAMETERS_CHARS: usize = 256;
+
+const BLOCK_SECTION_HEADER: u32 = 0x0A0D0D0A;
+const BLOCK_INTERFACE_DESCRIPTION: u32 = 0x00000001;
+const BLOCK_ENHANCED_PACKET: u32 = 0x00000006;
+const OPTION_END: u16 = 0;
+const OPTION_COMMENT: u16 = 1;
+const LINKTYPE_ETHERNET: u16 = 1;
+const LINKTYPE_LINUX_SLL: u16 = 113;
+
+pub struct PcapngSink<'a, W: Write> {
+    matrix: &'a Matrix,
+    writer: W,
+    /// 选中的方法，None表示全部
+    selected: Option<Vec<(SomeipServiceId, SomeipMethodId)>>,
+    /// 已经写出的Interface Description Block数量，每个数据源一个
+    interfaces: u32,
+    frames: BTreeMap<PacketIndex, RawPacket>,
+    comments: BTreeMap<PacketIndex, Vec<String>>,
+}
+
+/// 块的内容按4字节对齐，块长度写在首尾
+fn write_block<W: Write>(writer: &mut W, block_type: u32, body: &[u8]) -> Result<(), MyError> {
+    let padding = (4 - body.len() % 4) % 4;
+    let length = (12 + body.len() + padding) as u32;
+    writer.write_all(&block_type.to_le_bytes())?;
+    writer.write_all(&length.to_le_bytes())?;
+    writer.write_all(body)?;
+    writer.write_all(&[0; 3][..padding])?;
+    writer.write_all(&length.to_le_bytes())?;
+    Ok(())
+}
+
+/// 选项长度只有16位，超长的注释在UTF-8字符边界截断
+fn push_option(body: &mut Vec<u8>, code: u16, value: &str) {
+    let mut end = value.len().min(u16::MAX as usize);
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    let value = &value.as_bytes()[..end];
+    body.extend_from_slice(&code.to_le_bytes());
+    body.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    body.extend_from_slice(value);
+    body.resize(body.len() + (4 - value.len() % 4) % 4, 0);
+}
+
+impl<'a, W: Write> PcapngSink<'a, W> {
+    pub fn new(
+        matrix: &'a Matrix,
+        mut writer: W,
+        selected: Option<Vec<(SomeipServiceId, SomeipMethodId)>>,
+    ) -> Result<Self, MyError> {
+        // 字节序标识、版本1.0、Section长度未知
+        let mut body = vec![];
+        body.extend_from_slice(&0x1A2B3C4Du32.to_le_bytes());
+        body.extend_from_slice(&1u16.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        body.extend_from_slice(&(-1i64).to_le_bytes());
+        write_block(&mut writer, BLOCK_SECTION_HEADER, &body)?;
+        Ok(PcapngSink {
+            matrix,
+            writer,
+            selected,
+            interfaces: 0,
+            frames: BTreeMap::new(),
+            comments: BTreeMap::new(),
+        })
+    }
+
+    fn summary(&self, msg: &SomeipMessage) -> String {
+        let matrix = self.matrix;
+        let mut summary = format!(
+            "{} -> {} ",
+            matrix.role_name_or_ip(&msg.source.ip_addr),
+            matrix.role_name_or_ip(&msg.destination.ip_addr)
+        );
+        if let Some(sd) = &msg.sd {
+            let entries: Vec<String> = sd
+                .entries
+                .iter()
+                .map(|entry| {
+                    let service = match matrix.service_name(entry.service_id) {
+                        Some(name) => name.to_owned(),
+                        None => format!("0x{:04x}", entry.service_id),
+                    };
+                    format!("{} {}", entry.entry_type, service)
+                })
+                .collect();
+            return summary + &format!("SD {}", entries.join(", "));
+        }
+
+        let service = matrix.services.get(&msg.service_id);
+        let method = service.and_then(|service| service.methods.get(&msg.method_id));
+        summary += &match (service, method) {
+            (Some(service), Some(method)) => {
+                format!("{}.{}", service.service_name, method.method_name)
+            }
+            _ => format!("0x{:04x}.0x{:04x}", msg.service_id, msg.method_id),
+        };
+        summary += &format!(" {} session 0x{:04x}", msg.message_type, msg.session_id);
+        if msg.return_code != 0 {
+            summary += &format!(" {}", return_code_name(method, msg.return_code));
+        }
+        let decoded = decode_message(matrix, msg);
+        if let Some(Ok(values)) = &decoded {
+            let values: Vec<String> = values.iter().map(|(_, value)| value.to_string()).collect();
+            let parameters: String = values
+                .join(", ")
+                .chars()
+                .take(MAX_PARAMETERS_CHARS)
+                .collect();
+            summary += &format!(" {}", parameters);
+        }
+        let anomalies = anomalies(matrix, msg, matches!(decoded, Some(Err(_))));
+        if !anomalies.is_empty() {
+            summary += &format!(" [{}]", anomalies.join(", "));
+        }
+        summary
+    }
+
+    fn write_frame(&mut self, raw: &RawPacket, comments: &[String]) -> Result<(), MyError> {
+        // 时间戳单位为微秒，这里也是接口的缺省精度
+        let timestamp = raw.timestamp.as_micros() as u64;
+        let mut body = vec![];
+        body.extend_from_slice(&(self.interfaces - 1).to_le_bytes());
+        body.extend_from_slice(&((timestamp >> 32) as u32).to_le_bytes());
+        body.extend_from_slice(&(timestamp as u32).to_le_bytes());
+        body.extend_from_slice(&(raw.data.len() as u32).to_le_bytes());
+        body.extend_from_slice(&(raw.data.len() as u32).to_le_bytes());
+        body.extend_from_slice(&raw.data);
+        body.resize(body.len() + (4 - raw.data.len() % 4) % 4, 0);
+        push_option(&mut body, OPTION_COMMENT, &comments.join("\n"));
+        push_option(&mut body, OPTION_END, "");
+        write_block(&mut self.writer, BLOCK_ENHANCED_PACKET, &body)
+    }
+
+    /// 写出保留时间之前的帧，没有被选中的帧直接丢弃
+    fn evict(&mut self, before: Duration) -> Result<(), MyError> {
+        while let Some(entry) = self.frames.first_entry() {
+            if entry.get().timestamp + FRAME_RETENTION >= before {
+                break;
+            }
+            let (index, raw) = entry.remove_entry();
+            if let Some(comments) = self.comments.remove(&index) {
+                self.write_frame(&raw, &comments)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a, W: Write> Sink for PcapngSink<'a, W> {
+    fn start(&mut self, channel_type: datalink::ChannelType) -> Result<(), MyError> {
+        // 帧序号在每个数据源中重新开始，先写出上一个数据源缓存的帧
+        self.evict(Duration::MAX)?;
+        let link_type = match channel_type {
+            datalink::ChannelType::Layer2 => LINKTYPE_ETHERNET,
+            datalink::ChannelType::Layer3(_) => LINKTYPE_LINUX_SLL,
+        };
+        let mut body = vec![];
+        body.extend_from_slice(&link_type.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        body.extend_from_slice(&0u32.to_le_bytes());
+        write_block(&mut self.writer, BLOCK_INTERFACE_DESCRIPTION, &body)?;
+        self.interfaces += 1;
+        Ok(())
+    }
+
+    fn write_packet(&mut self, raw: &RawPacket) -> Result<(), MyError> {
+        self.evict(raw.timestamp)?;
+        self.frames.insert(
+            raw.packet_index,
+            RawPacket {
+                packet_index: raw.packet_index,
+                timestamp: raw.timestamp,
+                data: raw.data.clone(),
+            },
+        );
+        Ok(())
+    }
+
+    fn write_message(&mut self, msg: &SomeipMessage) -> Result<(), MyError> {
+        if !is_selected(self.selected.as_deref(), msg) {
+            return Ok(());
+        }
+        let summary = self.summary(msg);
+        for index in &msg.frames {
+            match self.frames.contains_key(index) {
+                true => self
+                    .comments
+                    .entry(*index)
+                    .or_default()
+                    .push(summary.clone()),
+                false => debug!("frame {} of message {} was dropped", index, summary),
+            }
+        }
+        Ok(())
+    }
+
+    /// 全部数据源结束后写出最后一个数据源缓存的帧
+    fn finish(&mut self) -> Result<(), MyError> {
+        self.evict(Duration::MAX)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+#[test]
+fn annotated_frames() {
+    use crate::analyzers::test_message;
+    use crate::types::SomeipMessageType;
+
+    let matrix = Matrix::default();
+    let mut sink = PcapngSink::new(&matrix, vec![], Some(vec![(0x1234, 1)])).unwrap();
+    sink.start(datalink::ChannelType::Layer2).unwrap();
+    for packet_index in 0..3 {
+        let raw = RawPacket {
+            packet_index,
+            timestamp: Duration::from_millis(packet_index as u64),
+            data: vec![packet_index as u8; 5],
+        };
+        sink.write_packet(&raw).unwrap();
+    }
+    // 第0、2帧组成一条选中的TP报文，第1帧中的报文没有选中
+    let mut selected = test_message(
+        2,
+        "10.0.0.1:40000",
+        "10.0.0.2:30501",
+        SomeipMessageType::Request,
+        0x1234,
+        1,
+    );
+    selected.frames = vec![0, 2];
+    sink.write_message(&selected).unwrap();
+    let mut other = test_message(
+        1,
+        "10.0.0.1:40000",
+        "10.0.0.2:30501",
+        SomeipMessageType::Request,
+        0x1234,
+        2,
+    );
+    other.frames = vec![1];
+    sink.write_message(&other).unwrap();
+    sink.finish().unwrap();
+
+    let mut blocks = vec![];
+    let mut data = &sink.writer[..];
+    while data.len() >= 12 {
+        let block_type = u32::from_le_bytes(data[..4].try_into().unwrap());
+        let length = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+        blocks.push((block_type, data[8..length - 4].to_vec()));
+        data = &data[length..];
+    }
+    let packets: Vec<&Vec<u8>> = blocks
+        .iter()
+        .filter(|(block_type, _)| *block_type == BLOCK_ENHANCED_PACKET)
+        .map(|(_, body)| body)
+        .collect();
+    assert_eq!(blocks.len(), 4);
+    assert_eq!(packets.len(), 2);
+    assert_eq!(packets[1][20..25], [2; 5]);
+    let comment = String::from_utf8_lossy(&packets[0][32..]);
+    assert!(comment.starts_with("10.0.0.1 -> 10.0.0.2 0x1234.0x0001 Request session 0x0001"));
+}
+
+#[test]
+fn frames_of_each_source() {
+    use crate::analyzers::test_message;
+    use crate::types::SomeipMessageType;
+
+    let matrix = Matrix::default();
+    let mut sink = PcapngSink::new(&matrix, vec![], None).unwrap();
+    // 两个数据源的帧序号都从0开始，第一个数据源的帧写在第一个接口下
+    for source in 0..2u8 {
+        sink.start(datalink::ChannelType::Layer2).unwrap();
+        let raw = RawPacket {
+            packet_index: 0,
+            timestamp: Duration::from_millis(source as u64),
+            data: vec![source; 4],
+        };
+        sink.write_packet(&raw).unwrap();
+        let msg = test_message(
+            source as u64,
+            "10.0.0.1:40000",
+            "10.0.0.2:30501",
+            SomeipMessageType::Request,
+            0x1234,
+            1,
+        );
+        sink.write_message(&msg).unwrap();
+    }
+    sink.finish().unwrap();
+
+    let mut packets = vec![];
+    let mut data = &sink.writer[..];
+    while data.len() >= 12 {
+        let block_type = u32::from_le_bytes(data[..4].try_into().unwrap());
+        let length = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+        if block_type == BLOCK_ENHANCED_PACKET {
+            packets.push(data[8..length - 4].to_vec());
+        }
+        data = &data[length..];
+    }
+    assert_eq!(packets.len(), 2);
+    for (interface, packet) in packets.iter().enumerate() {
+        assert_eq!(packet[..4], (interface as u32).to_le_bytes());
+        assert_eq!(packet[20..24], [interface as u8; 4]);
+    }
+}
+
+#[test]
+fn long_comment_option() {
+    // 65535字节处落在一个3字节字符的中间，截断到前一个字符边界并补齐到4字节
+    let comment = format!("a{}", "中".repeat(30000));
+    let mut body = vec![];
+    push_option(&mut body, OPTION_COMMENT, &comment);
+    assert_eq!(&body[..2], &OPTION_COMMENT.to_le_bytes());
+    assert_eq!(u16::from_le_bytes([body[2], body[3]]), 65533);
+    assert_eq!(body.len(), 4 + 65536);
+    assert!(std::str::from_utf8(&body[4..4 + 65533]).is_ok());
+}
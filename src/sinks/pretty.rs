@@ -0,0 +1,323 @@
+/// 终端上的可读输出：每条报文一行摘要，下面按照矩阵中的成员名缩进显示解析出的参数
+/// 数值附带单位与枚举名；无法解析的载荷显示十六进制；错误与异常用红色标出
+/// 是否使用颜色由调用者决定，输出不是终端时不应使用颜色
+use std::io::Write;
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::analyzers::return_code::return_code_name;
+use crate::errors::MyError;
+use crate::matrix::types::{Matrix, MatrixType};
+use crate::types::SomeipMessage;
+
+use super::{anomalies, decode_message, Sink};
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const CYAN: &str = "\x1b[36m";
+
+/// 十六进制显示时每行的字节数
+const HEX_LINE_BYTES: usize = 16;
+
+pub struct PrettySink<'a, W: Write> {
+    matrix: &'a Matrix,
+    writer: W,
+    color: bool,
+    first_timestamp: Option<Duration>,
+}
+
+impl<'a, W: Write> PrettySink<'a, W> {
+    pub fn new(matrix: &'a Matrix, writer: W, color: bool) -> Self {
+        PrettySink {
+            matrix,
+            writer,
+            color,
+            first_timestamp: None,
+        }
+    }
+
+    fn paint(&self, color: &str, text: &str) -> String {
+        match self.color {
+            true => format!("{}{}{}", color, text, RESET),
+            false => text.to_owned(),
+        }
+    }
+
+    fn header(&self, msg: &SomeipMessage, time: f64, failed: bool) -> String {
+        let matrix = self.matrix;
+        let service = matrix.services.get(&msg.service_id);
+        let method = service.and_then(|service| service.methods.get(&msg.method_id));
+        let name = match (msg.sd.is_some(), service, method) {
+            (true, ..) => "SD".to_owned(),
+            (_, Some(service), Some(method)) => {
+                format!("{}.{}", service.service_name, method.method_name)
+            }
+            (_, Some(service), None) => format!("{}.0x{:04x}", service.service_name, msg.method_id),
+            _ => format!("0x{:04x}.0x{:04x}", msg.service_id, msg.method_id),
+        };
+        let mut header = format!(
+            "{:.3}s {} -> {}  {} {}",
+            time,
+            matrix.role_name_or_ip(&msg.source.ip_addr),
+            matrix.role_name_or_ip(&msg.destination.ip_addr),
+            name,
+            msg.message_type
+        );
+        if msg.return_code != 0 {
+            header += &format!(" {}", return_code_name(method, msg.return_code));
+        }
+        match failed {
+            true => self.paint(RED, &header),
+            false => self.paint(BOLD, &header),
+        }
+    }
+
+    /// 按照数据类型递归显示一个值，结构体与数组的成员缩进一级
+    fn tree(
+        &self,
+        lines: &mut Vec<String>,
+        depth: usize,
+        label: &str,
+        description: &str,
+        type_name: &str,
+        value: &Value,
+    ) {
+        let indent = "  ".repeat(depth);
+        let node = self.matrix.data_types.get(type_name);
+        let description = match (description, node) {
+            ("", Some(node)) => node.description.as_str(),
+            (description, _) => description,
+        };
+        let comment = match description.is_empty() {
+            true => String::new(),
+            false => format!("  {}", self.paint(DIM, &format!("// {}", description))),
+        };
+        match (node.map(|node| &node.data_type), value) {
+            (Some(MatrixType::Struct { members }), Value::Object(values)) => {
+                lines.push(format!("{}{}: {}{}", indent, label, type_name, comment));
+                for member in members {
+                    let value = values.get(&member.member_name).unwrap_or(&Value::Null);
+                    self.tree(
+                        lines,
+                        depth + 1,
+                        &member.member_name,
+                        &member.member_description,
+                        &member.member_data_type,
+                        value,
+                    );
+                }
+            }
+            (Some(MatrixType::Array { member, .. }), Value::Array(values)) => {
+                lines.push(format!(
+                    "{}{}: {}[{}]{}",
+                    indent,
+                    label,
+                    type_name,
+                    values.len(),
+                    comment
+                ));
+                for (i, value) in values.iter().enumerate() {
+                    self.tree(
+                        lines,
+                        depth + 1,
+                        &format!("[{}]", i),
+                        &member.member_description,
+                        &member.member_data_type,
+                        value,
+                    );
+                }
+            }
+            _ => {
+                let mut text = value.to_string();
+                if let Some(node) = node {
+                    if !node.unit.is_empty() {
+                        text += &format!(" {}", node.unit);
+                    }
+                    if let Some(label) = value.as_u64().and_then(|v| node.enum_label(v)) {
+                        text += &format!(" ({})", label);
+                    }
+                }
+                lines.push(format!(
+                    "{}{} = {}{}",
+                    indent,
+                    label,
+                    self.paint(CYAN, &text),
+                    comment
+                ));
+            }
+        }
+    }
+
+    fn hex_dump(&self, lines: &mut Vec<String>, depth: usize, data: &[u8]) {
+        let indent = "  ".repeat(depth);
+        for (i, chunk) in data.chunks(HEX_LINE_BYTES).enumerate() {
+            let bytes: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+            lines.push(format!(
+                "{}{}  {}",
+                indent,
+                self.paint(DIM, &format!("{:04x}", i * HEX_LINE_BYTES)),
+                bytes.join(" ")
+            ));
+        }
+    }
+}
+
+impl<'a, W: Write> Sink for PrettySink<'a, W> {
+    fn write_message(&mut self, msg: &SomeipMessage) -> Result<(), MyError> {
+        let first_timestamp = *self.first_timestamp.get_or_insert(msg.timestamp);
+        let time = msg.timestamp.saturating_sub(first_timestamp).as_secs_f64();
+        let decoded = decode_message(self.matrix, msg);
+        let anomalies = anomalies(self.matrix, msg, matches!(decoded, Some(Err(_))));
+
+        let mut lines = vec![self.header(msg, time, !anomalies.is_empty())];
+        if !anomalies.is_empty() {
+            lines.push(format!("  {}", self.paint(RED, &anomalies.join(", "))));
+        }
+        if let Some(sd) = &msg.sd {
+            for entry in &sd.entries {
+                let endpoints: Vec<String> = entry
+                    .endpoints
+                    .iter()
+                    .map(|endpoint| {
+                        format!(
+                            "{}:{}/{:?}",
+                            endpoint.ip_addr, endpoint.port, endpoint.transport_protocol
+                        )
+                    })
+                    .collect();
+                lines.push(format!(
+                    "  {} {} instance 0x{:04x} eventgroup 0x{:04x} ttl {} {}",
+                    self.paint(GREEN, &entry.entry_type.to_string()),
+                    self.matrix
+                        .service_name(entry.service_id)
+                        .map(str::to_owned)
+                        .unwrap_or_else(|| format!("0x{:04x}", entry.service_id)),
+                    entry.instance_id,
+                    entry.eventgroup_id,
+                    entry.ttl,
+                    endpoints.join(" ")
+                ));
+            }
+        }
+        match &decoded {
+            Some(Ok(values)) => {
+                for (type_name, value) in values {
+                    self.tree(&mut lines, 1, type_name, "", type_name, value);
+                }
+            }
+            Some(Err(error)) => {
+                lines.push(format!(
+                    "  {}",
+                    self.paint(RED, &format!("undecodable payload: {:?}", error))
+                ));
+                self.hex_dump(&mut lines, 2, &msg.payload);
+            }
+            None if msg.sd.is_none() => self.hex_dump(&mut lines, 1, &msg.payload),
+            None => {}
+        }
+
+        for line in lines {
+            writeln!(self.writer, "{}", line)?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), MyError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+#[test]
+fn pretty_tree() {
+    use crate::analyzers::{test_ff_method, test_matrix, test_message, test_payload};
+    use crate::matrix::types::{MatrixDataNode, NumberType};
+    use crate::types::SomeipMessageType;
+
+    let mut matrix = test_matrix(vec![test_ff_method(1, "SetTemp", &["Temp"])]);
+    matrix.data_types.insert(
+        "Temp".to_owned(),
+        MatrixDataNode {
+            name: "Temp".to_owned(),
+            description: "target temperature".to_owned(),
+            data_type: MatrixType::Number {
+                size: NumberType::Uint8,
+            },
+            unit: "degC".to_owned(),
+            enum_values: vec![(0xFF, "Invalid".to_owned())],
+        },
+    );
+
+    let mut sink = PrettySink::new(&matrix, vec![], false);
+    let msg = test_message(
+        12345,
+        "10.0.0.1:40000",
+        "10.0.0.2:30501",
+        SomeipMessageType::RequestWithoutResponse,
+        0x1234,
+        1,
+    );
+    sink.write_message(&test_payload(msg, &[0xFF])).unwrap();
+
+    let output = String::from_utf8(sink.writer).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(
+        lines[0],
+        "0.000s 10.0.0.1 -> 10.0.0.2  Climate.SetTemp RequestNoReturn"
+    );
+    assert_eq!(
+        lines[1],
+        "  Temp = 255 degC (Invalid)  // target temperature"
+    );
+}
+
+#[test]
+fn pretty_color_and_hex() {
+    use crate::analyzers::{
+        test_data_type, test_ff_method, test_matrix, test_message, test_payload,
+    };
+    use crate::matrix::types::NumberType;
+    use crate::types::SomeipMessageType;
+
+    let mut matrix = test_matrix(vec![test_ff_method(1, "SetTemp", &["Temp"])]);
+    let size = NumberType::Uint8;
+    test_data_type(&mut matrix, "Temp", MatrixType::Number { size });
+    let mut sink = PrettySink::new(&matrix, vec![], true);
+    for (ts_ms, method_id, payload) in [
+        (1000, 1, vec![21]),
+        (1250, 2, (0..18).collect()),
+        (1500, 1, vec![]),
+    ] {
+        let msg = test_message(
+            ts_ms,
+            "10.0.0.1:40000",
+            "10.0.0.2:30501",
+            SomeipMessageType::RequestWithoutResponse,
+            0x1234,
+            method_id,
+        );
+        sink.write_message(&test_payload(msg, &payload)).unwrap();
+    }
+
+    // 矩阵中没有的方法按每行16字节显示十六进制，偏移暗色；异常的报文头与说明为红色
+    let output = String::from_utf8(sink.writer).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "\x1b[1m0.000s 10.0.0.1 -> 10.0.0.2  Climate.SetTemp RequestNoReturn\x1b[0m",
+            "  Temp = \x1b[36m21\x1b[0m",
+            "\x1b[31m0.250s 10.0.0.1 -> 10.0.0.2  Climate.0x0002 RequestNoReturn\x1b[0m",
+            "  \x1b[31munknown_method\x1b[0m",
+            "  \x1b[2m0000\x1b[0m  00 01 02 03 04 05 06 07 08 09 0a 0b 0c 0d 0e 0f",
+            "  \x1b[2m0010\x1b[0m  10 11",
+            "\x1b[31m0.500s 10.0.0.1 -> 10.0.0.2  Climate.SetTemp RequestNoReturn\x1b[0m",
+            "  \x1b[31mdecode_error\x1b[0m",
+            "  \x1b[31mundecodable payload: TooShort\x1b[0m",
+        ]
+    );
+}
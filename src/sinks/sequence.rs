@@ -0,0 +1,432 @@
+/// 时序图：把矩阵中各角色之间的交互画成PlantUML或Mermaid时序图，用于问题报告与设计评审
+/// - Request与Response是一对实线与虚线箭头，Response上标出时延，带错误的Response用红色（Mermaid用叉）标出
+/// - FF方法与事件是异步箭头
+/// - SD报文可选，画成灰色的注释
+///
+/// 只画过滤表达式选中、且在时间范围内的报文，时间是相对第一条报文的秒数；多个数据源画在同一张图中
+use std::collections::HashMap;
+use std::io::Write;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use pnet::datalink;
+
+use crate::analyzers::return_code::return_code_name;
+use crate::errors::MyError;
+use crate::matrix::types::Matrix;
+use crate::types::{
+    SomeipClientId, SomeipMessage, SomeipMessageType, SomeipMethodId, SomeipServiceId,
+    SomeipSessionId,
+};
+
+use super::{is_selected, Sink};
+
+/// 一张图中最多的箭头与注释数量，超出的部分省略，避免图大到无法渲染
+const MAX_EVENTS: usize = 1000;
+
+type RequestKey = (
+    SomeipServiceId,
+    SomeipMethodId,
+    SomeipClientId,
+    SomeipSessionId,
+);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagramFormat {
+    PlantUml,
+    Mermaid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Arrow {
+    Request,
+    Response,
+    Error,
+    Async,
+}
+
+enum Event {
+    Arrow {
+        from: usize,
+        to: usize,
+        arrow: Arrow,
+        label: String,
+    },
+    Note {
+        over: Vec<usize>,
+        text: String,
+    },
+}
+
+pub struct SequenceSink<'a, W: Write> {
+    matrix: &'a Matrix,
+    writer: W,
+    format: DiagramFormat,
+    selected: Option<Vec<(SomeipServiceId, SomeipMethodId)>>,
+    /// 相对第一条报文的时间范围
+    from: Option<Duration>,
+    to: Option<Duration>,
+    sd: bool,
+    first_timestamp: Option<Duration>,
+    /// 按照第一次出现的顺序排列的参与者
+    participants: Vec<String>,
+    events: Vec<Event>,
+    omitted: usize,
+    pending: HashMap<RequestKey, Duration>,
+}
+
+/// Mermaid中分号与#有特殊含义
+fn mermaid_text(text: &str) -> String {
+    text.replace(';', ",").replace('#', "")
+}
+
+impl<'a, W: Write> SequenceSink<'a, W> {
+    pub fn new(
+        matrix: &'a Matrix,
+        writer: W,
+        format: DiagramFormat,
+        selected: Option<Vec<(SomeipServiceId, SomeipMethodId)>>,
+        from: Option<Duration>,
+        to: Option<Duration>,
+        sd: bool,
+    ) -> Self {
+        SequenceSink {
+            matrix,
+            writer,
+            format,
+            selected,
+            from,
+            to,
+            sd,
+            first_timestamp: None,
+            participants: vec![],
+            events: vec![],
+            omitted: 0,
+            pending: HashMap::new(),
+        }
+    }
+
+    fn participant(&mut self, ip_addr: &IpAddr) -> usize {
+        let name = self.matrix.role_name_or_ip(ip_addr);
+        match self.participants.iter().position(|p| *p == name) {
+            Some(i) => i,
+            None => {
+                self.participants.push(name);
+                self.participants.len() - 1
+            }
+        }
+    }
+
+    fn method_name(&self, msg: &SomeipMessage) -> String {
+        let service = self.matrix.services.get(&msg.service_id);
+        let method = service.and_then(|service| service.methods.get(&msg.method_id));
+        match (service, method) {
+            (Some(service), Some(method)) => {
+                format!("{}.{}", service.service_name, method.method_name)
+            }
+            (Some(service), None) => format!("{}.0x{:04x}", service.service_name, msg.method_id),
+            _ => format!("0x{:04x}.0x{:04x}", msg.service_id, msg.method_id),
+        }
+    }
+
+    fn push(&mut self, event: Event) {
+        match self.events.len() < MAX_EVENTS {
+            true => self.events.push(event),
+            false => self.omitted += 1,
+        }
+    }
+
+    fn record_sd(&mut self, msg: &SomeipMessage, time: f64) {
+        let sd = match &msg.sd {
+            Some(sd) => sd,
+            None => return,
+        };
+        let entries: Vec<String> = sd
+            .entries
+            .iter()
+            .map(|entry| {
+                let service = match self.matrix.service_name(entry.service_id) {
+                    Some(name) => name.to_owned(),
+                    None => format!("0x{:04x}", entry.service_id),
+                };
+                format!("{} {}", entry.entry_type, service)
+            })
+            .collect();
+        let mut over = vec![self.participant(&msg.source.ip_addr)];
+        if !msg.destination.ip_addr.is_multicast() {
+            let destination = self.participant(&msg.destination.ip_addr);
+            if destination != over[0] {
+                over.push(destination);
+            }
+        }
+        self.push(Event::Note {
+            over,
+            text: format!("{:.3}s SD {}", time, entries.join(", ")),
+        });
+    }
+
+    fn render(&mut self) -> Result<(), MyError> {
+        let w = &mut self.writer;
+        match self.format {
+            DiagramFormat::PlantUml => {
+                writeln!(w, "@startuml")?;
+                for (i, name) in self.participants.iter().enumerate() {
+                    writeln!(w, "participant \"{}\" as P{}", name, i)?;
+                }
+            }
+            DiagramFormat::Mermaid => {
+                writeln!(w, "sequenceDiagram")?;
+                for (i, name) in self.participants.iter().enumerate() {
+                    writeln!(w, "    participant P{} as {}", i, mermaid_text(name))?;
+                }
+            }
+        }
+        for event in &self.events {
+            match (self.format, event) {
+                (
+                    DiagramFormat::PlantUml,
+                    Event::Arrow {
+                        from,
+                        to,
+                        arrow,
+                        label,
+                    },
+                ) => {
+                    let arrow = match arrow {
+                        Arrow::Request => "->",
+                        Arrow::Response => "-->",
+                        Arrow::Error => "-[#red]->",
+                        Arrow::Async => "->>",
+                    };
+                    writeln!(w, "P{} {} P{} : {}", from, arrow, to, label)?;
+                }
+                (DiagramFormat::PlantUml, Event::Note { over, text }) => {
+                    let over: Vec<String> = over.iter().map(|i| format!("P{}", i)).collect();
+                    writeln!(w, "note over {} #DDDDDD : {}", over.join(", "), text)?;
+                }
+                (
+                    DiagramFormat::Mermaid,
+                    Event::Arrow {
+                        from,
+                        to,
+                        arrow,
+                        label,
+                    },
+                ) => {
+                    let arrow = match arrow {
+                        Arrow::Request => "->>",
+                        Arrow::Response => "-->>",
+                        Arrow::Error => "--x",
+                        Arrow::Async => "-)",
+                    };
+                    writeln!(w, "    P{}{}P{}: {}", from, arrow, to, mermaid_text(label))?;
+                }
+                (DiagramFormat::Mermaid, Event::Note { over, text }) => {
+                    let over: Vec<String> = over.iter().map(|i| format!("P{}", i)).collect();
+                    writeln!(w, "    rect rgb(221, 221, 221)")?;
+                    writeln!(
+                        w,
+                        "    Note over {}: {}",
+                        over.join(","),
+                        mermaid_text(text)
+                    )?;
+                    writeln!(w, "    end")?;
+                }
+            }
+        }
+        if self.omitted > 0 {
+            let text = format!("{} more messages omitted", self.omitted);
+            match self.format {
+                DiagramFormat::PlantUml => writeln!(w, "... {} ...", text)?,
+                DiagramFormat::Mermaid if self.participants.is_empty() => {}
+                DiagramFormat::Mermaid => writeln!(w, "    Note over P0: {}", text)?,
+            }
+        }
+        if self.format == DiagramFormat::PlantUml {
+            writeln!(w, "@enduml")?;
+        }
+        w.flush()?;
+        Ok(())
+    }
+}
+
+impl<'a, W: Write> Sink for SequenceSink<'a, W> {
+    fn write_message(&mut self, msg: &SomeipMessage) -> Result<(), MyError> {
+        let first_timestamp = *self.first_timestamp.get_or_insert(msg.timestamp);
+        let relative = msg.timestamp.saturating_sub(first_timestamp);
+        if self.from.is_some_and(|from| relative < from)
+            || self.to.is_some_and(|to| relative > to)
+            || !is_selected(self.selected.as_deref(), msg)
+        {
+            return Ok(());
+        }
+        let time = relative.as_secs_f64();
+        if msg.sd.is_some() {
+            if self.sd {
+                self.record_sd(msg, time);
+            }
+            return Ok(());
+        }
+
+        let request = (msg.service_id, msg.method_id, msg.client_id, msg.session_id);
+        let mut label = format!("{:.3}s {}", time, self.method_name(msg));
+        let arrow = match msg.message_type {
+            SomeipMessageType::Request => {
+                self.pending.insert(request, msg.timestamp);
+                Arrow::Request
+            }
+            SomeipMessageType::Response | SomeipMessageType::ResponseWithError => {
+                if let Some(sent) = self.pending.remove(&request) {
+                    let latency = msg.timestamp.saturating_sub(sent).as_secs_f64() * 1000.0;
+                    label += &format!(" (+{:.1} ms)", latency);
+                }
+                match msg.return_code {
+                    0 => Arrow::Response,
+                    return_code => {
+                        let service = self.matrix.services.get(&msg.service_id);
+                        let method = service.and_then(|s| s.methods.get(&msg.method_id));
+                        label += &format!(" {}", return_code_name(method, return_code));
+                        Arrow::Error
+                    }
+                }
+            }
+            _ => Arrow::Async,
+        };
+        let from = self.participant(&msg.source.ip_addr);
+        let to = self.participant(&msg.destination.ip_addr);
+        self.push(Event::Arrow {
+            from,
+            to,
+            arrow,
+            label,
+        });
+        Ok(())
+    }
+
+    /// 会话号在每个数据源中重新开始，不与上一个数据源的Request配对
+    fn start(&mut self, _channel_type: datalink::ChannelType) -> Result<(), MyError> {
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// 全部数据源处理完成后画一张图
+    fn finish(&mut self) -> Result<(), MyError> {
+        self.render()
+    }
+}
+
+#[test]
+fn mermaid_diagram() {
+    use crate::analyzers::{test_message, test_sd_entry, test_sd_message};
+    use crate::types::{SomeipSdEntry, SomeipSdEntryType};
+
+    let matrix = Matrix::default();
+    let mut sink = SequenceSink::new(
+        &matrix,
+        vec![],
+        DiagramFormat::Mermaid,
+        None,
+        None,
+        Some(Duration::from_millis(100)),
+        true,
+    );
+    let offer = test_sd_message(
+        1000,
+        "10.0.0.2:30490",
+        "224.224.224.245:30490",
+        1,
+        false,
+        SomeipSdEntry {
+            eventgroup_id: 0,
+            ..test_sd_entry(SomeipSdEntryType::OfferService)
+        },
+    );
+    sink.write_message(&offer).unwrap();
+    for (ts_ms, source, destination, message_type, method_id) in [
+        (
+            1010,
+            "10.0.0.1:40000",
+            "10.0.0.2:30501",
+            SomeipMessageType::Request,
+            1,
+        ),
+        (
+            1012,
+            "10.0.0.2:30501",
+            "10.0.0.1:40000",
+            SomeipMessageType::Response,
+            1,
+        ),
+        (
+            1050,
+            "10.0.0.2:30501",
+            "10.0.0.1:40000",
+            SomeipMessageType::Notification,
+            0x8001,
+        ),
+        // 超出时间范围
+        (
+            1200,
+            "10.0.0.1:40000",
+            "10.0.0.2:30501",
+            SomeipMessageType::Request,
+            1,
+        ),
+    ] {
+        let msg = test_message(ts_ms, source, destination, message_type, 0x1234, method_id);
+        sink.write_message(&msg).unwrap();
+    }
+    sink.finish().unwrap();
+
+    assert_eq!(
+        String::from_utf8(sink.writer).unwrap(),
+        "sequenceDiagram\n\
+         \x20   participant P0 as 10.0.0.2\n\
+         \x20   participant P1 as 10.0.0.1\n\
+         \x20   rect rgb(221, 221, 221)\n\
+         \x20   Note over P0: 0.000s SD OfferService 0x1234\n\
+         \x20   end\n\
+         \x20   P1->>P0: 0.010s 0x1234.0x0001\n\
+         \x20   P0-->>P1: 0.012s 0x1234.0x0001 (+2.0 ms)\n\
+         \x20   P0-)P1: 0.050s 0x1234.0x8001\n"
+    );
+}
+
+#[test]
+fn one_diagram_for_sources() {
+    use crate::analyzers::test_message;
+
+    let matrix = Matrix::default();
+    let mut sink = SequenceSink::new(
+        &matrix,
+        vec![],
+        DiagramFormat::Mermaid,
+        None,
+        None,
+        None,
+        false,
+    );
+    // 第一个数据源的Request没有Response，不与第二个数据源中会话号相同的Response配对
+    for (ts_ms, message_type) in [
+        (1000, SomeipMessageType::Request),
+        (2000, SomeipMessageType::Response),
+    ] {
+        sink.start(datalink::ChannelType::Layer2).unwrap();
+        let (source, destination) = match message_type {
+            SomeipMessageType::Request => ("10.0.0.1:40000", "10.0.0.2:30501"),
+            _ => ("10.0.0.2:30501", "10.0.0.1:40000"),
+        };
+        let msg = test_message(ts_ms, source, destination, message_type, 0x1234, 1);
+        sink.write_message(&msg).unwrap();
+    }
+    sink.finish().unwrap();
+
+    assert_eq!(
+        String::from_utf8(sink.writer).unwrap(),
+        "sequenceDiagram\n\
+         \x20   participant P0 as 10.0.0.1\n\
+         \x20   participant P1 as 10.0.0.2\n\
+         \x20   P0->>P1: 0.000s 0x1234.0x0001\n\
+         \x20   P1-->>P0: 1.000s 0x1234.0x0001\n"
+    );
+}
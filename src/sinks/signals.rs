@@ -0,0 +1,368 @@
+/// 信号CSV导出：把选中的参数路径（如 Climate.Status.temp）展开成叶子成员，每个叶子一列
+/// 选中的是结构体时展开其所有成员；数组不展开，整个数组作为一个JSON值
+/// 方法只有一个参数时路径直接从参数开始，有多个参数时路径的第一级是参数的类型名
+///
+/// 两种格式：
+/// - wide：每条带有选中信号的报文一行，列为 时间、发送方角色、每个信号一列，可以用上一次的值填充空列
+/// - long：每个信号值一行，列为 时间、发送方角色、信号名、值
+///
+/// 时间可以是相对第一条报文的秒数，也可以是抓包中的绝对时间
+use std::io::Write;
+use std::time::Duration;
+
+use serde_json::{Map, Value};
+
+use crate::errors::MyError;
+use crate::matrix::types::{Matrix, MatrixType};
+use crate::types::{SomeipMessage, SomeipMessageType, SomeipMethodId, SomeipServiceId};
+
+use super::Sink;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsvLayout {
+    Wide,
+    Long,
+}
+
+/// 展开后的一列
+struct SignalColumn {
+    name: String,
+    methods: Vec<(SomeipServiceId, SomeipMethodId)>,
+    /// 从参数开始的成员路径
+    members: Vec<String>,
+}
+
+pub struct SignalCsvSink<'a, W: Write> {
+    matrix: &'a Matrix,
+    writer: csv::Writer<W>,
+    columns: Vec<SignalColumn>,
+    layout: CsvLayout,
+    fill: bool,
+    absolute_time: bool,
+    first_timestamp: Option<Duration>,
+    /// wide格式下每一列最近一次的值，用于填充
+    last_values: Vec<String>,
+}
+
+/// 沿着成员名找到选中的类型，再把结构体展开成叶子成员
+fn expand(
+    matrix: &Matrix,
+    type_name: &str,
+    rest: &[&str],
+    name: String,
+    members: Vec<String>,
+    out: &mut Vec<(String, Vec<String>)>,
+) -> Option<()> {
+    let data_type = matrix.data_types.get(type_name).map(|node| &node.data_type);
+    match (rest, data_type) {
+        ([], Some(MatrixType::Struct { members: children })) => {
+            for child in children {
+                let mut members = members.clone();
+                members.push(child.member_name.clone());
+                let name = format!("{}.{}", name, child.member_name);
+                expand(matrix, &child.member_data_type, &[], name, members, out)?;
+            }
+        }
+        ([], _) => out.push((name, members)),
+        ([first, rest @ ..], Some(MatrixType::Struct { members: children })) => {
+            let child = children.iter().find(|child| child.member_name == *first)?;
+            let mut members = members;
+            members.push(child.member_name.clone());
+            expand(matrix, &child.member_data_type, rest, name, members, out)?;
+        }
+        _ => return None,
+    }
+    Some(())
+}
+
+/// 信号路径展开成列；在矩阵中找不到成员时按原样作为一列
+fn resolve(matrix: &Matrix, path: &str) -> Result<Vec<SignalColumn>, MyError> {
+    let (methods, rest) = matrix.find_methods_by_path(path);
+    if methods.is_empty() {
+        return Err(MyError::ArgInputError(format!("unknown signal {}", path)));
+    }
+    let method_type = &matrix.services[&methods[0].0].methods[&methods[0].1].method_type;
+    let mut expanded = vec![];
+    for message_type in [
+        SomeipMessageType::Notification,
+        SomeipMessageType::Response,
+        SomeipMessageType::Request,
+        SomeipMessageType::RequestWithoutResponse,
+    ] {
+        let result = match method_type.parameter_types(message_type).as_deref() {
+            None | Some([]) => continue,
+            Some([type_name]) => expand(
+                matrix,
+                type_name,
+                &rest,
+                path.to_owned(),
+                vec![],
+                &mut expanded,
+            ),
+            Some(types) => match rest.split_first() {
+                Some((first, rest)) if types.contains(first) => {
+                    let members = vec![first.to_string()];
+                    expand(matrix, first, rest, path.to_owned(), members, &mut expanded)
+                }
+                _ => None,
+            },
+        };
+        if result.is_some() {
+            break;
+        }
+        expanded.clear();
+    }
+    if expanded.is_empty() {
+        expanded.push((
+            path.to_owned(),
+            rest.iter().map(|s| s.to_string()).collect(),
+        ));
+    }
+    Ok(expanded
+        .into_iter()
+        .map(|(name, members)| SignalColumn {
+            name,
+            methods: methods.clone(),
+            members,
+        })
+        .collect())
+}
+
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+impl<'a, W: Write> SignalCsvSink<'a, W> {
+    pub fn new(
+        matrix: &'a Matrix,
+        writer: W,
+        signals: &[&str],
+        layout: CsvLayout,
+        fill: bool,
+        absolute_time: bool,
+    ) -> Result<Self, MyError> {
+        let mut columns = vec![];
+        for signal in signals {
+            columns.extend(resolve(matrix, signal)?);
+        }
+        let mut writer = csv::Writer::from_writer(writer);
+        match layout {
+            CsvLayout::Wide => writer.write_record(
+                ["time", "sender"]
+                    .into_iter()
+                    .chain(columns.iter().map(|column| column.name.as_str())),
+            )?,
+            CsvLayout::Long => writer.write_record(["time", "sender", "signal", "value"])?,
+        }
+        Ok(SignalCsvSink {
+            matrix,
+            writer,
+            last_values: vec![String::new(); columns.len()],
+            columns,
+            layout,
+            fill,
+            absolute_time,
+            first_timestamp: None,
+        })
+    }
+
+    /// 解析出的参数：只有一个参数时就是该参数，多个参数时是以类型名为键的对象
+    fn parameters(&self, msg: &SomeipMessage) -> Option<Value> {
+        let mut values = super::decode_message(self.matrix, msg)?.ok()?;
+        match values.len() {
+            0 => None,
+            1 => Some(values.remove(0).1),
+            _ => Some(Value::Object(values.into_iter().collect::<Map<_, _>>())),
+        }
+    }
+}
+
+impl<'a, W: Write> Sink for SignalCsvSink<'a, W> {
+    fn write_message(&mut self, msg: &SomeipMessage) -> Result<(), MyError> {
+        let first_timestamp = *self.first_timestamp.get_or_insert(msg.timestamp);
+        let key = (msg.service_id, msg.method_id);
+        if !self
+            .columns
+            .iter()
+            .any(|column| column.methods.contains(&key))
+        {
+            return Ok(());
+        }
+        let parameters = match self.parameters(msg) {
+            Some(parameters) => parameters,
+            None => return Ok(()),
+        };
+        let values: Vec<Option<String>> = self
+            .columns
+            .iter()
+            .map(|column| {
+                if !column.methods.contains(&key) {
+                    return None;
+                }
+                let mut value = &parameters;
+                for member in &column.members {
+                    value = value.get(member)?;
+                }
+                Some(cell(value))
+            })
+            .collect();
+        if values.iter().all(Option::is_none) {
+            return Ok(());
+        }
+
+        let time = match self.absolute_time {
+            true => msg.timestamp.as_secs_f64(),
+            false => msg.timestamp.saturating_sub(first_timestamp).as_secs_f64(),
+        };
+        let time = format!("{:.6}", time);
+        let sender = self.matrix.role_name_or_ip(&msg.source.ip_addr);
+        match self.layout {
+            CsvLayout::Wide => {
+                let mut record = vec![time, sender];
+                for (i, value) in values.into_iter().enumerate() {
+                    match value {
+                        Some(value) => {
+                            self.last_values[i] = value.clone();
+                            record.push(value);
+                        }
+                        None if self.fill => record.push(self.last_values[i].clone()),
+                        None => record.push(String::new()),
+                    }
+                }
+                self.writer.write_record(&record)?;
+            }
+            CsvLayout::Long => {
+                for (column, value) in self.columns.iter().zip(values) {
+                    if let Some(value) = value {
+                        self.writer
+                            .write_record([&time, &sender, &column.name, &value])?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), MyError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// 服务0x1234的事件Status（结构体temp、fan）与Fan（uint8）
+#[cfg(test)]
+fn signals_matrix() -> Matrix {
+    use crate::analyzers::{test_data_type, test_event, test_matrix};
+    use crate::matrix::types::{MatrixMember, NumberType};
+
+    let mut matrix = test_matrix(vec![
+        test_event(0x8001, "Status", "Status"),
+        test_event(0x8002, "Fan", "uint8"),
+    ]);
+    test_data_type(
+        &mut matrix,
+        "uint8",
+        MatrixType::Number {
+            size: NumberType::Uint8,
+        },
+    );
+    let member = |name: &str| MatrixMember {
+        member_name: name.to_owned(),
+        member_data_type: "uint8".to_owned(),
+        ..Default::default()
+    };
+    test_data_type(
+        &mut matrix,
+        "Status",
+        MatrixType::Struct {
+            members: vec![member("temp"), member("fan")],
+        },
+    );
+    matrix
+}
+
+#[test]
+fn wide_forward_fill() {
+    use crate::analyzers::{test_message, test_payload};
+
+    let matrix = signals_matrix();
+    let mut sink = SignalCsvSink::new(
+        &matrix,
+        vec![],
+        &["Climate.Status", "Climate.Fan"],
+        CsvLayout::Wide,
+        true,
+        false,
+    )
+    .unwrap();
+    for (ts_ms, method_id, payload) in [
+        (1000, 0x8001, vec![21, 3]),
+        (1500, 0x8002, vec![4]),
+        (2000, 0x8001, vec![22]),
+    ] {
+        let msg = test_message(
+            ts_ms,
+            "10.0.0.2:30501",
+            "10.0.0.1:40000",
+            SomeipMessageType::Notification,
+            0x1234,
+            method_id,
+        );
+        sink.write_message(&test_payload(msg, &payload)).unwrap();
+    }
+    sink.finish().unwrap();
+
+    let output = String::from_utf8(sink.writer.into_inner().unwrap()).unwrap();
+    assert_eq!(
+        output,
+        "time,sender,Climate.Status.temp,Climate.Status.fan,Climate.Fan\n\
+         0.000000,10.0.0.2,21,3,\n\
+         0.500000,10.0.0.2,21,3,4\n"
+    );
+}
+
+#[test]
+fn long_absolute_time() {
+    use crate::analyzers::{test_message, test_payload, test_role};
+
+    let mut matrix = signals_matrix();
+    test_role(&mut matrix, "ECU", "10.0.0.2");
+    let mut sink = SignalCsvSink::new(
+        &matrix,
+        vec![],
+        &["Climate.Status.temp", "Climate.Fan"],
+        CsvLayout::Long,
+        true,
+        true,
+    )
+    .unwrap();
+    for (ts_ms, method_id, payload) in [
+        (1000, 0x8003, vec![]),
+        (1500, 0x8001, vec![21, 3]),
+        (2250, 0x8002, vec![4]),
+    ] {
+        let msg = test_message(
+            ts_ms,
+            "10.0.0.2:30501",
+            "10.0.0.1:40000",
+            SomeipMessageType::Notification,
+            0x1234,
+            method_id,
+        );
+        sink.write_message(&test_payload(msg, &payload)).unwrap();
+    }
+    sink.finish().unwrap();
+
+    // 每个信号值一行，没有值的信号不填充；时间是抓包中的绝对时间
+    let output = String::from_utf8(sink.writer.into_inner().unwrap()).unwrap();
+    assert_eq!(
+        output,
+        "time,sender,signal,value\n\
+         1.500000,ECU,Climate.Status.temp,21\n\
+         2.250000,ECU,Climate.Fan,4\n"
+    );
+}
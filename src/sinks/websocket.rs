@@ -0,0 +1,383 @@
+/// WebSocket实时推送：客户端连接 ws://<地址>/?filter=<过滤表达式>&backpressure=drop|sample，主要用于网卡与adb等实时数据源
+/// 推送两类JSON消息：
+/// - {"type": "message", "message": ...}，报文内容与message_json一致
+/// - {"type": "sd_state", ...}，服务的提供状态与事件组的订阅状态发生变化时推送
+///
+/// 每个客户端有独立的发送队列与发送线程，客户端跟不上时按照backpressure处理：
+/// - drop（缺省）：队列满时丢弃新消息，队列再有空间时先推送 {"type": "dropped", "count": n}
+/// - sample：队列超过一半时每SAMPLE_INTERVAL条报文只推送一条，队列满时仍然丢弃
+///
+/// 矩阵不能跨线程，过滤表达式在处理报文的线程中解析，解析失败时推送错误后断开
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+use log::{debug, info};
+use serde_json::{json, Value};
+use tungstenite::handshake::server::{Request, Response};
+use tungstenite::Message;
+
+use crate::errors::MyError;
+use crate::matrix::types::Matrix;
+use crate::server::parse_query;
+use crate::types::{
+    SomeipEventgroupId, SomeipInstanceId, SomeipMessage, SomeipMethodId, SomeipSdEntryType,
+    SomeipServiceId,
+};
+
+use super::{is_selected, message_json, select_methods, Sink};
+
+/// 每个客户端发送队列的长度
+const QUEUE_SIZE: usize = 256;
+/// sample模式下落后时每多少条报文推送一条
+const SAMPLE_INTERVAL: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backpressure {
+    Drop,
+    Sample,
+}
+
+/// 接受连接的线程完成握手后交给处理报文的线程
+struct Connection {
+    peer: SocketAddr,
+    filter: Option<String>,
+    backpressure: Backpressure,
+    sender: Sender<String>,
+}
+
+struct Client {
+    peer: SocketAddr,
+    selected: Option<Vec<(SomeipServiceId, SomeipMethodId)>>,
+    backpressure: Backpressure,
+    sender: Sender<String>,
+    /// 还没有通知客户端的丢弃数量
+    dropped: usize,
+    /// sample模式下落后期间收到的报文数量
+    sampled: usize,
+}
+
+impl Client {
+    /// 放入发送队列，客户端已经断开时返回false
+    fn push(&mut self, text: &str, sample: bool) -> bool {
+        if self.backpressure == Backpressure::Sample && sample {
+            match self.sender.len() > QUEUE_SIZE / 2 {
+                true => {
+                    self.sampled += 1;
+                    if !self.sampled.is_multiple_of(SAMPLE_INTERVAL) {
+                        return true;
+                    }
+                }
+                false => self.sampled = 0,
+            }
+        }
+        if self.dropped > 0 {
+            let notice = json!({ "type": "dropped", "count": self.dropped }).to_string();
+            match self.sender.try_send(notice) {
+                Ok(()) => self.dropped = 0,
+                Err(TrySendError::Full(_)) => {
+                    self.dropped += 1;
+                    return true;
+                }
+                Err(TrySendError::Disconnected(_)) => return false,
+            }
+        }
+        match self.sender.try_send(text.to_owned()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                self.dropped += 1;
+                true
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    }
+}
+
+/// SD状态的键：端点IP、服务、实例、事件组（服务提供状态没有事件组）
+type SdStateKey = (
+    IpAddr,
+    SomeipServiceId,
+    SomeipInstanceId,
+    Option<SomeipEventgroupId>,
+);
+
+pub struct WebSocketSink<'a> {
+    matrix: &'a Matrix,
+    connections: Receiver<Connection>,
+    clients: Vec<Client>,
+    sd_states: HashMap<SdStateKey, &'static str>,
+}
+
+/// 完成握手，从URL中取出参数，然后把发送队列中的消息依次发给客户端，直到客户端断开
+fn serve_client(stream: TcpStream, connections: Sender<Connection>) {
+    let peer = match stream.peer_addr() {
+        Ok(peer) => peer,
+        Err(_) => return,
+    };
+    let mut query = HashMap::new();
+    // 错误应答的类型由tungstenite决定
+    #[allow(clippy::result_large_err)]
+    let callback = |request: &Request, response: Response| {
+        query = parse_query(request.uri().query().unwrap_or_default());
+        Ok(response)
+    };
+    let mut websocket = match tungstenite::accept_hdr(stream, callback) {
+        Ok(websocket) => websocket,
+        Err(e) => {
+            debug!("websocket handshake with {} failed: {}", peer, e);
+            return;
+        }
+    };
+    let (sender, receiver) = crossbeam_channel::bounded(QUEUE_SIZE);
+    let connection = Connection {
+        peer,
+        filter: query.remove("filter").filter(|filter| !filter.is_empty()),
+        backpressure: match query.get("backpressure").map(|s| s.as_str()) {
+            Some("sample") => Backpressure::Sample,
+            _ => Backpressure::Drop,
+        },
+        sender,
+    };
+    if connections.send(connection).is_err() {
+        return;
+    }
+    for text in receiver {
+        if let Err(e) = websocket.send(Message::Text(text)) {
+            debug!("websocket {} closed: {}", peer, e);
+            return;
+        }
+    }
+    let _ = websocket.close(None);
+}
+
+impl<'a> WebSocketSink<'a> {
+    pub fn new(matrix: &'a Matrix, listen: &str) -> Result<Self, MyError> {
+        let listener = TcpListener::bind(listen)?;
+        let local_addr = listener.local_addr()?;
+        let (sender, connections) = crossbeam_channel::unbounded();
+        std::thread::Builder::new()
+            .name("websocket-acceptor".to_string())
+            .spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let sender = sender.clone();
+                    let _ = std::thread::Builder::new()
+                        .name("websocket-client".to_string())
+                        .spawn(move || serve_client(stream, sender));
+                }
+            })?;
+        info!("websocket listening on {}", local_addr);
+        Ok(WebSocketSink {
+            matrix,
+            connections,
+            clients: vec![],
+            sd_states: HashMap::new(),
+        })
+    }
+
+    /// 加入新连接的客户端，过滤表达式无法解析时推送错误后断开
+    fn accept(&mut self) {
+        for connection in self.connections.try_iter() {
+            let selected = match &connection.filter {
+                Some(filter) => match select_methods(self.matrix, filter) {
+                    Ok(selected) => Some(selected),
+                    Err(e) => {
+                        let error = json!({ "type": "error", "error": e.to_string() });
+                        let _ = connection.sender.try_send(error.to_string());
+                        continue;
+                    }
+                },
+                None => None,
+            };
+            info!("websocket client {} connected", connection.peer);
+            self.clients.push(Client {
+                peer: connection.peer,
+                selected,
+                backpressure: connection.backpressure,
+                sender: connection.sender,
+                dropped: 0,
+                sampled: 0,
+            });
+        }
+    }
+
+    /// 根据SD条目更新状态，返回发生变化的 (服务, 推送内容)
+    fn sd_changes(&mut self, msg: &SomeipMessage) -> Vec<(SomeipServiceId, Value)> {
+        let sd = match &msg.sd {
+            Some(sd) => sd,
+            None => return vec![],
+        };
+        let mut changes = vec![];
+        for entry in &sd.entries {
+            let source = msg.source.ip_addr;
+            let destination = msg.destination.ip_addr;
+            let eventgroup = Some(entry.eventgroup_id);
+            // 订阅状态记在订阅方，应答由服务端发给订阅方
+            let (ip_addr, eventgroup_id, state) = match entry.entry_type {
+                SomeipSdEntryType::OfferService => (source, None, "offered"),
+                SomeipSdEntryType::StopOfferService => (source, None, "stopped"),
+                SomeipSdEntryType::Subscribe => (source, eventgroup, "requested"),
+                SomeipSdEntryType::StopSubscribe => (source, eventgroup, "unsubscribed"),
+                SomeipSdEntryType::SubscribeAck => (destination, eventgroup, "subscribed"),
+                SomeipSdEntryType::SubscribeNack => (destination, eventgroup, "rejected"),
+                _ => continue,
+            };
+            let (service_id, instance_id) = (entry.service_id, entry.instance_id);
+            let key = (ip_addr, service_id, instance_id, eventgroup_id);
+            let previous = self.sd_states.get(&key).copied();
+            // 已经订阅成功后的周期性续订不算状态变化
+            if previous == Some(state) || (state == "requested" && previous == Some("subscribed")) {
+                continue;
+            }
+            self.sd_states.insert(key, state);
+            changes.push((
+                service_id,
+                json!({
+                    "type": "sd_state",
+                    "timestamp": msg.timestamp.as_secs_f64(),
+                    "endpoint": self.matrix.role_name_or_ip(&ip_addr),
+                    "service_id": service_id,
+                    "service_name": self.matrix.service_name(service_id),
+                    "instance_id": instance_id,
+                    "eventgroup_id": eventgroup_id,
+                    "state": state,
+                    "previous": previous,
+                }),
+            ));
+        }
+        changes
+    }
+}
+
+impl<'a> Sink for WebSocketSink<'a> {
+    fn write_message(&mut self, msg: &SomeipMessage) -> Result<(), MyError> {
+        self.accept();
+        let changes = self.sd_changes(msg);
+        if self.clients.is_empty() {
+            return Ok(());
+        }
+        let matrix = self.matrix;
+        let mut text = None;
+        self.clients.retain_mut(|client| {
+            let mut alive = true;
+            if is_selected(client.selected.as_deref(), msg) {
+                let text = text.get_or_insert_with(|| {
+                    json!({ "type": "message", "message": message_json(matrix, msg) }).to_string()
+                });
+                alive = client.push(text, true);
+            }
+            for (service_id, change) in &changes {
+                let selected = match &client.selected {
+                    Some(selected) => selected.iter().any(|(id, _)| id == service_id),
+                    None => true,
+                };
+                if alive && selected {
+                    alive = client.push(&change.to_string(), false);
+                }
+            }
+            if !alive {
+                info!("websocket client {} disconnected", client.peer);
+            }
+            alive
+        });
+        Ok(())
+    }
+}
+
+#[test]
+fn drop_when_behind() {
+    let (sender, receiver) = crossbeam_channel::bounded(QUEUE_SIZE);
+    let mut client = Client {
+        peer: "127.0.0.1:1".parse().unwrap(),
+        selected: None,
+        backpressure: Backpressure::Drop,
+        sender,
+        dropped: 0,
+        sampled: 0,
+    };
+    for i in 0..QUEUE_SIZE + 5 {
+        assert!(client.push(&i.to_string(), true));
+    }
+    assert_eq!(client.dropped, 5);
+    assert_eq!(receiver.try_iter().count(), QUEUE_SIZE);
+
+    assert!(client.push("next", true));
+    let sent: Vec<String> = receiver.try_iter().collect();
+    assert_eq!(sent, [r#"{"count":5,"type":"dropped"}"#, "next"]);
+    drop(receiver);
+    assert!(!client.push("closed", true));
+}
+
+#[test]
+fn sample_when_behind() {
+    let (sender, receiver) = crossbeam_channel::bounded(QUEUE_SIZE);
+    let mut client = Client {
+        peer: "127.0.0.1:1".parse().unwrap(),
+        selected: None,
+        backpressure: Backpressure::Sample,
+        sender,
+        dropped: 0,
+        sampled: 0,
+    };
+    for i in 0..=QUEUE_SIZE / 2 {
+        assert!(client.push(&i.to_string(), true));
+    }
+    assert_eq!(client.sampled, 0);
+    // 落后后每SAMPLE_INTERVAL条报文只推送一条，SD状态不抽样
+    for i in 0..SAMPLE_INTERVAL * 3 {
+        assert!(client.push(&format!("sampled {}", i), true));
+    }
+    assert!(client.push("sd_state", false));
+    assert_eq!(client.sampled, SAMPLE_INTERVAL * 3);
+    assert_eq!(client.dropped, 0);
+    let sent: Vec<String> = receiver.try_iter().skip(QUEUE_SIZE / 2 + 1).collect();
+    assert_eq!(sent, ["sampled 9", "sampled 19", "sampled 29", "sd_state"]);
+
+    // 追上后恢复逐条推送
+    assert!(client.push("next", true));
+    assert_eq!(client.sampled, 0);
+    assert_eq!(receiver.try_iter().collect::<Vec<_>>(), ["next"]);
+}
+
+#[test]
+fn sd_state_changes() {
+    use crate::analyzers::{test_role, test_sd_entry, test_sd_message};
+    use crate::types::SomeipSdEntryType::*;
+
+    let mut matrix = Matrix::default();
+    test_role(&mut matrix, "HU", "10.0.0.1");
+    let mut sink = WebSocketSink::new(&matrix, "127.0.0.1:0").unwrap();
+    let multicast = "224.224.224.245:30490";
+    let (client, server) = ("10.0.0.1:30490", "10.0.0.2:30490");
+    let mut states = vec![];
+    for (ts_ms, source, destination, entry_type) in [
+        (0, server, multicast, OfferService),
+        (1000, server, multicast, OfferService),
+        (1010, client, server, Subscribe),
+        (1020, server, client, SubscribeAck),
+        (2010, client, server, Subscribe),
+        (3000, server, multicast, StopOfferService),
+    ] {
+        let entry = test_sd_entry(entry_type);
+        let msg = test_sd_message(ts_ms, source, destination, 1, false, entry);
+        for (service_id, change) in sink.sd_changes(&msg) {
+            assert_eq!(service_id, 0x1234);
+            states.push(format!(
+                "{} {} {} <- {}",
+                change["endpoint"].as_str().unwrap(),
+                change["eventgroup_id"],
+                change["state"].as_str().unwrap(),
+                change["previous"]
+            ));
+        }
+    }
+    // 重复的提供与订阅成功后的续订不推送
+    assert_eq!(
+        states,
+        [
+            "10.0.0.2 null offered <- null",
+            "HU 1 requested <- null",
+            "HU 1 subscribed <- \"requested\"",
+            "10.0.0.2 null stopped <- \"offered\"",
+        ]
+    );
+}
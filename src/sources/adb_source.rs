@@ -0,0 +1,257 @@
+use std::io::{BufReader, Read};
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::time::Duration;
+
+use log::{error, info};
+use pnet::datalink;
+
+use crate::errors::MyError;
+
+use super::RawPacket;
+
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+/// 单帧的最大长度，与tcpdump的最大snaplen一致；文件头中的snaplen为0或更大时使用该值
+const MAX_SNAPLEN: u32 = 262144;
+
+/// 在安卓设备上运行tcpdump，从标准输出读取pcap格式的报文流
+/// 设备需要有root权限与tcpdump；连接了多个设备时通过环境变量ANDROID_SERIAL指定
+pub struct AdbSource {
+    interface: String,
+    child: Child,
+    reader: PcapStreamReader<BufReader<ChildStdout>>,
+}
+
+/// pcap文件格式的流式读取，不需要能够seek
+pub struct PcapStreamReader<R: Read> {
+    reader: R,
+    big_endian: bool,
+    nanosecond: bool,
+    snaplen: u32,
+    link_type: u32,
+}
+
+impl<R: Read> PcapStreamReader<R> {
+    /// 读取文件头，根据魔数确定字节序与时间戳精度
+    pub fn new(mut reader: R) -> Result<Self, MyError> {
+        let mut header = [0u8; 24];
+        reader.read_exact(&mut header)?;
+        let (big_endian, nanosecond) = match header[..4] {
+            [0xd4, 0xc3, 0xb2, 0xa1] => (false, false),
+            [0xa1, 0xb2, 0xc3, 0xd4] => (true, false),
+            [0x4d, 0x3c, 0xb2, 0xa1] => (false, true),
+            [0xa1, 0xb2, 0x3c, 0x4d] => (true, true),
+            _ => return Err(MyError::Custom("pcap stream: bad magic number".to_owned())),
+        };
+        let mut stream = PcapStreamReader {
+            reader,
+            big_endian,
+            nanosecond,
+            snaplen: 0,
+            link_type: 0,
+        };
+        stream.snaplen = match stream.u32(&header[16..20]) {
+            snaplen if snaplen == 0 || snaplen > MAX_SNAPLEN => MAX_SNAPLEN,
+            snaplen => snaplen,
+        };
+        stream.link_type = stream.u32(&header[20..24]);
+        Ok(stream)
+    }
+
+    fn u32(&self, bytes: &[u8]) -> u32 {
+        let bytes: [u8; 4] = bytes.try_into().unwrap();
+        match self.big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        }
+    }
+
+    /// 读取下一帧，流结束时返回None；帧头损坏时返回错误，不再继续读取
+    pub fn next_packet(&mut self) -> Result<Option<(Duration, Vec<u8>)>, MyError> {
+        let mut header = [0u8; 16];
+        match self.reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let seconds = self.u32(&header[..4]) as u64;
+        let fraction = self.u32(&header[4..8]);
+        let timestamp = match self.nanosecond {
+            true if fraction < 1_000_000_000 => Duration::new(seconds, fraction),
+            false if fraction < 1_000_000 => Duration::new(seconds, fraction * 1000),
+            _ => {
+                return Err(MyError::Custom(format!(
+                    "pcap stream: bad timestamp fraction {}",
+                    fraction
+                )))
+            }
+        };
+        let length = self.u32(&header[8..12]);
+        if length > self.snaplen {
+            return Err(MyError::Custom(format!(
+                "pcap stream: packet length {} exceeds snaplen {}",
+                length, self.snaplen
+            )));
+        }
+        let mut data = vec![0; length as usize];
+        self.reader.read_exact(&mut data)?;
+        Ok(Some((timestamp, data)))
+    }
+}
+
+impl AdbSource {
+    pub fn new(interface: &str) -> Result<Self, MyError> {
+        // 网卡名拼接在设备上的shell命令中，只允许常见的字符
+        if interface.is_empty()
+            || !interface
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "._-:@".contains(c))
+        {
+            return Err(MyError::ArgInputError(format!(
+                "adb interface {}",
+                interface
+            )));
+        }
+        // exec-out会把标准错误也混进报文流，tcpdump的提示信息在设备上丢弃
+        let mut child = Command::new("adb")
+            .args([
+                "exec-out",
+                &format!("tcpdump -i {} -U -s 0 -w - 2>/dev/null", interface),
+            ])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| MyError::Custom("adb: no stdout".to_owned()))?;
+        let reader = PcapStreamReader::new(BufReader::new(stdout))?;
+        match reader.link_type {
+            LINKTYPE_ETHERNET | LINKTYPE_LINUX_SLL => {}
+            link_type => {
+                let _ = child.kill();
+                return Err(MyError::Custom(format!(
+                    "adb {}: unhandled link type {}",
+                    interface, link_type
+                )));
+            }
+        }
+
+        Ok(AdbSource {
+            interface: interface.to_owned(),
+            child,
+            reader,
+        })
+    }
+
+    pub fn channel_type(&self) -> datalink::ChannelType {
+        match self.reader.link_type {
+            LINKTYPE_LINUX_SLL => datalink::ChannelType::Layer3(0),
+            _ => datalink::ChannelType::Layer2,
+        }
+    }
+
+    pub fn start(
+        mut self,
+        send_data: crossbeam_channel::Sender<RawPacket>,
+    ) -> std::io::Result<std::thread::JoinHandle<()>> {
+        std::thread::Builder::new()
+            .name("adb-reader".to_string())
+            .spawn(move || {
+                let mut packet_index = 0;
+                loop {
+                    match self.reader.next_packet() {
+                        Ok(Some((timestamp, data))) => {
+                            let raw = RawPacket {
+                                packet_index,
+                                timestamp,
+                                data,
+                            };
+                            if send_data.send(raw).is_err() {
+                                break;
+                            }
+                            packet_index += 1;
+                        }
+                        Ok(None) => break,
+                        Err(e) => {
+                            error!("adb capture on {} error: {}", self.interface, e);
+                            break;
+                        }
+                    }
+                }
+                let _ = self.child.kill();
+                let _ = self.child.wait();
+                info!(
+                    "captured {} packets on adb {}",
+                    packet_index, self.interface
+                );
+            })
+    }
+}
+
+#[test]
+fn pcap_stream() {
+    let mut stream = vec![0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0];
+    stream.extend_from_slice(&[0; 8]);
+    stream.extend_from_slice(&65535u32.to_le_bytes());
+    stream.extend_from_slice(&LINKTYPE_LINUX_SLL.to_le_bytes());
+    for (seconds, micros, data) in [(10u32, 500u32, vec![1u8, 2, 3]), (11, 0, vec![4])] {
+        stream.extend_from_slice(&seconds.to_le_bytes());
+        stream.extend_from_slice(&micros.to_le_bytes());
+        stream.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        stream.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        stream.extend_from_slice(&data);
+    }
+
+    let mut reader = PcapStreamReader::new(&stream[..]).unwrap();
+    assert_eq!(reader.link_type, LINKTYPE_LINUX_SLL);
+    let (timestamp, data) = reader.next_packet().unwrap().unwrap();
+    assert_eq!(timestamp, Duration::new(10, 500_000));
+    assert_eq!(data, [1, 2, 3]);
+    assert_eq!(reader.next_packet().unwrap().unwrap().1, [4]);
+    assert!(reader.next_packet().unwrap().is_none());
+}
+
+#[test]
+fn pcap_stream_bad_headers() {
+    let header = |snaplen: u32| {
+        let mut stream = vec![0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0];
+        stream.extend_from_slice(&[0; 8]);
+        stream.extend_from_slice(&snaplen.to_le_bytes());
+        stream.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+        stream
+    };
+    let packet = |stream: &mut Vec<u8>, micros: u32, length: u32| {
+        stream.extend_from_slice(&1u32.to_le_bytes());
+        stream.extend_from_slice(&micros.to_le_bytes());
+        stream.extend_from_slice(&length.to_le_bytes());
+        stream.extend_from_slice(&length.to_le_bytes());
+    };
+
+    // 帧长度超过snaplen时不分配内存
+    let mut stream = header(64);
+    packet(&mut stream, 0, 65);
+    assert!(PcapStreamReader::new(&stream[..])
+        .unwrap()
+        .next_packet()
+        .is_err());
+    // snaplen为0时使用最大值
+    let mut stream = header(0);
+    packet(&mut stream, 0, u32::MAX);
+    let mut reader = PcapStreamReader::new(&stream[..]).unwrap();
+    assert_eq!(reader.snaplen, MAX_SNAPLEN);
+    assert!(reader.next_packet().is_err());
+    // 微秒部分超出范围
+    let mut stream = header(64);
+    packet(&mut stream, 1_000_000, 0);
+    assert!(PcapStreamReader::new(&stream[..])
+        .unwrap()
+        .next_packet()
+        .is_err());
+    let mut stream = header(64);
+    packet(&mut stream, u32::MAX, 0);
+    assert!(PcapStreamReader::new(&stream[..])
+        .unwrap()
+        .next_packet()
+        .is_err());
+}
@@ -0,0 +1,86 @@
+use std::time::Duration;
+
+use log::{error, info};
+use pcap::{Active, Capture, Linktype};
+use pnet::datalink;
+
+use crate::errors::MyError;
+
+use super::RawPacket;
+
+/// 读取超时，超时后继续等待，避免没有报文时一直阻塞在系统调用中
+const READ_TIMEOUT_MS: i32 = 100;
+
+/// 从本机网卡实时抓包，直到出错或进程退出
+pub struct InterfaceSource {
+    device: String,
+    pcap_capture: Capture<Active>,
+}
+
+impl InterfaceSource {
+    pub fn new(device: &str) -> Result<Self, MyError> {
+        let pcap_capture = Capture::from_device(device)?
+            .promisc(true)
+            .snaplen(65535)
+            .immediate_mode(true)
+            .timeout(READ_TIMEOUT_MS)
+            .open()?;
+        match pcap_capture.get_datalink() {
+            Linktype::ETHERNET | Linktype::LINUX_SLL => {}
+            linktype => {
+                return Err(MyError::Custom(format!(
+                    "interface {}: unhandled link type {:?}",
+                    device, linktype
+                )))
+            }
+        }
+
+        Ok(InterfaceSource {
+            device: device.to_owned(),
+            pcap_capture,
+        })
+    }
+
+    pub fn channel_type(&self) -> datalink::ChannelType {
+        match self.pcap_capture.get_datalink() {
+            Linktype::LINUX_SLL => datalink::ChannelType::Layer3(0),
+            _ => datalink::ChannelType::Layer2,
+        }
+    }
+
+    pub fn start(
+        mut self,
+        send_data: crossbeam_channel::Sender<RawPacket>,
+    ) -> std::io::Result<std::thread::JoinHandle<()>> {
+        std::thread::Builder::new()
+            .name("pcap-interface-reader".to_string())
+            .spawn(move || {
+                let mut packet_index = 0;
+                loop {
+                    match self.pcap_capture.next_packet() {
+                        Ok(pkt) => {
+                            let timestamp = Duration::new(
+                                pkt.header.ts.tv_sec as u64,
+                                pkt.header.ts.tv_usec as u32 * 1000,
+                            );
+                            let raw = RawPacket {
+                                packet_index,
+                                timestamp,
+                                data: pkt.data.to_vec(),
+                            };
+                            if send_data.send(raw).is_err() {
+                                break;
+                            }
+                            packet_index += 1;
+                        }
+                        Err(pcap::Error::TimeoutExpired) => continue,
+                        Err(e) => {
+                            error!("capture on {} error: {}", self.device, e);
+                            break;
+                        }
+                    }
+                }
+                info!("captured {} packets on {}", packet_index, self.device);
+            })
+    }
+}
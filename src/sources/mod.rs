@@ -1,5 +1,23 @@
+pub mod adb_source;
+pub mod interface_source;
 pub mod pcap_source;
 
+use std::time::Duration;
+
+use pnet::datalink;
+
+use crate::types::PacketIndex;
+use adb_source::AdbSource;
+use interface_source::InterfaceSource;
+use pcap_source::PcapFileSource;
+
+/// 数据源输出的一帧原始报文
+pub struct RawPacket {
+    pub packet_index: PacketIndex,
+    pub timestamp: Duration,
+    pub data: Vec<u8>,
+}
+
 // pub trait SourceContext {}
 
 // pub trait Source {
@@ -8,3 +26,32 @@ pub mod pcap_source;
 //     // 每个Source都需要有一个执行方式，Source只有一个输出
 //     fn start(self, send_data: crossbeam_channel::Sender<Vec<u8>>) -> std::io::Result<std::thread::JoinHandle<()>>;
 // }
+
+/// 不同的Source有不同的创建参数，但每个Source都需要有一个执行方式，Source只有一个输出
+pub enum Source {
+    PcapFile(PcapFileSource),
+    Interface(InterfaceSource),
+    Adb(AdbSource),
+}
+
+impl Source {
+    /// 原始报文的链路层类型，解析器需要据此选择从哪一层开始解析
+    pub fn channel_type(&self) -> datalink::ChannelType {
+        match self {
+            Source::PcapFile(source) => source.channel_type(),
+            Source::Interface(source) => source.channel_type(),
+            Source::Adb(source) => source.channel_type(),
+        }
+    }
+
+    pub fn start(
+        self,
+        send_data: crossbeam_channel::Sender<RawPacket>,
+    ) -> std::io::Result<std::thread::JoinHandle<()>> {
+        match self {
+            Source::PcapFile(source) => source.start(send_data),
+            Source::Interface(source) => source.start(send_data),
+            Source::Adb(source) => source.start(send_data),
+        }
+    }
+}
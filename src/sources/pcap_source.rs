@@ -1,54 +1,78 @@
-use std::borrow::{Borrow, BorrowMut};
-use std::cell::{Cell, RefCell};
 use std::path::PathBuf;
 use std::time::Duration;
 
 use log::{error, info};
-use pnet::datalink::Channel::Ethernet;
-use pnet::datalink::{self, Config, DataLinkReceiver};
-use pnet::packet::ethernet::{EtherTypes, EthernetPacket};
-use pnet::packet::ip::IpNextHeaderProtocols::{self};
-use pnet::packet::sll::SLLPacket;
-use pnet::packet::udp::UdpPacket;
-use pnet::packet::Packet;
+use pcap::{Capture, Linktype, Offline};
+use pnet::datalink;
 
 use crate::errors::MyError;
-use crate::types::SomeipMessage;
+
+use super::RawPacket;
 
 pub struct PcapFileSource {
     file_path: PathBuf,
-    pcap_capture: Box<dyn DataLinkReceiver>,
+    pcap_capture: Capture<Offline>,
 }
 
 impl PcapFileSource {
-    fn new(path: &PathBuf) -> Result<Self, MyError> {
-        let config = pnet::datalink::pcap::Config::default();
+    pub fn new(path: &PathBuf) -> Result<Self, MyError> {
+        let pcap_capture = Capture::from_file(path)?;
+        match pcap_capture.get_datalink() {
+            Linktype::ETHERNET | Linktype::LINUX_SLL => {}
+            linktype => {
+                return Err(MyError::Custom(format!(
+                    "packetdump: unhandled link type {:?}",
+                    linktype
+                )))
+            }
+        }
 
         Ok(PcapFileSource {
             file_path: path.clone(),
-            pcap_capture: match datalink::pcap::from_file(path, config) {
-                Ok(Ethernet(_, rx)) => Ok(rx),
-                Ok(_) => Err(MyError::Custom(
-                    "packetdump: unhandled channel type".to_string(),
-                )),
-                Err(e) => Err(MyError::Custom(format!(
-                    "packetdump: unable to create channel: {}",
-                    e
-                ))),
-            }?,
+            pcap_capture,
         })
     }
 
-    fn start(
+    pub fn channel_type(&self) -> datalink::ChannelType {
+        match self.pcap_capture.get_datalink() {
+            Linktype::LINUX_SLL => datalink::ChannelType::Layer3(0),
+            _ => datalink::ChannelType::Layer2,
+        }
+    }
+
+    pub fn start(
         mut self,
-        send_data: crossbeam_channel::Sender<Vec<u8>>,
+        send_data: crossbeam_channel::Sender<RawPacket>,
     ) -> std::io::Result<std::thread::JoinHandle<()>> {
         std::thread::Builder::new()
             .name("pcap-file-reader".to_string())
             .spawn(move || {
-                while let Ok((ts,pkt))= self.pcap_capture.next() {
-
+                let mut packet_index = 0;
+                loop {
+                    match self.pcap_capture.next_packet() {
+                        Ok(pkt) => {
+                            let timestamp = Duration::new(
+                                pkt.header.ts.tv_sec as u64,
+                                pkt.header.ts.tv_usec as u32 * 1000,
+                            );
+                            let raw = RawPacket {
+                                packet_index,
+                                timestamp,
+                                data: pkt.data.to_vec(),
+                            };
+                            if send_data.send(raw).is_err() {
+                                break;
+                            }
+                            packet_index += 1;
+                        }
+                        Err(pcap::Error::NoMorePackets) => break,
+                        Err(e) => {
+                            error!("read {:?} error: {}", self.file_path, e);
+                            break;
+                        }
+                    }
                 }
+                info!("read {} packets from {:?}", packet_index, self.file_path);
             })
     }
 }
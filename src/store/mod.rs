@@ -0,0 +1,353 @@
+/// 解析结果的持久化：把解析出的报文与SD条目写入SQLite数据库，之后的查询不需要重新解析抓包
+/// 数据库中同时保存矩阵，过滤表达式按照保存的矩阵解析，与命令行的结果一致
+///
+/// 表结构：
+/// - messages：每条报文一行，按时间、服务与方法、收发双方的角色、ClientID与SessionID建立索引，json列与message_json一致
+/// - sd_entries：每个SD条目一行，message_id指向所在的报文，按时间与服务实例建立索引
+/// - meta：matrix（矩阵的JSON）、schema_version
+///
+/// 原始SQL查询以只读方式打开数据库
+use std::net::IpAddr;
+use std::path::Path;
+
+use rusqlite::types::ValueRef;
+use rusqlite::{params, Connection, OpenFlags};
+use serde_json::Value;
+
+use crate::errors::MyError;
+use crate::matrix::types::Matrix;
+use crate::sinks::{message_json, select_methods, Sink};
+use crate::types::SomeipMessage;
+
+const SCHEMA_VERSION: &str = "1";
+/// 每写入这么多条报文提交一次，实时数据源中途退出时已经提交的报文不会丢失
+const COMMIT_INTERVAL: usize = 10000;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+CREATE TABLE IF NOT EXISTS messages (
+    id INTEGER PRIMARY KEY,
+    timestamp REAL NOT NULL,
+    packet_index INTEGER NOT NULL,
+    source_ip TEXT NOT NULL,
+    source_port INTEGER NOT NULL,
+    source_role TEXT,
+    destination_ip TEXT NOT NULL,
+    destination_port INTEGER NOT NULL,
+    destination_role TEXT,
+    transport TEXT NOT NULL,
+    service_id INTEGER NOT NULL,
+    service_name TEXT,
+    method_id INTEGER NOT NULL,
+    method_name TEXT,
+    client_id INTEGER NOT NULL,
+    session_id INTEGER NOT NULL,
+    message_type TEXT NOT NULL,
+    return_code INTEGER NOT NULL,
+    length INTEGER NOT NULL,
+    payload BLOB NOT NULL,
+    json TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS sd_entries (
+    message_id INTEGER NOT NULL REFERENCES messages(id),
+    timestamp REAL NOT NULL,
+    source_ip TEXT NOT NULL,
+    source_role TEXT,
+    destination_ip TEXT NOT NULL,
+    entry_type TEXT NOT NULL,
+    service_id INTEGER NOT NULL,
+    instance_id INTEGER NOT NULL,
+    eventgroup_id INTEGER NOT NULL,
+    major_version INTEGER NOT NULL,
+    minor_version INTEGER NOT NULL,
+    ttl INTEGER NOT NULL
+);
+";
+
+/// 批量写入完成后再建立索引，写入更快
+const INDEXES: &str = "
+CREATE INDEX IF NOT EXISTS messages_timestamp ON messages(timestamp);
+CREATE INDEX IF NOT EXISTS messages_method ON messages(service_id, method_id);
+CREATE INDEX IF NOT EXISTS messages_source_role ON messages(source_role);
+CREATE INDEX IF NOT EXISTS messages_destination_role ON messages(destination_role);
+CREATE INDEX IF NOT EXISTS messages_session ON messages(client_id, session_id);
+CREATE INDEX IF NOT EXISTS sd_entries_timestamp ON sd_entries(timestamp);
+CREATE INDEX IF NOT EXISTS sd_entries_service ON sd_entries(service_id, instance_id);
+CREATE INDEX IF NOT EXISTS sd_entries_message ON sd_entries(message_id);
+";
+
+/// 解析时写入数据库，已有的文件会被覆盖
+pub struct StoreSink<'a> {
+    matrix: &'a Matrix,
+    connection: Connection,
+    uncommitted: usize,
+}
+
+impl<'a> StoreSink<'a> {
+    pub fn new<P: AsRef<Path>>(matrix: &'a Matrix, path: P) -> Result<Self, MyError> {
+        if path.as_ref().exists() {
+            std::fs::remove_file(&path)?;
+        }
+        let connection = Connection::open(path)?;
+        connection.execute_batch("PRAGMA synchronous = OFF;")?;
+        connection.execute_batch(SCHEMA)?;
+        connection.execute(
+            "INSERT INTO meta (key, value) VALUES ('schema_version', ?1), ('matrix', ?2)",
+            params![SCHEMA_VERSION, serde_json::to_string(matrix)?],
+        )?;
+        Ok(StoreSink {
+            matrix,
+            connection,
+            uncommitted: 0,
+        })
+    }
+
+    fn role(&self, ip_addr: &IpAddr) -> Option<&str> {
+        self.matrix
+            .find_role_by_ip(ip_addr)
+            .map(|role| role.name.as_str())
+    }
+}
+
+impl<'a> Sink for StoreSink<'a> {
+    fn start(&mut self, _channel_type: pnet::datalink::ChannelType) -> Result<(), MyError> {
+        // 多个数据源写入同一个事务，finish时统一提交
+        if self.connection.is_autocommit() {
+            self.connection.execute_batch("BEGIN")?;
+        }
+        Ok(())
+    }
+
+    fn write_message(&mut self, msg: &SomeipMessage) -> Result<(), MyError> {
+        let json = message_json(self.matrix, msg);
+        let source_role = self.role(&msg.source.ip_addr);
+        let destination_role = self.role(&msg.destination.ip_addr);
+        self.connection
+            .prepare_cached(
+                "INSERT INTO messages (timestamp, packet_index, source_ip, source_port, source_role,
+                 destination_ip, destination_port, destination_role, transport, service_id,
+                 service_name, method_id, method_name, client_id, session_id, message_type,
+                 return_code, length, payload, json)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16,
+                 ?17, ?18, ?19, ?20)",
+            )?
+            .execute(params![
+                msg.timestamp.as_secs_f64(),
+                msg.packet_index,
+                msg.source.ip_addr.to_string(),
+                msg.source.port,
+                source_role,
+                msg.destination.ip_addr.to_string(),
+                msg.destination.port,
+                destination_role,
+                json["transport"].as_str(),
+                msg.service_id,
+                json["service_name"].as_str(),
+                msg.method_id,
+                json["method_name"].as_str(),
+                msg.client_id,
+                msg.session_id,
+                msg.message_type.to_string(),
+                msg.return_code,
+                msg.length,
+                msg.payload,
+                json.to_string(),
+            ])?;
+        let message_id = self.connection.last_insert_rowid();
+
+        if let Some(sd) = &msg.sd {
+            let mut statement = self.connection.prepare_cached(
+                "INSERT INTO sd_entries (message_id, timestamp, source_ip, source_role,
+                 destination_ip, entry_type, service_id, instance_id, eventgroup_id,
+                 major_version, minor_version, ttl)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            )?;
+            for entry in &sd.entries {
+                statement.execute(params![
+                    message_id,
+                    msg.timestamp.as_secs_f64(),
+                    msg.source.ip_addr.to_string(),
+                    source_role,
+                    msg.destination.ip_addr.to_string(),
+                    entry.entry_type.to_string(),
+                    entry.service_id,
+                    entry.instance_id,
+                    entry.eventgroup_id,
+                    entry.major_version,
+                    entry.minor_version,
+                    entry.ttl,
+                ])?;
+            }
+        }
+
+        self.uncommitted += 1;
+        if self.uncommitted >= COMMIT_INTERVAL {
+            self.connection.execute_batch("COMMIT; BEGIN")?;
+            self.uncommitted = 0;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), MyError> {
+        self.connection.execute_batch("COMMIT")?;
+        self.connection.execute_batch(INDEXES)?;
+        self.uncommitted = 0;
+        Ok(())
+    }
+}
+
+/// 只读打开的数据库
+pub struct Store {
+    connection: Connection,
+    matrix: Matrix,
+}
+
+fn json_value(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(i) => i.into(),
+        ValueRef::Real(f) => f.into(),
+        ValueRef::Text(text) => String::from_utf8_lossy(text).into(),
+        ValueRef::Blob(blob) => blob
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()
+            .into(),
+    }
+}
+
+impl Store {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, MyError> {
+        let connection = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        let matrix: String =
+            connection.query_row("SELECT value FROM meta WHERE key = 'matrix'", [], |row| {
+                row.get(0)
+            })?;
+        Ok(Store {
+            connection,
+            matrix: serde_json::from_str(&matrix)?,
+        })
+    }
+
+    /// 过滤表达式对应的条件，SD报文中有一个条目属于选中的服务即选中
+    fn condition(&self, filter: Option<&str>) -> Result<String, MyError> {
+        let selected = match filter {
+            Some(filter) => select_methods(&self.matrix, filter)?,
+            None => return Ok("1".to_owned()),
+        };
+        let methods: Vec<String> = selected
+            .iter()
+            .map(|(service_id, method_id)| {
+                format!(
+                    "(service_id = {} AND method_id = {})",
+                    service_id, method_id
+                )
+            })
+            .collect();
+        let services: Vec<String> = selected
+            .iter()
+            .map(|(service_id, _)| service_id.to_string())
+            .collect();
+        Ok(format!(
+            "{} OR id IN (SELECT message_id FROM sd_entries WHERE service_id IN ({}))",
+            methods.join(" OR "),
+            services.join(", ")
+        ))
+    }
+
+    /// 选中的报文总数与其中一页，报文内容与message_json一致；limit为usize::MAX时不限制
+    pub fn messages(
+        &self,
+        filter: Option<&str>,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(usize, Vec<Value>), MyError> {
+        let condition = self.condition(filter)?;
+        let total: i64 = self.connection.query_row(
+            &format!("SELECT COUNT(*) FROM messages WHERE {}", condition),
+            [],
+            |row| row.get(0),
+        )?;
+        let mut statement = self.connection.prepare(&format!(
+            "SELECT json FROM messages WHERE {} ORDER BY id LIMIT ?1 OFFSET ?2",
+            condition
+        ))?;
+        let messages = statement
+            .query_map(
+                params![limit.min(i64::MAX as usize) as i64, offset as i64],
+                |row| row.get::<_, String>(0),
+            )?
+            .map(|json| Ok(serde_json::from_str(&json?)?))
+            .collect::<Result<Vec<Value>, MyError>>()?;
+        Ok((total as usize, messages))
+    }
+
+    /// 执行原始SQL，返回列名与每一行的值
+    pub fn sql(&self, query: &str) -> Result<(Vec<String>, Vec<Vec<Value>>), MyError> {
+        let mut statement = self.connection.prepare(query)?;
+        let columns: Vec<String> = statement
+            .column_names()
+            .into_iter()
+            .map(str::to_owned)
+            .collect();
+        let mut rows = statement.query([])?;
+        let mut values = vec![];
+        while let Some(row) = rows.next()? {
+            values.push(
+                (0..columns.len())
+                    .map(|i| row.get_ref(i).map(json_value))
+                    .collect::<Result<Vec<Value>, _>>()?,
+            );
+        }
+        Ok((columns, values))
+    }
+}
+
+#[test]
+fn store_and_query() {
+    use crate::analyzers::{test_ff_method, test_matrix, test_message};
+    use crate::types::SomeipMessageType;
+
+    let matrix = test_matrix(vec![test_ff_method(1, "SetTemp", &[])]);
+
+    let path = std::env::temp_dir().join(format!("someip-store-{}.sqlite", std::process::id()));
+    let mut sink = StoreSink::new(&matrix, &path).unwrap();
+    // 两个数据源写入同一个事务，最后只提交一次
+    for source in [&[(1000, 1), (1010, 2)][..], &[(1020, 1)][..]] {
+        sink.start(pnet::datalink::ChannelType::Layer2).unwrap();
+        for &(ts_ms, method_id) in source {
+            let msg = test_message(
+                ts_ms,
+                "10.0.0.1:40000",
+                "10.0.0.2:30501",
+                SomeipMessageType::RequestWithoutResponse,
+                0x1234,
+                method_id,
+            );
+            sink.write_message(&msg).unwrap();
+        }
+    }
+    sink.finish().unwrap();
+    drop(sink);
+
+    let store = Store::open(&path).unwrap();
+    let (total, messages) = store.messages(Some("Climate.SetTemp"), 1, 10).unwrap();
+    assert_eq!(total, 2);
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0]["timestamp"], 1.02);
+    let (columns, rows) = store
+        .sql("SELECT method_id, COUNT(*) AS n FROM messages GROUP BY method_id")
+        .unwrap();
+    assert_eq!(columns, ["method_id", "n"]);
+    assert_eq!(rows, [[1, 2], [2, 1]]);
+    let (_, rows) = store
+        .sql("SELECT COUNT(*) FROM sqlite_master WHERE type = 'index' AND sql IS NOT NULL")
+        .unwrap();
+    assert_eq!(rows, [[8]]);
+    assert!(store.sql("DELETE FROM messages").is_err());
+    drop(store);
+    std::fs::remove_file(path).unwrap();
+}
@@ -1,8 +1,5 @@
-use std::cell::RefCell;
-use std::collections::{HashMap, HashSet};
-use std::default;
-use std::net::IpAddr;
-use std::rc::{Rc, Weak};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr};
 use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
@@ -14,14 +11,39 @@ pub type SomeipSessionId = u16;
 pub type SomeipInstanceId = u16;
 pub type SomeipMajorVersion = u16;
 pub type SomeipMinorVersion = u16;
+pub type SomeipEventgroupId = u16;
 
 pub type PacketIndex = isize;
 
 pub type Port = u16;
 pub type ServerPort = Port;
 
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(untagged)]
+pub type MacAddr = [u8; 6];
+pub type VlanId = u16;
+
+pub fn mac_to_string(mac_addr: &MacAddr) -> String {
+    mac_addr
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// 支持 aa:bb:cc:dd:ee:ff 与 aa-bb-cc-dd-ee-ff 两种写法
+pub fn mac_from_str(s: &str) -> Option<MacAddr> {
+    let mut mac_addr = MacAddr::default();
+    let mut parts = s.trim().split([':', '-']);
+    for b in mac_addr.iter_mut() {
+        *b = u8::from_str_radix(parts.next()?, 16).ok()?;
+    }
+    match parts.next() {
+        Some(_) => None,
+        None => Some(mac_addr),
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum SomeipTransportPortocol {
     TCP,
     UDP,
@@ -29,29 +51,156 @@ pub enum SomeipTransportPortocol {
 
 pub type SomeipReturnCode = u8;
 
-/// 基础SomeIP消息类型，这里涵盖了服务发现的报文类型
+/// 基础SomeIP消息类型，与报文头中的MessageType字段一一对应
 /// 对于SomeIP-TP类型，不包含在此处，自动解包至单个SomeIP包
+/// 服务发现报文本身是Notification，具体的Offer、Subscribe等见SomeipSdEntryType
 /// 设计上不考虑显示最最原始的报文，只显示收到/发送的报文类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum SomeipMessageType {
     Request,
     RequestWithoutResponse,
     Response,
     ResponseWithError,
     Notification,
-    ServiceOffer, // 实际上所有Sd报文都是Notifiction
-    ServiceSubscribe,
-    ServiceSubscribeAck,
+    Unknown(u8),
+}
+
+impl From<u8> for SomeipMessageType {
+    /// 传入的值需要先去掉TP标志位
+    fn from(value: u8) -> Self {
+        match value {
+            0x00 => SomeipMessageType::Request,
+            0x01 => SomeipMessageType::RequestWithoutResponse,
+            0x02 => SomeipMessageType::Notification,
+            0x80 => SomeipMessageType::Response,
+            0x81 => SomeipMessageType::ResponseWithError,
+            v => SomeipMessageType::Unknown(v),
+        }
+    }
+}
+
+impl fmt::Display for SomeipMessageType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SomeipMessageType::Request => write!(f, "Request"),
+            SomeipMessageType::RequestWithoutResponse => write!(f, "RequestNoReturn"),
+            SomeipMessageType::Response => write!(f, "Response"),
+            SomeipMessageType::ResponseWithError => write!(f, "Error"),
+            SomeipMessageType::Notification => write!(f, "Notification"),
+            SomeipMessageType::Unknown(v) => write!(f, "Unknown(0x{:02x})", v),
+        }
+    }
+}
+
+/// 服务发现报文中的Entry类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SomeipSdEntryType {
+    FindService,
+    OfferService,
+    StopOfferService,
+    Subscribe,
+    StopSubscribe,
+    SubscribeAck,
+    SubscribeNack,
+    Unknown(u8),
+}
+
+impl fmt::Display for SomeipSdEntryType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SomeipSdEntryType::Unknown(v) => write!(f, "Unknown(0x{:02x})", v),
+            other => write!(f, "{:?}", other),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SomeipEndpoint {
+    pub mac_addr: MacAddr,
+    pub ip_addr: IpAddr,
+    pub port: Port,
+}
+
+impl Default for SomeipEndpoint {
+    fn default() -> Self {
+        Self {
+            mac_addr: Default::default(),
+            ip_addr: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: Default::default(),
+        }
+    }
+}
+
+impl fmt::Display for SomeipEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.ip_addr {
+            IpAddr::V4(ip) => write!(f, "{}:{}", ip, self.port),
+            IpAddr::V6(ip) => write!(f, "[{}]:{}", ip, self.port),
+        }
+    }
+}
+
+/// 服务发现报文中的IPv4/IPv6 Endpoint Option与Multicast Option
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SomeipSdEndpointOption {
+    pub ip_addr: IpAddr,
+    pub port: Port,
+    pub transport_protocol: SomeipTransportPortocol,
+    pub multicast: bool,
+}
+
+/// 服务发现报文中的一条Entry，Option中的端点信息已经展开
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct SomeipSdEntry {
+    pub entry_type: SomeipSdEntryType,
+    pub service_id: SomeipServiceId,
+    pub instance_id: SomeipInstanceId,
+    pub major_version: u8,
+    pub ttl: u32,
+    /// 仅Service类型的Entry有效
+    pub minor_version: u32,
+    /// 仅Eventgroup类型的Entry有效
+    pub eventgroup_id: SomeipEventgroupId,
+    pub counter: u8,
+    /// Entry引用的端点Option，其他类型的Option忽略
+    pub endpoints: Vec<SomeipSdEndpointOption>,
+}
+
+/// 服务发现报文头之后的内容
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct SomeipSdHeader {
+    pub reboot_flag: bool,
+    pub unicast_flag: bool,
+    pub entries: Vec<SomeipSdEntry>,
 }
 
-pub struct SomeipMessage<'a> {
+#[allow(dead_code)]
+pub struct SomeipMessage {
+    /// 抓包文件中的序号，TP或TCP重组后的报文取最后一帧的序号
+    pub packet_index: PacketIndex,
+    /// 组成该报文的所有帧的序号，TP或TCP重组后的报文包含多帧
+    pub frames: Vec<PacketIndex>,
     pub timestamp: Duration,
+    pub source: SomeipEndpoint,
+    pub destination: SomeipEndpoint,
+    pub vlan_id: Option<VlanId>,
     pub message_type: SomeipMessageType,
     pub service_id: SomeipServiceId,
     pub method_id: SomeipMethodId,
     pub client_id: SomeipClientId,
     pub session_id: SomeipSessionId,
+    pub protocol_version: u8,
+    pub interface_version: u8,
+    /// 报文头中的Length字段，TP重组后的报文按照重组后的长度填写
+    pub length: u32,
+    /// Length字段之后实际收到的字节数，小于Length说明被截断，大于Length说明后面跟着无法解析的字节
+    pub available_length: usize,
     pub return_code: SomeipReturnCode,
     pub transport_protocol: SomeipTransportPortocol,
+    /// 仅服务发现报文有效
+    pub sd: Option<SomeipSdHeader>,
     // 注意TCP/UDP-SOMEIP-TP均需要解包出来再生成该结构体
-    pub payload: Box<&'a [u8]>,
+    pub payload: Vec<u8>,
 }